@@ -0,0 +1,160 @@
+// Human-in-the-loop approval for agent tool calls.
+// Each tool is allowed, denied, or needs the user's approval before it runs.
+// "Ask" sends an `ApprovalRequest` to the front-end (the TUI shows a modal)
+// and waits for the decision; a rejection becomes the tool's observation.
+
+use std::collections::HashMap;
+use tokio::sync::{mpsc, oneshot};
+
+use super::registry::Tool;
+
+// ── Policy ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    /// Run without asking.
+    Allow,
+    /// Ask the user before every call.
+    Ask,
+    /// Never run; the model is told the tool is not allowed.
+    Deny,
+}
+
+impl std::str::FromStr for Approval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "allow" => Ok(Approval::Allow),
+            "ask" => Ok(Approval::Ask),
+            "deny" => Ok(Approval::Deny),
+            other => Err(format!("unknown approval '{other}' (expected allow, ask or deny)")),
+        }
+    }
+}
+
+/// Per-tool approval settings. Tools without an explicit rule fall back to
+/// `mutating` or `read_only` depending on `Tool::mutating`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalPolicy {
+    rules: HashMap<String, Approval>,
+    pub mutating: Approval,
+    pub read_only: Approval,
+}
+
+impl Default for ApprovalPolicy {
+    /// Ask before anything that writes files, runs commands or changes git state.
+    fn default() -> Self {
+        Self { rules: HashMap::new(), mutating: Approval::Ask, read_only: Approval::Allow }
+    }
+}
+
+impl ApprovalPolicy {
+    /// Run every tool without asking.
+    pub fn allow_all() -> Self {
+        Self { rules: HashMap::new(), mutating: Approval::Allow, read_only: Approval::Allow }
+    }
+
+    /// Set the approval for a single tool.
+    pub fn set(&mut self, tool: &str, approval: Approval) -> &mut Self {
+        self.rules.insert(tool.to_string(), approval);
+        self
+    }
+
+    /// Approval for `tool`.
+    pub fn for_tool(&self, tool: &dyn Tool) -> Approval {
+        match self.rules.get(tool.name()) {
+            Some(&a) => a,
+            None if tool.mutating() => self.mutating,
+            None => self.read_only,
+        }
+    }
+}
+
+// ── Requests ──────────────────────────────────────────────────────────────────
+
+/// The user's answer to an `ApprovalRequest`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApprovalDecision {
+    Approve,
+    /// Run the tool with this input instead.
+    Edit(String),
+    /// Don't run it; the reason (may be empty) is passed to the model.
+    Reject(String),
+}
+
+/// A pending tool call waiting for the user.
+#[derive(Debug)]
+pub struct ApprovalRequest {
+    pub step: usize,
+    pub tool: String,
+    pub input: String,
+    /// What the call will do: the command, or a diff for file edits.
+    pub preview: String,
+    pub reply: oneshot::Sender<ApprovalDecision>,
+}
+
+pub type ApprovalSender = mpsc::UnboundedSender<ApprovalRequest>;
+
+// ── Diff preview ──────────────────────────────────────────────────────────────
+
+/// Unchanged lines kept around each change in `line_diff`.
+const CONTEXT: usize = 2;
+/// Above this many lines per side the diff is not computed line by line.
+const MAX_DIFF_LINES: usize = 2000;
+
+/// A unified-style line diff of `old` → `new`: removed lines start with `-`,
+/// added lines with `+`, unchanged context with a space. Long unchanged runs
+/// are collapsed to `…`.
+pub fn line_diff(old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    if a.len() > MAX_DIFF_LINES || b.len() > MAX_DIFF_LINES {
+        return format!("({} lines → {} lines; too large to diff)", a.len(), b.len());
+    }
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut ops: Vec<(char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((' ', a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] > lcs[i + 1][j]) {
+            ops.push(('+', b[j]));
+            j += 1;
+        } else {
+            ops.push(('-', a[i]));
+            i += 1;
+        }
+    }
+
+    let changed: Vec<usize> = ops.iter().enumerate().filter(|(_, (op, _))| *op != ' ').map(|(k, _)| k).collect();
+    if changed.is_empty() {
+        return "(no changes)".to_string();
+    }
+    let near_change = |k: usize| changed.iter().any(|&c| c.abs_diff(k) <= CONTEXT);
+
+    let mut out = String::new();
+    let mut skipped = false;
+    for (k, (op, line)) in ops.iter().enumerate() {
+        if *op == ' ' && !near_change(k) {
+            if !skipped {
+                out.push_str("  …\n");
+                skipped = true;
+            }
+            continue;
+        }
+        skipped = false;
+        out.push_str(&format!("{op} {line}\n"));
+    }
+    out
+}
@@ -0,0 +1,144 @@
+// Run limits for the ReAct loop: step count, consecutive format nudges and
+// wall-clock time. When a limit is hit the loop stops with a summary instead
+// of spinning (and spending tokens) forever.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use super::truncate;
+
+// ── Limits ────────────────────────────────────────────────────────────────────
+
+/// Per-run limits for `ReActAgent::run`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentLimits {
+    /// Maximum number of LLM round-trips.
+    pub max_steps: usize,
+    /// Maximum consecutive format nudges (think-only or malformed tool-call replies).
+    pub max_format_retries: usize,
+    /// Wall-clock budget for the whole run; `None` means unlimited.
+    pub max_duration: Option<Duration>,
+}
+
+impl Default for AgentLimits {
+    fn default() -> Self {
+        Self {
+            max_steps: 15,
+            max_format_retries: 3,
+            max_duration: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+// ── Outcome ───────────────────────────────────────────────────────────────────
+
+/// Why a run stopped before producing an answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    MaxSteps(usize),
+    FormatRetries(usize),
+    TimeBudget(Duration),
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::MaxSteps(n) => write!(f, "step limit reached ({n} steps)"),
+            StopReason::FormatRetries(n) => {
+                write!(f, "model ignored the response format {n} times in a row")
+            }
+            StopReason::TimeBudget(d) => write!(f, "time budget exhausted ({}s)", d.as_secs()),
+        }
+    }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    Answered(String),
+    Stopped(StopReason),
+    /// Cancelled by the user during `step`.
+    Cancelled { step: usize },
+    /// The LLM request failed.
+    Failed(String),
+}
+
+/// What a run did, reported when it finishes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RunSummary {
+    pub steps: usize,
+    /// One short line per tool call, e.g. "`read_file` README.md".
+    pub tool_calls: Vec<String>,
+    pub elapsed: Duration,
+}
+
+// ── Per-run tracker ───────────────────────────────────────────────────────────
+
+pub(crate) struct Budget<'a> {
+    limits: &'a AgentLimits,
+    started: Instant,
+    pub step: usize,
+    nudges: usize,
+    actions: Vec<String>,
+}
+
+impl<'a> Budget<'a> {
+    pub fn start(limits: &'a AgentLimits) -> Self {
+        Self { limits, started: Instant::now(), step: 0, nudges: 0, actions: Vec::new() }
+    }
+
+    /// Advance to the next step, or return why the run must stop.
+    pub fn next_step(&mut self) -> Result<usize, StopReason> {
+        if self.step >= self.limits.max_steps {
+            return Err(StopReason::MaxSteps(self.limits.max_steps));
+        }
+        if let Some(max) = self.limits.max_duration {
+            if self.started.elapsed() >= max {
+                return Err(StopReason::TimeBudget(max));
+            }
+        }
+        self.step += 1;
+        Ok(self.step)
+    }
+
+    /// Count a format nudge; errors once the consecutive-retry cap is exceeded.
+    pub fn nudge(&mut self) -> Result<(), StopReason> {
+        self.nudges += 1;
+        if self.nudges > self.limits.max_format_retries {
+            return Err(StopReason::FormatRetries(self.nudges));
+        }
+        Ok(())
+    }
+
+    /// A well-formed action resets the consecutive nudge counter.
+    pub fn reset_nudges(&mut self) {
+        self.nudges = 0;
+    }
+
+    pub fn record_tool(&mut self, name: &str, input: &str) {
+        let first_line = input.trim().lines().next().unwrap_or("");
+        self.actions.push(format!("`{name}` {}", truncate(first_line, 80)));
+    }
+
+    /// Run `fut` within the remaining wall-clock budget.
+    pub async fn timed<F: Future>(&self, fut: F) -> Result<F::Output, StopReason> {
+        match self.limits.max_duration {
+            None => Ok(fut.await),
+            Some(max) => {
+                let remaining = max.saturating_sub(self.started.elapsed());
+                tokio::time::timeout(remaining, fut)
+                    .await
+                    .map_err(|_| StopReason::TimeBudget(max))
+            }
+        }
+    }
+
+    /// Summary of the run so far.
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            steps: self.step,
+            tool_calls: self.actions.clone(),
+            elapsed: self.started.elapsed(),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// Checkpoints of the files an agent run changed.
+// Every write by a file tool records the file's content from before the
+// edit, tagged with the agent step that made it. The run can then be rolled
+// back one edit (`undo_edit`), one step, or entirely — untracked files
+// included, since nothing here relies on git.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// One file edit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Agent step that made the edit (0 outside an agent run).
+    pub step: usize,
+    pub path: PathBuf,
+    /// The path as the tool was given it.
+    pub display: String,
+    /// Bytes before the edit, which need not be UTF-8; `None` if the edit
+    /// created the file.
+    pub before: Option<Vec<u8>>,
+    /// Content the edit wrote, to notice later changes before restoring.
+    pub after: String,
+}
+
+/// The checkpoints of one run, shared by its tools and the front-end.
+/// Clones share the same list.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoints {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    step: usize,
+    edits: Vec<Checkpoint>,
+}
+
+impl Checkpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Tag the edits that follow with `step`.
+    pub fn set_step(&self, step: usize) {
+        self.lock().step = step;
+    }
+
+    /// Record an edit of `path` from `before` to `after`.
+    pub fn record(&self, path: PathBuf, display: &str, before: Option<Vec<u8>>, after: &str) {
+        let mut inner = self.lock();
+        let checkpoint = Checkpoint { step: inner.step, path, display: display.to_string(), before, after: after.to_string() };
+        inner.edits.push(checkpoint);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().edits.is_empty()
+    }
+
+    /// All edits, oldest first.
+    pub fn list(&self) -> Vec<Checkpoint> {
+        self.lock().edits.clone()
+    }
+
+    /// The most recent edit.
+    pub fn last(&self) -> Option<Checkpoint> {
+        self.lock().edits.last().cloned()
+    }
+
+    /// Revert the most recent edit. `None` if there is nothing to undo.
+    pub fn undo_last(&self) -> Option<String> {
+        let checkpoint = self.lock().edits.pop()?;
+        Some(restore(&checkpoint))
+    }
+
+    /// Revert every edit of the most recent step that made one, newest
+    /// first. Returns the step and a line per file.
+    pub fn undo_step(&self) -> Option<(usize, Vec<String>)> {
+        let undone = {
+            let mut inner = self.lock();
+            let step = inner.edits.last()?.step;
+            let keep = inner.edits.iter().rposition(|c| c.step != step).map_or(0, |i| i + 1);
+            inner.edits.split_off(keep)
+        };
+        let step = undone[0].step;
+        Some((step, undone.iter().rev().map(restore).collect()))
+    }
+
+    /// Revert every edit of the run, newest first.
+    pub fn revert_all(&self) -> Vec<String> {
+        let undone = std::mem::take(&mut self.lock().edits);
+        undone.iter().rev().map(restore).collect()
+    }
+}
+
+/// Put the file back as it was before `checkpoint` and describe what happened.
+fn restore(checkpoint: &Checkpoint) -> String {
+    let display = &checkpoint.display;
+    let changed_since = match std::fs::read(&checkpoint.path) {
+        Ok(current) => current != checkpoint.after.as_bytes(),
+        Err(_) => true,
+    };
+    let note = if changed_since { " (it had changed since the edit; those changes were overwritten)" } else { "" };
+    let result = match &checkpoint.before {
+        Some(content) => std::fs::write(&checkpoint.path, content).map(|_| format!("restored {display}{note}")),
+        None if !checkpoint.path.exists() => return format!("{display} was already removed"),
+        None => std::fs::remove_file(&checkpoint.path).map(|_| format!("removed {display}, which the edit created{note}")),
+    };
+    result.unwrap_or_else(|e| format!("could not restore {display}: {e}"))
+}
@@ -0,0 +1,224 @@
+// JSON Lines log of chat and agent runs (`--events-jsonl <path|->`).
+// Every `AgentEvent` worth keeping becomes one JSON object per line with its
+// type, timestamp, run number and step index, so scripts and dashboards can
+// follow a run without parsing the TUI's markdown. Closing the log appends a
+// `summary` record with totals over all runs.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::budget::RunOutcome;
+use super::events::{AgentEvent, EventSender};
+use crate::clock::rfc3339;
+use crate::llm::TokenUsage;
+
+/// How long `close` waits for runs still in flight to report their last events.
+const CLOSE_GRACE: Duration = Duration::from_secs(2);
+
+/// A shared JSONL event log. Clones write to the same stream.
+#[derive(Clone)]
+pub struct EventLog {
+    inner: Arc<Mutex<Inner>>,
+    to_stdout: bool,
+}
+
+struct Inner {
+    out: Box<dyn Write + Send>,
+    started: Instant,
+    /// Runs finished so far; the current run is `runs + 1`.
+    runs: usize,
+    steps: usize,
+    tool_calls: usize,
+    errors: usize,
+    usage: TokenUsage,
+    last_outcome: Option<&'static str>,
+    closed: bool,
+    forwarders: Vec<JoinHandle<()>>,
+}
+
+impl EventLog {
+    /// Log to `target`: a file path (truncated), or `-` for stdout.
+    pub fn open(target: &str) -> Result<Self> {
+        if target == "-" {
+            return Ok(Self::new(Box::new(io::stdout()), true));
+        }
+        let file = File::create(Path::new(target))
+            .with_context(|| format!("Failed to create event log {target}"))?;
+        Ok(Self::new(Box::new(BufWriter::new(file)), false))
+    }
+
+    /// Log to any writer.
+    pub fn to_writer(out: Box<dyn Write + Send>) -> Self {
+        Self::new(out, false)
+    }
+
+    fn new(out: Box<dyn Write + Send>, to_stdout: bool) -> Self {
+        let inner = Inner {
+            out,
+            started: Instant::now(),
+            runs: 0,
+            steps: 0,
+            tool_calls: 0,
+            errors: 0,
+            usage: TokenUsage::default(),
+            last_outcome: None,
+            closed: false,
+            forwarders: Vec::new(),
+        };
+        Self { inner: Arc::new(Mutex::new(inner)), to_stdout }
+    }
+
+    /// True when the log goes to stdout, which then carries nothing else.
+    pub fn is_stdout(&self) -> bool {
+        self.to_stdout
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write the record for `event`, if it has one.
+    pub fn record(&self, event: &AgentEvent) {
+        if matches!(event, AgentEvent::ThoughtDelta { .. } | AgentEvent::AnswerDelta { .. }) {
+            return;
+        }
+        let mut inner = self.lock();
+        if inner.closed {
+            return;
+        }
+        let run = inner.runs + 1;
+        match event {
+            AgentEvent::ToolCall { .. } => inner.tool_calls += 1,
+            AgentEvent::Error { .. } => inner.errors += 1,
+            AgentEvent::Usage { usage, .. } => {
+                inner.usage.input_tokens += usage.input_tokens;
+                inner.usage.output_tokens += usage.output_tokens;
+            }
+            AgentEvent::Finished { outcome, summary } => {
+                inner.runs += 1;
+                inner.steps += summary.steps;
+                inner.last_outcome = Some(outcome_name(outcome));
+            }
+            _ => {}
+        }
+        let mut record = json!({ "ts": rfc3339(SystemTime::now()), "run": run });
+        if let (Some(record), Value::Object(mut fields)) = (record.as_object_mut(), event_json(event)) {
+            record.append(&mut fields);
+        }
+        write_line(&mut inner, &record);
+    }
+
+    /// A sender whose events are logged, then passed on to `downstream`.
+    pub fn tee(&self, downstream: EventSender) -> EventSender {
+        self.forward(Some(downstream))
+    }
+
+    /// A sender whose events are only logged.
+    pub fn sender(&self) -> EventSender {
+        self.forward(None)
+    }
+
+    fn forward(&self, downstream: Option<EventSender>) -> EventSender {
+        let (tx, mut rx) = mpsc::unbounded_channel::<AgentEvent>();
+        let log = self.clone();
+        let handle = tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                log.record(&event);
+                if let Some(downstream) = &downstream {
+                    let _ = downstream.send(event);
+                }
+            }
+        });
+        let mut inner = self.lock();
+        inner.forwarders.retain(|h| !h.is_finished());
+        inner.forwarders.push(handle);
+        tx
+    }
+
+    /// Wait briefly for runs in flight, then write the `summary` record.
+    /// Later calls and later events are ignored.
+    pub async fn close(&self) {
+        let forwarders = std::mem::take(&mut self.lock().forwarders);
+        let _ = tokio::time::timeout(CLOSE_GRACE, futures_util::future::join_all(forwarders)).await;
+        let mut inner = self.lock();
+        if inner.closed {
+            return;
+        }
+        let record = json!({
+            "type": "summary",
+            "ts": rfc3339(SystemTime::now()),
+            "runs": inner.runs,
+            "steps": inner.steps,
+            "tool_calls": inner.tool_calls,
+            "errors": inner.errors,
+            "usage": usage_json(&inner.usage),
+            "outcome": inner.last_outcome,
+            "elapsed_ms": inner.started.elapsed().as_millis() as u64,
+        });
+        write_line(&mut inner, &record);
+        inner.closed = true;
+    }
+}
+
+fn write_line(inner: &mut Inner, record: &Value) {
+    // Flush per line so followers (`tail -f`, the IDE plugin) see events live
+    let _ = writeln!(inner.out, "{record}");
+    let _ = inner.out.flush();
+}
+
+/// `event` as a JSON object with its `type`, `step` and fields. Shared by the
+/// log and `qai-cli serve`, which also streams the deltas the log skips.
+pub fn event_json(event: &AgentEvent) -> Value {
+    let (kind, step, mut fields) = match event {
+        AgentEvent::StepStart { step } => ("step", *step, json!({})),
+        AgentEvent::ThoughtDelta { step, text } => ("thought_delta", *step, json!({ "text": text })),
+        AgentEvent::Thought { step, text } => ("thought", *step, json!({ "text": text })),
+        AgentEvent::AnswerDelta { step, text } => ("answer_delta", *step, json!({ "text": text })),
+        AgentEvent::ToolCall { step, name, input } => ("tool_call", *step, json!({ "name": name, "input": input })),
+        AgentEvent::Observation { step, name, output } => {
+            ("observation", *step, json!({ "name": name, "output": output }))
+        }
+        AgentEvent::Answer { step, text } => ("answer", *step, json!({ "text": text })),
+        AgentEvent::Notice { step, message } => ("notice", *step, json!({ "message": message })),
+        AgentEvent::Error { step, message } => ("error", *step, json!({ "message": message })),
+        AgentEvent::Usage { step, usage } => ("usage", *step, usage_json(usage)),
+        AgentEvent::Finished { outcome, summary } => {
+            let mut fields = json!({
+                "outcome": outcome_name(outcome),
+                "steps": summary.steps,
+                "tool_calls": summary.tool_calls.len(),
+                "elapsed_ms": summary.elapsed.as_millis() as u64,
+            });
+            match outcome {
+                RunOutcome::Stopped(reason) => fields["reason"] = json!(reason.to_string()),
+                RunOutcome::Failed(message) => fields["reason"] = json!(message),
+                RunOutcome::Answered(_) | RunOutcome::Cancelled { .. } => {}
+            }
+            ("run_end", summary.steps, fields)
+        }
+    };
+    fields["type"] = json!(kind);
+    fields["step"] = json!(step);
+    fields
+}
+
+fn usage_json(usage: &TokenUsage) -> Value {
+    json!({ "input_tokens": usage.input_tokens, "output_tokens": usage.output_tokens })
+}
+
+pub(crate) fn outcome_name(outcome: &RunOutcome) -> &'static str {
+    match outcome {
+        RunOutcome::Answered(_) => "answered",
+        RunOutcome::Stopped(_) => "stopped",
+        RunOutcome::Cancelled { .. } => "cancelled",
+        RunOutcome::Failed(_) => "failed",
+    }
+}
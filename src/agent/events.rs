@@ -0,0 +1,205 @@
+// Typed progress events emitted by `ReActAgent::run`.
+// The agent reports what happened; renderers decide how it looks. The TUI
+// uses `MarkdownRenderer`, other front-ends can consume the events directly.
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::budget::{RunOutcome, RunSummary};
+use super::{truncate, ReActAgent};
+use crate::llm::TokenUsage;
+
+// ── Events ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    /// A new LLM round-trip begins.
+    StepStart { step: usize },
+    /// Partial thought text while the model is still streaming.
+    ThoughtDelta { step: usize, text: String },
+    /// A complete thought. Follows any `ThoughtDelta`s for the same block.
+    Thought { step: usize, text: String },
+    /// Partial answer text while the model is still streaming.
+    AnswerDelta { step: usize, text: String },
+    /// The model called a tool.
+    ToolCall { step: usize, name: String, input: String },
+    /// A tool's output.
+    Observation { step: usize, name: String, output: String },
+    /// The final answer. Follows any `AnswerDelta`s.
+    Answer { step: usize, text: String },
+    /// A recoverable oddity, e.g. the model ignored the response format.
+    Notice { step: usize, message: String },
+    /// A failure, e.g. the LLM request errored.
+    Error { step: usize, message: String },
+    /// Tokens used by this step's LLM request.
+    Usage { step: usize, usage: TokenUsage },
+    /// The run ended. Always the last event.
+    Finished { outcome: RunOutcome, summary: RunSummary },
+}
+
+pub type EventSender = mpsc::UnboundedSender<AgentEvent>;
+
+/// Send an event, ignoring a closed receiver (the UI may have gone away).
+pub(crate) fn emit(tx: &EventSender, event: AgentEvent) {
+    let _ = tx.send(event);
+}
+
+// ── Markdown rendering ────────────────────────────────────────────────────────
+
+/// Renders events as the markdown shown in the TUI conversation panel.
+/// Stateful: streamed deltas are printed as they arrive, and the complete
+/// `Thought` / `Answer` that follows them only closes the block.
+#[derive(Debug, Default)]
+pub struct MarkdownRenderer {
+    in_thought: bool,
+    in_answer: bool,
+}
+
+impl MarkdownRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Markdown for `event`, or `None` when it has no visible output.
+    pub fn render(&mut self, event: &AgentEvent) -> Option<String> {
+        match event {
+            AgentEvent::StepStart { step } => Some(format!("\n---\n🔄 **Step {step}**\n")),
+            AgentEvent::ThoughtDelta { text, .. } => {
+                if std::mem::replace(&mut self.in_thought, true) {
+                    Some(text.clone())
+                } else {
+                    Some(format!("💭 **Thought:** {text}"))
+                }
+            }
+            AgentEvent::Thought { text, .. } => {
+                if std::mem::take(&mut self.in_thought) {
+                    Some("\n\n".to_string())
+                } else {
+                    Some(format!("💭 **Thought:** {}\n\n", text.trim()))
+                }
+            }
+            AgentEvent::AnswerDelta { text, .. } => {
+                if std::mem::replace(&mut self.in_answer, true) {
+                    Some(text.clone())
+                } else {
+                    Some(format!("\n✅ **Answer:**\n{text}"))
+                }
+            }
+            AgentEvent::Answer { text, .. } => {
+                if std::mem::take(&mut self.in_answer) {
+                    Some("\n".to_string())
+                } else {
+                    Some(format!("\n✅ **Answer:**\n{}\n", text.trim()))
+                }
+            }
+            AgentEvent::ToolCall { name, input, .. } => {
+                Some(format!("🔧 **Tool `{name}`:** `{}`\n", truncate(input, 120)))
+            }
+            AgentEvent::Observation { output, .. } => {
+                Some(format!("👁 **Observation:**\n```\n{}\n```\n\n", truncate(output, 800)))
+            }
+            AgentEvent::Notice { message, .. } => Some(format!("⚠️ *{message}*\n")),
+            AgentEvent::Error { message, .. } => Some(format!("\n❌ **Error:** {message}\n")),
+            AgentEvent::Usage { .. } => None,
+            AgentEvent::Finished { outcome, summary } => match outcome {
+                RunOutcome::Stopped(reason) => {
+                    let mut out = format!(
+                        "\n⏹ **Stopped:** {reason}.\n**Summary:** {} step(s), {} tool call(s) in {}s.\n",
+                        summary.steps,
+                        summary.tool_calls.len(),
+                        summary.elapsed.as_secs()
+                    );
+                    for call in &summary.tool_calls {
+                        out.push_str(&format!("- {call}\n"));
+                    }
+                    Some(out)
+                }
+                RunOutcome::Cancelled { step } => {
+                    Some(format!("\n⛔ **Run stopped by user at step {step}.**\n"))
+                }
+                RunOutcome::Answered(_) | RunOutcome::Failed(_) => None,
+            },
+        }
+    }
+}
+
+// ── Plain progress ────────────────────────────────────────────────────────────
+
+/// One line of plain-text progress for `event`, as `qai-cli run` prints to
+/// stderr. Streamed deltas and the answer itself are left out.
+pub fn render_progress(event: &AgentEvent) -> Option<String> {
+    match event {
+        AgentEvent::StepStart { step } => Some(format!("[step {step}]")),
+        AgentEvent::Thought { text, .. } => Some(format!("  thought: {}", truncate(text.trim(), 300))),
+        AgentEvent::ToolCall { name, input, .. } => {
+            let first_line = input.trim().lines().next().unwrap_or("");
+            Some(format!("  tool: {name} {}", truncate(first_line, 120)))
+        }
+        AgentEvent::Observation { output, .. } => {
+            let lines = output.lines().count();
+            let first_line = output.trim().lines().next().unwrap_or("");
+            Some(format!("  observation ({lines} lines): {}", truncate(first_line, 120)))
+        }
+        AgentEvent::Notice { message, .. } => Some(format!("  notice: {message}")),
+        AgentEvent::Error { message, .. } => Some(format!("  error: {message}")),
+        AgentEvent::Finished { outcome, summary } => {
+            let what = match outcome {
+                RunOutcome::Answered(_) => "answered".to_string(),
+                RunOutcome::Stopped(reason) => format!("stopped: {reason}"),
+                RunOutcome::Cancelled { step } => format!("cancelled at step {step}"),
+                RunOutcome::Failed(e) => format!("failed: {e}"),
+            };
+            Some(format!(
+                "{what} ({} step(s), {} tool call(s) in {}s)",
+                summary.steps,
+                summary.tool_calls.len(),
+                summary.elapsed.as_secs()
+            ))
+        }
+        AgentEvent::ThoughtDelta { .. }
+        | AgentEvent::AnswerDelta { .. }
+        | AgentEvent::Answer { .. }
+        | AgentEvent::Usage { .. } => None,
+    }
+}
+
+/// Forward agent events to the TUI's token channel as markdown. Sends `None`
+/// once the run finishes (or the event channel closes).
+pub async fn forward_as_markdown(
+    mut events: mpsc::UnboundedReceiver<AgentEvent>,
+    tx: mpsc::UnboundedSender<Option<String>>,
+) {
+    let mut renderer = MarkdownRenderer::new();
+    while let Some(event) = events.recv().await {
+        if let Some(text) = renderer.render(&event) {
+            let _ = tx.send(Some(text));
+        }
+        if matches!(event, AgentEvent::Finished { .. }) {
+            break;
+        }
+    }
+    let _ = tx.send(None);
+}
+
+/// Run `agent` and stream its progress into the TUI's token channel as markdown.
+/// A run that fails outright is reported as an error event, so `tx` always
+/// ends with `None`.
+pub async fn run_as_markdown(
+    agent: &ReActAgent,
+    task: String,
+    prior_history: Vec<(String, String)>,
+    tx: mpsc::UnboundedSender<Option<String>>,
+    cancel: CancellationToken,
+) {
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let forward = tokio::spawn(forward_as_markdown(events_rx, tx));
+    if let Err(e) = agent.run(task, prior_history, events_tx.clone(), cancel).await {
+        emit(&events_tx, AgentEvent::Error { step: 0, message: format!("Agent error: {e}") });
+        emit(&events_tx, AgentEvent::Finished {
+            outcome: RunOutcome::Failed(e.to_string()),
+            summary: RunSummary::default(),
+        });
+    }
+    drop(events_tx);
+    let _ = forward.await;
+}
@@ -0,0 +1,468 @@
+// Model Context Protocol (MCP) client.
+// Servers listed in `~/.config/qai/mcp.json` (the `mcpServers` format other
+// MCP clients use) are started over stdio when QAI launches. Their tools join
+// the registry as `<server>__<tool>`, so the agent calls them like built-ins
+// and the approval policy applies to them too.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+
+use super::registry::Tool;
+
+/// Protocol revision sent in `initialize`; servers answer with the one they speak.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+/// How long a server may take to start up and list its tools.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest tool name providers accept for native function calling.
+const MAX_TOOL_NAME: usize = 64;
+
+// ── Config ────────────────────────────────────────────────────────────────────
+
+/// One entry of `mcpServers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpServerConfig {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    /// Working directory (defaults to the workspace root).
+    pub cwd: Option<PathBuf>,
+}
+
+/// `~/.config/qai/mcp.json` on Linux; the platform config dir elsewhere.
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("qai").join("mcp.json"))
+}
+
+/// Parse `{"mcpServers": {"name": {"command", "args", "env", "cwd", "disabled"}}}`.
+pub fn parse_config(text: &str) -> Result<Vec<McpServerConfig>> {
+    let root: Value = serde_json::from_str(text).context("MCP config is not valid JSON")?;
+    let Some(servers) = root.get("mcpServers") else {
+        return Ok(Vec::new());
+    };
+    let servers = servers.as_object().context("'mcpServers' must be an object")?;
+    let mut configs = Vec::new();
+    for (name, server) in servers {
+        if server["disabled"].as_bool().unwrap_or(false) {
+            continue;
+        }
+        let command = server["command"]
+            .as_str()
+            .filter(|c| !c.trim().is_empty())
+            .with_context(|| format!("MCP server '{name}' has no 'command'"))?;
+        let args = match &server["args"] {
+            Value::Null => Vec::new(),
+            Value::Array(args) => args
+                .iter()
+                .map(|a| a.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .with_context(|| format!("MCP server '{name}': 'args' must be strings"))?,
+            _ => bail!("MCP server '{name}': 'args' must be an array"),
+        };
+        let env = match &server["env"] {
+            Value::Null => Vec::new(),
+            Value::Object(env) => env
+                .iter()
+                .map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                .collect::<Option<Vec<_>>>()
+                .with_context(|| format!("MCP server '{name}': 'env' values must be strings"))?,
+            _ => bail!("MCP server '{name}': 'env' must be an object"),
+        };
+        configs.push(McpServerConfig {
+            name: name.clone(),
+            command: command.to_string(),
+            args,
+            env,
+            cwd: server["cwd"].as_str().map(PathBuf::from),
+        });
+    }
+    Ok(configs)
+}
+
+/// Read the config at `path`; a missing file means no servers.
+pub fn load_config(path: &Path) -> Result<Vec<McpServerConfig>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => parse_config(&text).with_context(|| format!("Failed to load {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+// ── Client ────────────────────────────────────────────────────────────────────
+
+type Reply = Result<Value, String>;
+
+#[derive(Default)]
+struct Pending {
+    calls: HashMap<u64, oneshot::Sender<Reply>>,
+    /// Set once the server's output ends; later requests fail at once.
+    closed: Option<String>,
+}
+
+/// A running MCP server, spoken to with newline-delimited JSON-RPC on stdio.
+/// The process is killed when the client is dropped.
+pub struct McpClient {
+    name: String,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Arc<Mutex<Pending>>,
+    next_id: AtomicU64,
+    _child: Child,
+}
+
+impl McpClient {
+    /// Start the server and complete the `initialize` handshake.
+    pub async fn start(config: &McpServerConfig, workspace_root: &Path) -> Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(config.env.iter().cloned())
+            .current_dir(config.cwd.as_deref().unwrap_or(workspace_root))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Server logs would draw over the TUI
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start `{}`", config.command))?;
+        let stdin = Arc::new(tokio::sync::Mutex::new(child.stdin.take().context("no stdin")?));
+        let stdout = child.stdout.take().context("no stdout")?;
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        tokio::spawn(read_messages(config.name.clone(), stdout, stdin.clone(), pending.clone()));
+
+        let client = Self { name: config.name.clone(), stdin, pending, next_id: AtomicU64::new(1), _child: child };
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "qai-cli", "version": env!("CARGO_PKG_VERSION") },
+        });
+        tokio::time::timeout(STARTUP_TIMEOUT, client.request("initialize", params))
+            .await
+            .map_err(|_| anyhow!("no answer to `initialize` within {}s", STARTUP_TIMEOUT.as_secs()))??;
+        client.send(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+        Ok(client)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Every tool the server offers, following `nextCursor` pages.
+    pub async fn list_tools(&self) -> Result<Vec<Value>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let page = tokio::time::timeout(STARTUP_TIMEOUT, self.request("tools/list", params))
+                .await
+                .map_err(|_| anyhow!("no answer to `tools/list` within {}s", STARTUP_TIMEOUT.as_secs()))??;
+            tools.extend(page["tools"].as_array().cloned().unwrap_or_default());
+            match page["nextCursor"].as_str() {
+                Some(next) if !next.is_empty() => cursor = Some(next.to_string()),
+                _ => return Ok(tools),
+            }
+        }
+    }
+
+    /// Call `tool` with `arguments`; returns the raw `tools/call` result.
+    pub async fn call_tool(&self, tool: &str, arguments: Value) -> Result<Value> {
+        self.request("tools/call", json!({ "name": tool, "arguments": arguments })).await
+    }
+
+    fn lock_pending(&self) -> MutexGuard<'_, Pending> {
+        lock(&self.pending)
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply, answer) = oneshot::channel();
+        {
+            let mut pending = self.lock_pending();
+            if let Some(reason) = &pending.closed {
+                bail!("{reason}");
+            }
+            pending.calls.insert(id, reply);
+        }
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        match answer.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(message)) => bail!("{message}"),
+            Err(_) => bail!("the MCP server '{}' exited", self.name),
+        }
+    }
+
+    async fn send(&self, message: &Value) -> Result<()> {
+        write_message(&self.stdin, message)
+            .await
+            .with_context(|| format!("Failed to write to the MCP server '{}'", self.name))
+    }
+}
+
+fn lock(pending: &Mutex<Pending>) -> MutexGuard<'_, Pending> {
+    pending.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+async fn write_message(stdin: &tokio::sync::Mutex<ChildStdin>, message: &Value) -> std::io::Result<()> {
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(format!("{message}\n").as_bytes()).await?;
+    stdin.flush().await
+}
+
+/// Route responses to their callers until the server's output ends.
+async fn read_messages(
+    server: String,
+    stdout: ChildStdout,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Arc<Mutex<Pending>>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        // Some servers print banners on stdout; skip anything that isn't JSON
+        let Ok(message) = serde_json::from_str::<Value>(&line) else { continue };
+        match (message.get("id"), message.get("method")) {
+            (Some(id), Some(method)) => {
+                // A request from the server: answer pings, decline the rest
+                let reply = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "not supported" } })
+                };
+                let _ = write_message(&stdin, &reply).await;
+            }
+            (Some(id), None) => {
+                let Some(id) = id.as_u64() else { continue };
+                let reply = match message.get("error") {
+                    Some(error) => Err(error["message"].as_str().unwrap_or("unknown error").to_string()),
+                    None => Ok(message["result"].clone()),
+                };
+                if let Some(caller) = lock(&pending).calls.remove(&id) {
+                    let _ = caller.send(reply);
+                }
+            }
+            // Notifications (logging, progress) are not shown
+            _ => {}
+        }
+    }
+    let mut pending = lock(&pending);
+    pending.closed = Some(format!("the MCP server '{server}' exited"));
+    // Dropping the senders fails every call still waiting
+    pending.calls.clear();
+}
+
+// ── Tools ─────────────────────────────────────────────────────────────────────
+
+/// A tool offered by an MCP server, registered as `<server>__<tool>`.
+pub struct McpTool {
+    client: Arc<McpClient>,
+    name: String,
+    remote_name: String,
+    description: String,
+    input_format: String,
+    schema: Value,
+    read_only: bool,
+}
+
+impl McpTool {
+    /// Wrap one entry of a `tools/list` result.
+    pub fn new(client: Arc<McpClient>, tool: &Value) -> Option<Self> {
+        let remote_name = tool["name"].as_str()?.to_string();
+        let summary = tool["description"].as_str().and_then(|d| d.lines().map(str::trim).find(|l| !l.is_empty()));
+        let description = match summary {
+            Some(summary) => format!("{} (MCP server {})", summary.trim_end_matches('.'), client.name()),
+            None => format!("{remote_name} from the MCP server {}", client.name()),
+        };
+        let schema = match &tool["inputSchema"] {
+            Value::Object(_) => tool["inputSchema"].clone(),
+            _ => json!({ "type": "object", "properties": {} }),
+        };
+        Some(Self {
+            name: namespaced(client.name(), &remote_name),
+            input_format: input_format(&schema),
+            read_only: tool["annotations"]["readOnlyHint"].as_bool().unwrap_or(false),
+            client,
+            remote_name,
+            description,
+            schema,
+        })
+    }
+
+    /// Turn the string input into the call's arguments: a JSON object, or
+    /// plain text when the tool takes a single argument.
+    fn arguments(&self, input: &str) -> Result<Value, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(json!({}));
+        }
+        if let Ok(args @ Value::Object(_)) = serde_json::from_str::<Value>(input) {
+            return Ok(args);
+        }
+        match self.schema["properties"].as_object() {
+            Some(properties) if properties.len() == 1 => {
+                let key = properties.keys().next().cloned().unwrap_or_default();
+                Ok(json!({ key: input }))
+            }
+            _ => Err(format!("input must be a JSON object of arguments; {}", self.input_format)),
+        }
+    }
+}
+
+impl Tool for McpTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_format(&self) -> &str {
+        &self.input_format
+    }
+
+    fn input_schema(&self) -> Value {
+        self.schema.clone()
+    }
+
+    fn input_from_args(&self, args: &Value) -> String {
+        args.to_string()
+    }
+
+    /// Unless the server marks a tool read-only, assume it has side effects.
+    fn mutating(&self) -> bool {
+        !self.read_only
+    }
+
+    fn execute<'a>(&'a self, input: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let args = match self.arguments(input) {
+                Ok(args) => args,
+                Err(e) => return Ok(format!("[{} error: {e}]", self.name)),
+            };
+            match self.client.call_tool(&self.remote_name, args).await {
+                Ok(result) if result["isError"].as_bool().unwrap_or(false) => {
+                    Ok(format!("[{} error: {}]", self.name, result_text(&result)))
+                }
+                Ok(result) => Ok(result_text(&result)),
+                Err(e) => Ok(format!("[{} error: {e}]", self.name)),
+            }
+        })
+    }
+}
+
+/// `<server>__<tool>`, reduced to the characters and length every provider
+/// accepts for function names.
+pub fn namespaced(server: &str, tool: &str) -> String {
+    format!("{server}__{tool}")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(MAX_TOOL_NAME)
+        .collect()
+}
+
+/// The XML-prompt input format: the argument names, required ones starred.
+fn input_format(schema: &Value) -> String {
+    let required: Vec<&str> = schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    let keys: Vec<String> = schema["properties"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, _)| if required.contains(&key.as_str()) { format!("{key}*") } else { key.clone() })
+        .collect();
+    if keys.is_empty() {
+        "none (leave empty)".to_string()
+    } else {
+        format!("JSON object with {} (* = required)", keys.join(", "))
+    }
+}
+
+/// The text of a `tools/call` result's content blocks.
+fn result_text(result: &Value) -> String {
+    let blocks: Vec<String> = result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|block| match block["type"].as_str() {
+            Some("text") => block["text"].as_str().unwrap_or("").to_string(),
+            Some("resource") => match block["resource"]["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!("[resource: {}]", block["resource"]["uri"].as_str().unwrap_or("?")),
+            },
+            Some(other) => format!("[{other} content: {}]", block["mimeType"].as_str().unwrap_or("unknown type")),
+            None => block.to_string(),
+        })
+        .collect();
+    let text = blocks.join("\n");
+    if !text.trim().is_empty() {
+        return text;
+    }
+    match result.get("structuredContent") {
+        Some(structured) => structured.to_string(),
+        None => "(no output)".to_string(),
+    }
+}
+
+// ── Servers ───────────────────────────────────────────────────────────────────
+
+/// The configured MCP servers that started, and their tools. Cheap to clone;
+/// the processes stop once the last clone is dropped.
+#[derive(Clone, Default)]
+pub struct McpServers {
+    clients: Vec<Arc<McpClient>>,
+    tools: Vec<Arc<dyn Tool>>,
+    errors: Vec<String>,
+}
+
+impl McpServers {
+    /// Start every server in `configs` concurrently. A server that fails to
+    /// start is left out and reported in `errors`.
+    pub async fn start(configs: &[McpServerConfig], workspace_root: &Path) -> Self {
+        let started = futures_util::future::join_all(configs.iter().map(|config| async move {
+            let client = Arc::new(McpClient::start(config, workspace_root).await?);
+            let tools = client.list_tools().await?;
+            Ok::<_, anyhow::Error>((client, tools))
+        }))
+        .await;
+        let mut servers = Self::default();
+        for (config, result) in configs.iter().zip(started) {
+            match result {
+                Ok((client, tools)) => {
+                    for tool in &tools {
+                        if let Some(tool) = McpTool::new(client.clone(), tool) {
+                            servers.tools.push(Arc::new(tool));
+                        }
+                    }
+                    servers.clients.push(client);
+                }
+                Err(e) => servers.errors.push(format!("MCP server '{}' failed to start: {e:#}", config.name)),
+            }
+        }
+        servers
+    }
+
+    /// Every tool of every running server.
+    pub fn tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.tools.clone()
+    }
+
+    /// Names of the running servers.
+    pub fn servers(&self) -> Vec<&str> {
+        self.clients.iter().map(|c| c.name()).collect()
+    }
+
+    /// One message per server that failed to start.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
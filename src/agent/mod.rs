@@ -1,15 +1,39 @@
 pub mod tools;
+pub mod registry;
+pub mod approval;
+pub mod checkpoint;
+pub mod workspace;
+pub mod shell;
+pub mod terminal;
+pub mod viewer;
+pub mod budget;
+pub mod events;
+pub mod event_log;
+pub mod mcp;
+pub mod native;
+pub mod stream;
 pub mod pr_review;
 
 use anyhow::Result;
-use reqwest::Client;
-use serde_json::json;
-use tokio::sync::mpsc;
-
-use crate::tui::providers::Provider;
-
-// ── Constants ─────────────────────────────────────────────────────────────────
+use futures_util::StreamExt;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
 
+use crate::llm::{self, ChatRequest, HttpError, LlmClient, StreamChunk, TokenUsage};
+use crate::tui::providers::{AuthStyle, Provider};
+use stream::{StreamingTagParser, TagEvent};
+use approval::{Approval, ApprovalDecision, ApprovalPolicy, ApprovalRequest, ApprovalSender};
+use budget::Budget;
+pub use budget::{AgentLimits, RunOutcome, RunSummary, StopReason};
+use events::emit;
+pub use events::{render_progress, AgentEvent, EventSender, MarkdownRenderer};
+pub use checkpoint::{Checkpoint, Checkpoints};
+pub use event_log::EventLog;
+pub use mcp::McpServers;
+pub use registry::{Tool, ToolRegistry, ToolSchema};
+pub use workspace::Workspace;
 
 // ── ReAct step types ──────────────────────────────────────────────────────────
 
@@ -116,11 +140,12 @@ pub fn parse_step(text: &str) -> Option<StepKind> {
 ///   [TOOL_CALL]{"name": "name", "input": "..."}[/TOOL_CALL]
 ///   [TOOL_CALL]{"tool": "name", "parameters": {...}}[/TOOL_CALL]
 pub fn try_recover_plain_tool(text: &str) -> Option<StepKind> {
-    const KNOWN_TOOLS: &[&str] = &[
-        "read_file", "write_file", "edit_file", "shell", "grep_search", "web_search",
-        "git_status", "git_diff", "git_add", "git_commit", "git_log", "answer",
-    ];
+    try_recover_plain_tool_in(text, &tools::builtin_registry().names())
+}
 
+/// Like [`try_recover_plain_tool`], but recognises the given tool names
+/// (e.g. those of an agent's own registry) instead of the built-ins.
+pub fn try_recover_plain_tool_in(text: &str, known_tools: &[&str]) -> Option<StepKind> {
     let trimmed = text.trim();
 
     // Pattern 0: [TOOL_CALL]{...}[/TOOL_CALL] or [TOOL_CALL]{...} (unclosed)
@@ -134,7 +159,7 @@ pub fn try_recover_plain_tool(text: &str) -> Option<StepKind> {
     let first_line = lines.next()?.trim();
     // Strip markdown backticks if present
     let tool_name = first_line.trim_matches('`').trim();
-    if KNOWN_TOOLS.contains(&tool_name) {
+    if known_tools.contains(&tool_name) {
         let input = lines.collect::<Vec<_>>().join("\n").trim().to_string();
         return Some(StepKind::ToolCall {
             name: tool_name.to_string(),
@@ -145,7 +170,7 @@ pub fn try_recover_plain_tool(text: &str) -> Option<StepKind> {
     // Pattern 2: line contains "tool_name:" or "tool_name :" prefix
     for line in trimmed.lines() {
         let l = line.trim();
-        for &tool in KNOWN_TOOLS {
+        for &tool in known_tools {
             let prefix_colon = format!("{tool}:");
             let prefix_space = format!("{tool} :");
             if l.starts_with(&prefix_colon) || l.starts_with(&prefix_space) {
@@ -224,7 +249,7 @@ fn looks_like_tool_call_scaffold(text: &str) -> bool {
 /// Walk `text` starting at the first `{` and return the slice that forms a
 /// balanced JSON object (including the surrounding braces).  Returns `None`
 /// if the braces are never balanced.
-fn extract_balanced_braces(text: &str) -> Option<&str> {
+pub(crate) fn extract_balanced_braces(text: &str) -> Option<&str> {
     let mut depth = 0i32;
     let mut in_string = false;
     let mut escape_next = false;
@@ -298,12 +323,52 @@ fn extract_attr_in(text: &str, tag: &str, attr: &str) -> Option<String> {
 
 // ── ReActAgent ────────────────────────────────────────────────────────────────
 
+/// How the agent asks the model for tool calls.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToolMode {
+    /// `<think>` / `<tool>` / `<answer>` tags parsed from plain text. Works with any model.
+    #[default]
+    Xml,
+    /// The provider's native function-calling API. Falls back to `Xml` when the
+    /// provider rejects the tools payload on the first request.
+    Native,
+}
+
+/// Returned by the first native request when the provider/model does not accept tools.
+#[derive(Debug)]
+struct NativeToolsUnsupported(String);
+
+impl std::fmt::Display for NativeToolsUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native tool calling unsupported: {}", self.0)
+    }
+}
+
+impl std::error::Error for NativeToolsUnsupported {}
+
 pub struct ReActAgent {
     pub provider: Provider,
     pub api_token: String,
+    /// Base URL overriding the provider's endpoint; empty for the default.
     pub custom_url: String,
+    /// How the token is sent. Defaults to the provider's usual header.
+    pub auth: AuthStyle,
     pub model: String,
+    /// Reply length cap per request; `None` sends `llm::DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
     pub system_prompt: String,
+    pub tool_mode: ToolMode,
+    pub limits: AgentLimits,
+    /// Tools the agent may call. Defaults to the built-ins.
+    pub tools: Arc<ToolRegistry>,
+    /// Which tool calls need the user's approval. Defaults to allowing all.
+    pub approval: ApprovalPolicy,
+    /// Where "ask" requests are sent. Without one, "ask" calls are refused.
+    pub approver: Option<ApprovalSender>,
+    /// The checkpoints the tools record into; each edit is tagged with its step.
+    pub checkpoints: Option<Checkpoints>,
+    /// Where every run's events are also written as JSON lines.
+    pub event_log: Option<EventLog>,
 }
 
 impl ReActAgent {
@@ -318,22 +383,117 @@ impl ReActAgent {
             provider,
             api_token,
             custom_url,
+            auth: provider.auth_style(),
             model,
+            max_tokens: None,
             system_prompt,
+            tool_mode: ToolMode::Xml,
+            limits: AgentLimits::default(),
+            // A fresh registry so each agent gets its own terminal session
+            tools: Arc::new(ToolRegistry::builtin()),
+            approval: ApprovalPolicy::allow_all(),
+            approver: None,
+            checkpoints: None,
+            event_log: None,
         }
     }
 
+    /// Send the token with `auth` instead of the provider's usual header.
+    pub fn with_auth(mut self, auth: AuthStyle) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Cap each reply at `max_tokens` instead of the default.
+    pub fn with_max_tokens(mut self, max_tokens: Option<u32>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Override the step, format-retry and wall-clock limits for this run.
+    pub fn with_limits(mut self, limits: AgentLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Replace the tools available to the agent.
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = Arc::new(tools);
+        self
+    }
+
+    /// Require approval for tool calls according to `policy`, sending "ask"
+    /// requests to `approver`.
+    pub fn with_approval(mut self, policy: ApprovalPolicy, approver: Option<ApprovalSender>) -> Self {
+        self.approval = policy;
+        self.approver = approver;
+        self
+    }
+
+    /// Tag the edits recorded in `checkpoints` with the step that made them.
+    /// Pass the same `Checkpoints` to the tools (`ToolContext::with_checkpoints`).
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> Self {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Also write each run's events to `log`.
+    pub fn with_event_log(mut self, log: EventLog) -> Self {
+        self.event_log = Some(log);
+        self
+    }
+
+    /// Select XML-tag or native function-calling mode.
+    pub fn with_tool_mode(mut self, mode: ToolMode) -> Self {
+        self.tool_mode = mode;
+        self
+    }
+
     /// Run the ReAct loop for the given task.
     /// `prior_history` contains all previous conversation turns (role, content)
     /// so the agent has memory of the full session.
-    /// Each step (Thought / ToolCall / Observation / Answer) is sent as a
-    /// `Some(String)` token through `tx`; `None` signals completion.
+    /// Progress is reported as typed `AgentEvent`s through `events`; the last
+    /// event is always `AgentEvent::Finished`.
+    /// The run stops with a summary once any of `self.limits` is exceeded.
+    /// Cancelling `cancel` aborts the in-flight LLM request or tool call
+    /// (killing any running shell child) and ends the run at the current step.
     pub async fn run(
         &self,
         task: String,
         prior_history: Vec<(String, String)>,
-        tx: mpsc::UnboundedSender<Option<String>>,
-    ) -> Result<()> {
+        events: EventSender,
+        cancel: CancellationToken,
+    ) -> Result<RunOutcome> {
+        let events = match &self.event_log {
+            Some(log) => log.tee(events),
+            None => events,
+        };
+        let mut step_started = false;
+        if self.tool_mode == ToolMode::Native {
+            match self.run_native(&task, &prior_history, &events, &cancel).await {
+                Err(e) if e.is::<NativeToolsUnsupported>() => {
+                    emit(&events, AgentEvent::Notice {
+                        step: 1,
+                        message: format!("{e} — falling back to XML tool format."),
+                    });
+                    // Step 1 carries on in XML mode; it was already announced
+                    step_started = true;
+                }
+                other => return other,
+            }
+        }
+        self.run_xml(task, prior_history, &events, &cancel, step_started).await
+    }
+
+    /// The XML-tag ReAct loop.
+    async fn run_xml(
+        &self,
+        task: String,
+        prior_history: Vec<(String, String)>,
+        tx: &EventSender,
+        cancel: &CancellationToken,
+        first_step_started: bool,
+    ) -> Result<RunOutcome> {
         let react_system = format!(
             "{}\n\n\
             You are operating in ReAct mode. You MUST use XML tags for EVERY response. No plain text outside tags.\n\n\
@@ -342,17 +502,7 @@ impl ReActAgent {
               <tool name=\"TOOL_NAME\">tool input here</tool>\n\
               <answer>final answer to the user</answer>\n\n\
             Available tools:\n\
-              read_file    — read a file. Input: file path\n\
-              write_file   — create/overwrite a file. Input: path on first line, then full content\n\
-              edit_file    — search-and-replace in a file. Input: path\\n<<<\\nsearch\\n===\\nreplacement\\n>>>\n\
-              shell        — run a shell command. Input: the command string\n\
-              grep_search  — search file contents by regex. Input: pattern on first line, path on second (optional, default .), file glob on third (optional, e.g. *.rs)\n\
-              web_search   — search the web. Input: search query\n\
-              git_status   — show git status. Input: (empty)\n\
-              git_diff     — show git diff. Input: (empty or path)\n\
-              git_add      — stage files. Input: path or .\n\
-              git_commit   — commit staged files. Input: commit message\n\
-              git_log      — show git log. Input: (empty)\n\n\
+            {}\n\
             CONCRETE EXAMPLES — copy this exact format:\n\n\
             Example 1 (read a file):\n\
             <think>I need to read README.md to understand its current content.</think>\n\
@@ -372,7 +522,8 @@ impl ReActAgent {
               3. NEVER write tool names as plain text (e.g. do NOT write `read_file` or `shell` outside a <tool> tag).\n\
               4. After receiving an <observation>, continue with <think> then <tool> or <answer>.\n\
               5. Only output <answer> when the task is fully complete.",
-            self.system_prompt
+            self.system_prompt,
+            self.tools.prompt_section()
         );
 
         // Seed history with prior turns, deduplicating the current task if already present
@@ -382,15 +533,49 @@ impl ReActAgent {
             .collect();
         history.push(("user".to_string(), task.clone()));
 
-        let mut step = 0usize;
+        let mut budget = Budget::start(&self.limits);
         loop {
-            step += 1;
-            let _ = tx.send(Some(format!("\n---\n🔄 **Step {}**\n", step)));
+            if cancel.is_cancelled() {
+                return Ok(finish(tx, &budget, RunOutcome::Cancelled { step: budget.step }));
+            }
+            let step = match budget.next_step() {
+                Ok(step) => step,
+                Err(reason) => return Ok(finish(tx, &budget, RunOutcome::Stopped(reason))),
+            };
+            if !(first_step_started && step == 1) {
+                emit(tx, AgentEvent::StepStart { step });
+            }
 
-            let llm_response = self
-                .call_llm(&react_system, &history)
-                .await
-                .unwrap_or_else(|e| format!("<answer>[LLM error: {e}]</answer>"));
+            // Stream the step: thought/answer text is reported as it arrives and
+            // the stream is cut as soon as a `</tool>` closes.
+            let mut parser = StreamingTagParser::new();
+            let mut relay = StreamRelay { tx, step, text: String::new() };
+            let call = budget.timed(self.call_llm_stream(&react_system, &history, |delta| {
+                for event in parser.push(delta) {
+                    relay.relay(event);
+                }
+                !parser.tool_closed()
+            }));
+            let llm_response = match unless_cancelled(cancel, call).await {
+                None => return Ok(finish(tx, &budget, RunOutcome::Cancelled { step })),
+                Some(Ok(Ok(usage))) => {
+                    for event in parser.finish() {
+                        relay.relay(event);
+                    }
+                    if let Some(usage) = usage {
+                        emit(tx, AgentEvent::Usage { step, usage });
+                    }
+                    parser.text().to_string()
+                }
+                Some(Ok(Err(e))) => {
+                    let message = format!("LLM error: {e}");
+                    emit(tx, AgentEvent::Error { step, message: message.clone() });
+                    return Ok(finish(tx, &budget, RunOutcome::Failed(message)));
+                }
+                Some(Err(reason)) => return Ok(finish(tx, &budget, RunOutcome::Stopped(reason))),
+            };
+            let streamed_thought = parser.streamed("think");
+            let streamed_answer = parser.streamed("answer");
 
             // Try XML tag parsing first; fall back to plain-text tool detection
             let mut steps = parse_steps(&llm_response);
@@ -398,15 +583,22 @@ impl ReActAgent {
             if steps.is_empty() {
                 // Attempt to recover a plain-text tool call emitted by the LLM
                 // e.g. the model writes:  read_file\nREADME.md  instead of <tool name="read_file">README.md</tool>
-                if let Some(recovered) = try_recover_plain_tool(&llm_response) {
-                    let _ = tx.send(Some("⚠️ *Model ignored XML format — auto-recovering tool call.*\n".to_string()));
+                if let Some(recovered) = try_recover_plain_tool_in(&llm_response, &self.tools.names()) {
+                    emit(tx, AgentEvent::Notice {
+                        step,
+                        message: "Model ignored XML format — auto-recovering tool call.".to_string(),
+                    });
                     steps.push(recovered);
                 } else if looks_like_tool_call_scaffold(&llm_response) {
+                    if let Err(reason) = budget.nudge() {
+                        return Ok(finish(tx, &budget, RunOutcome::Stopped(reason)));
+                    }
                     // Avoid leaking malformed tool-call scaffolding to the user.
-                    let _ = tx.send(Some(
-                        "⚠️ *Model emitted malformed tool-call syntax — retrying with strict XML format.*\n"
+                    emit(tx, AgentEvent::Notice {
+                        step,
+                        message: "Model emitted malformed tool-call syntax — retrying with strict XML format."
                             .to_string(),
-                    ));
+                    });
                     history.push((
                         "user".to_string(),
                         "Your last response used malformed tool-call scaffolding (e.g. [TOOL_CALL]). \
@@ -415,11 +607,10 @@ impl ReActAgent {
                     ));
                     continue;
                 } else {
-                    // No tags and no recoverable tool call — show response and finish
-                    let _ = tx.send(Some(llm_response.clone()));
-                    history.push(("assistant".to_string(), llm_response));
-                    let _ = tx.send(None);
-                    return Ok(());
+                    // No tags and no recoverable tool call — the reply is the answer
+                    let answer = llm_response.trim().to_string();
+                    emit(tx, AgentEvent::Answer { step, text: answer.clone() });
+                    return Ok(finish(tx, &budget, RunOutcome::Answered(answer)));
                 }
             }
 
@@ -430,11 +621,13 @@ impl ReActAgent {
                 matches!(s, StepKind::ToolCall { .. } | StepKind::Answer(_))
             });
             if !has_action {
-                // Display the thought so the user can see reasoning
-                if let Some(inner) = extract_tag(&llm_response, "think") {
-                    let _ = tx.send(Some(format!("💭 **Thought:** {}\n\n", inner.trim())));
-                } else {
-                    let _ = tx.send(Some(format!("💭 {}\n\n", llm_response.trim())));
+                // Report the thought so the user can see reasoning
+                if !streamed_thought {
+                    let text = extract_tag(&llm_response, "think").unwrap_or(llm_response.clone());
+                    emit(tx, AgentEvent::Thought { step, text: text.trim().to_string() });
+                }
+                if let Err(reason) = budget.nudge() {
+                    return Ok(finish(tx, &budget, RunOutcome::Stopped(reason)));
                 }
                 // Forceful nudge with a concrete example
                 history.push((
@@ -448,14 +641,17 @@ impl ReActAgent {
                 continue;
             }
 
-            let mut finished = false;
+            budget.reset_nudges();
+
             let mut remaining_resp = llm_response.as_str();
             for parsed_step in steps {
                 match parsed_step {
                     StepKind::Thought => {
                         // Extract the next think block from remaining text
                         if let Some(inner) = extract_tag(remaining_resp, "think") {
-                            let _ = tx.send(Some(format!("💭 **Thought:** {}\n\n", inner.trim())));
+                            if !streamed_thought {
+                                emit(tx, AgentEvent::Thought { step, text: inner.trim().to_string() });
+                            }
                             // Advance past this think block
                             if let Some(end) = remaining_resp.find("</think>") {
                                 remaining_resp = &remaining_resp[end + "</think>".len()..];
@@ -463,133 +659,366 @@ impl ReActAgent {
                         }
                     }
                     StepKind::ToolCall { name, input } => {
-                        // Don't display "answer" as a tool call — it's a final answer in disguise
-                        if name != "answer" {
-                            let _ = tx.send(Some(format!("🔧 **Tool `{name}`:** `{}`\n", truncate(&input, 120))));
-                        }
-                        let observation = tools::dispatch(&name, &input)
-                            .await
-                            .unwrap_or_else(|e| format!("[error: {e}]"));
-                        // Some models emit <tool name="answer"> instead of <answer> — treat as final answer
-                        if let Some(ans) = observation.strip_prefix("__AGENT_ANSWER__:") {
-                            let _ = tx.send(Some(format!("\n✅ **Answer:**\n{}\n", ans.trim())));
-                            let _ = tx.send(None);
-                            finished = true;
-                            break;
+                        match self.call_tool(tx, cancel, &mut budget, step, &name, &input).await {
+                            ToolResult::Cancelled => {
+                                return Ok(finish(tx, &budget, RunOutcome::Cancelled { step }));
+                            }
+                            ToolResult::Stopped(reason) => {
+                                return Ok(finish(tx, &budget, RunOutcome::Stopped(reason)));
+                            }
+                            // Some models emit <tool name="answer"> instead of <answer> — treat as final answer
+                            ToolResult::Answer(ans) => {
+                                return Ok(finish(tx, &budget, RunOutcome::Answered(ans)));
+                            }
+                            ToolResult::Observation(observation) => {
+                                history.push(("user".to_string(), format!("<observation>{observation}</observation>")));
+                            }
                         }
-                        let _ = tx.send(Some(format!("👁 **Observation:**\n```\n{}\n```\n\n", truncate(&observation, 800))));
-                        history.push(("user".to_string(), format!("<observation>{observation}</observation>")));
                         // Advance past this tool block
                         if let Some(end) = remaining_resp.find("</tool>") {
                             remaining_resp = &remaining_resp[end + "</tool>".len()..];
                         }
                     }
                     StepKind::Answer(ans) => {
-                        let _ = tx.send(Some(format!("\n✅ **Answer:**\n{ans}\n")));
-                        let _ = tx.send(None);
-                        finished = true;
-                        break;
+                        if !streamed_answer {
+                            emit(tx, AgentEvent::Answer { step, text: ans.clone() });
+                        }
+                        return Ok(finish(tx, &budget, RunOutcome::Answered(ans)));
                     }
                     StepKind::Observation(_) => {}
                 }
             }
+        }
+    }
 
-            if finished {
-                return Ok(());
+    /// The native function-calling loop. Tool calls come back as structured
+    /// data; a turn without tool calls (or an `answer` call) ends the run.
+    async fn run_native(
+        &self,
+        task: &str,
+        prior_history: &[(String, String)],
+        tx: &EventSender,
+        cancel: &CancellationToken,
+    ) -> Result<RunOutcome> {
+        let wire = native::Wire::for_provider(self.provider);
+        let schemas = self.tools.schemas();
+        let system = format!(
+            "{}\n\n\
+            You are operating in agent mode with native tool calling. Use the provided tools \
+            to inspect and change the workspace. Call one or more tools per turn; when the task \
+            is complete, reply with the final answer as plain text (or call the `answer` tool).",
+            self.system_prompt
+        );
+
+        let mut messages: Vec<serde_json::Value> = prior_history
+            .iter()
+            .filter(|(role, content)| !(role == "user" && content == task))
+            .map(|(role, content)| native::text_message(role, content))
+            .collect();
+        messages.push(native::text_message("user", task));
+
+        let mut budget = Budget::start(&self.limits);
+        loop {
+            if cancel.is_cancelled() {
+                return Ok(finish(tx, &budget, RunOutcome::Cancelled { step: budget.step }));
             }
+            let step = match budget.next_step() {
+                Ok(step) => step,
+                Err(reason) => return Ok(finish(tx, &budget, RunOutcome::Stopped(reason))),
+            };
+            emit(tx, AgentEvent::StepStart { step });
+
+            let call = budget.timed(self.call_llm_native(&system, &messages, &schemas));
+            let result = match unless_cancelled(cancel, call).await {
+                None => return Ok(finish(tx, &budget, RunOutcome::Cancelled { step })),
+                Some(Ok(result)) => result,
+                Some(Err(reason)) => return Ok(finish(tx, &budget, RunOutcome::Stopped(reason))),
+            };
+            let turn = match result {
+                Ok(turn) => turn,
+                // Only the very first request may trigger the XML fallback;
+                // later failures are reported like any other LLM error.
+                Err(e) if step == 1 && e.is::<NativeToolsUnsupported>() => return Err(e),
+                Err(e) => {
+                    let message = format!("LLM error: {e}");
+                    emit(tx, AgentEvent::Error { step, message: message.clone() });
+                    return Ok(finish(tx, &budget, RunOutcome::Failed(message)));
+                }
+            };
+            if let Some(usage) = turn.usage {
+                emit(tx, AgentEvent::Usage { step, usage });
+            }
+
+            if turn.calls.is_empty() {
+                let answer = turn.text.trim().to_string();
+                emit(tx, AgentEvent::Answer { step, text: answer.clone() });
+                return Ok(finish(tx, &budget, RunOutcome::Answered(answer)));
+            }
+
+            if !turn.text.trim().is_empty() {
+                emit(tx, AgentEvent::Thought { step, text: turn.text.trim().to_string() });
+            }
+            messages.push(native::assistant_message(wire, &turn));
+
+            let mut results = Vec::new();
+            for call in &turn.calls {
+                let input = self.tools.input_from_args(&call.name, &call.arguments);
+                match self.call_tool(tx, cancel, &mut budget, step, &call.name, &input).await {
+                    ToolResult::Cancelled => return Ok(finish(tx, &budget, RunOutcome::Cancelled { step })),
+                    ToolResult::Stopped(reason) => return Ok(finish(tx, &budget, RunOutcome::Stopped(reason))),
+                    ToolResult::Answer(ans) => return Ok(finish(tx, &budget, RunOutcome::Answered(ans))),
+                    ToolResult::Observation(observation) => results.push((call.clone(), observation)),
+                }
+            }
+            messages.extend(native::tool_result_messages(wire, &results));
         }
     }
 
-    async fn call_llm(&self, system: &str, history: &[(String, String)]) -> Result<String> {
-        let client = Client::builder()
-            .connect_timeout(std::time::Duration::from_secs(10))
-            .timeout(std::time::Duration::from_secs(1800))
-            .build()?;
-
-        let url = if !self.custom_url.is_empty() && self.provider == Provider::Ollama {
-            // For Ollama with a custom server, append the chat endpoint if not already present
-            let base = self.custom_url.trim_end_matches('/');
-            format!("{}/api/chat", base)
-        } else {
-            self.provider.api_url().to_string()
+    /// Dispatch one tool call, reporting the call and its observation.
+    async fn call_tool(
+        &self,
+        tx: &EventSender,
+        cancel: &CancellationToken,
+        budget: &mut Budget<'_>,
+        step: usize,
+        name: &str,
+        input: &str,
+    ) -> ToolResult {
+        // Don't report "answer"/"submit" as a tool call — it's a final answer in disguise
+        if name != "answer" && name != "submit" {
+            emit(tx, AgentEvent::ToolCall { step, name: name.to_string(), input: input.to_string() });
+            budget.record_tool(name, input);
+        }
+        // Waiting for the user counts against the time budget too
+        let input = match budget.timed(self.approve(tx, cancel, step, name, input)).await {
+            Err(reason) => return ToolResult::Stopped(reason),
+            Ok(None) => return ToolResult::Cancelled,
+            Ok(Some(Ok(input))) => input,
+            Ok(Some(Err(refusal))) => {
+                emit(tx, AgentEvent::Observation { step, name: name.to_string(), output: refusal.clone() });
+                return ToolResult::Observation(refusal);
+            }
+        };
+        if let Some(checkpoints) = &self.checkpoints {
+            checkpoints.set_step(step);
+        }
+        let observation = match budget.timed(unless_cancelled(cancel, self.tools.dispatch(name, &input))).await {
+            Err(reason) => return ToolResult::Stopped(reason),
+            Ok(None) => return ToolResult::Cancelled,
+            Ok(Some(out)) => out.unwrap_or_else(|e| format!("[error: {e}]")),
         };
+        if let Some(ans) = observation.strip_prefix("__AGENT_ANSWER__:") {
+            let text = ans.trim().to_string();
+            emit(tx, AgentEvent::Answer { step, text: text.clone() });
+            return ToolResult::Answer(text);
+        }
+        emit(tx, AgentEvent::Observation { step, name: name.to_string(), output: observation.clone() });
+        ToolResult::Observation(observation)
+    }
 
-        // Build messages array — for Anthropic, system goes top-level;
-        // for all others (OpenAI-compatible, Ollama) it goes as a system message.
-        let resp_text = match self.provider {
-            Provider::Anthropic => {
-                let msgs: Vec<serde_json::Value> = history
-                    .iter()
-                    .map(|(role, content)| json!({ "role": role, "content": content }))
-                    .collect();
-                let body = json!({
-                    "model": self.model,
-                    "system": system,
-                    "messages": msgs,
-                    "stream": false,
-                    "max_tokens": 4096,
-                });
-                client
-                    .post(&url)
-                    .header("x-api-key", &self.api_token)
-                    .header("anthropic-version", "2023-06-01")
-                    .json(&body)
-                    .send()
-                    .await?
-                    .text()
-                    .await?
-            }
-            _ => {
-                // OpenAI-compatible (Ollama, OpenAI, xAI, GitHubModels)
-                let mut msgs: Vec<serde_json::Value> =
-                    vec![json!({ "role": "system", "content": system })];
-                for (role, content) in history {
-                    msgs.push(json!({ "role": role, "content": content }));
+    /// Apply the approval policy to a tool call. Returns the input to run it
+    /// with, the observation to report instead, or `None` if the run was
+    /// cancelled while waiting for the user.
+    async fn approve(
+        &self,
+        tx: &EventSender,
+        cancel: &CancellationToken,
+        step: usize,
+        name: &str,
+        input: &str,
+    ) -> Option<Result<String, String>> {
+        let Some(tool) = self.tools.get(name) else {
+            return Some(Ok(input.to_string()));
+        };
+        match self.approval.for_tool(tool.as_ref()) {
+            Approval::Allow => Some(Ok(input.to_string())),
+            Approval::Deny => Some(Err(format!("[denied: the approval policy does not allow `{name}`]"))),
+            Approval::Ask => {
+                let unattended = format!("[denied: `{name}` needs the user's approval and no one is there to give it]");
+                let Some(approver) = &self.approver else {
+                    return Some(Err(unattended));
+                };
+                let (reply, decision) = oneshot::channel();
+                let request = ApprovalRequest {
+                    step,
+                    tool: name.to_string(),
+                    input: input.to_string(),
+                    preview: tool.preview(input),
+                    reply,
+                };
+                if approver.send(request).is_err() {
+                    return Some(Err(unattended));
                 }
-                let body = json!({
-                    "model": self.model,
-                    "messages": msgs,
-                    "stream": false,
-                    "max_tokens": 4096,
-                });
-                let mut req = client.post(&url).json(&body);
-                if self.provider != Provider::Ollama && !self.api_token.is_empty() {
-                    req = req.header("Authorization", format!("Bearer {}", self.api_token));
+                match unless_cancelled(cancel, decision).await? {
+                    Ok(ApprovalDecision::Approve) => Some(Ok(input.to_string())),
+                    Ok(ApprovalDecision::Edit(edited)) => {
+                        emit(tx, AgentEvent::Notice { step, message: format!("The user edited the `{name}` input before running it.") });
+                        Some(Ok(edited))
+                    }
+                    Ok(ApprovalDecision::Reject(reason)) => Some(Err(rejected(name, &reason))),
+                    // The request was dropped (the UI went away)
+                    Err(_) => Some(Err(rejected(name, ""))),
                 }
-                req.send().await?.text().await?
             }
+        }
+    }
+
+    /// This agent's model client: the provider's wire protocol at its endpoint
+    /// (or base URL override), with the token sent the configured way.
+    fn client(&self) -> Result<Box<dyn LlmClient>> {
+        llm::client(self.provider, &self.custom_url, &self.api_token, self.auth)
+    }
+
+    async fn call_llm_native(
+        &self,
+        system: &str,
+        messages: &[serde_json::Value],
+        schemas: &[ToolSchema],
+    ) -> Result<native::NativeTurn> {
+        let request = ChatRequest {
+            model: self.model.clone(),
+            system: system.to_string(),
+            messages: messages.to_vec(),
+            tools: schemas.to_vec(),
+            max_tokens: self.max_tokens,
         };
+        // Providers and models without tool support reject the payload with 400/422
+        // and say so; any other 400 (unknown model, bad request) is a real error.
+        self.client()?.complete(&request).await.map_err(|e| match e.downcast_ref::<HttpError>() {
+            Some(http) if matches!(http.status.as_u16(), 400 | 422) && rejects_tools(&http.body) => {
+                NativeToolsUnsupported(http.to_string()).into()
+            }
+            _ => e,
+        })
+    }
 
-        let v: serde_json::Value = serde_json::from_str(&resp_text)?;
+    pub(crate) async fn call_llm(&self, system: &str, history: &[(String, String)]) -> Result<String> {
+        let request = ChatRequest { max_tokens: self.max_tokens, ..ChatRequest::text(&self.model, system, history) };
+        Ok(self.client()?.complete(&request).await?.text)
+    }
 
-        // Anthropic format
-        if let Some(content) = v["content"][0]["text"].as_str() {
-            return Ok(content.to_string());
-        }
-        // OpenAI-compatible format
-        if let Some(content) = v["choices"][0]["message"]["content"].as_str() {
-            return Ok(content.to_string());
+    /// Streaming variant of `call_llm`. `on_delta` sees each text delta and
+    /// returns `false` to stop reading early. Returns the token usage, if the
+    /// server reported it.
+    pub(crate) async fn call_llm_stream(
+        &self,
+        system: &str,
+        history: &[(String, String)],
+        mut on_delta: impl FnMut(&str) -> bool,
+    ) -> Result<Option<TokenUsage>> {
+        let request = ChatRequest { max_tokens: self.max_tokens, ..ChatRequest::text(&self.model, system, history) };
+        let mut chunks = self.client()?.stream(&request).await?;
+        let mut usage: Option<TokenUsage> = None;
+        while let Some(chunk) = chunks.next().await {
+            match chunk? {
+                StreamChunk::Delta(delta) => {
+                    if !on_delta(&delta) {
+                        break;
+                    }
+                }
+                StreamChunk::Usage(u) => usage.get_or_insert_with(TokenUsage::default).merge(u),
+                StreamChunk::Done => break,
+            }
         }
-        // Ollama non-streaming format
-        if let Some(content) = v["message"]["content"].as_str() {
-            return Ok(content.to_string());
+        Ok(usage)
+    }
+}
+
+
+enum ToolResult {
+    Observation(String),
+    Answer(String),
+    Cancelled,
+    Stopped(StopReason),
+}
+
+/// Report the end of a run and return its outcome.
+fn finish(tx: &EventSender, budget: &Budget<'_>, outcome: RunOutcome) -> RunOutcome {
+    emit(tx, AgentEvent::Finished { outcome: outcome.clone(), summary: budget.summary() });
+    outcome
+}
+
+/// Turns streamed tag events into agent events, collecting each block's text
+/// so the complete `Thought` / `Answer` can follow its deltas.
+struct StreamRelay<'a> {
+    tx: &'a EventSender,
+    step: usize,
+    text: String,
+}
+
+impl StreamRelay<'_> {
+    fn relay(&mut self, event: TagEvent) {
+        let step = self.step;
+        match event {
+            TagEvent::Open(_) => self.text.clear(),
+            TagEvent::Text("think", text) => {
+                self.text.push_str(&text);
+                emit(self.tx, AgentEvent::ThoughtDelta { step, text });
+            }
+            TagEvent::Text(_, text) => {
+                self.text.push_str(&text);
+                emit(self.tx, AgentEvent::AnswerDelta { step, text });
+            }
+            TagEvent::Close("think") => {
+                let text = std::mem::take(&mut self.text).trim().to_string();
+                emit(self.tx, AgentEvent::Thought { step, text });
+            }
+            TagEvent::Close(_) => {
+                let text = std::mem::take(&mut self.text).trim().to_string();
+                emit(self.tx, AgentEvent::Answer { step, text });
+            }
+            TagEvent::ToolClosed => {}
         }
+    }
+}
 
-        Ok(format!("[unexpected response: {resp_text}]"))
+/// Whether an error body says the model or provider does not do tool calling,
+/// e.g. Ollama's "model does not support tools".
+fn rejects_tools(body: &str) -> bool {
+    let body = body.to_ascii_lowercase();
+    let about_tools = ["tool", "function"].iter().any(|w| body.contains(w));
+    let unsupported = ["not support", "unsupported", "not enabled", "not available"].iter().any(|w| body.contains(w));
+    about_tools && unsupported
+}
+
+/// Observation for a tool call the user rejected.
+fn rejected(name: &str, reason: &str) -> String {
+    match reason.trim() {
+        "" => format!("[rejected by user: `{name}` was not run]"),
+        reason => format!("[rejected by user: `{name}` was not run — {reason}]"),
+    }
+}
+
+/// Await `fut` unless `cancel` fires first; the future is dropped on cancel.
+async fn unless_cancelled<F: Future>(cancel: &CancellationToken, fut: F) -> Option<F::Output> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        out = fut => Some(out),
     }
 }
 
+/// Cut `s` to at most `max` bytes, backing off to a char boundary.
 fn truncate(s: &str, max: usize) -> String {
     if s.len() <= max {
         s.to_string()
     } else {
-        format!("{}…(truncated)", &s[..max])
+        format!("{}…(truncated)", &s[..s.floor_char_boundary(max)])
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::looks_like_tool_call_scaffold;
+    use super::{looks_like_tool_call_scaffold, truncate};
+
+    #[test]
+    fn truncate_cuts_on_a_char_boundary() {
+        assert_eq!(truncate("abcdef", 6), "abcdef");
+        assert_eq!(truncate("abcdef", 3), "abc…(truncated)");
+        // 'é' takes bytes 2..4, so a 3-byte limit falls inside it
+        assert_eq!(truncate("abé", 3), "ab…(truncated)");
+        assert_eq!(truncate("日本語", 4), "日…(truncated)");
+    }
 
     #[test]
     fn detects_tool_call_scaffold_markers() {
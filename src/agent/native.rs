@@ -0,0 +1,178 @@
+// Native function-calling support.
+// Translates between the agent's tool schemas / tool calls and each provider's
+// wire format: OpenAI `tools`/`tool_calls`, Anthropic `tool_use`/`tool_result`
+// content blocks, and Ollama `tools` on `/api/chat`. Request bodies and reply
+// parsing belong to the wire's `LlmClient` in `crate::llm`.
+
+use serde_json::{json, Value};
+
+use crate::agent::registry::ToolSchema;
+use crate::llm::TokenUsage;
+use crate::tui::providers::Provider;
+
+// ── Wire formats ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wire {
+    /// OpenAI-compatible chat completions (OpenAI, xAI, GitHub Models, Zen, Custom).
+    OpenAi,
+    /// Anthropic Messages API.
+    Anthropic,
+    /// Ollama `/api/chat`.
+    Ollama,
+}
+
+impl Wire {
+    pub fn for_provider(provider: Provider) -> Wire {
+        match provider {
+            Provider::Anthropic => Wire::Anthropic,
+            Provider::Ollama => Wire::Ollama,
+            _ => Wire::OpenAi,
+        }
+    }
+}
+
+// ── Parsed model turn ─────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeToolCall {
+    /// Provider-assigned call id (synthesised for Ollama, which has none).
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NativeTurn {
+    pub text: String,
+    pub calls: Vec<NativeToolCall>,
+    pub usage: Option<TokenUsage>,
+}
+
+// ── Requests ──────────────────────────────────────────────────────────────────
+
+/// Render tool schemas in the shape the wire format expects.
+pub fn tools_payload(wire: Wire, schemas: &[ToolSchema]) -> Value {
+    let tools: Vec<Value> = schemas
+        .iter()
+        .map(|s| match wire {
+            Wire::Anthropic => json!({
+                "name": s.name,
+                "description": s.description,
+                "input_schema": s.parameters,
+            }),
+            Wire::OpenAi | Wire::Ollama => json!({
+                "type": "function",
+                "function": {
+                    "name": s.name,
+                    "description": s.description,
+                    "parameters": s.parameters,
+                },
+            }),
+        })
+        .collect();
+    Value::Array(tools)
+}
+
+/// A plain-text user or assistant message.
+pub fn text_message(role: &str, content: &str) -> Value {
+    json!({ "role": role, "content": content })
+}
+
+// ── Responses ─────────────────────────────────────────────────────────────────
+
+/// Parse OpenAI / Ollama style `tool_calls`. OpenAI sends `arguments` as a JSON
+/// string, Ollama as an object; both are normalised to a `Value`.
+pub(crate) fn parse_function_calls(calls: &Value) -> Vec<NativeToolCall> {
+    let Some(calls) = calls.as_array() else { return Vec::new() };
+    calls
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            let name = c["function"]["name"].as_str()?.to_string();
+            let arguments = match &c["function"]["arguments"] {
+                Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| json!({ "input": s })),
+                Value::Null => json!({}),
+                other => other.clone(),
+            };
+            let id = c["id"]
+                .as_str()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| format!("call_{i}"));
+            Some(NativeToolCall { id, name, arguments })
+        })
+        .collect()
+}
+
+// ── History messages ──────────────────────────────────────────────────────────
+
+/// The assistant message that records `turn` (text plus tool calls) in history.
+pub fn assistant_message(wire: Wire, turn: &NativeTurn) -> Value {
+    match wire {
+        Wire::Anthropic => {
+            let mut blocks = Vec::new();
+            if !turn.text.is_empty() {
+                blocks.push(json!({ "type": "text", "text": turn.text }));
+            }
+            for c in &turn.calls {
+                blocks.push(json!({
+                    "type": "tool_use",
+                    "id": c.id,
+                    "name": c.name,
+                    "input": c.arguments,
+                }));
+            }
+            json!({ "role": "assistant", "content": blocks })
+        }
+        Wire::OpenAi => {
+            let calls: Vec<Value> = turn
+                .calls
+                .iter()
+                .map(|c| json!({
+                    "id": c.id,
+                    "type": "function",
+                    "function": { "name": c.name, "arguments": c.arguments.to_string() },
+                }))
+                .collect();
+            let mut msg = json!({ "role": "assistant", "content": turn.text });
+            if !calls.is_empty() {
+                msg["tool_calls"] = Value::Array(calls);
+            }
+            msg
+        }
+        Wire::Ollama => {
+            let calls: Vec<Value> = turn
+                .calls
+                .iter()
+                .map(|c| json!({ "function": { "name": c.name, "arguments": c.arguments } }))
+                .collect();
+            let mut msg = json!({ "role": "assistant", "content": turn.text });
+            if !calls.is_empty() {
+                msg["tool_calls"] = Value::Array(calls);
+            }
+            msg
+        }
+    }
+}
+
+/// Messages that feed tool outputs back to the model, one per call.
+/// Anthropic groups all results into a single user message.
+pub fn tool_result_messages(wire: Wire, results: &[(NativeToolCall, String)]) -> Vec<Value> {
+    match wire {
+        Wire::Anthropic => {
+            let blocks: Vec<Value> = results
+                .iter()
+                .map(|(c, out)| json!({ "type": "tool_result", "tool_use_id": c.id, "content": out }))
+                .collect();
+            vec![json!({ "role": "user", "content": blocks })]
+        }
+        Wire::OpenAi => results
+            .iter()
+            .map(|(c, out)| json!({ "role": "tool", "tool_call_id": c.id, "content": out }))
+            .collect(),
+        Wire::Ollama => results
+            .iter()
+            .map(|(c, out)| json!({ "role": "tool", "tool_name": c.name, "content": out }))
+            .collect(),
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::{bail, Result};
+use serde_json::json;
+
+use crate::agent::tools::git_output;
+use crate::agent::{extract_balanced_braces, extract_tag, ReActAgent};
+use crate::tui::providers::{AuthStyle, Provider};
+
+// ── Review prompt ─────────────────────────────────────────────────────────────
+
+const REVIEW_SYSTEM_PROMPT: &str = "You are QA-Bot acting as a senior QA automation reviewer. \
+You review one file of a git diff at a time, hunk by hunk, with a focus on test quality: \
+missing or weak assertions, flaky waits and hard-coded sleeps, brittle locators, shared state \
+between tests, missing edge cases, disabled or skipped tests, and test code that hides failures.\n\n\
+Each hunk is shown with new-file line numbers. Lines starting with '+' were added, '-' were removed.\n\n\
+Respond ONLY with a JSON array wrapped in <findings> tags, one object per comment:\n\
+<findings>[{\"line\": 42, \"severity\": \"warning\", \"message\": \"Assertion only checks visibility, not the text.\"}]</findings>\n\n\
+Rules:\n\
+  1. \"line\" is a new-file line number taken from the hunk, or null for a file-level comment.\n\
+  2. \"severity\" is one of: info, warning, error.\n\
+  3. Only comment on changed lines; keep each message to one or two sentences.\n\
+  4. If the file needs no comments, respond with <findings>[]</findings>.";
+
+// ── Findings ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Severity::Info    => "info",
+            Severity::Warning => "warning",
+            Severity::Error   => "error",
+        }
+    }
+
+    /// Parse a severity label, accepting the common synonyms models tend to use.
+    pub fn parse(label: &str) -> Option<Severity> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "info" | "note" | "nit" | "suggestion" | "minor" => Some(Severity::Info),
+            "warning" | "warn" | "medium" | "major" => Some(Severity::Warning),
+            "error" | "critical" | "blocker" | "high" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewFinding {
+    pub file: String,
+    /// New-file line number; `None` for file-level comments.
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ReviewFinding {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "file": self.file,
+            "line": self.line,
+            "severity": self.severity.as_str(),
+            "message": self.message,
+        })
+    }
+}
+
+/// Render findings as one `file:line: [severity] message` line each.
+pub fn format_findings(findings: &[ReviewFinding]) -> String {
+    if findings.is_empty() {
+        return "No review findings.".to_string();
+    }
+    findings
+        .iter()
+        .map(|f| match f.line {
+            Some(line) => format!("{}:{}: [{}] {}", f.file, line, f.severity.as_str(), f.message),
+            None => format!("{}: [{}] {}", f.file, f.severity.as_str(), f.message),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render findings as a pretty-printed JSON array.
+pub fn findings_to_json(findings: &[ReviewFinding]) -> String {
+    let arr: Vec<serde_json::Value> = findings.iter().map(|f| f.to_json()).collect();
+    serde_json::to_string_pretty(&arr).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Parse the model's findings for `file` from its response text.
+/// Accepts a `<findings>` wrapped JSON array, a bare JSON array, or loose JSON objects.
+/// Entries without a message are dropped; unknown severities default to `info`.
+pub fn parse_findings(file: &str, response: &str) -> Vec<ReviewFinding> {
+    let body = extract_tag(response, "findings").unwrap_or_else(|| response.to_string());
+
+    let items: Vec<serde_json::Value> = match parse_json_array(&body) {
+        Some(items) => items,
+        None => {
+            // Fall back to every balanced JSON object in the text.
+            let mut items = Vec::new();
+            let mut rest = body.as_str();
+            while let Some(start) = rest.find('{') {
+                match extract_balanced_braces(&rest[start..]) {
+                    Some(obj) => {
+                        if let Ok(v) = serde_json::from_str::<serde_json::Value>(obj) {
+                            items.push(v);
+                        }
+                        rest = &rest[start + obj.len()..];
+                    }
+                    None => break,
+                }
+            }
+            items
+        }
+    };
+
+    items
+        .iter()
+        .filter_map(|v| {
+            let message = v["message"].as_str()?.trim().to_string();
+            if message.is_empty() {
+                return None;
+            }
+            let line = v["line"]
+                .as_u64()
+                .or_else(|| v["line"].as_str().and_then(|s| s.trim().parse().ok()))
+                .map(|n| n as usize);
+            let severity = v["severity"].as_str().and_then(Severity::parse).unwrap_or(Severity::Info);
+            Some(ReviewFinding { file: file.to_string(), line, severity, message })
+        })
+        .collect()
+}
+
+fn parse_json_array(text: &str) -> Option<Vec<serde_json::Value>> {
+    let start = text.find('[')?;
+    let end = text.rfind(']')?;
+    if end < start {
+        return None;
+    }
+    serde_json::from_str::<serde_json::Value>(&text[start..=end])
+        .ok()?
+        .as_array()
+        .cloned()
+}
+
+// ── Diff parsing ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffHunk {
+    pub header: String,
+    /// First line number of the hunk in the new file.
+    pub new_start: usize,
+    /// Raw hunk body lines, each still prefixed with ' ', '+' or '-'.
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    pub path: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Split a unified `git diff` into per-file, per-hunk pieces.
+pub fn parse_diff(diff: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = Vec::new();
+
+    for line in diff.lines() {
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            // "a/<path> b/<path>" — prefer the b/ side; refined by the +++ line below.
+            let path = rest
+                .rsplit_once(" b/")
+                .map(|(_, b)| b.to_string())
+                .unwrap_or_else(|| rest.to_string());
+            files.push(FileDiff { path, hunks: Vec::new() });
+            continue;
+        }
+        let Some(file) = files.last_mut() else { continue };
+
+        if let Some(path) = line.strip_prefix("+++ ") {
+            if file.hunks.is_empty() {
+                if let Some(p) = path.strip_prefix("b/") {
+                    file.path = p.to_string();
+                }
+                continue;
+            }
+        }
+        if line.starts_with("--- ") && file.hunks.is_empty() {
+            continue;
+        }
+        if line.starts_with("@@") {
+            file.hunks.push(DiffHunk {
+                header: line.to_string(),
+                new_start: parse_new_start(line).unwrap_or(1),
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        if let Some(hunk) = file.hunks.last_mut() {
+            if line.starts_with(['+', '-', ' ']) || line.is_empty() {
+                hunk.lines.push(line.to_string());
+            }
+        }
+    }
+
+    files
+}
+
+/// Extract `c` from a hunk header of the form `@@ -a,b +c,d @@`.
+fn parse_new_start(header: &str) -> Option<usize> {
+    let plus = header.find('+')?;
+    let rest = &header[plus + 1..];
+    let end = rest.find([',', ' ']).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Render one file's hunks with new-file line numbers for the review prompt.
+pub fn render_file_for_review(file: &FileDiff) -> String {
+    let mut out = format!("File: {}\n", file.path);
+    for (i, hunk) in file.hunks.iter().enumerate() {
+        out.push_str(&format!("\nHunk {} {}\n", i + 1, hunk.header));
+        let mut new_line = hunk.new_start;
+        for line in &hunk.lines {
+            if line.starts_with('-') {
+                out.push_str(&format!("      | {line}\n"));
+            } else {
+                out.push_str(&format!("{new_line:>5} | {line}\n"));
+                new_line += 1;
+            }
+        }
+    }
+    out
+}
+
+// ── PRReviewAgent ─────────────────────────────────────────────────────────────
+
+/// What to diff: an explicit git range (`main..HEAD`, `HEAD~3`) or a base/head branch pair.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReviewTarget {
+    Range(String),
+    Branches { base: String, head: String },
+}
+
+impl ReviewTarget {
+    /// Arguments passed to `git diff` after the fixed flags.
+    fn diff_spec(&self) -> String {
+        match self {
+            ReviewTarget::Range(range) => range.trim().to_string(),
+            // Three dots: changes on `head` since it diverged from `base`, like a PR.
+            ReviewTarget::Branches { base, head } => format!("{}...{}", base.trim(), head.trim()),
+        }
+    }
+}
+
+/// Reviews a local git diff file by file and returns typed findings.
+/// Runs entirely against the local repository; only the configured LLM provider is contacted.
+pub struct PRReviewAgent {
+    agent: ReActAgent,
+    /// Repository to diff; empty means the current directory.
+    pub repo: String,
+    /// Optional pathspecs limiting the review, e.g. `tests/` or `*.spec.ts`.
+    pub paths: Vec<String>,
+}
+
+impl PRReviewAgent {
+    pub fn new(provider: Provider, api_token: String, custom_url: String, model: String) -> Self {
+        Self {
+            agent: ReActAgent::new(provider, api_token, custom_url, model, REVIEW_SYSTEM_PROMPT.to_string()),
+            repo: String::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Send the token with `auth` instead of the provider's usual header.
+    pub fn with_auth(mut self, auth: AuthStyle) -> Self {
+        self.agent.auth = auth;
+        self
+    }
+
+    /// Cap each reply at `max_tokens` instead of the default.
+    pub fn with_max_tokens(mut self, max_tokens: Option<u32>) -> Self {
+        self.agent.max_tokens = max_tokens;
+        self
+    }
+
+    /// Collect the unified diff for `target` with the local `git` binary.
+    pub fn collect_diff(&self, target: &ReviewTarget) -> Result<String> {
+        let spec = target.diff_spec();
+        let mut args = vec!["diff", "--no-color", "--no-ext-diff", "-U3", spec.as_str()];
+        if !self.paths.is_empty() {
+            args.push("--");
+            args.extend(self.paths.iter().map(|p| p.as_str()));
+        }
+        let out = git_output(&args, &self.repo)?;
+        if !out.status.success() {
+            bail!("git diff {spec} failed: {}", String::from_utf8_lossy(&out.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).to_string())
+    }
+
+    /// Review every changed file in `target` and return all findings,
+    /// ordered by file, then line.
+    pub async fn review(&self, target: &ReviewTarget) -> Result<Vec<ReviewFinding>> {
+        let diff = self.collect_diff(target)?;
+        let mut findings = Vec::new();
+        for file in parse_diff(&diff) {
+            if file.hunks.is_empty() {
+                continue; // binary files, pure renames, mode changes
+            }
+            findings.extend(self.review_file(&file).await?);
+        }
+        findings.sort_by(|a, b| a.file.cmp(&b.file).then(a.line.cmp(&b.line)));
+        Ok(findings)
+    }
+
+    async fn review_file(&self, file: &FileDiff) -> Result<Vec<ReviewFinding>> {
+        let history = vec![("user".to_string(), render_file_for_review(file))];
+        let response = self.agent.call_llm(&self.agent.system_prompt, &history).await?;
+        Ok(parse_findings(&file.path, &response))
+    }
+}
@@ -0,0 +1,171 @@
+// Tool trait and registry.
+// Every tool the agent can call is registered here once; the XML prompt's tool
+// list, the plain-text recovery heuristics and the native function-calling
+// schemas are all generated from the registry so they cannot drift apart.
+
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::tools::ToolContext;
+use super::workspace::Workspace;
+
+// ── Tool trait ────────────────────────────────────────────────────────────────
+
+/// A tool the agent can call. Tools take a single string input — the text
+/// between `<tool name="...">` and `</tool>` — and return the observation.
+/// Errors the model should see are returned as `Ok("[<name> error: ...]")`.
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// One-line description, e.g. "read a file".
+    fn description(&self) -> &str;
+
+    /// How the string input is laid out, shown in the XML prompt,
+    /// e.g. "path on first line, then full content".
+    fn input_format(&self) -> &str;
+
+    /// JSON schema of the structured arguments for native function calling.
+    fn input_schema(&self) -> Value;
+
+    /// Convert native function-call arguments into the string input.
+    /// The default passes a lone `input` field through, or the raw JSON.
+    fn input_from_args(&self, args: &Value) -> String {
+        match &args["input"] {
+            Value::String(s) => s.clone(),
+            _ => args.to_string(),
+        }
+    }
+
+    /// Whether the tool changes files, runs commands or otherwise has side
+    /// effects. Mutating tools need approval under the default policy.
+    fn mutating(&self) -> bool {
+        false
+    }
+
+    /// What a call with `input` will do, shown when asking for approval:
+    /// the command, or a diff for file edits. Defaults to the raw input.
+    fn preview(&self, input: &str) -> String {
+        input.to_string()
+    }
+
+    fn execute<'a>(&'a self, input: &'a str) -> BoxFuture<'a, Result<String>>;
+}
+
+/// JSON-schema description of a registered tool, used for native function calling.
+#[derive(Debug, Clone)]
+pub struct ToolSchema {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+// ── Registry ──────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding all built-in tools, confined to the launch directory.
+    pub fn builtin() -> Self {
+        Self::builtin_in(Workspace::launch_dir())
+    }
+
+    /// A registry holding all built-in tools, confined to `workspace`.
+    pub fn builtin_in(workspace: Workspace) -> Self {
+        Self::builtin_with(ToolContext::new(workspace))
+    }
+
+    /// A registry holding all built-in tools, sharing `ctx`.
+    pub fn builtin_with(ctx: ToolContext) -> Self {
+        let mut registry = Self::new();
+        for tool in super::tools::builtin_tools(ctx) {
+            registry.register_arc(tool);
+        }
+        registry
+    }
+
+    /// Add a tool, replacing any existing tool with the same name.
+    pub fn register(&mut self, tool: impl Tool + 'static) -> &mut Self {
+        self.register_arc(Arc::new(tool))
+    }
+
+    pub fn register_arc(&mut self, tool: Arc<dyn Tool>) -> &mut Self {
+        match self.tools.iter().position(|t| t.name() == tool.name()) {
+            Some(i) => self.tools[i] = tool,
+            None => self.tools.push(tool),
+        }
+        self
+    }
+
+    /// This registry plus `tools` (e.g. those of MCP servers).
+    pub fn with_extra_tools(mut self, tools: impl IntoIterator<Item = Arc<dyn Tool>>) -> Self {
+        for tool in tools {
+            self.register_arc(tool);
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.tools.iter().find(|t| t.name() == name)
+    }
+
+    /// Tool names, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.iter().map(|t| t.name()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Tool>> {
+        self.tools.iter()
+    }
+
+    /// Run the named tool. Unknown tools produce an observation, not an error.
+    pub async fn dispatch(&self, name: &str, input: &str) -> Result<String> {
+        match self.get(name) {
+            Some(tool) => tool.execute(input).await,
+            None => Ok(format!("[unknown tool: {name}]")),
+        }
+    }
+
+    /// Convert native arguments for `name` into its string input.
+    pub fn input_from_args(&self, name: &str, args: &Value) -> String {
+        match self.get(name) {
+            Some(tool) => tool.input_from_args(args),
+            None => match &args["input"] {
+                Value::String(s) => s.clone(),
+                _ => args.to_string(),
+            },
+        }
+    }
+
+    /// Schemas for native function calling.
+    pub fn schemas(&self) -> Vec<ToolSchema> {
+        self.tools
+            .iter()
+            .map(|t| ToolSchema {
+                name: t.name().to_string(),
+                description: t.description().to_string(),
+                parameters: t.input_schema(),
+            })
+            .collect()
+    }
+
+    /// The "Available tools" section of the XML-mode system prompt.
+    /// `answer` is left out: XML mode has a dedicated `<answer>` tag for it.
+    pub fn prompt_section(&self) -> String {
+        let width = self.tools.iter().map(|t| t.name().len()).max().unwrap_or(0).max(12);
+        self.tools
+            .iter()
+            .filter(|t| t.name() != "answer")
+            .map(|t| format!("  {:<width$} — {}. Input: {}\n", t.name(), t.description(), t.input_format()))
+            .collect()
+    }
+}
@@ -0,0 +1,155 @@
+// Async command execution for the `shell` tool.
+// Each command runs in its own process group with a timeout. When it finishes,
+// times out, or the run is cancelled and the future dropped, the whole group
+// is killed so pipelines and spawned children don't outlive the call. Output is capped
+// to the first and last part, and the exit code is always reported.
+
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// ── Options ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellOptions {
+    /// How long a single command may run before its process group is killed.
+    pub timeout: Duration,
+    /// Maximum bytes of output kept; beyond this only the head and tail are shown.
+    pub max_output: usize,
+}
+
+impl Default for ShellOptions {
+    fn default() -> Self {
+        Self { timeout: Duration::from_secs(120), max_output: 16 * 1024 }
+    }
+}
+
+// ── Execution ─────────────────────────────────────────────────────────────────
+
+/// How long to wait for the pipes to close after `sh` exits.
+const LEFTOVER_GRACE: Duration = Duration::from_millis(200);
+
+/// Run `cmd` with `sh -c` in `cwd` and return the observation: the (capped)
+/// combined stdout + stderr followed by the exit status.
+pub async fn run(cmd: &str, cwd: &Path, opts: &ShellOptions) -> String {
+    let mut command = tokio::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(cmd)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => return format!("[shell error: {e}]"),
+    };
+    // Kills the group when the call ends, times out, or this future is dropped (cancel)
+    let group = ProcessGroup(child.id());
+    let mut stdout_pipe = child.stdout.take();
+    let mut stderr_pipe = child.stderr.take();
+
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let finished = tokio::time::timeout(opts.timeout, async {
+        let mut drained = std::pin::pin!(async {
+            tokio::join!(drain(stdout_pipe.as_mut(), &mut stdout), drain(stderr_pipe.as_mut(), &mut stderr))
+        });
+        tokio::select! {
+            _ = &mut drained => (child.wait().await, false),
+            status = child.wait() => {
+                // `sh` has exited; anything still holding the pipes was left running in the background
+                let leftovers = tokio::time::timeout(LEFTOVER_GRACE, &mut drained).await.is_err();
+                (status, leftovers)
+            }
+        }
+    })
+    .await;
+
+    // Whatever is still in the group is killed, so no process outlives the call
+    drop(group);
+    let status = match finished {
+        Ok((Ok(status), false)) => describe(status),
+        Ok((Ok(status), true)) => format!(
+            "{}\n[killed processes the command left running; end a command with `&` to run it in the background]",
+            describe(status)
+        ),
+        Ok((Err(e), _)) => format!("[shell error: {e}]"),
+        Err(_) => format!("[timed out after {:?}; process group killed]", opts.timeout),
+    };
+
+    let combined = format!("{}{}", String::from_utf8_lossy(&stdout), String::from_utf8_lossy(&stderr));
+    let combined = combined.trim();
+    let output = if combined.is_empty() { "(no output)".to_string() } else { cap_output(combined, opts.max_output) };
+    format!("{output}\n{status}")
+}
+
+/// Read `reader` to the end into `buf`. Whatever was read is kept in `buf`
+/// even if this future is dropped part-way.
+async fn drain<R: AsyncRead + Unpin>(reader: Option<&mut R>, buf: &mut Vec<u8>) {
+    let Some(reader) = reader else { return };
+    let mut chunk = [0u8; 8192];
+    while let Ok(n) = reader.read(&mut chunk).await {
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn describe(status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("[exit code: {code}]");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("[killed by signal {signal}]");
+        }
+    }
+    "[exit code: unknown]".to_string()
+}
+
+/// Keep the first and last `max / 2` bytes of `text`, marking what was cut.
+pub fn cap_output(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let half = max / 2;
+    let mut head_end = half;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - half;
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    format!(
+        "{}\n[... {} bytes omitted ...]\n{}",
+        &text[..head_end],
+        tail_start - head_end,
+        &text[tail_start..]
+    )
+}
+
+// ── Process group ─────────────────────────────────────────────────────────────
+
+/// A process group, SIGKILLed on drop.
+pub(crate) struct ProcessGroup(pub(crate) Option<u32>);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0 {
+            // The child leads its own group (process_group(0)), so pgid == pid
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+// Incremental XML tag parser for streamed agent steps.
+// Fed token deltas as they arrive, it reports the text inside `<think>` and
+// `<answer>` while the model is still writing, and signals the moment a
+// `</tool>` closes so the tool can be dispatched without waiting for the rest.
+// A `<tool>` body is opaque: tags inside it (say, file content passed to
+// `write_file`) are never reported.
+
+/// Tags whose content is shown to the user while it streams.
+const STREAMED_TAGS: [&str; 2] = ["think", "answer"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagEvent {
+    /// A streamed tag (`think` / `answer`) opened.
+    Open(&'static str),
+    /// More text inside the currently open streamed tag.
+    Text(&'static str, String),
+    /// The streamed tag closed.
+    Close(&'static str),
+    /// A `</tool>` closed; the response up to here is complete.
+    ToolClosed,
+}
+
+#[derive(Debug, Default)]
+pub struct StreamingTagParser {
+    buf: String,
+    /// Byte offset up to which `buf` has been scanned.
+    pos: usize,
+    open: Option<&'static str>,
+    /// Inside a `<tool ...>` body, which only `</tool>` ends.
+    in_tool: bool,
+    /// No text has been emitted yet for the currently open tag.
+    fresh: bool,
+    tool_closed: bool,
+    /// Streamed tags that have opened so far.
+    seen: Vec<&'static str>,
+}
+
+impl StreamingTagParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The full response so far. Once a tool call closes, anything the model
+    /// wrote after `</tool>` is dropped.
+    pub fn text(&self) -> &str {
+        &self.buf
+    }
+
+    pub fn tool_closed(&self) -> bool {
+        self.tool_closed
+    }
+
+    /// Whether any `<tag>` content has already been shown while streaming.
+    pub fn streamed(&self, tag: &str) -> bool {
+        self.seen.contains(&tag)
+    }
+
+    /// Feed the next delta and return the events it completes.
+    pub fn push(&mut self, delta: &str) -> Vec<TagEvent> {
+        let mut events = Vec::new();
+        if self.tool_closed {
+            return events;
+        }
+        self.buf.push_str(delta);
+
+        loop {
+            if self.in_tool {
+                if let Some(j) = self.buf[self.pos..].find("</tool>") {
+                    self.close_tool(self.pos + j, &mut events);
+                    break;
+                }
+                // Only the tail can still hold the start of a split `</tool>`
+                let mut keep = self.buf.len().saturating_sub("</tool>".len() - 1).max(self.pos);
+                while !self.buf.is_char_boundary(keep) {
+                    keep -= 1;
+                }
+                self.pos = keep;
+                break;
+            }
+            match self.open {
+                None => {
+                    let Some(rel) = self.buf[self.pos..].find('<') else {
+                        self.pos = self.buf.len();
+                        break;
+                    };
+                    let i = self.pos + rel;
+                    let rest = &self.buf[i..];
+                    if let Some(&tag) = STREAMED_TAGS.iter().find(|t| rest.starts_with(&format!("<{t}>"))) {
+                        self.open = Some(tag);
+                        self.seen.push(tag);
+                        self.fresh = true;
+                        self.pos = i + tag.len() + 2;
+                        events.push(TagEvent::Open(tag));
+                        continue;
+                    }
+                    if rest.starts_with("<tool ") || rest.starts_with("<tool>") {
+                        self.in_tool = true;
+                        self.pos = i + "<tool".len();
+                        continue;
+                    }
+                    if rest.starts_with("</tool>") {
+                        self.close_tool(i, &mut events);
+                        break;
+                    }
+                    let could_complete = STREAMED_TAGS
+                        .iter()
+                        .map(|t| format!("<{t}>"))
+                        .chain(["<tool ".to_string(), "<tool>".to_string(), "</tool>".to_string()])
+                        .any(|cand| cand.starts_with(rest));
+                    if could_complete {
+                        // Wait for more input before deciding what this `<` starts
+                        self.pos = i;
+                        break;
+                    }
+                    self.pos = i + 1;
+                }
+                Some(tag) => {
+                    let close = format!("</{tag}>");
+                    let rest = &self.buf[self.pos..];
+                    if let Some(j) = rest.find(&close) {
+                        self.emit_text(tag, self.pos, self.pos + j, &mut events);
+                        self.pos += j + close.len();
+                        self.open = None;
+                        events.push(TagEvent::Close(tag));
+                        continue;
+                    }
+                    // Hold back a trailing partial `</tag` so it is never shown
+                    let safe = match rest.rfind('<') {
+                        Some(k) if close.starts_with(&rest[k..]) => self.pos + k,
+                        _ => self.buf.len(),
+                    };
+                    self.emit_text(tag, self.pos, safe, &mut events);
+                    self.pos = safe;
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    /// Flush text still pending in an unclosed tag once the stream ends,
+    /// and treat the end of the stream as closing it.
+    pub fn finish(&mut self) -> Vec<TagEvent> {
+        let mut events = Vec::new();
+        if let Some(tag) = self.open.take() {
+            let end = self.buf.len();
+            self.emit_text(tag, self.pos, end, &mut events);
+            self.pos = end;
+            events.push(TagEvent::Close(tag));
+        }
+        events
+    }
+
+    /// The `</tool>` at byte `i` ends the response; drop anything after it.
+    fn close_tool(&mut self, i: usize, events: &mut Vec<TagEvent>) {
+        self.buf.truncate(i + "</tool>".len());
+        self.pos = self.buf.len();
+        self.in_tool = false;
+        self.tool_closed = true;
+        events.push(TagEvent::ToolClosed);
+    }
+
+    fn emit_text(&mut self, tag: &'static str, start: usize, end: usize, events: &mut Vec<TagEvent>) {
+        let mut text = &self.buf[start..end];
+        if self.fresh {
+            text = text.trim_start();
+        }
+        if !text.is_empty() {
+            self.fresh = false;
+            events.push(TagEvent::Text(tag, text.to_string()));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Persistent terminal session for the `shell` tool.
+// Every command still runs as its own `sh -c` (so timeouts and process-group
+// kills keep working), but the working directory and exported environment
+// are saved after each command and restored before the next. A command ending
+// in `&` becomes a tracked background job with its output in a log file.
+// Each result ends with a `<terminal_status>` block: the cwd and every live
+// process the agent started.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Instant;
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+
+use super::shell::{self, ProcessGroup, ShellOptions};
+
+pub struct TerminalSession {
+    state: Mutex<State>,
+    /// Where cwd, env and job logs are kept: a fresh directory only this user
+    /// can enter, since the saved env is sourced. Removed on drop.
+    state_dir: std::io::Result<TempDir>,
+    root: PathBuf,
+    opts: ShellOptions,
+}
+
+struct State {
+    cwd: PathBuf,
+    jobs: Vec<Job>,
+    next_job: usize,
+}
+
+struct Job {
+    id: usize,
+    /// Also the job's process group id.
+    pid: Option<u32>,
+    command: String,
+    log: PathBuf,
+    started: Instant,
+    child: tokio::process::Child,
+}
+
+impl TerminalSession {
+    /// A session starting in `root`.
+    pub fn new(root: &Path, opts: ShellOptions) -> Self {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("qai-terminal-");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o700));
+        }
+        let state_dir = builder.tempdir();
+        Self {
+            state: Mutex::new(State { cwd: root.to_path_buf(), jobs: Vec::new(), next_job: 1 }),
+            state_dir,
+            root: root.to_path_buf(),
+            opts,
+        }
+    }
+
+    /// Run `cmd` in the session and return its output followed by the
+    /// `<terminal_status>` block. A trailing `&` starts a background job.
+    pub async fn run(&self, cmd: &str) -> String {
+        let dir = match &self.state_dir {
+            Ok(dir) => dir.path(),
+            Err(e) => return format!("[shell error: cannot create the session directory: {e}]"),
+        };
+        let mut state = self.state.lock().await;
+        let mut notes = String::new();
+        if !state.cwd.is_dir() {
+            notes.push_str(&format!("[{} no longer exists; back in {}]\n", state.cwd.display(), self.root.display()));
+            state.cwd = self.root.clone();
+        }
+
+        let output = match background_command(cmd) {
+            Some(command) => self.start_job(dir, &mut state, command),
+            None => {
+                let out = shell::run(&self.script(dir, cmd), &state.cwd, &self.opts).await;
+                if let Ok(cwd) = std::fs::read_to_string(dir.join("cwd")) {
+                    let cwd = PathBuf::from(cwd.trim_end_matches('\n'));
+                    if cwd.is_dir() {
+                        state.cwd = cwd;
+                    }
+                }
+                out
+            }
+        };
+        format!("{notes}{output}\n{}", status_block(&mut state))
+    }
+
+    /// The current `<terminal_status>` block.
+    pub async fn status(&self) -> String {
+        status_block(&mut *self.state.lock().await)
+    }
+
+    pub async fn cwd(&self) -> PathBuf {
+        self.state.lock().await.cwd.clone()
+    }
+
+    /// Wrap `cmd` so the exported environment is restored before it runs and
+    /// the cwd and environment are saved when it exits (even via `exit`).
+    fn script(&self, state_dir: &Path, cmd: &str) -> String {
+        let dir = sh_quote(&state_dir.display().to_string());
+        format!(
+            "trap '__qai_status=$?; pwd > {dir}/cwd; export -p > {dir}/env; exit $__qai_status' EXIT\n\
+             if [ -f {dir}/env ]; then . {dir}/env; fi\n\
+             {cmd}\n"
+        )
+    }
+
+    fn start_job(&self, state_dir: &Path, state: &mut State, command: &str) -> String {
+        let id = state.next_job;
+        let log = state_dir.join(format!("job-{id}.log"));
+        let dir = sh_quote(&state_dir.display().to_string());
+        let script = format!("if [ -f {dir}/env ]; then . {dir}/env; fi\n{command}\n");
+
+        let log_file = match std::fs::File::create(&log) {
+            Ok(f) => f,
+            Err(e) => return format!("[shell error: cannot create log {}: {e}]", log.display()),
+        };
+        let stderr = match log_file.try_clone() {
+            Ok(f) => f,
+            Err(e) => return format!("[shell error: {e}]"),
+        };
+        let mut spawn = tokio::process::Command::new("sh");
+        spawn
+            .arg("-c")
+            .arg(script)
+            .current_dir(&state.cwd)
+            .stdin(Stdio::null())
+            .stdout(log_file)
+            .stderr(stderr)
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        spawn.process_group(0);
+
+        match spawn.spawn() {
+            Ok(child) => {
+                let pid = child.id();
+                state.next_job += 1;
+                state.jobs.push(Job {
+                    id,
+                    pid,
+                    command: command.to_string(),
+                    log: log.clone(),
+                    started: Instant::now(),
+                    child,
+                });
+                format!(
+                    "[started background job [{id}] pid {}; output goes to {}]",
+                    pid.unwrap_or_default(),
+                    log.display()
+                )
+            }
+            Err(e) => format!("[shell error: {e}]"),
+        }
+    }
+}
+
+impl Drop for TerminalSession {
+    /// Background jobs end with the session.
+    fn drop(&mut self) {
+        for job in self.state.get_mut().jobs.drain(..) {
+            drop(ProcessGroup(job.pid));
+        }
+    }
+}
+
+/// The command without its trailing `&`, if it should run in the background.
+fn background_command(cmd: &str) -> Option<&str> {
+    let cmd = cmd.trim();
+    let rest = cmd.strip_suffix('&')?;
+    if rest.ends_with('&') {
+        return None; // `&&` continuation, not a background job
+    }
+    let rest = rest.trim_end();
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// Render the status block, dropping jobs that have exited (they are
+/// reported once, here).
+fn status_block(state: &mut State) -> String {
+    let mut lines = Vec::new();
+    state.jobs.retain_mut(|job| {
+        match job.child.try_wait() {
+            Ok(None) => {
+                lines.push(format!(
+                    "  [{}] pid {} running {}s: {} (log: {})",
+                    job.id,
+                    job.pid.unwrap_or_default(),
+                    job.started.elapsed().as_secs(),
+                    job.command,
+                    job.log.display()
+                ));
+                true
+            }
+            Ok(Some(status)) => {
+                let how = status.code().map(|c| format!("exited with code {c}")).unwrap_or_else(|| "was killed".to_string());
+                lines.push(format!("  [{}] {how}: {} (log: {})", job.id, job.command, job.log.display()));
+                // Take down anything the job left behind in its group
+                drop(ProcessGroup(job.pid));
+                false
+            }
+            Err(_) => false,
+        }
+    });
+    let processes = if lines.is_empty() { "  (none)".to_string() } else { lines.join("\n") };
+    format!("<terminal_status>\ncwd: {}\nprocesses:\n{processes}\n</terminal_status>", state.cwd.display())
+}
+
+/// Single-quote `s` for `sh`.
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
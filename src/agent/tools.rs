@@ -1,68 +1,441 @@
 use anyhow::Result;
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use std::path::Path;
 use std::process::Command;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use super::approval::line_diff;
+use super::checkpoint::Checkpoints;
+use super::registry::{Tool, ToolRegistry};
+use super::shell::ShellOptions;
+use super::terminal::TerminalSession;
+use super::viewer::FileViewer;
+use super::workspace::Workspace;
+
+// ── Built-in tools ────────────────────────────────────────────────────────────
+
+/// A built-in tool's static metadata plus the function that runs it.
+struct Spec {
+    name: &'static str,
+    description: &'static str,
+    input_format: &'static str,
+    /// Native-call parameters as (name, description); all are strings.
+    params: &'static [(&'static str, &'static str)],
+    required: &'static [&'static str],
+    /// Native arguments → string input.
+    to_input: fn(&Value) -> String,
+    mutating: bool,
+    /// Approval preview; `None` shows the raw input.
+    preview: Option<fn(&Shared, &str) -> String>,
+    run: fn(Arc<Shared>, String) -> BoxFuture<'static, Result<String>>,
+}
+
+/// Settings shared by the built-in tools of one registry.
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    /// Where file tools may read and write; also where the terminal session starts.
+    pub workspace: Workspace,
+    pub shell: ShellOptions,
+    /// Where file edits are recorded so they can be undone.
+    pub checkpoints: Checkpoints,
+}
+
+impl ToolContext {
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace, shell: ShellOptions::default(), checkpoints: Checkpoints::new() }
+    }
+
+    /// Record edits into `checkpoints` (shared with whoever wants to roll them back).
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+}
+
+/// What the built-ins of one registry share.
+struct Shared {
+    workspace: Workspace,
+    /// One terminal session, so cwd, env and background jobs persist between calls.
+    terminal: TerminalSession,
+    /// The file `open` and `scroll_*` look at.
+    viewer: Mutex<FileViewer>,
+    checkpoints: Checkpoints,
+}
+
+impl Shared {
+    /// Write `content` to `target`, checkpointing what was there before.
+    /// A file that exists but cannot be read is left alone, since it could not be restored.
+    fn save(&self, target: &Path, display: &str, content: &str) -> std::io::Result<()> {
+        let before = match std::fs::read(target) {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(target, content)?;
+        self.checkpoints.record(target.to_path_buf(), display, before, content);
+        Ok(())
+    }
+}
+
+/// A built-in tool bound to its registry's shared state.
+struct Builtin {
+    spec: Spec,
+    ctx: Arc<Shared>,
+}
+
+impl Tool for Builtin {
+    fn name(&self) -> &str {
+        self.spec.name
+    }
+
+    fn description(&self) -> &str {
+        self.spec.description
+    }
+
+    fn input_format(&self) -> &str {
+        self.spec.input_format
+    }
+
+    fn input_schema(&self) -> Value {
+        let mut properties = serde_json::Map::new();
+        for (name, desc) in self.spec.params {
+            properties.insert(name.to_string(), json!({ "type": "string", "description": desc }));
+        }
+        json!({ "type": "object", "properties": properties, "required": self.spec.required })
+    }
+
+    fn input_from_args(&self, args: &Value) -> String {
+        (self.spec.to_input)(args)
+    }
+
+    fn mutating(&self) -> bool {
+        self.spec.mutating
+    }
+
+    fn preview(&self, input: &str) -> String {
+        match self.spec.preview {
+            Some(preview) => preview(&self.ctx, input),
+            None => input.to_string(),
+        }
+    }
+
+    fn execute<'a>(&'a self, input: &'a str) -> BoxFuture<'a, Result<String>> {
+        (self.spec.run)(self.ctx.clone(), input.to_string())
+    }
+}
+
+/// String value of a native argument; numbers and other JSON are stringified.
+fn arg(args: &Value, key: &str) -> String {
+    match &args[key] {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// All built-in tools, in prompt order, sharing `ctx`.
+pub(crate) fn builtin_tools(ctx: ToolContext) -> Vec<Arc<dyn Tool>> {
+    let terminal = TerminalSession::new(ctx.workspace.root(), ctx.shell);
+    let ctx = Arc::new(Shared {
+        workspace: ctx.workspace,
+        terminal,
+        viewer: Mutex::default(),
+        checkpoints: ctx.checkpoints,
+    });
+    let specs = vec![
+        Spec {
+            name: "read_file",
+            description: "read a file",
+            input_format: "file path",
+            params: &[("path", "File path to read")],
+            required: &["path"],
+            to_input: |a| arg(a, "path"),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { read_file(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "open",
+            description: "open a file in the viewer, showing 100 numbered lines around a line",
+            input_format: "file path, optionally a line number on the second line",
+            params: &[("path", "File path to open"), ("line", "Optional line number to center the window on")],
+            required: &["path"],
+            to_input: |a| format!("{}\n{}", arg(a, "path"), arg(a, "line")),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { open(&ctx, &input) }),
+        },
+        Spec {
+            name: "open_entire_file",
+            description: "open a file in the viewer, showing every line (use sparingly for large files)",
+            input_format: "file path",
+            params: &[("path", "File path to open")],
+            required: &["path"],
+            to_input: |a| arg(a, "path"),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { open_entire_file(&ctx, &input) }),
+        },
+        Spec {
+            name: "scroll_down",
+            description: "move the viewer down one window in the open file",
+            input_format: "(empty)",
+            params: &[],
+            required: &[],
+            to_input: |_| String::new(),
+            mutating: false,
+            preview: None,
+            run: |ctx, _| Box::pin(async move { scroll(&ctx, "scroll_down", 1) }),
+        },
+        Spec {
+            name: "scroll_up",
+            description: "move the viewer up one window in the open file",
+            input_format: "(empty)",
+            params: &[],
+            required: &[],
+            to_input: |_| String::new(),
+            mutating: false,
+            preview: None,
+            run: |ctx, _| Box::pin(async move { scroll(&ctx, "scroll_up", -1) }),
+        },
+        Spec {
+            name: "write_file",
+            description: "create/overwrite a file",
+            input_format: "path on first line, then full content",
+            params: &[("path", "File path to write"), ("content", "Full file content")],
+            required: &["path", "content"],
+            to_input: |a| format!("{}\n{}", arg(a, "path"), arg(a, "content")),
+            mutating: true,
+            preview: Some(|ctx, input| preview_write(&ctx.workspace, input)),
+            run: |ctx, input| Box::pin(async move { write_file(&ctx, &input) }),
+        },
+        Spec {
+            name: "create",
+            description: "create a new file (fails if it already exists)",
+            input_format: "path on first line, then full content",
+            params: &[("path", "Path of the new file"), ("content", "File content")],
+            required: &["path", "content"],
+            to_input: |a| format!("{}\n{}", arg(a, "path"), arg(a, "content")),
+            mutating: true,
+            preview: Some(|ctx, input| preview_write(&ctx.workspace, input)),
+            run: |ctx, input| Box::pin(async move { create(&ctx, &input) }),
+        },
+        Spec {
+            name: "edit_file",
+            description: "search-and-replace in a file",
+            input_format: "path\\n<<<\\nsearch\\n===\\nreplacement\\n>>>",
+            params: &[
+                ("path", "File path to edit"),
+                ("search", "Exact text to find"),
+                ("replace", "Replacement text"),
+            ],
+            required: &["path", "search", "replace"],
+            to_input: |a| format!("{}\n<<<\n{}\n===\n{}\n>>>", arg(a, "path"), arg(a, "search"), arg(a, "replace")),
+            mutating: true,
+            preview: Some(|ctx, input| preview_edit(&ctx.workspace, input)),
+            run: |ctx, input| Box::pin(async move { edit_file(&ctx, &input) }),
+        },
+        Spec {
+            name: "multi_edit",
+            description: "apply several search-and-replace edits to one file atomically (all or none)",
+            input_format: "path, then one or more <<<\\nsearch\\n===\\nreplacement\\n>>> blocks",
+            params: &[
+                ("path", "File path to edit"),
+                ("edits", "JSON array of {\"search\": ..., \"replace\": ...} objects, applied in order"),
+            ],
+            required: &["path", "edits"],
+            to_input: multi_edit_input,
+            mutating: true,
+            preview: Some(|ctx, input| preview_multi_edit(&ctx.workspace, input)),
+            run: |ctx, input| Box::pin(async move { multi_edit(&ctx, &input) }),
+        },
+        Spec {
+            name: "undo_edit",
+            description: "revert the last file edit made by write_file, create, edit_file or multi_edit",
+            input_format: "(empty)",
+            params: &[],
+            required: &[],
+            to_input: |_| String::new(),
+            mutating: true,
+            preview: Some(|ctx, _| preview_undo(ctx)),
+            run: |ctx, _| Box::pin(async move { undo_edit(&ctx) }),
+        },
+        Spec {
+            name: "shell",
+            description: "run a shell command in a persistent session (cd and exported variables carry over; end with `&` to run it in the background)",
+            input_format: "the command string",
+            params: &[("command", "The command string")],
+            required: &["command"],
+            to_input: |a| arg(a, "command"),
+            mutating: true,
+            preview: Some(|_, cmd| format!("$ {}", cmd.trim())),
+            run: |ctx, input| Box::pin(async move { Ok(ctx.terminal.run(&input).await) }),
+        },
+        Spec {
+            name: "grep_search",
+            description: "search file contents by regex",
+            input_format: "pattern on first line, path on second (optional, default .), file glob on third (optional, e.g. *.rs)",
+            params: &[
+                ("pattern", "Regex pattern"),
+                ("path", "Directory or file to search (default .)"),
+                ("glob", "Optional file glob filter, e.g. *.rs"),
+            ],
+            required: &["pattern"],
+            to_input: |a| format!("{}\n{}\n{}", arg(a, "pattern"), arg(a, "path"), arg(a, "glob")),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { grep_search(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "search_paths_by_glob",
+            description: "find files and folders whose path matches a glob",
+            input_format: "glob on first line (e.g. **/*.rs; a pattern without / matches at any depth), directory on second (optional, default .)",
+            params: &[("pattern", "Glob pattern, e.g. src/**/*.rs"), ("path", "Directory to search (default .)")],
+            required: &["pattern"],
+            to_input: |a| format!("{}\n{}", arg(a, "pattern"), arg(a, "path")),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { search_paths_by_glob(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "web_search",
+            description: "search the web",
+            input_format: "search query",
+            params: &[("query", "Search query")],
+            required: &["query"],
+            to_input: |a| arg(a, "query"),
+            mutating: false,
+            preview: None,
+            run: |_, input| Box::pin(async move { web_search(&input).await }),
+        },
+        Spec {
+            name: "git_status",
+            description: "show git status",
+            input_format: "(empty or paths)",
+            params: &[("paths", "Optional space-separated paths to limit the status to")],
+            required: &[],
+            to_input: |a| arg(a, "paths"),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { git_status(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "git_diff",
+            description: "show git diff",
+            input_format: "(empty or path)",
+            params: &[("target", "Optional path or ref")],
+            required: &[],
+            to_input: |a| arg(a, "target"),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { git_diff(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "git_add",
+            description: "stage files",
+            input_format: "path or .",
+            params: &[("paths", "Path(s) to stage, or .")],
+            required: &["paths"],
+            to_input: |a| arg(a, "paths"),
+            mutating: true,
+            preview: Some(|_, paths| format!("$ git add {}", paths.trim())),
+            run: |ctx, input| Box::pin(async move { git_add(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "git_commit",
+            description: "commit staged files",
+            input_format: "commit message",
+            params: &[("message", "Commit message")],
+            required: &["message"],
+            to_input: |a| arg(a, "message"),
+            mutating: true,
+            preview: Some(|_, msg| format!("$ git commit -m {:?}", msg.trim())),
+            run: |ctx, input| Box::pin(async move { git_commit(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "git_log",
+            description: "show git log",
+            input_format: "(empty)",
+            params: &[("count", "Number of entries (default 10)")],
+            required: &[],
+            to_input: |a| arg(a, "count"),
+            mutating: false,
+            preview: None,
+            run: |ctx, input| Box::pin(async move { git_log(&ctx.workspace, &input) }),
+        },
+        Spec {
+            name: "answer",
+            description: "give the final answer to the user and finish the task",
+            input_format: "the final answer",
+            params: &[("answer", "The final answer")],
+            required: &["answer"],
+            to_input: |a| arg(a, "answer"),
+            mutating: false,
+            preview: None,
+            // Some models wrap their final answer in <tool name="answer"> instead of <answer>
+            run: |_, input| Box::pin(async move { Ok(format!("__AGENT_ANSWER__:{input}")) }),
+        },
+        Spec {
+            name: "submit",
+            description: "submit a summary of your solution and finish the task",
+            input_format: "the solution summary",
+            params: &[("summary", "Summary of what was done")],
+            required: &["summary"],
+            to_input: |a| arg(a, "summary"),
+            mutating: false,
+            preview: None,
+            // Ends the run like `answer`; the summary is the final answer
+            run: |_, input| Box::pin(async move { Ok(format!("__AGENT_ANSWER__:{input}")) }),
+        },
+    ];
+    specs
+        .into_iter()
+        .map(|spec| Arc::new(Builtin { spec, ctx: ctx.clone() }) as Arc<dyn Tool>)
+        .collect()
+}
+
+static BUILTIN: LazyLock<ToolRegistry> = LazyLock::new(ToolRegistry::builtin);
+
+/// The shared registry of built-in tools.
+pub fn builtin_registry() -> &'static ToolRegistry {
+    &BUILTIN
+}
 
 // ── Built-in tool dispatcher ──────────────────────────────────────────────────
 
-/// Dispatch a tool call by name with the given input string.
+/// Dispatch a built-in tool call by name with the given input string.
 /// Returns the tool output as a string.
 pub async fn dispatch(tool: &str, input: &str) -> Result<String> {
-    match tool {
-        "read_file"   => read_file(input),
-        "write_file"  => write_file(input),
-        "edit_file"   => edit_file(input),
-        "git_status"  => git_status(input),
-        "git_diff"    => git_diff(input),
-        "git_add"     => git_add(input),
-        "git_commit"  => git_commit(input),
-        "git_log"     => git_log(input),
-        "shell"       => shell(input),
-        "grep_search" => grep_search(input),
-        "web_search"  => web_search(input).await,
-        // Some models wrap their final answer in <tool name="answer"> instead of <answer>
-        "answer" => Ok(format!("__AGENT_ANSWER__:{input}")),
-        _ => Ok(format!("[unknown tool: {tool}]")),
-    }
-}
-
-/// Read a file from the local filesystem.
-fn read_file(path: &str) -> Result<String> {
-    let path = path.trim();
+    BUILTIN.dispatch(tool, input).await
+}
+
+/// Read a file inside the workspace (or a read-only root).
+fn read_file(ws: &Workspace, path: &str) -> Result<String> {
+    let path = match ws.resolve_read(path) {
+        Ok(p) => p,
+        Err(e) => return Ok(format!("[read_file error: {e}]")),
+    };
     match std::fs::read_to_string(path) {
         Ok(content) => Ok(content),
         Err(e) => Ok(format!("[read_file error: {e}]")),
     }
 }
 
-/// Run a shell command and return its stdout + stderr.
-fn shell(cmd: &str) -> Result<String> {
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .output();
-    match output {
-        Ok(out) => {
-            let stdout = String::from_utf8_lossy(&out.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&out.stderr).to_string();
-            let combined = format!("{stdout}{stderr}").trim().to_string();
-            Ok(if combined.is_empty() { "(no output)".to_string() } else { combined })
-        }
-        Err(e) => Ok(format!("[shell error: {e}]")),
-    }
-}
-
 /// Write content to a file, creating it (and parent dirs) if needed.
 /// Input format: `<path>\n<content>`
-fn write_file(input: &str) -> Result<String> {
+fn write_file(ctx: &Shared, input: &str) -> Result<String> {
     let input = input.trim_start();
     if let Some((path, content)) = input.split_once('\n') {
         let path = path.trim();
-        if let Some(parent) = std::path::Path::new(path).parent() {
-            if !parent.as_os_str().is_empty() {
-                std::fs::create_dir_all(parent)
-                    .map_err(|e| anyhow::anyhow!("[write_file error creating dirs: {e}]"))?;
-            }
-        }
-        match std::fs::write(path, content) {
+        let target = match ctx.workspace.resolve_write(path) {
+            Ok(p) => p,
+            Err(e) => return Ok(format!("[write_file error: {e}]")),
+        };
+        match ctx.save(&target, path, content) {
             Ok(_) => Ok(format!("[write_file: wrote {} bytes to {path}]", content.len())),
             Err(e) => Ok(format!("[write_file error: {e}]")),
         }
@@ -71,16 +444,36 @@ fn write_file(input: &str) -> Result<String> {
     }
 }
 
-/// Edit a file by replacing the first occurrence of a search string with a replacement.
+/// Create a new file; refuses to overwrite an existing one.
+/// Input format: `<path>\n<content>`
+fn create(ctx: &Shared, input: &str) -> Result<String> {
+    let input = input.trim_start();
+    let (path, content) = input.split_once('\n').unwrap_or((input, ""));
+    let path = path.trim();
+    let target = match ctx.workspace.resolve_write(path) {
+        Ok(p) => p,
+        Err(e) => return Ok(format!("[create error: {e}]")),
+    };
+    if target.symlink_metadata().is_ok() {
+        return Ok(format!("[create error: {path} already exists; use edit_file or write_file to change it]"));
+    }
+    match ctx.save(&target, path, content) {
+        Ok(_) => Ok(format!("[create: created {path} ({} bytes)]", content.len())),
+        Err(e) => Ok(format!("[create error: {e}]")),
+    }
+}
+
+/// Split edit_file input into (path, search, replacement).
 /// Input format: `<path>\n<<<\n<search>\n===\n<replacement>\n>>>`
-fn edit_file(input: &str) -> Result<String> {
+/// The error is the observation to return.
+fn parse_edit(input: &str) -> Result<(&str, &str, &str), String> {
     let input = input.trim_start();
     // Expect: first line = path, then <<<\n<search>\n===\n<replacement>\n>>>
     let mut lines = input.splitn(2, '\n');
     let path = lines.next().unwrap_or("").trim();
     let rest = lines.next().unwrap_or("");
 
-    let (search_part, replace_part) = if let Some(after_open) = rest.strip_prefix("<<<\n") {
+    if let Some(after_open) = rest.strip_prefix("<<<\n") {
         if let Some(mid) = after_open.find("\n===\n") {
             let search = &after_open[..mid];
             let after_eq = &after_open[mid + 5..]; // skip "\n===\n"
@@ -88,22 +481,35 @@ fn edit_file(input: &str) -> Result<String> {
                 .strip_suffix("\n>>>")
                 .or_else(|| after_eq.strip_suffix(">>>"))
                 .unwrap_or(after_eq);
-            (search, replacement)
+            Ok((path, search, replacement))
         } else {
-            return Ok("[edit_file error: missing '===' separator]".to_string());
+            Err("[edit_file error: missing '===' separator]".to_string())
         }
     } else {
-        return Ok("[edit_file error: input must start with path then '<<<']".to_string());
+        Err("[edit_file error: input must start with path then '<<<']".to_string())
+    }
+}
+
+/// Edit a file by replacing the first occurrence of a search string with a replacement.
+/// Input format: `<path>\n<<<\n<search>\n===\n<replacement>\n>>>`
+fn edit_file(ctx: &Shared, input: &str) -> Result<String> {
+    let (path, search_part, replace_part) = match parse_edit(input) {
+        Ok(parts) => parts,
+        Err(msg) => return Ok(msg),
+    };
+    let target = match ctx.workspace.resolve_write(path) {
+        Ok(p) => p,
+        Err(e) => return Ok(format!("[edit_file error: {e}]")),
     };
 
-    match std::fs::read_to_string(path) {
+    match std::fs::read_to_string(&target) {
         Err(e) => Ok(format!("[edit_file error reading {path}: {e}]")),
         Ok(original) => {
             if !original.contains(search_part) {
                 return Ok(format!("[edit_file error: search string not found in {path}]"));
             }
             let updated = original.replacen(search_part, replace_part, 1);
-            match std::fs::write(path, &updated) {
+            match ctx.save(&target, path, &updated) {
                 Ok(_) => Ok(format!("[edit_file: applied edit to {path}]")),
                 Err(e) => Ok(format!("[edit_file error writing {path}: {e}]")),
             }
@@ -111,55 +517,341 @@ fn edit_file(input: &str) -> Result<String> {
     }
 }
 
-/// Run `git status` in the given directory (or cwd if input is empty).
-fn git_status(input: &str) -> Result<String> {
-    git_run(&["status", "--short"], input)
+/// Approval preview for write_file: a diff against the current file.
+fn preview_write(ws: &Workspace, input: &str) -> String {
+    let Some((path, content)) = input.trim_start().split_once('\n') else {
+        return input.to_string();
+    };
+    let path = path.trim();
+    let target = match ws.resolve_write(path) {
+        Ok(p) => p,
+        Err(e) => return format!("[write_file error: {e}]"),
+    };
+    match std::fs::read_to_string(target) {
+        Ok(old) => format!("--- {path}\n{}", line_diff(&old, content)),
+        Err(_) => format!("--- {path} (new file)\n{}", line_diff("", content)),
+    }
+}
+
+/// Approval preview for edit_file: a diff of the file with the edit applied.
+fn preview_edit(ws: &Workspace, input: &str) -> String {
+    let (path, search, replacement) = match parse_edit(input) {
+        Ok(parts) => parts,
+        Err(msg) => return msg,
+    };
+    let target = match ws.resolve_write(path) {
+        Ok(p) => p,
+        Err(e) => return format!("[edit_file error: {e}]"),
+    };
+    match std::fs::read_to_string(target) {
+        Ok(old) if old.contains(search) => {
+            format!("--- {path}\n{}", line_diff(&old, &old.replacen(search, replacement, 1)))
+        }
+        _ => format!("--- {path} (search text not found)\n{}", line_diff(search, replacement)),
+    }
+}
+
+/// One (search, replacement) pair of a multi_edit.
+type Hunk<'a> = (&'a str, &'a str);
+
+/// Split multi_edit input into the path and its hunks.
+/// Input format: `<path>` followed by one or more `<<<\n<search>\n===\n<replacement>\n>>>` blocks.
+fn parse_multi_edit(input: &str) -> Result<(&str, Vec<Hunk<'_>>), String> {
+    let input = input.trim_start();
+    let (path, mut rest) = input.split_once('\n').unwrap_or((input, ""));
+    let mut hunks = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(['\n', '\r']);
+        if rest.is_empty() {
+            break;
+        }
+        let Some(body) = rest.strip_prefix("<<<\n") else {
+            return Err(format!("[multi_edit error: edit {} must start with '<<<']", hunks.len() + 1));
+        };
+        let Some(mid) = body.find("\n===\n") else {
+            return Err(format!("[multi_edit error: edit {} is missing the '===' separator]", hunks.len() + 1));
+        };
+        let after = &body[mid + 5..];
+        let (replacement, next) = if let Some(stripped) = after.strip_prefix(">>>") {
+            ("", stripped)
+        } else if let Some(end) = after.find("\n>>>") {
+            (&after[..end], &after[end + 4..])
+        } else {
+            return Err(format!("[multi_edit error: edit {} is missing the closing '>>>']", hunks.len() + 1));
+        };
+        hunks.push((&body[..mid], replacement));
+        rest = next;
+    }
+    if hunks.is_empty() {
+        return Err("[multi_edit error: no edits given]".to_string());
+    }
+    Ok((path.trim(), hunks))
+}
+
+/// Native `multi_edit` arguments → string input. `edits` may be a JSON array
+/// or a string holding one.
+fn multi_edit_input(args: &Value) -> String {
+    let edits = match &args["edits"] {
+        Value::String(s) => serde_json::from_str(s).unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    let mut input = arg(args, "path");
+    for edit in edits.as_array().into_iter().flatten() {
+        input.push_str(&format!("\n<<<\n{}\n===\n{}\n>>>", arg(edit, "search"), arg(edit, "replace")));
+    }
+    input
+}
+
+/// Apply every hunk in order to `original`; the error names the first hunk
+/// whose search text is missing.
+fn apply_hunks(original: &str, hunks: &[Hunk]) -> Result<String, usize> {
+    let mut text = original.to_string();
+    for (i, (search, replacement)) in hunks.iter().enumerate() {
+        if search.is_empty() || !text.contains(search) {
+            return Err(i + 1);
+        }
+        text = text.replacen(search, replacement, 1);
+    }
+    Ok(text)
+}
+
+/// Apply several search-and-replace edits to one file. Nothing is written
+/// unless every edit applies.
+fn multi_edit(ctx: &Shared, input: &str) -> Result<String> {
+    let (path, hunks) = match parse_multi_edit(input) {
+        Ok(parsed) => parsed,
+        Err(msg) => return Ok(msg),
+    };
+    let target = match ctx.workspace.resolve_write(path) {
+        Ok(p) => p,
+        Err(e) => return Ok(format!("[multi_edit error: {e}]")),
+    };
+    let original = match std::fs::read_to_string(&target) {
+        Ok(text) => text,
+        Err(e) => return Ok(format!("[multi_edit error reading {path}: {e}]")),
+    };
+    let updated = match apply_hunks(&original, &hunks) {
+        Ok(text) => text,
+        Err(n) => {
+            return Ok(format!("[multi_edit error: search string of edit {n} not found in {path}; no edits applied]"))
+        }
+    };
+    match ctx.save(&target, path, &updated) {
+        Ok(_) => Ok(format!("[multi_edit: applied {} edits to {path}]", hunks.len())),
+        Err(e) => Ok(format!("[multi_edit error writing {path}: {e}]")),
+    }
+}
+
+/// Approval preview for multi_edit: a diff of the file with every edit applied.
+fn preview_multi_edit(ws: &Workspace, input: &str) -> String {
+    let (path, hunks) = match parse_multi_edit(input) {
+        Ok(parsed) => parsed,
+        Err(msg) => return msg,
+    };
+    let old = match ws.resolve_write(path).map(std::fs::read_to_string) {
+        Ok(Ok(text)) => text,
+        Ok(Err(e)) => return format!("[multi_edit error reading {path}: {e}]"),
+        Err(e) => return format!("[multi_edit error: {e}]"),
+    };
+    match apply_hunks(&old, &hunks) {
+        Ok(new) => format!("--- {path}\n{}", line_diff(&old, &new)),
+        Err(n) => format!("--- {path} (search text of edit {n} not found; nothing will change)"),
+    }
+}
+
+/// Restore the file changed by the most recent edit.
+fn undo_edit(ctx: &Shared) -> Result<String> {
+    Ok(match ctx.checkpoints.undo_last() {
+        Some(report) => format!("[undo_edit: {report}]"),
+        None => "[undo_edit: no edits to undo]".to_string(),
+    })
+}
+
+/// Approval preview for undo_edit: the diff back to the pre-edit content.
+fn preview_undo(ctx: &Shared) -> String {
+    let Some(last) = ctx.checkpoints.last() else {
+        return "(no edits to undo)".to_string();
+    };
+    let current = std::fs::read_to_string(&last.path).unwrap_or_default();
+    match &last.before {
+        Some(before) => format!("--- {}\n{}", last.display, line_diff(&current, &String::from_utf8_lossy(before))),
+        None => format!("--- {} (delete)\n{}", last.display, line_diff(&current, "")),
+    }
 }
 
-/// Run `git diff` — optionally pass a file path or ref as input.
-fn git_diff(input: &str) -> Result<String> {
+// ── Viewer and path search ───────────────────────────────────────────────────
+
+/// Open a file in the viewer. Input: `<path>` and an optional `\n<line>`.
+fn open(ctx: &Shared, input: &str) -> Result<String> {
+    let input = input.trim();
+    let (path, line) = input.split_once('\n').unwrap_or((input, ""));
+    let line = match line.trim() {
+        "" => None,
+        n => match n.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => return Ok(format!("[open error: '{n}' is not a line number]")),
+        },
+    };
+    let mut viewer = ctx.viewer.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(viewer.open(&ctx.workspace, path, line).unwrap_or_else(|e| format!("[open error: {e}]")))
+}
+
+fn open_entire_file(ctx: &Shared, input: &str) -> Result<String> {
+    let mut viewer = ctx.viewer.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(viewer.open_entire(&ctx.workspace, input).unwrap_or_else(|e| format!("[open_entire_file error: {e}]")))
+}
+
+fn scroll(ctx: &Shared, tool: &str, windows: isize) -> Result<String> {
+    let mut viewer = ctx.viewer.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(viewer.scroll(windows).unwrap_or_else(|e| format!("[{tool}: {e}]")))
+}
+
+/// Paths listed by search_paths_by_glob at most.
+const MAX_GLOB_RESULTS: usize = 200;
+
+/// Find paths matching a glob under a directory in the workspace.
+/// Input format: `<pattern>\n<dir>` (dir optional, default `.`)
+fn search_paths_by_glob(ws: &Workspace, input: &str) -> Result<String> {
+    let input = input.trim_start();
+    let (pattern, dir) = input.split_once('\n').unwrap_or((input, ""));
+    let pattern = pattern.trim();
+    let dir = if dir.trim().is_empty() { "." } else { dir.trim() };
+    if pattern.is_empty() {
+        return Ok("[search_paths_by_glob error: pattern must not be empty]".to_string());
+    }
+    if pattern.starts_with('/') || pattern.split('/').any(|part| part == "..") {
+        return Ok("[search_paths_by_glob error: the pattern must be relative and stay inside the directory]".to_string());
+    }
+    let base = match ws.resolve_read(dir) {
+        Ok(p) => p,
+        Err(e) => return Ok(format!("[search_paths_by_glob error: {e}]")),
+    };
+    // A bare `*.rs` means "at any depth", like most file finders
+    let pattern = if pattern.contains('/') { pattern.to_string() } else { format!("**/{pattern}") };
+    let full = format!("{}/{pattern}", glob::Pattern::escape(&base.display().to_string()));
+    let paths = match glob::glob(&full) {
+        Ok(paths) => paths,
+        Err(e) => return Ok(format!("[search_paths_by_glob error: {e}]")),
+    };
+
+    let mut found = Vec::new();
+    let mut more = 0;
+    for path in paths.flatten() {
+        // Skip VCS internals and anything a symlink leads out of the workspace
+        if path.components().any(|c| c.as_os_str() == ".git") {
+            continue;
+        }
+        let Ok(real) = path.canonicalize() else { continue };
+        if ws.resolve_read(&real.display().to_string()).is_err() {
+            continue;
+        }
+        if found.len() == MAX_GLOB_RESULTS {
+            more += 1;
+            continue;
+        }
+        let suffix = if path.is_dir() { "/" } else { "" };
+        found.push(format!("{}{suffix}", ws.display(&path).display()));
+    }
+    if found.is_empty() {
+        return Ok("[search_paths_by_glob: no matches found]".to_string());
+    }
+    let mut out = found.join("\n");
+    if more > 0 {
+        out.push_str(&format!("\n[... {more} more paths not shown]"));
+    }
+    Ok(out)
+}
+
+/// Reject git arguments that would be read as options (`--output=<file>` and
+/// friends), and paths outside the workspace.
+fn git_paths(ws: &Workspace, tool: &str, input: &str) -> Result<Vec<String>, String> {
+    input
+        .split_whitespace()
+        .map(|arg| {
+            if arg.starts_with('-') {
+                return Err(format!("[{tool} error: options are not allowed, got '{arg}']"));
+            }
+            ws.resolve_write(arg).map_err(|e| format!("[{tool} error: {e}]"))?;
+            Ok(arg.to_string())
+        })
+        .collect()
+}
+
+/// Run `git status` in the workspace root, optionally limited to paths.
+fn git_status(ws: &Workspace, input: &str) -> Result<String> {
+    let paths = match git_paths(ws, "git_status", input) {
+        Ok(paths) => paths,
+        Err(e) => return Ok(e),
+    };
+    let mut args = vec!["status", "--short", "--"];
+    args.extend(paths.iter().map(String::as_str));
+    git_run(&args, ws.root())
+}
+
+/// Run `git diff` in the workspace root — optionally pass a file path or ref as input.
+fn git_diff(ws: &Workspace, input: &str) -> Result<String> {
     let arg = input.trim();
     if arg.is_empty() {
-        git_run(&["diff"], "")
+        return git_run(&["diff", "--no-ext-diff"], ws.root());
+    }
+    if arg.starts_with('-') {
+        return Ok(format!("[git_diff error: options are not allowed, got '{arg}']"));
+    }
+    // An existing path in the workspace is a pathspec; anything else is a ref
+    if ws.resolve_write(arg).is_ok_and(|p| p.exists()) {
+        git_run(&["diff", "--no-ext-diff", "--", arg], ws.root())
     } else {
-        git_run(&["diff", arg], "")
+        git_run(&["diff", "--no-ext-diff", arg, "--"], ws.root())
     }
 }
 
 /// Stage files with `git add`. Input: space-separated paths (or `.` for all).
-fn git_add(input: &str) -> Result<String> {
-    let arg = input.trim();
-    if arg.is_empty() {
+fn git_add(ws: &Workspace, input: &str) -> Result<String> {
+    let paths = match git_paths(ws, "git_add", input) {
+        Ok(paths) => paths,
+        Err(e) => return Ok(e),
+    };
+    if paths.is_empty() {
         return Ok("[git_add error: provide path(s) to stage, e.g. '.' or 'src/main.rs']".to_string());
     }
-    git_run(&["add", arg], "")
+    let mut args = vec!["add", "--"];
+    args.extend(paths.iter().map(String::as_str));
+    git_run(&args, ws.root())
 }
 
 /// Commit staged changes. Input: commit message.
-fn git_commit(input: &str) -> Result<String> {
+fn git_commit(ws: &Workspace, input: &str) -> Result<String> {
     let msg = input.trim();
     if msg.is_empty() {
         return Ok("[git_commit error: commit message must not be empty]".to_string());
     }
-    git_run(&["commit", "-m", msg], "")
+    git_run(&["commit", "-m", msg], ws.root())
 }
 
 /// Show recent git log. Input: optional number of entries (default 10).
-fn git_log(input: &str) -> Result<String> {
+fn git_log(ws: &Workspace, input: &str) -> Result<String> {
     let n = input.trim().parse::<usize>().unwrap_or(10);
     let n_str = format!("-{n}");
-    git_run(&["log", "--oneline", &n_str], "")
+    git_run(&["log", "--oneline", &n_str], ws.root())
 }
 
-/// Helper: run a git sub-command, optionally in a specific working directory.
-fn git_run(args: &[&str], workdir: &str) -> Result<String> {
+/// Helper: run a git sub-command, optionally in a specific working directory,
+/// and return the raw process output (stdout and stderr kept separate).
+pub(crate) fn git_output(args: &[&str], workdir: &str) -> std::io::Result<std::process::Output> {
     let mut cmd = Command::new("git");
     cmd.args(args);
     let wd = workdir.trim();
     if !wd.is_empty() {
         cmd.current_dir(wd);
     }
-    match cmd.output() {
+    cmd.output()
+}
+
+/// Helper: run a git sub-command in `workdir`. Paths are literal, so pathspec
+/// magic such as `:/` or `:(top)` cannot reach past what `git_paths` checked.
+fn git_run(args: &[&str], workdir: &Path) -> Result<String> {
+    let args: Vec<&str> = std::iter::once("--literal-pathspecs").chain(args.iter().copied()).collect();
+    match git_output(&args, &workdir.display().to_string()) {
         Ok(out) => {
             let stdout = String::from_utf8_lossy(&out.stdout).to_string();
             let stderr = String::from_utf8_lossy(&out.stderr).to_string();
@@ -173,7 +865,7 @@ fn git_run(args: &[&str], workdir: &str) -> Result<String> {
 /// Search file contents using a regex pattern.
 /// Input format: `<pattern>\n<path>` (path is optional, defaults to `.`)
 /// Optionally add a third line with a file glob filter, e.g. `*.rs`
-fn grep_search(input: &str) -> Result<String> {
+fn grep_search(ws: &Workspace, input: &str) -> Result<String> {
     let input = input.trim_start();
     let mut lines = input.splitn(3, '\n');
     let pattern = lines.next().unwrap_or("").trim();
@@ -185,11 +877,20 @@ fn grep_search(input: &str) -> Result<String> {
         return Ok("[grep_search error: pattern must not be empty]".to_string());
     }
 
+    let target = match ws.resolve_read(path) {
+        Ok(p) => ws.display(&p),
+        Err(e) => return Ok(format!("[grep_search error: {e}]")),
+    };
+
+    // -r (not -R) so symlinks met while recursing are not followed out of the root
     let mut cmd = Command::new("grep");
-    cmd.args(["-rn", "--color=never", pattern, path]);
+    cmd.current_dir(ws.root());
+    cmd.arg("-rn").arg("--color=never");
     if !glob.is_empty() {
         cmd.arg(format!("--include={glob}"));
     }
+    // -e and -- keep a pattern or path such as "-R" from being read as an option
+    cmd.arg("-e").arg(pattern).arg("--").arg(&target);
 
     match cmd.output() {
         Ok(out) => {
@@ -0,0 +1,107 @@
+// Moving-window file viewer for the `open`, `scroll_up` and `scroll_down` tools.
+// The agent sees one window of numbered lines at a time, with how many lines
+// lie above and below, instead of whole files flooding the context. The file
+// is re-read on every render so the window reflects edits made in between.
+
+use std::path::PathBuf;
+
+use super::workspace::Workspace;
+
+/// Lines shown per window.
+pub const WINDOW: usize = 100;
+
+#[derive(Debug, Default)]
+pub struct FileViewer {
+    open: Option<OpenFile>,
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    path: PathBuf,
+    /// The path as the agent gave it, for headers.
+    display: String,
+    /// 1-based number of the first line in the window.
+    first: usize,
+}
+
+impl FileViewer {
+    /// Open `path` with the window around `line` (1-based; `None` = top).
+    pub fn open(&mut self, ws: &Workspace, path: &str, line: Option<usize>) -> Result<String, String> {
+        let resolved = ws.resolve_read(path)?;
+        let content = read(&resolved, path)?;
+        let total = content.lines().count();
+        if let Some(line) = line {
+            if line == 0 || line > total.max(1) {
+                return Err(format!("line {line} is out of range; {path} has {total} lines"));
+            }
+        }
+        let first = line.map_or(1, |l| l.saturating_sub(WINDOW / 2).max(1));
+        self.open = Some(OpenFile { path: resolved, display: path.trim().to_string(), first });
+        Ok(self.render_content(&content))
+    }
+
+    /// Open `path` and show every line.
+    pub fn open_entire(&mut self, ws: &Workspace, path: &str) -> Result<String, String> {
+        let resolved = ws.resolve_read(path)?;
+        let content = read(&resolved, path)?;
+        let display = path.trim().to_string();
+        let total = content.lines().count();
+        let body = numbered(&content, 1, total);
+        self.open = Some(OpenFile { path: resolved, display: display.clone(), first: 1 });
+        Ok(format!("[File: {display} ({total} lines total)]\n{body}"))
+    }
+
+    /// Move the window by `windows` (negative = up).
+    pub fn scroll(&mut self, windows: isize) -> Result<String, String> {
+        let Some(file) = &mut self.open else {
+            return Err("no file is open; use `open` first".to_string());
+        };
+        let content = read(&file.path, &file.display)?;
+        let total = content.lines().count();
+        let last_first = total.saturating_sub(WINDOW - 1).max(1);
+        let before = file.first;
+        file.first = if windows < 0 {
+            file.first.saturating_sub(WINDOW * windows.unsigned_abs()).max(1)
+        } else {
+            (file.first + WINDOW * windows as usize).min(last_first)
+        };
+        if file.first == before {
+            let edge = if windows < 0 { "top" } else { "end" };
+            return Err(format!("already at the {edge} of {}", file.display));
+        }
+        Ok(self.render_content(&content))
+    }
+
+    fn render_content(&self, content: &str) -> String {
+        let Some(file) = &self.open else { return String::new() };
+        let total = content.lines().count();
+        let first = file.first.min(total.max(1));
+        let last = (first + WINDOW - 1).min(total);
+        let mut out = format!("[File: {} ({total} lines total)]\n", file.display);
+        if first > 1 {
+            out.push_str(&format!("({} more lines above)\n", first - 1));
+        }
+        out.push_str(&numbered(content, first, last));
+        if last < total {
+            out.push_str(&format!("\n({} more lines below)", total - last));
+        }
+        out
+    }
+}
+
+fn read(path: &std::path::Path, display: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", display.trim()))
+}
+
+/// Lines `first..=last` (1-based) prefixed with their numbers.
+fn numbered(content: &str, first: usize, last: usize) -> String {
+    let width = last.to_string().len();
+    content
+        .lines()
+        .enumerate()
+        .skip(first.saturating_sub(1))
+        .take((last + 1).saturating_sub(first))
+        .map(|(i, line)| format!("{:>width$}: {line}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
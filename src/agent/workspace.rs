@@ -0,0 +1,119 @@
+// Workspace-root confinement for the file tools.
+// Paths given to the file tools (read_file, open, write_file, edit_file,
+// grep_search, ...) are resolved against the workspace root with symlinks
+// followed, and anything that lands outside it is refused. Extra roots can be allowlisted for reading only.
+
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workspace {
+    root: PathBuf,
+    read_only: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// A workspace rooted at `root` (canonicalized when it exists).
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        Self { root: canonical_or(root), read_only: Vec::new() }
+    }
+
+    /// A workspace rooted at the directory the process was launched from.
+    pub fn launch_dir() -> Self {
+        Self::new(std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+    }
+
+    /// Also allow reading (not writing) under `root`.
+    pub fn with_read_only_root(mut self, root: impl AsRef<Path>) -> Self {
+        self.read_only.push(canonical_or(root.as_ref()));
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn read_only_roots(&self) -> &[PathBuf] {
+        &self.read_only
+    }
+
+    /// Resolve a path the tools may read: inside the root or a read-only root.
+    pub fn resolve_read(&self, path: &str) -> Result<PathBuf, String> {
+        let resolved = self.resolve(path)?;
+        if resolved.starts_with(&self.root) || self.read_only.iter().any(|r| resolved.starts_with(r)) {
+            Ok(resolved)
+        } else {
+            Err(self.outside(path))
+        }
+    }
+
+    /// Resolve a path the tools may write: inside the root only.
+    pub fn resolve_write(&self, path: &str) -> Result<PathBuf, String> {
+        let resolved = self.resolve(path)?;
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else if self.read_only.iter().any(|r| resolved.starts_with(r)) {
+            Err(format!("'{path}' is in a read-only root"))
+        } else {
+            Err(self.outside(path))
+        }
+    }
+
+    /// `path` relative to the root, for display; `.` for the root itself.
+    pub fn display(&self, resolved: &Path) -> PathBuf {
+        match resolved.strip_prefix(&self.root) {
+            Ok(rel) if rel.as_os_str().is_empty() => PathBuf::from("."),
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => resolved.to_path_buf(),
+        }
+    }
+
+    fn outside(&self, path: &str) -> String {
+        format!("'{path}' is outside the workspace root {}", self.root.display())
+    }
+
+    /// Join `path` onto the root and follow symlinks. The longest existing
+    /// prefix is canonicalized by the OS; the not-yet-existing rest (a file
+    /// about to be created) may only contain plain names.
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let path = path.trim();
+        if path.is_empty() {
+            return Err("path must not be empty".to_string());
+        }
+        let full = self.root.join(path);
+        let components: Vec<Component> = full.components().collect();
+
+        let mut existing = components.len();
+        while existing > 0 {
+            let prefix: PathBuf = components[..existing].iter().collect();
+            // symlink_metadata so a dangling symlink counts as existing (and then fails to resolve)
+            if prefix.symlink_metadata().is_ok() {
+                break;
+            }
+            existing -= 1;
+        }
+
+        let prefix: PathBuf = components[..existing].iter().collect();
+        let mut resolved = prefix
+            .canonicalize()
+            .map_err(|e| format!("cannot resolve '{path}': {e}"))?;
+        for component in &components[existing..] {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir => {}
+                _ => return Err(format!("cannot resolve '{path}': '..' after a missing directory")),
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::launch_dir()
+    }
+}
+
+fn canonical_or(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,617 @@
+// Typed settings from `config.toml`.
+// Layers are merged key by key, later ones winning: the global file
+// (`~/.config/qai/config.toml`, the platform config dir), the project's
+// `.qai/config.toml`, then `QAI_*` environment variables. The project file
+// comes with the workspace, which may be a repository someone else wrote, so
+// the keys that decide where tokens go and what runs unprompted are only taken
+// from the global file and the environment (see `global_only`). Files are read and
+// written through `toml_edit`, so setting a value keeps the user's comments,
+// key order and formatting. API tokens normally live in the secret store
+// (see `secrets`) rather than in these files.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+use crate::agent::approval::{Approval, ApprovalPolicy};
+use crate::agent::AgentLimits;
+use crate::secrets::{self, SecretBackend};
+use crate::tui::keys::{KeyAction, KeyBinding, Keymap};
+use crate::tui::providers::{AuthStyle, Provider};
+use crate::tui::theme::Theme;
+
+pub fn global_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("qai").join("config.toml"))
+}
+
+pub fn project_config_path(root: &Path) -> PathBuf {
+    root.join(".qai").join("config.toml")
+}
+
+// ── Settings ──────────────────────────────────────────────────────────────────
+
+/// `[providers.<name>]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderConfig {
+    pub api_token: Option<String>,
+    /// Base URL (or full chat URL) to call instead of the provider's default.
+    pub url: Option<String>,
+    pub model: Option<String>,
+    /// How the token is sent, e.g. `api-key` for Azure OpenAI.
+    pub auth: Option<AuthStyle>,
+}
+
+/// `[agent]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentConfig {
+    pub max_steps: Option<usize>,
+    /// Consecutive nudges for think-only or malformed tool-call replies.
+    pub max_format_retries: Option<usize>,
+    /// Wall-clock budget for a run, in seconds.
+    pub timeout: Option<u64>,
+    pub native_tools: Option<bool>,
+}
+
+/// `[approval]`, with per-tool rules in `[approval.tools]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApprovalConfig {
+    pub mutating: Option<Approval>,
+    pub read_only: Option<Approval>,
+    pub tools: BTreeMap<String, Approval>,
+}
+
+/// `[tui]`, with action → key overrides in `[tui.keybindings]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TuiConfig {
+    pub theme: Option<Theme>,
+    pub keybindings: BTreeMap<KeyAction, KeyBinding>,
+}
+
+/// `[secrets]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SecretsConfig {
+    /// Where API tokens are kept; the keyring when unset.
+    pub backend: Option<SecretBackend>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Provider used when none is chosen.
+    pub provider: Option<Provider>,
+    /// Model for the default provider, unless its own table sets one.
+    pub model: Option<String>,
+    /// Reply length cap sent to every provider (default `llm::DEFAULT_MAX_TOKENS`).
+    pub max_tokens: Option<u32>,
+    /// The single token older versions saved. `layered` hands it to the
+    /// default provider only, so it never reaches a provider it wasn't for.
+    pub api_token: Option<String>,
+    pub providers: HashMap<Provider, ProviderConfig>,
+    pub agent: AgentConfig,
+    pub approval: ApprovalConfig,
+    pub tui: TuiConfig,
+    pub secrets: SecretsConfig,
+}
+
+/// Provider, model, token and URL for one request, after defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmSettings {
+    pub provider: Provider,
+    pub model: String,
+    pub api_token: String,
+    /// Empty means the provider's default endpoint.
+    pub url: String,
+    pub auth: AuthStyle,
+    /// `None` means `llm::DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
+}
+
+impl Config {
+    /// The global file, then `root`'s project file, then the environment;
+    /// tokens from provider env vars and the secret store win over the files.
+    pub fn load(root: &Path) -> Result<Self> {
+        let files: Vec<PathBuf> = global_config_path().into_iter().collect();
+        let env = |name: &str| std::env::var(name).ok();
+        let mut config = Self::layered(&files, Some(&project_config_path(root)), env)?;
+        if let Some(store) = secrets::store_for(&config) {
+            secrets::resolve_tokens(&mut config, store.as_ref(), env)?;
+        }
+        Ok(config)
+    }
+
+    /// Merge `files` in order (missing ones are skipped), then `project`
+    /// without its global-only keys, then the variables `env` returns. A
+    /// legacy `api_token` ends up as the default provider's token.
+    pub fn layered(files: &[PathBuf], project: Option<&Path>, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut config = Config::default();
+        for path in files.iter().filter(|p| p.exists()) {
+            config.merge(ConfigFile::open(path)?.config()?);
+        }
+        if let Some(path) = project.filter(|p| p.exists()) {
+            config.merge(ConfigFile::open(path)?.config()?.project_scoped());
+        }
+        config.merge(Self::from_env(env)?);
+        config.adopt_legacy_token();
+        Ok(config)
+    }
+
+    /// Give the legacy top-level token to the default provider, unless it has its own.
+    pub(crate) fn adopt_legacy_token(&mut self) {
+        if let Some(token) = self.api_token.take() {
+            let provider = self.default_provider();
+            self.providers.entry(provider).or_default().api_token.get_or_insert(token);
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::from_doc(&text.parse::<DocumentMut>()?)
+    }
+
+    fn from_doc(doc: &DocumentMut) -> Result<Self> {
+        let root = doc.as_table();
+        let mut config = Config {
+            provider: get_str(root, "", "provider")?.map(|p| parse_provider(&p, "provider")).transpose()?,
+            model: get_str(root, "", "model")?,
+            max_tokens: get_uint(root, "", "max_tokens")?.map(|n| max_tokens(n, "max_tokens")).transpose()?,
+            api_token: get_str(root, "", "api_token")?,
+            ..Config::default()
+        };
+        if let Some(providers) = get_table(root, "", "providers")? {
+            for (name, item) in providers.iter() {
+                let key = format!("providers.{name}");
+                let provider = parse_provider(name, &key)?;
+                let table = item.as_table_like().with_context(|| format!("{key}: expected a table"))?;
+                let settings = ProviderConfig {
+                    api_token: get_str(table, &key, "api_token")?,
+                    url: get_str(table, &key, "url")?,
+                    model: get_str(table, &key, "model")?,
+                    auth: get_str(table, &key, "auth")?
+                        .map(|a| a.parse::<AuthStyle>().map_err(|e| anyhow::anyhow!("{key}.auth: {e}")))
+                        .transpose()?,
+                };
+                config.providers.insert(provider, settings);
+            }
+        }
+        if let Some(agent) = get_table(root, "", "agent")? {
+            config.agent = AgentConfig {
+                max_steps: get_uint(agent, "agent", "max_steps")?.map(|n| n as usize),
+                max_format_retries: get_uint(agent, "agent", "max_format_retries")?.map(|n| n as usize),
+                timeout: get_uint(agent, "agent", "timeout")?,
+                native_tools: get_bool(agent, "agent", "native_tools")?,
+            };
+        }
+        if let Some(approval) = get_table(root, "", "approval")? {
+            config.approval.mutating = get_approval(approval, "approval", "mutating")?;
+            config.approval.read_only = get_approval(approval, "approval", "read_only")?;
+            if let Some(tools) = get_table(approval, "approval", "tools")? {
+                for (tool, _) in tools.iter() {
+                    if let Some(rule) = get_approval(tools, "approval.tools", tool)? {
+                        config.approval.tools.insert(tool.to_string(), rule);
+                    }
+                }
+            }
+        }
+        if let Some(tui) = get_table(root, "", "tui")? {
+            config.tui.theme = get_str(tui, "tui", "theme")?
+                .map(|t| t.parse::<Theme>().map_err(|e| anyhow::anyhow!("tui.theme: {e}")))
+                .transpose()?;
+            if let Some(keys) = get_table(tui, "tui", "keybindings")? {
+                for (name, _) in keys.iter() {
+                    let key = format!("tui.keybindings.{name}");
+                    let action = KeyAction::from_name(name).with_context(|| format!("{key}: unknown action '{name}'"))?;
+                    if let Some(binding) = get_str(keys, "tui.keybindings", name)? {
+                        let binding = binding.parse::<KeyBinding>().map_err(|e| anyhow::anyhow!("{key}: {e}"))?;
+                        config.tui.keybindings.insert(action, binding);
+                    }
+                }
+            }
+        }
+        if let Some(table) = get_table(root, "", "secrets")? {
+            config.secrets.backend = get_str(table, "secrets", "backend")?
+                .map(|b| b.parse::<SecretBackend>().map_err(|e| anyhow::anyhow!("secrets.backend: {e}")))
+                .transpose()?;
+        }
+        Ok(config)
+    }
+
+    /// `QAI_PROVIDER`, `QAI_MODEL`, `QAI_MAX_TOKENS`, `QAI_MAX_STEPS`, `QAI_MAX_FORMAT_RETRIES`,
+    /// `QAI_TIMEOUT`, `QAI_NATIVE_TOOLS`, `QAI_APPROVAL` (for mutating tools),
+    /// `QAI_THEME` and `QAI_SECRETS_BACKEND`.
+    pub fn from_env(env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| env(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let number = |name: &str| -> Result<Option<u64>> {
+            var(name).map(|v| v.parse::<u64>().with_context(|| format!("{name}: expected a number, got '{v}'"))).transpose()
+        };
+        let mut config = Config {
+            provider: var("QAI_PROVIDER").map(|p| parse_provider(&p, "QAI_PROVIDER")).transpose()?,
+            model: var("QAI_MODEL"),
+            max_tokens: number("QAI_MAX_TOKENS")?.map(|n| max_tokens(n, "QAI_MAX_TOKENS")).transpose()?,
+            ..Config::default()
+        };
+        config.agent.max_steps = number("QAI_MAX_STEPS")?.map(|n| n as usize);
+        config.agent.max_format_retries = number("QAI_MAX_FORMAT_RETRIES")?.map(|n| n as usize);
+        config.agent.timeout = number("QAI_TIMEOUT")?;
+        config.agent.native_tools = match var("QAI_NATIVE_TOOLS").as_deref() {
+            None => None,
+            Some("1" | "true" | "yes") => Some(true),
+            Some("0" | "false" | "no") => Some(false),
+            Some(other) => bail!("QAI_NATIVE_TOOLS: expected true or false, got '{other}'"),
+        };
+        config.approval.mutating = var("QAI_APPROVAL")
+            .map(|v| v.parse::<Approval>().map_err(|e| anyhow::anyhow!("QAI_APPROVAL: {e}")))
+            .transpose()?;
+        config.tui.theme = var("QAI_THEME")
+            .map(|v| v.parse::<Theme>().map_err(|e| anyhow::anyhow!("QAI_THEME: {e}")))
+            .transpose()?;
+        config.secrets.backend = var("QAI_SECRETS_BACKEND")
+            .map(|v| v.parse::<SecretBackend>().map_err(|e| anyhow::anyhow!("QAI_SECRETS_BACKEND: {e}")))
+            .transpose()?;
+        Ok(config)
+    }
+
+    /// Drop what a project file may not set; see `global_only`.
+    fn project_scoped(mut self) -> Self {
+        for settings in self.providers.values_mut() {
+            settings.url = None;
+            settings.auth = None;
+        }
+        self.approval = ApprovalConfig::default();
+        self.secrets = SecretsConfig::default();
+        self
+    }
+
+    /// Overlay `over`: every value it sets replaces ours.
+    pub fn merge(&mut self, over: Config) {
+        fn set<T>(value: &mut Option<T>, over: Option<T>) {
+            if over.is_some() {
+                *value = over;
+            }
+        }
+        set(&mut self.provider, over.provider);
+        set(&mut self.model, over.model);
+        set(&mut self.max_tokens, over.max_tokens);
+        set(&mut self.api_token, over.api_token);
+        for (provider, settings) in over.providers {
+            let ours = self.providers.entry(provider).or_default();
+            set(&mut ours.api_token, settings.api_token);
+            set(&mut ours.url, settings.url);
+            set(&mut ours.model, settings.model);
+            set(&mut ours.auth, settings.auth);
+        }
+        set(&mut self.agent.max_steps, over.agent.max_steps);
+        set(&mut self.agent.max_format_retries, over.agent.max_format_retries);
+        set(&mut self.agent.timeout, over.agent.timeout);
+        set(&mut self.agent.native_tools, over.agent.native_tools);
+        set(&mut self.approval.mutating, over.approval.mutating);
+        set(&mut self.approval.read_only, over.approval.read_only);
+        self.approval.tools.extend(over.approval.tools);
+        set(&mut self.tui.theme, over.tui.theme);
+        self.tui.keybindings.extend(over.tui.keybindings);
+        set(&mut self.secrets.backend, over.secrets.backend);
+    }
+
+    pub fn default_provider(&self) -> Provider {
+        self.provider.unwrap_or(Provider::Ollama)
+    }
+
+    pub fn model_for(&self, provider: Provider) -> Option<String> {
+        let own = self.providers.get(&provider).and_then(|p| p.model.clone());
+        own.or_else(|| self.model.clone().filter(|_| self.provider == Some(provider)))
+    }
+
+    pub fn url_for(&self, provider: Provider) -> Option<String> {
+        self.providers.get(&provider).and_then(|p| p.url.clone())
+    }
+
+    pub fn auth_for(&self, provider: Provider) -> AuthStyle {
+        self.providers.get(&provider).and_then(|p| p.auth).unwrap_or_else(|| provider.auth_style())
+    }
+
+    pub fn token_for(&self, provider: Provider) -> Option<String> {
+        self.providers.get(&provider).and_then(|p| p.api_token.clone())
+    }
+
+    /// Fill in whatever the caller left unset: the configured provider (else
+    /// Ollama), then that provider's configured or default model, token and URL.
+    pub fn llm(
+        &self,
+        provider: Option<Provider>,
+        model: Option<String>,
+        api_token: Option<String>,
+        url: Option<String>,
+    ) -> LlmSettings {
+        let provider = provider.unwrap_or_else(|| self.default_provider());
+        LlmSettings {
+            provider,
+            model: model
+                .or_else(|| self.model_for(provider))
+                .unwrap_or_else(|| provider.default_model().to_string()),
+            api_token: api_token.or_else(|| self.token_for(provider)).unwrap_or_default(),
+            url: url.filter(|u| !u.trim().is_empty()).or_else(|| self.url_for(provider)).unwrap_or_default(),
+            auth: self.auth_for(provider),
+            max_tokens: self.max_tokens,
+        }
+    }
+
+    pub fn limits(&self) -> AgentLimits {
+        let mut limits = AgentLimits::default();
+        if let Some(max_steps) = self.agent.max_steps {
+            limits.max_steps = max_steps;
+        }
+        if let Some(retries) = self.agent.max_format_retries {
+            limits.max_format_retries = retries;
+        }
+        if let Some(secs) = self.agent.timeout {
+            limits.max_duration = Some(Duration::from_secs(secs));
+        }
+        limits
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.tui.theme.unwrap_or_default()
+    }
+
+    pub fn keymap(&self) -> Keymap {
+        Keymap::new(self.tui.keybindings.clone())
+    }
+
+    pub fn approval_policy(&self) -> ApprovalPolicy {
+        let mut policy = ApprovalPolicy::default();
+        if let Some(mutating) = self.approval.mutating {
+            policy.mutating = mutating;
+        }
+        if let Some(read_only) = self.approval.read_only {
+            policy.read_only = read_only;
+        }
+        for (tool, &rule) in &self.approval.tools {
+            policy.set(tool, rule);
+        }
+        policy
+    }
+
+    /// Every value that is set as `(dotted key, value)`, tokens masked.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        let mut push = |key: String, value: Option<String>| entries.extend(value.map(|v| (key, v)));
+        push("provider".into(), self.provider.map(|p| p.name().to_string()));
+        push("model".into(), self.model.clone());
+        push("max_tokens".into(), self.max_tokens.map(|n| n.to_string()));
+        push("api_token".into(), self.api_token.as_deref().map(mask));
+        for provider in Provider::all() {
+            let Some(settings) = self.providers.get(provider) else { continue };
+            let key = |field: &str| format!("providers.{}.{field}", provider.name());
+            push(key("api_token"), settings.api_token.as_deref().map(mask));
+            push(key("url"), settings.url.clone());
+            push(key("model"), settings.model.clone());
+            push(key("auth"), settings.auth.map(|a| a.name().to_string()));
+        }
+        push("agent.max_steps".into(), self.agent.max_steps.map(|n| n.to_string()));
+        push("agent.max_format_retries".into(), self.agent.max_format_retries.map(|n| n.to_string()));
+        push("agent.timeout".into(), self.agent.timeout.map(|n| n.to_string()));
+        push("agent.native_tools".into(), self.agent.native_tools.map(|b| b.to_string()));
+        push("approval.mutating".into(), self.approval.mutating.map(approval_name));
+        push("approval.read_only".into(), self.approval.read_only.map(approval_name));
+        for (tool, &rule) in &self.approval.tools {
+            push(format!("approval.tools.{tool}"), Some(approval_name(rule)));
+        }
+        push("tui.theme".into(), self.tui.theme.map(|t| t.name().to_string()));
+        for (action, key) in &self.tui.keybindings {
+            push(format!("tui.keybindings.{}", action.name()), Some(key.to_string()));
+        }
+        push("secrets.backend".into(), self.secrets.backend.map(|b| b.name().to_string()));
+        entries
+    }
+}
+
+fn mask(token: &str) -> String {
+    match token.char_indices().rev().nth(3) {
+        Some((cut, _)) if token.chars().count() > 8 => format!("…{}", &token[cut..]),
+        _ => "…".to_string(),
+    }
+}
+
+fn approval_name(approval: Approval) -> String {
+    match approval {
+        Approval::Allow => "allow",
+        Approval::Ask => "ask",
+        Approval::Deny => "deny",
+    }
+    .to_string()
+}
+
+/// A `max_tokens` value: at least one, and small enough for the wire.
+fn max_tokens(n: u64, key: &str) -> Result<u32> {
+    match u32::try_from(n) {
+        Ok(n) if n > 0 => Ok(n),
+        _ => bail!("{key}: expected a number from 1 to {}", u32::MAX),
+    }
+}
+
+fn parse_provider(name: &str, key: &str) -> Result<Provider> {
+    Provider::from_name(name).with_context(|| format!("{key}: unknown provider '{name}'"))
+}
+
+fn dotted(parent: &str, key: &str) -> String {
+    if parent.is_empty() { key.to_string() } else { format!("{parent}.{key}") }
+}
+
+fn get_str(table: &dyn TableLike, parent: &str, key: &str) -> Result<Option<String>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(item) => match item.as_str() {
+            Some(s) => Ok(Some(s.to_string())),
+            None => bail!("{}: expected a string", dotted(parent, key)),
+        },
+    }
+}
+
+fn get_uint(table: &dyn TableLike, parent: &str, key: &str) -> Result<Option<u64>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(item) => match item.as_integer() {
+            Some(n) if n >= 0 => Ok(Some(n as u64)),
+            _ => bail!("{}: expected a non-negative integer", dotted(parent, key)),
+        },
+    }
+}
+
+fn get_bool(table: &dyn TableLike, parent: &str, key: &str) -> Result<Option<bool>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(item) => match item.as_bool() {
+            Some(b) => Ok(Some(b)),
+            None => bail!("{}: expected true or false", dotted(parent, key)),
+        },
+    }
+}
+
+fn get_approval(table: &dyn TableLike, parent: &str, key: &str) -> Result<Option<Approval>> {
+    get_str(table, parent, key)?
+        .map(|v| v.parse::<Approval>().map_err(|e| anyhow::anyhow!("{}: {e}", dotted(parent, key))))
+        .transpose()
+}
+
+fn get_table<'a>(table: &'a dyn TableLike, parent: &str, key: &str) -> Result<Option<&'a dyn TableLike>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(item) => match item.as_table_like() {
+            Some(t) => Ok(Some(t)),
+            None => bail!("{}: expected a table", dotted(parent, key)),
+        },
+    }
+}
+
+// ── Editing ───────────────────────────────────────────────────────────────────
+
+/// What a settable key holds, so `set` can store `"30"` as a number.
+enum Kind {
+    Str,
+    Uint,
+    Bool,
+}
+
+/// The kind of a dotted config key, or `None` if there is no such key.
+fn key_kind(keys: &[&str]) -> Option<Kind> {
+    match keys {
+        ["provider" | "model" | "api_token"] => Some(Kind::Str),
+        ["max_tokens"] => Some(Kind::Uint),
+        ["providers", provider, "api_token" | "url" | "model" | "auth"] if Provider::from_name(provider).is_some() => {
+            Some(Kind::Str)
+        }
+        ["agent", "max_steps" | "max_format_retries" | "timeout"] => Some(Kind::Uint),
+        ["agent", "native_tools"] => Some(Kind::Bool),
+        ["approval", "mutating" | "read_only"] | ["approval", "tools", _] => Some(Kind::Str),
+        ["tui", "theme"] => Some(Kind::Str),
+        ["tui", "keybindings", action] if KeyAction::from_name(action).is_some() => Some(Kind::Str),
+        ["secrets", "backend"] => Some(Kind::Str),
+        _ => None,
+    }
+}
+
+/// Keys read only from the global file and the environment, never from a
+/// project's `.qai/config.toml`: a provider's `url` and `auth` (where its
+/// token is sent), `approval.*` and `secrets.*`.
+pub fn global_only(key: &str) -> bool {
+    let keys: Vec<&str> = key.split('.').collect();
+    matches!(keys.as_slice(), ["providers", _, "url" | "auth"] | ["approval", ..] | ["secrets", ..])
+}
+
+/// One config file, edited in place.
+pub struct ConfigFile {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Read `path`; a missing file is an empty one.
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let doc = text.parse::<DocumentMut>().with_context(|| format!("Invalid TOML in {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf(), doc })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn config(&self) -> Result<Config> {
+        Config::from_doc(&self.doc).with_context(|| format!("Invalid setting in {}", self.path.display()))
+    }
+
+    /// Set a dotted key such as `providers.openai.model`. The value is checked
+    /// against the schema first; the rest of the file is left as it was.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let keys: Vec<&str> = key.split('.').collect();
+        let new = match key_kind(&keys) {
+            None => bail!("unknown config key '{key}'"),
+            Some(Kind::Str) => toml_edit::Value::from(value),
+            Some(Kind::Uint) => match value.trim().parse::<u64>() {
+                Ok(n) if i64::try_from(n).is_ok() => toml_edit::Value::from(n as i64),
+                _ => bail!("{key}: expected a non-negative integer, got '{value}'"),
+            },
+            Some(Kind::Bool) => match value.trim() {
+                "true" => toml_edit::Value::from(true),
+                "false" => toml_edit::Value::from(false),
+                _ => bail!("{key}: expected true or false, got '{value}'"),
+            },
+        };
+        let mut doc = self.doc.clone();
+        let (leaf, parents) = keys.split_last().expect("split never returns nothing");
+        let mut table = doc.as_table_mut();
+        for name in parents {
+            let item = table.entry(name).or_insert_with(|| {
+                let mut t = Table::new();
+                t.set_implicit(true);
+                Item::Table(t)
+            });
+            table = item.as_table_mut().with_context(|| format!("'{name}' in {key} is not a table"))?;
+        }
+        match table.get_mut(leaf).and_then(Item::as_value_mut) {
+            // Keep the old value's spacing and trailing comment
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = new;
+                *old.decor_mut() = decor;
+            }
+            None => {
+                table.insert(leaf, Item::Value(new));
+            }
+        }
+        Config::from_doc(&doc)?;
+        self.doc = doc;
+        Ok(())
+    }
+
+    /// Remove a dotted key; returns whether it was set.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let keys: Vec<&str> = key.split('.').collect();
+        let (leaf, parents) = keys.split_last().expect("split never returns nothing");
+        let mut table: &mut dyn TableLike = self.doc.as_table_mut();
+        for name in parents {
+            match table.get_mut(name).and_then(Item::as_table_like_mut) {
+                Some(t) => table = t,
+                None => return false,
+            }
+        }
+        table.remove(leaf).is_some()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, self.doc.to_string())
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+impl std::fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
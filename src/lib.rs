@@ -1,13 +1,32 @@
 pub mod tui;
 pub mod agent;
+pub mod sessions;
+pub mod clock;
+pub mod config;
+pub mod serve;
+pub mod mcp_server;
+pub mod secrets;
+pub mod llm;
 
-pub use tui::{render_to_buffer, save_api_token, load_api_token, strip_model_tags, App, ChatFocus, Provider, Screen, TextInput};
-pub use agent::{parse_step, parse_steps, try_recover_plain_tool, StepKind, pr_review::PRReviewAgent};
+pub use tui::{
+    render_to_buffer, save_api_token, load_api_token, strip_model_tags, App, AuthStyle, ChatFocus, Provider, Screen, TextInput,
+};
+pub use agent::{parse_step, parse_steps, try_recover_plain_tool, try_recover_plain_tool_in, AgentEvent, StepKind, pr_review::PRReviewAgent};
+
+use agent::pr_review::{findings_to_json, format_findings, ReviewTarget};
+use tui::api::{stream_message, StreamRequest};
+use agent::{approval::ApprovalPolicy, tools::ToolContext, AgentLimits, EventLog, McpServers, ReActAgent, RunOutcome, ToolMode, ToolRegistry, Workspace};
+use clock::format_time;
+use config::Config;
+use sessions::SessionStore;
 
 use anyhow::{bail, Context, Result};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 pub fn read_prompt(prompt: &Path) -> Result<String> {
     fs::read_to_string(prompt)
@@ -78,3 +97,300 @@ pub fn tools() -> Result<()> {
     println!("- Web search");
     Ok(())
 }
+
+pub struct ReviewOptions {
+    pub target: ReviewTarget,
+    pub provider: Provider,
+    pub model: Option<String>,
+    pub api_token: Option<String>,
+    pub custom_url: String,
+    /// How the token is sent; `None` uses the provider's usual header.
+    pub auth: Option<AuthStyle>,
+    /// Reply length cap; `None` sends `llm::DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
+    pub repo: String,
+    pub paths: Vec<String>,
+    pub json: bool,
+    pub output: Option<PathBuf>,
+}
+
+pub async fn review(opts: ReviewOptions) -> Result<()> {
+    let model = opts
+        .model
+        .unwrap_or_else(|| opts.provider.default_model().to_string());
+    let token = opts.api_token.or_else(|| load_api_token(opts.provider)).unwrap_or_default();
+    let auth = opts.auth.unwrap_or_else(|| opts.provider.auth_style());
+    let mut reviewer = PRReviewAgent::new(opts.provider, token, opts.custom_url, model)
+        .with_auth(auth)
+        .with_max_tokens(opts.max_tokens);
+    reviewer.repo = opts.repo;
+    reviewer.paths = opts.paths;
+
+    let findings = reviewer.review(&opts.target).await?;
+    let rendered = if opts.json {
+        findings_to_json(&findings)
+    } else {
+        format_findings(&findings)
+    };
+    match opts.output {
+        Some(dest) => {
+            fs::write(&dest, format!("{rendered}\n"))
+                .with_context(|| format!("Failed to write to {}", dest.display()))?;
+            println!("Wrote {} finding(s) to {}", findings.len(), dest.display());
+        }
+        None => println!("{rendered}"),
+    }
+    Ok(())
+}
+
+/// The task for `run`: the argument, or all of `stdin` when it is absent or `-`.
+pub fn read_task(arg: Option<String>, mut stdin: impl io::Read) -> Result<String> {
+    let task = match arg.filter(|t| t != "-") {
+        Some(task) => task,
+        None => {
+            let mut task = String::new();
+            stdin.read_to_string(&mut task).context("Failed to read the task from stdin")?;
+            task
+        }
+    };
+    let task = task.trim();
+    if task.is_empty() {
+        bail!("the task is empty");
+    }
+    Ok(task.to_string())
+}
+
+pub struct RunOptions {
+    pub task: String,
+    pub provider: Provider,
+    pub model: Option<String>,
+    pub api_token: Option<String>,
+    pub custom_url: String,
+    /// How the token is sent; `None` uses the provider's usual header.
+    pub auth: Option<AuthStyle>,
+    /// Reply length cap; `None` sends `llm::DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
+    pub system_prompt: String,
+    pub workspace: Workspace,
+    pub native_tools: bool,
+    pub max_steps: Option<usize>,
+    pub max_format_retries: Option<usize>,
+    /// Wall-clock budget in seconds (`None` keeps the default).
+    pub timeout_secs: Option<u64>,
+    /// Also log the run's events here; closed with a summary when the run ends.
+    pub event_log: Option<EventLog>,
+    /// MCP servers whose tools the agent may call.
+    pub mcp: McpServers,
+    /// Which tools run or are refused. No one is there to confirm, so "ask" refuses too.
+    pub approval: ApprovalPolicy,
+}
+
+/// Run the agent on `opts.task` without the TUI, writing progress lines to
+/// `progress`. Returns the final answer; a run that stops, fails or is
+/// cancelled is an error.
+pub async fn run_task(
+    opts: RunOptions,
+    cancel: CancellationToken,
+    progress: &mut (dyn Write + Send),
+) -> Result<String> {
+    let model = opts.model.unwrap_or_else(|| opts.provider.default_model().to_string());
+    let token = opts.api_token.or_else(|| load_api_token(opts.provider)).unwrap_or_default();
+    let mut limits = AgentLimits::default();
+    if let Some(max_steps) = opts.max_steps {
+        limits.max_steps = max_steps;
+    }
+    if let Some(retries) = opts.max_format_retries {
+        limits.max_format_retries = retries;
+    }
+    if let Some(secs) = opts.timeout_secs {
+        limits.max_duration = Some(Duration::from_secs(secs));
+    }
+    let tool_mode = if opts.native_tools { ToolMode::Native } else { ToolMode::Xml };
+    let mut agent = ReActAgent::new(opts.provider, token, opts.custom_url, model, opts.system_prompt)
+        .with_auth(opts.auth.unwrap_or_else(|| opts.provider.auth_style()))
+        .with_max_tokens(opts.max_tokens)
+        .with_tool_mode(tool_mode)
+        .with_limits(limits)
+        .with_approval(opts.approval, None)
+        .with_tools(ToolRegistry::builtin_with(ToolContext::new(opts.workspace)).with_extra_tools(opts.mcp.tools()));
+    agent.event_log = opts.event_log.clone();
+
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let run = agent.run(opts.task, Vec::new(), events_tx, cancel);
+    let report = async {
+        while let Some(event) = events_rx.recv().await {
+            if let Some(line) = agent::render_progress(&event) {
+                let _ = writeln!(progress, "{line}");
+            }
+        }
+    };
+    let (outcome, ()) = tokio::join!(run, report);
+    if let Some(log) = &opts.event_log {
+        log.close().await;
+    }
+    match outcome.context("agent run failed")? {
+        RunOutcome::Answered(answer) => Ok(answer),
+        RunOutcome::Stopped(reason) => bail!("agent stopped without an answer: {reason}"),
+        RunOutcome::Cancelled { step } => bail!("agent run cancelled at step {step}"),
+        RunOutcome::Failed(e) => bail!("agent run failed: {e}"),
+    }
+}
+
+/// The message `ask` sends: the prompt, then piped input, then each attached
+/// file in a fenced block under its path.
+pub fn build_ask_prompt(prompt: Option<String>, stdin: Option<String>, files: &[PathBuf]) -> Result<String> {
+    let mut parts: Vec<String> = Vec::new();
+    parts.extend(prompt.map(|p| p.trim().to_string()).filter(|p| !p.is_empty()));
+    parts.extend(stdin.map(|s| s.trim_end().to_string()).filter(|s| !s.trim().is_empty()));
+    for file in files {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read attachment {}", file.display()))?;
+        parts.push(format!("File: {}\n```\n{}\n```", file.display(), content.trim_end()));
+    }
+    if parts.is_empty() {
+        bail!("nothing to ask; pass a prompt or pipe text on stdin");
+    }
+    Ok(parts.join("\n\n"))
+}
+
+pub struct AskOptions {
+    pub prompt: String,
+    pub provider: Provider,
+    pub model: Option<String>,
+    pub api_token: Option<String>,
+    pub custom_url: String,
+    /// How the token is sent; `None` uses the provider's usual header.
+    pub auth: Option<AuthStyle>,
+    /// Reply length cap; `None` sends `llm::DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
+    pub system_prompt: String,
+    /// Print one JSON object at the end instead of streaming text.
+    pub json: bool,
+    /// Also log the reply's events here; closed with a summary at the end.
+    pub event_log: Option<EventLog>,
+}
+
+/// Send `opts.prompt` as a single chat message and write the reply to `out`,
+/// token by token (or as one JSON object with `json`). Returns the reply.
+pub async fn ask(opts: AskOptions, cancel: CancellationToken, out: &mut (dyn Write + Send)) -> Result<String> {
+    let model = opts.model.unwrap_or_else(|| opts.provider.default_model().to_string());
+    let token = opts.api_token.or_else(|| load_api_token(opts.provider)).unwrap_or_default();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let request = stream_message(StreamRequest {
+        provider: opts.provider,
+        api_token: token,
+        custom_url: opts.custom_url,
+        auth: opts.auth.unwrap_or_else(|| opts.provider.auth_style()),
+        model: model.clone(),
+        max_tokens: opts.max_tokens,
+        system_prompt: opts.system_prompt,
+        history: vec![("user".to_string(), opts.prompt)],
+        tx,
+        cancel: cancel.clone(),
+        events: opts.event_log.as_ref().map(EventLog::sender),
+    });
+    // A log on stdout carries the reply in its `answer` record instead
+    let quiet = opts.event_log.as_ref().is_some_and(EventLog::is_stdout);
+    let json = opts.json;
+    let receive = async {
+        let mut reply = String::new();
+        while let Some(Some(token)) = rx.recv().await {
+            if !json && !quiet {
+                let _ = out.write_all(token.as_bytes());
+                let _ = out.flush();
+            }
+            reply.push_str(&token);
+        }
+        reply
+    };
+    let (sent, reply) = tokio::join!(request, receive);
+    if let Some(log) = &opts.event_log {
+        log.close().await;
+    }
+    sent?;
+    if cancel.is_cancelled() {
+        bail!("cancelled");
+    }
+    if quiet {
+        return Ok(reply);
+    }
+    if json {
+        let record = serde_json::json!({ "provider": opts.provider.name(), "model": model, "response": reply });
+        writeln!(out, "{record}")?;
+    } else if !reply.ends_with('\n') {
+        writeln!(out)?;
+    }
+    Ok(reply)
+}
+
+pub fn mcp_tools(servers: &McpServers) -> Result<()> {
+    for error in servers.errors() {
+        eprintln!("warning: {error}");
+    }
+    if servers.servers().is_empty() {
+        println!("No MCP servers running. Add them to the \"mcpServers\" object in mcp.json.");
+        return Ok(());
+    }
+    println!("MCP servers: {}", servers.servers().join(", "));
+    for tool in servers.tools() {
+        let access = if tool.mutating() { "" } else { " (read-only)" };
+        println!("- {}{access}: {}", tool.name(), tool.description());
+        println!("  input: {}", tool.input_format());
+    }
+    Ok(())
+}
+
+pub fn sessions_list(store: &SessionStore) -> Result<()> {
+    let sessions = store.list()?;
+    if sessions.is_empty() {
+        println!("No saved sessions in {}", store.dir().display());
+        return Ok(());
+    }
+    for s in sessions {
+        let mode = if s.agent_mode { "agent" } else { "chat" };
+        println!(
+            "{}  {}  {}/{}  {}  {} msgs  {}",
+            s.id, format_time(s.updated_at), s.provider.name(), s.model, mode, s.messages.len(), s.title,
+        );
+    }
+    Ok(())
+}
+
+pub fn sessions_show(store: &SessionStore, id: &str) -> Result<()> {
+    let s = store.load(id)?;
+    println!("Session: {}", s.id);
+    println!("Title: {}", s.title);
+    println!("Provider: {}", s.provider.label());
+    println!("Model: {}", s.model);
+    println!("Agent mode: {}", s.agent_mode);
+    println!("Created: {}", format_time(s.created_at));
+    println!("Updated: {}", format_time(s.updated_at));
+    for (role, content) in &s.messages {
+        let label = if role == "user" { "You" } else { "QA-Bot" };
+        println!("\n{label}:\n{}", content.trim_end());
+    }
+    Ok(())
+}
+
+pub fn sessions_delete(store: &SessionStore, id: &str) -> Result<()> {
+    let deleted = store.delete(id)?;
+    println!("Deleted session {deleted}");
+    Ok(())
+}
+
+/// Print the config files in the order they are merged, then every setting.
+pub fn config_show(config: &Config, files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        let missing = if file.exists() { "" } else { " (not found)" };
+        println!("# {}{missing}", file.display());
+    }
+    println!("# then QAI_* environment variables");
+    let entries = config.entries();
+    if entries.is_empty() {
+        println!("(nothing set; built-in defaults apply)");
+    }
+    for (key, value) in entries {
+        println!("{key} = {value}");
+    }
+    Ok(())
+}
@@ -0,0 +1,513 @@
+// Model clients, one per wire protocol: OpenAI-compatible chat completions,
+// the Anthropic Messages API and Ollama's `/api/chat`. The TUI chat, `ask`,
+// `serve` and the agent all reach models through `LlmClient`, so each
+// protocol's request body, headers and reply parsing exist in one place.
+
+use anyhow::{anyhow, bail, Result};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::agent::native::{self, NativeTurn, Wire};
+use crate::agent::registry::ToolSchema;
+use crate::tui::providers::{AuthStyle, Provider};
+
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Upper bound on a non-streaming reply. Streams have none: a slow local model
+/// may legitimately stream for minutes, so callers bound them by cancelling.
+const COMPLETE_TIMEOUT_SECS: u64 = 1800;
+/// Reply length cap on every wire when a request sets none (`max_tokens` in
+/// config.toml changes it). Anthropic rejects requests without one.
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+// ── Token usage ───────────────────────────────────────────────────────────────
+
+/// Token counts reported by the provider for one request.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Fold in a later report. Counts are cumulative within a stream
+    /// (Anthropic sends input and output in separate events), so keep the max.
+    pub fn merge(&mut self, other: TokenUsage) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+    }
+}
+
+/// Extract token usage from a response body or stream event, if present:
+/// OpenAI `usage.prompt_tokens/completion_tokens`, Anthropic
+/// `usage.input_tokens/output_tokens` (also nested in `message_start`), and
+/// Ollama `prompt_eval_count/eval_count`.
+pub fn usage_from_json(v: &Value) -> Option<TokenUsage> {
+    let count = |x: &Value| x.as_u64();
+    for usage in [&v["usage"], &v["message"]["usage"]] {
+        if usage.is_object() {
+            let input = count(&usage["prompt_tokens"]).or_else(|| count(&usage["input_tokens"]));
+            let output = count(&usage["completion_tokens"]).or_else(|| count(&usage["output_tokens"]));
+            if input.is_some() || output.is_some() {
+                return Some(TokenUsage {
+                    input_tokens: input.unwrap_or(0),
+                    output_tokens: output.unwrap_or(0),
+                });
+            }
+        }
+    }
+    let input = count(&v["prompt_eval_count"]);
+    let output = count(&v["eval_count"]);
+    if input.is_some() || output.is_some() {
+        return Some(TokenUsage { input_tokens: input.unwrap_or(0), output_tokens: output.unwrap_or(0) });
+    }
+    None
+}
+
+// ── Stream decoding ───────────────────────────────────────────────────────────
+
+/// One decoded piece of a streamed chat response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamChunk {
+    /// A text delta to append to the reply.
+    Delta(String),
+    /// Token usage reported by the server.
+    Usage(TokenUsage),
+    /// The server signalled the end of the reply.
+    Done,
+}
+
+/// Incremental decoder for streamed chat responses: Anthropic SSE (`delta.text`),
+/// OpenAI-compatible SSE (`choices[0].delta.content`) and Ollama NDJSON
+/// (`message.content`, `done`). Bytes are buffered until a full line arrives,
+/// so lines (and UTF-8 characters) split across network chunks decode correctly.
+#[derive(Debug, Default)]
+pub struct StreamDecoder {
+    buf: Vec<u8>,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed raw bytes; returns the chunks decoded from every completed line.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<StreamChunk> {
+        self.buf.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(nl) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=nl).collect();
+            decode_line(&String::from_utf8_lossy(&line), &mut out);
+        }
+        out
+    }
+
+    /// Decode whatever is left once the body ends without a trailing newline.
+    pub fn finish(&mut self) -> Vec<StreamChunk> {
+        let mut out = Vec::new();
+        let rest = std::mem::take(&mut self.buf);
+        decode_line(&String::from_utf8_lossy(&rest), &mut out);
+        out
+    }
+}
+
+fn decode_line(line: &str, out: &mut Vec<StreamChunk>) {
+    let line = line.trim();
+    // OpenAI / Anthropic SSE lines start with "data: "; Ollama NDJSON lines are bare JSON
+    let data = line.strip_prefix("data:").map(str::trim_start).unwrap_or(line);
+    if data.is_empty() {
+        return;
+    }
+    if data == "[DONE]" {
+        out.push(StreamChunk::Done);
+        return;
+    }
+    let Ok(v) = serde_json::from_str::<Value>(data) else { return };
+    let delta = v["delta"]["text"]
+        .as_str()
+        .or_else(|| v["choices"][0]["delta"]["content"].as_str())
+        .or_else(|| v["message"]["content"].as_str())
+        // Non-streaming bodies, in case a server ignores `"stream": true`
+        .or_else(|| v["choices"][0]["message"]["content"].as_str())
+        .or_else(|| v["content"][0]["text"].as_str());
+    if let Some(delta) = delta {
+        if !delta.is_empty() {
+            out.push(StreamChunk::Delta(delta.to_string()));
+        }
+    }
+    if let Some(usage) = usage_from_json(&v) {
+        out.push(StreamChunk::Usage(usage));
+    }
+    if v["done"].as_bool().unwrap_or(false) || v["type"] == "message_stop" {
+        out.push(StreamChunk::Done);
+    }
+}
+
+/// Decoded chunks of a streamed reply, ending at `Done` or the end of the body.
+pub type ChunkStream = BoxStream<'static, Result<StreamChunk>>;
+
+fn decode_body(resp: reqwest::Response) -> ChunkStream {
+    struct State<S> {
+        body: S,
+        decoder: StreamDecoder,
+        pending: VecDeque<StreamChunk>,
+        ended: bool,
+    }
+    let state = State { body: resp.bytes_stream(), decoder: StreamDecoder::new(), pending: VecDeque::new(), ended: false };
+    stream::unfold(state, |mut s| async move {
+        loop {
+            if let Some(chunk) = s.pending.pop_front() {
+                if chunk == StreamChunk::Done {
+                    s.ended = true;
+                    s.pending.clear();
+                }
+                return Some((Ok(chunk), s));
+            }
+            if s.ended {
+                return None;
+            }
+            match s.body.next().await {
+                None => {
+                    let rest = s.decoder.finish();
+                    s.pending.extend(rest);
+                    s.ended = true;
+                }
+                Some(Err(e)) => {
+                    s.ended = true;
+                    return Some((Err(e.into()), s));
+                }
+                Some(Ok(bytes)) => {
+                    let chunks = s.decoder.feed(&bytes);
+                    s.pending.extend(chunks);
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+// ── Endpoints ─────────────────────────────────────────────────────────────────
+
+/// Chat endpoint for `provider`, or for the base URL `custom_url` overrides it
+/// with. A base URL gets the provider's chat path (`/chat/completions`,
+/// `/messages`, Ollama's `/api/chat`) appended unless it already ends in it;
+/// a query string, like Azure OpenAI's `api-version`, is kept at the end.
+pub fn chat_url(provider: Provider, custom_url: &str) -> String {
+    let custom = custom_url.trim();
+    if custom.is_empty() {
+        return provider.api_url().to_string();
+    }
+    let (base, query) = match custom.split_once('?') {
+        Some((base, query)) => (base, Some(query)),
+        None => (custom, None),
+    };
+    let base = base.trim_end_matches('/');
+    let path = provider.chat_path();
+    let url = if base.ends_with(path) { base.to_string() } else { format!("{base}{path}") };
+    match query {
+        Some(query) => format!("{url}?{query}"),
+        None => url,
+    }
+}
+
+/// `chat_url`, or an error when there is no endpoint at all (Custom without a URL).
+pub fn endpoint(provider: Provider, custom_url: &str) -> Result<String> {
+    let url = chat_url(provider, custom_url);
+    if url.is_empty() {
+        bail!(
+            "the {} provider has no default endpoint; set providers.{}.url or pass --url",
+            provider.label(),
+            provider.name()
+        );
+    }
+    Ok(url)
+}
+
+/// Attach `token` the way `auth` says; an empty token sends no header.
+pub fn authorize(req: reqwest::RequestBuilder, auth: AuthStyle, token: &str) -> reqwest::RequestBuilder {
+    let token = token.trim();
+    if token.is_empty() {
+        return req;
+    }
+    match auth {
+        AuthStyle::Bearer => req.bearer_auth(token),
+        AuthStyle::ApiKey => req.header("api-key", token),
+        AuthStyle::XApiKey => req.header("x-api-key", token),
+        AuthStyle::None => req,
+    }
+}
+
+/// Where a client sends its requests and how it authenticates.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub url: String,
+    pub token: String,
+    pub auth: AuthStyle,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Endpoint { url: String::new(), token: String::new(), auth: AuthStyle::Bearer }
+    }
+}
+
+/// A non-success HTTP response, kept typed so callers can act on the status
+/// (the agent falls back to XML tools when a provider rejects them with 400/422).
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {}: {}", self.status, self.body.chars().take(300).collect::<String>())
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+// ── Requests ──────────────────────────────────────────────────────────────────
+
+/// One chat request, independent of the wire protocol that carries it.
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequest {
+    pub model: String,
+    pub system: String,
+    /// Conversation so far, already in the wire's message shape
+    /// (see `native::text_message`, `assistant_message`, `tool_result_messages`).
+    pub messages: Vec<Value>,
+    /// Tools offered for native function calling; empty sends none.
+    pub tools: Vec<ToolSchema>,
+    /// Reply length cap; `None` sends `DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
+}
+
+impl ChatRequest {
+    /// A plain-text conversation from `(role, content)` turns.
+    pub fn text(model: &str, system: &str, history: &[(String, String)]) -> Self {
+        ChatRequest {
+            model: model.to_string(),
+            system: system.to_string(),
+            messages: history.iter().map(|(role, content)| native::text_message(role, content)).collect(),
+            ..ChatRequest::default()
+        }
+    }
+}
+
+// ── Clients ───────────────────────────────────────────────────────────────────
+
+/// A model reached over one wire protocol. Implementations supply the body,
+/// extra headers and reply parsing; `complete` and `stream` do the HTTP.
+pub trait LlmClient: Send + Sync {
+    fn wire(&self) -> Wire;
+
+    fn endpoint(&self) -> &Endpoint;
+
+    /// Request body for `req`, with `"stream"` set to `stream`.
+    fn body(&self, req: &ChatRequest, stream: bool) -> Value;
+
+    /// Text, tool calls and usage from a non-streaming reply, or `None` when
+    /// the body is not a reply in this protocol.
+    fn parse(&self, reply: &Value) -> Option<NativeTurn>;
+
+    /// Headers the protocol needs besides auth.
+    fn headers(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        req
+    }
+
+    /// Send `req` and wait for the whole reply.
+    fn complete<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<NativeTurn>> {
+        Box::pin(async move {
+            let text = post(self, req, false).await?.text().await?;
+            serde_json::from_str::<Value>(&text)
+                .ok()
+                .and_then(|v| self.parse(&v))
+                .ok_or_else(|| anyhow!("unexpected response: {text}"))
+        })
+    }
+
+    /// Send `req` and decode the reply as it streams. Only text and usage are
+    /// streamed; tool calls need `complete`.
+    fn stream<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<ChunkStream>> {
+        Box::pin(async move { Ok(decode_body(post(self, req, true).await?)) })
+    }
+}
+
+async fn post<C: LlmClient + ?Sized>(client: &C, req: &ChatRequest, stream: bool) -> Result<reqwest::Response> {
+    let http = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .build()?;
+    let endpoint = client.endpoint();
+    let mut request = client.headers(http.post(&endpoint.url)).json(&client.body(req, stream));
+    if !stream {
+        request = request.timeout(Duration::from_secs(COMPLETE_TIMEOUT_SECS));
+    }
+    let resp = authorize(request, endpoint.auth, &endpoint.token).send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(HttpError { status, body }.into());
+    }
+    Ok(resp)
+}
+
+/// The client for `provider`: its wire protocol, at its endpoint or the base
+/// URL `custom_url` overrides it with, sending `token` the way `auth` says.
+pub fn client(provider: Provider, custom_url: &str, token: &str, auth: AuthStyle) -> Result<Box<dyn LlmClient>> {
+    let token = token.trim();
+    if token.is_empty() && provider.requires_token() && auth != AuthStyle::None {
+        bail!("API token is empty");
+    }
+    let endpoint = Endpoint { url: endpoint(provider, custom_url)?, token: token.to_string(), auth };
+    Ok(match Wire::for_provider(provider) {
+        Wire::OpenAi => Box::new(OpenAiChat { endpoint, stream_usage: provider.accepts_stream_options() }),
+        wire => for_wire(wire, endpoint),
+    })
+}
+
+/// The client speaking `wire` to `endpoint`, using only what every server
+/// speaking it accepts.
+pub fn for_wire(wire: Wire, endpoint: Endpoint) -> Box<dyn LlmClient> {
+    match wire {
+        Wire::OpenAi => Box::new(OpenAiChat { endpoint, stream_usage: false }),
+        Wire::Anthropic => Box::new(AnthropicMessages { endpoint }),
+        Wire::Ollama => Box::new(OllamaChat { endpoint }),
+    }
+}
+
+/// OpenAI-compatible `/chat/completions` (OpenAI, xAI, GitHub Models, Zen, Custom).
+pub struct OpenAiChat {
+    pub endpoint: Endpoint,
+    /// Ask for token usage on streams (`stream_options`), which only some
+    /// servers accept; see `Provider::accepts_stream_options`.
+    pub stream_usage: bool,
+}
+
+impl LlmClient for OpenAiChat {
+    fn wire(&self) -> Wire {
+        Wire::OpenAi
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    fn body(&self, req: &ChatRequest, stream: bool) -> Value {
+        let mut messages = vec![native::text_message("system", &req.system)];
+        messages.extend(req.messages.iter().cloned());
+        let mut body = json!({ "model": req.model, "messages": messages, "stream": stream });
+        if stream && self.stream_usage {
+            // Without this, OpenAI streams never report token usage
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+        if !req.tools.is_empty() {
+            body["tools"] = native::tools_payload(Wire::OpenAi, &req.tools);
+        }
+        body["max_tokens"] = json!(req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS));
+        body
+    }
+
+    fn parse(&self, reply: &Value) -> Option<NativeTurn> {
+        let msg = &reply["choices"][0]["message"];
+        if msg.is_null() {
+            return None;
+        }
+        Some(NativeTurn {
+            text: msg["content"].as_str().unwrap_or("").to_string(),
+            calls: native::parse_function_calls(&msg["tool_calls"]),
+            usage: usage_from_json(reply),
+        })
+    }
+}
+
+/// Anthropic Messages API: the system prompt is top-level and `max_tokens` is required.
+pub struct AnthropicMessages {
+    pub endpoint: Endpoint,
+}
+
+impl LlmClient for AnthropicMessages {
+    fn wire(&self) -> Wire {
+        Wire::Anthropic
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    fn body(&self, req: &ChatRequest, stream: bool) -> Value {
+        let mut body = json!({
+            "model": req.model,
+            "system": req.system,
+            "messages": req.messages,
+            "stream": stream,
+            "max_tokens": req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        });
+        if !req.tools.is_empty() {
+            body["tools"] = native::tools_payload(Wire::Anthropic, &req.tools);
+        }
+        body
+    }
+
+    fn parse(&self, reply: &Value) -> Option<NativeTurn> {
+        let blocks = reply["content"].as_array()?;
+        let mut turn = NativeTurn { usage: usage_from_json(reply), ..NativeTurn::default() };
+        for block in blocks {
+            match block["type"].as_str() {
+                Some("text") => turn.text.push_str(block["text"].as_str().unwrap_or("")),
+                Some("tool_use") => turn.calls.push(native::NativeToolCall {
+                    id: block["id"].as_str().unwrap_or("").to_string(),
+                    name: block["name"].as_str().unwrap_or("").to_string(),
+                    arguments: block["input"].clone(),
+                }),
+                _ => {}
+            }
+        }
+        Some(turn)
+    }
+
+    fn headers(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        req.header("anthropic-version", "2023-06-01")
+    }
+}
+
+/// Ollama `/api/chat`: NDJSON streaming, and the length cap goes in `options.num_predict`.
+pub struct OllamaChat {
+    pub endpoint: Endpoint,
+}
+
+impl LlmClient for OllamaChat {
+    fn wire(&self) -> Wire {
+        Wire::Ollama
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    fn body(&self, req: &ChatRequest, stream: bool) -> Value {
+        let mut messages = vec![native::text_message("system", &req.system)];
+        messages.extend(req.messages.iter().cloned());
+        let mut body = json!({ "model": req.model, "messages": messages, "stream": stream });
+        if !req.tools.is_empty() {
+            body["tools"] = native::tools_payload(Wire::Ollama, &req.tools);
+        }
+        body["options"] = json!({ "num_predict": req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) });
+        body
+    }
+
+    fn parse(&self, reply: &Value) -> Option<NativeTurn> {
+        let msg = &reply["message"];
+        if msg.is_null() {
+            return None;
+        }
+        Some(NativeTurn {
+            text: msg["content"].as_str().unwrap_or("").to_string(),
+            calls: native::parse_function_calls(&msg["tool_calls"]),
+            usage: usage_from_json(reply),
+        })
+    }
+}
@@ -1,8 +1,21 @@
-use qai_cli::{copy, info, show, tools, validate};
+use qai_cli::agent::pr_review::ReviewTarget;
+use qai_cli::config::{global_config_path, global_only, project_config_path, Config, ConfigFile};
+use qai_cli::agent::approval::ApprovalPolicy;
+use qai_cli::agent::{mcp, EventLog, McpServers, Workspace};
+use qai_cli::mcp_server::{serve_mcp, McpServerOptions};
+use qai_cli::secrets::{self, SecretStore};
+use qai_cli::serve::{serve, ServeOptions};
+use qai_cli::sessions::SessionStore;
+use qai_cli::{
+    ask, build_ask_prompt, copy, info, mcp_tools, read_prompt, read_task, review, run_task, sessions_delete, sessions_list,
+    sessions_show, show, tools, validate, config_show, AskOptions, Provider, ReviewOptions, RunOptions,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Parser)]
 #[command(name = "qai-cli", version, about = "Manage the QAI agent prompt")]
@@ -13,6 +26,18 @@ struct Cli {
     /// Skip the TUI and run a subcommand directly
     #[arg(long)]
     no_tui: bool,
+    /// Directory the agent's file tools are confined to (defaults to the current directory)
+    #[arg(long)]
+    workspace: Option<PathBuf>,
+    /// Extra directory the agent may read but not write (repeatable)
+    #[arg(long = "read-only-root")]
+    read_only_roots: Vec<PathBuf>,
+    /// Also write chat and agent events as JSON lines to this file (`-` = stdout)
+    #[arg(long, global = true, value_name = "PATH|-")]
+    events_jsonl: Option<String>,
+    /// MCP server config (defaults to mcp.json in the qai config directory)
+    #[arg(long, global = true, value_name = "PATH")]
+    mcp_config: Option<PathBuf>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -35,11 +60,261 @@ enum Commands {
     Validate,
     /// Print the expected tool categories for QA-Bot
     Tools,
+    /// Review a local git diff with the LLM and print findings
+    Review {
+        /// Git range to review, e.g. `main..HEAD` (ignored when --base is given)
+        range: Option<String>,
+        /// Base branch of a branch pair (reviews `base...head`)
+        #[arg(long)]
+        base: Option<String>,
+        /// Head branch of a branch pair (defaults to HEAD)
+        #[arg(long, default_value = "HEAD")]
+        head: String,
+        /// LLM provider: openai, anthropic, xai, ollama, github, zen, custom (defaults to the configured one, else ollama)
+        #[arg(long, value_parser = parse_provider)]
+        provider: Option<Provider>,
+        /// Model name (defaults to the configured model, else the provider's default)
+        #[arg(long)]
+        model: Option<String>,
+        /// API token (defaults to the configured token)
+        #[arg(long)]
+        api_token: Option<String>,
+        /// Base URL overriding the provider's endpoint (defaults to the configured URL)
+        #[arg(long)]
+        url: Option<String>,
+        /// Repository to review (defaults to the current directory)
+        #[arg(long, default_value = "")]
+        repo: String,
+        /// Print findings as JSON
+        #[arg(long)]
+        json: bool,
+        /// Write findings to a file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Only review these paths
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+    /// Run the agent on a task without the TUI; the answer goes to stdout
+    Run {
+        /// The task (read from stdin when omitted or `-`)
+        task: Option<String>,
+        /// LLM provider: openai, anthropic, xai, ollama, github, zen, custom (defaults to the configured one, else ollama)
+        #[arg(long, value_parser = parse_provider)]
+        provider: Option<Provider>,
+        /// Model name (defaults to the configured model, else the provider's default)
+        #[arg(long)]
+        model: Option<String>,
+        /// API token (defaults to the configured token)
+        #[arg(long)]
+        api_token: Option<String>,
+        /// Base URL overriding the provider's endpoint (defaults to the configured URL)
+        #[arg(long)]
+        url: Option<String>,
+        /// Use the provider's native function calling instead of XML tool tags
+        #[arg(long)]
+        native_tools: bool,
+        /// Maximum number of agent steps
+        #[arg(long)]
+        max_steps: Option<usize>,
+        /// Maximum consecutive nudges for think-only or malformed tool-call replies
+        #[arg(long)]
+        max_format_retries: Option<usize>,
+        /// Time budget for the whole run, in seconds
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Run mutating tools, which the approval policy would otherwise refuse with no one to ask
+        #[arg(long)]
+        allow_mutating: bool,
+    },
+    /// Ask the model one question and stream the reply to stdout
+    Ask {
+        /// The question (piped stdin is appended to it)
+        prompt: Option<String>,
+        /// Attach a file to the question (repeatable)
+        #[arg(long = "file", short = 'f')]
+        files: Vec<PathBuf>,
+        /// System prompt to use instead of the QA-Bot prompt
+        #[arg(long)]
+        system: Option<String>,
+        /// Print the reply as one JSON object once it is complete
+        #[arg(long)]
+        json: bool,
+        /// LLM provider: openai, anthropic, xai, ollama, github, zen, custom (defaults to the configured one, else ollama)
+        #[arg(long, value_parser = parse_provider)]
+        provider: Option<Provider>,
+        /// Model name (defaults to the configured model, else the provider's default)
+        #[arg(long)]
+        model: Option<String>,
+        /// API token (defaults to the configured token)
+        #[arg(long)]
+        api_token: Option<String>,
+        /// Base URL overriding the provider's endpoint (defaults to the configured URL)
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Start the configured MCP servers and list their tools
+    McpTools,
+    /// Serve the agent's built-in tools to other MCP clients over stdio
+    McpServer {
+        /// Run mutating tools without asking the client to confirm each call
+        #[arg(long)]
+        allow_mutating: bool,
+    },
+    /// Serve chat, agent runs, models and sessions as JSON-RPC over stdio (for IDE plugins)
+    Serve,
+    /// List, show, resume or delete saved conversations
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+    /// Show or edit config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Store, delete or migrate API tokens in the secret store
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretsAction {
+    /// Store a provider's API token, read from stdin
+    Set {
+        #[arg(value_parser = parse_provider)]
+        provider: Provider,
+    },
+    /// Delete a provider's stored API token
+    Delete {
+        #[arg(value_parser = parse_provider)]
+        provider: Provider,
+    },
+    /// Move API tokens out of the global config.toml into the secret store
+    Migrate,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the effective settings and the files they came from
+    Show,
+    /// Set a key, e.g. `agent.max_steps 30` or `providers.openai.model gpt-4o-mini`
+    Set {
+        /// Dotted key
+        key: String,
+        value: String,
+        /// Write the project's .qai/config.toml instead of the global file
+        #[arg(long)]
+        project: bool,
+    },
+    /// Remove a key
+    Unset {
+        /// Dotted key
+        key: String,
+        /// Edit the project's .qai/config.toml instead of the global file
+        #[arg(long)]
+        project: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionsAction {
+    /// List saved sessions, most recent first
+    List,
+    /// Print a saved session's conversation
+    Show {
+        /// Session id (or a unique prefix of it)
+        id: String,
+    },
+    /// Reopen a saved session in the TUI
+    Resume {
+        /// Session id (or a unique prefix of it)
+        id: String,
+    },
+    /// Delete a saved session
+    Delete {
+        /// Session id (or a unique prefix of it)
+        id: String,
+    },
+}
+
+fn parse_provider(name: &str) -> Result<Provider, String> {
+    Provider::from_name(name).ok_or_else(|| format!("unknown provider: {name}"))
+}
+
+/// The file `config set`/`unset` edit: the project's, or the global one.
+fn config_file(project: bool, ws: &Workspace) -> Result<ConfigFile> {
+    let path = if project {
+        project_config_path(ws.root())
+    } else {
+        global_config_path().context("no config directory on this platform")?
+    };
+    ConfigFile::open(&path)
+}
+
+/// The secret store the global config (and `QAI_SECRETS_BACKEND`) selects.
+fn secret_store() -> Result<(Box<dyn SecretStore>, Option<PathBuf>)> {
+    let path = global_config_path();
+    let config = Config::layered(path.as_slice(), None, |name| std::env::var(name).ok())?;
+    let store = secrets::store_for(&config).context("no config directory on this platform")?;
+    Ok((store, path))
+}
+
+fn workspace(root: Option<PathBuf>, read_only_roots: Vec<PathBuf>) -> Workspace {
+    let ws = root.map(Workspace::new).unwrap_or_else(Workspace::launch_dir);
+    read_only_roots.into_iter().fold(ws, Workspace::with_read_only_root)
+}
+
+/// Start the MCP servers in `config`, or in the default config file if it exists.
+async fn mcp_servers(config: Option<&Path>, ws: &Workspace) -> Result<McpServers> {
+    let configs = match config {
+        Some(path) if !path.exists() => bail!("no MCP config at {}", path.display()),
+        Some(path) => mcp::load_config(path)?,
+        None => mcp::default_config_path().map(|p| mcp::load_config(&p)).transpose()?.unwrap_or_default(),
+    };
+    Ok(McpServers::start(&configs, ws.root()).await)
+}
+
+/// Print why servers failed; the command still runs with the rest.
+fn warn_mcp_errors(servers: &McpServers) {
+    for error in servers.errors() {
+        eprintln!("warning: {error}");
+    }
+}
+
+/// A token cancelled by Ctrl+C, so the command can stop and exit non-zero.
+fn cancel_on_interrupt() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let on_interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_interrupt.cancel();
+        }
+    });
+    cancel
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let event_log = cli.events_jsonl.as_deref().map(EventLog::open).transpose()?;
+    let result = dispatch(cli, event_log.clone()).await;
+    // Every command ends the log with its summary record, even one that ran nothing
+    if let Some(log) = &event_log {
+        log.close().await;
+    }
+    result
+}
+
+async fn dispatch(cli: Cli, event_log: Option<EventLog>) -> Result<()> {
+    let tui_event_log = || -> Result<Option<EventLog>> {
+        match &event_log {
+            Some(log) if log.is_stdout() => bail!("the TUI draws on stdout; give --events-jsonl a file path"),
+            other => Ok(other.clone()),
+        }
+    };
 
     // If a subcommand is given or --no-tui is set, run in CLI mode
     if cli.no_tui || cli.command.is_some() {
@@ -49,12 +324,212 @@ async fn main() -> Result<()> {
             Some(Commands::Copy { dest, force }) => copy(&cli.prompt, dest, force),
             Some(Commands::Validate) => validate(&cli.prompt),
             Some(Commands::Tools) => tools(),
+            Some(Commands::Review {
+                range, base, head, provider, model, api_token, url, repo, json, output, paths,
+            }) => {
+                let target = match base {
+                    Some(base) => ReviewTarget::Branches { base, head },
+                    None => ReviewTarget::Range(range.unwrap_or_else(|| "HEAD".to_string())),
+                };
+                let config = Config::load(workspace(cli.workspace, cli.read_only_roots).root())?;
+                let llm = config.llm(provider, model, api_token, url);
+                review(ReviewOptions {
+                    target, provider: llm.provider, model: Some(llm.model), api_token: Some(llm.api_token),
+                    custom_url: llm.url, auth: Some(llm.auth), max_tokens: llm.max_tokens, repo, paths, json,
+                    output,
+                })
+                .await
+            }
+            Some(Commands::Run {
+                task, provider, model, api_token, url, native_tools, max_steps, max_format_retries, timeout,
+                allow_mutating,
+            }) => {
+                if task.as_deref().is_none_or(|t| t == "-") && io::stdin().is_terminal() {
+                    bail!("no task given; pass it as an argument or pipe it on stdin");
+                }
+                let task = read_task(task, io::stdin())?;
+                let system_prompt = read_prompt(&cli.prompt).unwrap_or_else(|_| {
+                    eprintln!("note: {} not found; running without the QA-Bot system prompt", cli.prompt.display());
+                    String::new()
+                });
+                let ws = workspace(cli.workspace, cli.read_only_roots);
+                let config = Config::load(ws.root())?;
+                let llm = config.llm(provider, model, api_token, url);
+                let mcp = mcp_servers(cli.mcp_config.as_deref(), &ws).await?;
+                warn_mcp_errors(&mcp);
+                let cancel = cancel_on_interrupt();
+                let opts = RunOptions {
+                    task,
+                    provider: llm.provider,
+                    model: Some(llm.model),
+                    api_token: Some(llm.api_token),
+                    custom_url: llm.url,
+                    auth: Some(llm.auth),
+                    max_tokens: llm.max_tokens,
+                    system_prompt,
+                    workspace: ws,
+                    native_tools: native_tools || config.agent.native_tools == Some(true),
+                    max_steps: max_steps.or(config.agent.max_steps),
+                    max_format_retries: max_format_retries.or(config.agent.max_format_retries),
+                    timeout_secs: timeout.or(config.agent.timeout),
+                    event_log: event_log.clone(),
+                    mcp,
+                    approval: if allow_mutating { ApprovalPolicy::allow_all() } else { config.approval_policy() },
+                };
+                let answer = run_task(opts, cancel, &mut io::stderr()).await?;
+                // A log on stdout carries the answer in its `answer` record
+                if !event_log.as_ref().is_some_and(EventLog::is_stdout) {
+                    println!("{answer}");
+                }
+                Ok(())
+            }
+            Some(Commands::Ask { prompt, files, system, json, provider, model, api_token, url }) => {
+                let stdin = if io::stdin().is_terminal() {
+                    None
+                } else {
+                    let mut piped = String::new();
+                    io::stdin().read_to_string(&mut piped)?;
+                    Some(piped)
+                };
+                let prompt = build_ask_prompt(prompt, stdin, &files)?;
+                let system_prompt = system.unwrap_or_else(|| read_prompt(&cli.prompt).unwrap_or_default());
+                let config = Config::load(workspace(cli.workspace, cli.read_only_roots).root())?;
+                let llm = config.llm(provider, model, api_token, url);
+                let opts = AskOptions {
+                    prompt, provider: llm.provider, model: Some(llm.model), api_token: Some(llm.api_token),
+                    custom_url: llm.url, auth: Some(llm.auth), max_tokens: llm.max_tokens, system_prompt, json,
+                    event_log: event_log.clone(),
+                };
+                ask(opts, cancel_on_interrupt(), &mut io::stdout()).await.map(|_| ())
+            }
+            Some(Commands::McpTools) => {
+                let ws = workspace(cli.workspace, cli.read_only_roots);
+                mcp_tools(&mcp_servers(cli.mcp_config.as_deref(), &ws).await?)
+            }
+            Some(Commands::McpServer { allow_mutating }) => {
+                if event_log.as_ref().is_some_and(EventLog::is_stdout) {
+                    bail!("mcp-server answers on stdout; give --events-jsonl a file path");
+                }
+                let ws = workspace(cli.workspace, cli.read_only_roots);
+                let approval = if allow_mutating { ApprovalPolicy::allow_all() } else { Config::load(ws.root())?.approval_policy() };
+                let opts = McpServerOptions { workspace: ws, approval };
+                let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+                serve_mcp(stdin, tokio::io::stdout(), opts).await
+            }
+            Some(Commands::Serve) => {
+                if event_log.as_ref().is_some_and(EventLog::is_stdout) {
+                    bail!("serve answers on stdout; give --events-jsonl a file path");
+                }
+                let ws = workspace(cli.workspace, cli.read_only_roots);
+                let config = Config::load(ws.root())?;
+                let mcp = mcp_servers(cli.mcp_config.as_deref(), &ws).await?;
+                warn_mcp_errors(&mcp);
+                let opts = ServeOptions {
+                    system_prompt: read_prompt(&cli.prompt).unwrap_or_default(),
+                    workspace: ws,
+                    sessions: SessionStore::open_default().ok(),
+                    event_log: event_log.clone(),
+                    mcp,
+                    config,
+                };
+                let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+                serve(stdin, tokio::io::stdout(), opts).await
+            }
+            Some(Commands::Sessions { action }) => {
+                let store = SessionStore::open_default()?;
+                match action {
+                    SessionsAction::List => sessions_list(&store),
+                    SessionsAction::Show { id } => sessions_show(&store, &id),
+                    SessionsAction::Delete { id } => sessions_delete(&store, &id),
+                    SessionsAction::Resume { id } => {
+                        let session = store.load(&id)?;
+                        let ws = workspace(cli.workspace, cli.read_only_roots);
+                        let config = Config::load(ws.root())?;
+                        let mcp = mcp_servers(cli.mcp_config.as_deref(), &ws).await?;
+                        qai_cli::tui::run(cli.prompt, ws, Some(session), tui_event_log()?, mcp, config).await
+                    }
+                }
+            }
+            Some(Commands::Config { action }) => {
+                let ws = workspace(cli.workspace, cli.read_only_roots);
+                match action {
+                    ConfigAction::Show => {
+                        let mut files: Vec<PathBuf> = global_config_path().into_iter().collect();
+                        files.push(project_config_path(ws.root()));
+                        config_show(&Config::load(ws.root())?, &files)
+                    }
+                    ConfigAction::Set { key, value, project } => {
+                        if project && global_only(&key) {
+                            bail!("{key} is only read from the global config file, not the project's");
+                        }
+                        let mut file = config_file(project, &ws)?;
+                        if key.ends_with("api_token") && !secret_store()?.0.is_plaintext() {
+                            bail!(
+                                "API tokens belong in the secret store: use `qai-cli secrets set <provider>`, \
+                                 or `config set secrets.backend plaintext` to keep them in config.toml"
+                            );
+                        }
+                        file.set(&key, &value)?;
+                        file.save()?;
+                        println!("Set {key} in {}", file.path().display());
+                        Ok(())
+                    }
+                    ConfigAction::Unset { key, project } => {
+                        let mut file = config_file(project, &ws)?;
+                        if !file.remove(&key) {
+                            bail!("{key} is not set in {}", file.path().display());
+                        }
+                        file.save()?;
+                        println!("Removed {key} from {}", file.path().display());
+                        Ok(())
+                    }
+                }
+            }
+            Some(Commands::Secrets { action }) => {
+                let (store, config_path) = secret_store()?;
+                match action {
+                    SecretsAction::Set { provider } => {
+                        if io::stdin().is_terminal() {
+                            eprint!("API token for {}: ", provider.label());
+                        }
+                        let mut token = String::new();
+                        io::stdin().read_line(&mut token)?;
+                        if token.trim().is_empty() {
+                            bail!("no token given on stdin");
+                        }
+                        secrets::save_token(store.as_ref(), config_path.as_deref(), provider, &token)?;
+                        println!("Saved the {} token in {}", provider.name(), store.describe());
+                        Ok(())
+                    }
+                    SecretsAction::Delete { provider } => {
+                        if !store.delete(provider.name())? {
+                            bail!("no {} token in {}", provider.name(), store.describe());
+                        }
+                        println!("Deleted the {} token from {}", provider.name(), store.describe());
+                        Ok(())
+                    }
+                    SecretsAction::Migrate => {
+                        let path = config_path.context("no config directory on this platform")?;
+                        let moved = secrets::migrate_plaintext(store.as_ref(), &path)?;
+                        if moved.is_empty() {
+                            println!("No provider tokens in {}", path.display());
+                        }
+                        for provider in moved {
+                            println!("Moved the {} token to {}", provider.name(), store.describe());
+                        }
+                        Ok(())
+                    }
+                }
+            }
             None => {
                 eprintln!("No subcommand given. Run without --no-tui to launch the TUI.");
                 Ok(())
             }
         }
     } else {
-        qai_cli::tui::run(cli.prompt).await
+        let ws = workspace(cli.workspace, cli.read_only_roots);
+        let config = Config::load(ws.root())?;
+        let mcp = mcp_servers(cli.mcp_config.as_deref(), &ws).await?;
+        qai_cli::tui::run(cli.prompt, ws, None, tui_event_log()?, mcp, config).await
     }
 }
@@ -0,0 +1,273 @@
+// `qai-cli mcp-server`: the built-in agent tools served over stdio MCP, so
+// other agents and editors can reuse them. File and git tools stay confined to
+// the workspace (git runs in its root and takes no options from the caller;
+// `shell` only starts there), and mutating tools follow the approval policy:
+// under the default "ask", the client is asked through MCP elicitation before
+// the tool runs, and clients that cannot be asked get a refusal instead.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::agent::approval::{Approval, ApprovalPolicy};
+use crate::agent::tools::ToolContext;
+use crate::agent::{Tool, ToolRegistry, Workspace};
+use crate::serve::write_messages;
+
+/// Protocol revisions this server speaks, oldest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+/// Built-ins that steer the agent loop rather than do anything.
+const AGENT_ONLY_TOOLS: &[&str] = &["answer", "submit"];
+
+pub struct McpServerOptions {
+    /// Directory the file and git tools are confined to; also where `shell` starts.
+    pub workspace: Workspace,
+    /// Which tools run, need the client's confirmation, or are refused.
+    pub approval: ApprovalPolicy,
+}
+
+struct Server {
+    tools: ToolRegistry,
+    approval: ApprovalPolicy,
+    out: mpsc::UnboundedSender<Value>,
+    /// Set by `initialize` when the client can show confirmation prompts.
+    elicitation: AtomicBool,
+    /// Tool calls in flight, keyed by their JSON id, for `notifications/cancelled`.
+    running: Mutex<HashMap<String, CancellationToken>>,
+    /// Our requests to the client, waiting for its answer.
+    pending: Mutex<HashMap<u64, oneshot::Sender<Value>>>,
+    next_id: AtomicU64,
+    input_closed: AtomicBool,
+}
+
+/// Serve the built-in tools to the MCP client on `input`/`output` until the
+/// input ends. Calls run concurrently; the client's answers to confirmation
+/// prompts arrive on the same input.
+pub async fn serve_mcp<R, W>(input: R, output: W, opts: McpServerOptions) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (out, messages) = mpsc::unbounded_channel();
+    let writer = tokio::spawn(write_messages(output, messages));
+    let server = Arc::new(Server {
+        tools: exposed_tools(opts.workspace),
+        approval: opts.approval,
+        out,
+        elicitation: AtomicBool::new(false),
+        running: Mutex::default(),
+        pending: Mutex::default(),
+        next_id: AtomicU64::new(1),
+        input_closed: AtomicBool::new(false),
+    });
+
+    let mut calls = JoinSet::new();
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await.context("Failed to read a request")? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            server.error(Value::Null, -32700, "parse error");
+            continue;
+        };
+        let id = message.get("id").cloned();
+        let Some(method) = message["method"].as_str() else {
+            // The client's answer to one of our requests
+            if let Some(reply) = id.and_then(|id| id.as_u64()).and_then(|id| server.lock_pending().remove(&id)) {
+                let _ = reply.send(message);
+            }
+            continue;
+        };
+        let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+        match (method, id) {
+            ("tools/call", Some(id)) => {
+                let cancel = CancellationToken::new();
+                server.lock_running().insert(id.to_string(), cancel.clone());
+                let server = server.clone();
+                calls.spawn(async move {
+                    let result = tokio::select! {
+                        result = server.call_tool(&params) => Some(result),
+                        _ = cancel.cancelled() => None,
+                    };
+                    server.lock_running().remove(&id.to_string());
+                    // A cancelled call gets no response
+                    if let Some(result) = result {
+                        server.result(id, result);
+                    }
+                });
+                while calls.try_join_next().is_some() {}
+            }
+            ("notifications/cancelled", None) => {
+                let request = params["requestId"].to_string();
+                if let Some(cancel) = server.lock_running().get(&request) {
+                    cancel.cancel();
+                }
+            }
+            (method, Some(id)) => match server.handle(method, &params) {
+                Some(result) => server.result(id, result),
+                None => server.error(id, -32601, &format!("unknown method '{method}'")),
+            },
+            // Other notifications (`notifications/initialized`, …) need nothing
+            (_, None) => {}
+        }
+    }
+
+    // No one is left to confirm anything; dropping the prompts declines them
+    server.input_closed.store(true, Ordering::Relaxed);
+    server.lock_pending().clear();
+    while calls.join_next().await.is_some() {}
+    drop(server);
+    writer.await.context("the response writer panicked")?
+}
+
+/// The built-in tools minus the agent-loop controls.
+fn exposed_tools(workspace: Workspace) -> ToolRegistry {
+    let mut tools = ToolRegistry::new();
+    for tool in ToolRegistry::builtin_with(ToolContext::new(workspace)).iter() {
+        if !AGENT_ONLY_TOOLS.contains(&tool.name()) {
+            tools.register_arc(tool.clone());
+        }
+    }
+    tools
+}
+
+impl Server {
+    fn lock_running(&self) -> MutexGuard<'_, HashMap<String, CancellationToken>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_pending(&self) -> MutexGuard<'_, HashMap<u64, oneshot::Sender<Value>>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn result(&self, id: Value, result: Value) {
+        let _ = self.out.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+    }
+
+    fn error(&self, id: Value, code: i64, message: &str) {
+        let _ = self.out.send(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }));
+    }
+
+    /// Answer everything but `tools/call`; `None` for unknown methods.
+    fn handle(&self, method: &str, params: &Value) -> Option<Value> {
+        match method {
+            "initialize" => {
+                self.elicitation.store(params["capabilities"]["elicitation"].is_object(), Ordering::Relaxed);
+                let asked = params["protocolVersion"].as_str().unwrap_or("");
+                let version = PROTOCOL_VERSIONS.iter().find(|v| **v == asked).or(PROTOCOL_VERSIONS.last());
+                Some(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": { "listChanged": false } },
+                    "serverInfo": { "name": "qai-cli", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "ping" => Some(json!({})),
+            "tools/list" => Some(json!({ "tools": self.tools.iter().map(|t| tool_json(t.as_ref())).collect::<Vec<_>>() })),
+            _ => None,
+        }
+    }
+
+    async fn call_tool(&self, params: &Value) -> Value {
+        let name = params["name"].as_str().unwrap_or("");
+        let Some(tool) = self.tools.get(name) else {
+            return tool_result(&format!("[unknown tool: {name}]"), true);
+        };
+        let args = match &params["arguments"] {
+            Value::Null => json!({}),
+            args => args.clone(),
+        };
+        let input = tool.input_from_args(&args);
+        match self.approval.for_tool(tool.as_ref()) {
+            Approval::Allow => {}
+            Approval::Deny => return tool_result(&format!("[denied: the approval policy does not allow `{name}`]"), true),
+            Approval::Ask => {
+                if let Err(refusal) = self.confirm(tool.as_ref(), &input).await {
+                    return tool_result(&refusal, true);
+                }
+            }
+        }
+        match tool.execute(&input).await {
+            Ok(output) => tool_result(&output, is_tool_error(&output)),
+            Err(e) => tool_result(&format!("[{name} error: {e:#}]"), true),
+        }
+    }
+
+    /// Ask the client to confirm a call; `Err` carries the refusal the caller sees.
+    async fn confirm(&self, tool: &dyn Tool, input: &str) -> Result<(), String> {
+        let name = tool.name();
+        if !self.elicitation.load(Ordering::Relaxed) {
+            return Err(format!(
+                "[denied: `{name}` changes files or runs commands and needs confirmation, which this client cannot \
+                 give; restart the server with --allow-mutating to run it without asking]"
+            ));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply, answer) = oneshot::channel();
+        {
+            // Checked under the lock, so `serve_mcp` can't clear the map in between
+            let mut pending = self.lock_pending();
+            if self.input_closed.load(Ordering::Relaxed) {
+                return Err(rejected(name));
+            }
+            pending.insert(id, reply);
+        }
+        let _ = self.out.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "elicitation/create",
+            "params": {
+                "message": format!("Allow QAI to run `{name}`?\n\n{}", tool.preview(input)),
+                "requestedSchema": { "type": "object", "properties": {} },
+            },
+        }));
+        match answer.await {
+            Ok(message) if message["result"]["action"] == "accept" => Ok(()),
+            _ => Err(rejected(name)),
+        }
+    }
+}
+
+fn rejected(name: &str) -> String {
+    format!("[rejected by user: `{name}` was not run]")
+}
+
+fn tool_json(tool: &dyn Tool) -> Value {
+    json!({
+        "name": tool.name(),
+        "description": format!("{}. Input: {}", capitalize(tool.description()), tool.input_format()),
+        "inputSchema": tool.input_schema(),
+        "annotations": {
+            "readOnlyHint": !tool.mutating(),
+            "destructiveHint": tool.mutating(),
+            "openWorldHint": tool.name() == "web_search",
+        },
+    })
+}
+
+fn tool_result(text: &str, is_error: bool) -> Value {
+    json!({ "content": [{ "type": "text", "text": text }], "isError": is_error })
+}
+
+/// Built-ins report failures as `[<tool> error: …]` observations.
+fn is_tool_error(output: &str) -> bool {
+    output
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(' '))
+        .is_some_and(|(_, rest)| rest.starts_with("error"))
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,420 @@
+// Where API tokens are kept.
+// The default store is the OS keyring (Secret Service on Linux, Keychain on
+// macOS, Credential Manager on Windows). When no keyring answers, tokens go to
+// `secrets.enc` in the qai config dir, encrypted with ChaCha20-Poly1305 under
+// a random key in `secrets.key` (0600) or, if `QAI_SECRETS_PASSPHRASE` is set,
+// a key derived from it with Argon2. Plaintext in config.toml is only used
+// when `secrets.backend = "plaintext"`. A provider's env var (`OPENAI_API_KEY`,
+// …) always wins over anything stored.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ConfigFile};
+use crate::tui::providers::Provider;
+
+/// Keyring service name the tokens are filed under.
+const KEYRING_SERVICE: &str = "qai-cli";
+
+pub fn default_secrets_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("qai"))
+}
+
+// ── Backends ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretBackend {
+    Keyring,
+    /// The encrypted `secrets.enc`.
+    File,
+    /// `[providers.<name>] api_token` in config.toml; needs explicit opt-in.
+    Plaintext,
+}
+
+impl std::str::FromStr for SecretBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keyring" => Ok(SecretBackend::Keyring),
+            "file" => Ok(SecretBackend::File),
+            "plaintext" => Ok(SecretBackend::Plaintext),
+            other => Err(format!("unknown secrets backend '{other}' (expected keyring, file or plaintext)")),
+        }
+    }
+}
+
+impl SecretBackend {
+    pub fn name(&self) -> &str {
+        match self {
+            SecretBackend::Keyring => "keyring",
+            SecretBackend::File => "file",
+            SecretBackend::Plaintext => "plaintext",
+        }
+    }
+}
+
+/// A place to keep secrets, one per account name (the provider's short name).
+pub trait SecretStore: Send + Sync {
+    /// Shown to the user, e.g. "the system keyring".
+    fn describe(&self) -> String;
+    fn get(&self, account: &str) -> Result<Option<String>>;
+    fn set(&self, account: &str, secret: &str) -> Result<()>;
+    /// Returns whether there was anything to delete.
+    fn delete(&self, account: &str) -> Result<bool>;
+    /// Whether secrets end up readable on disk.
+    fn is_plaintext(&self) -> bool {
+        false
+    }
+}
+
+/// The store `backend` names, rooted in `dir` (the qai config dir). The
+/// keyring is the default and falls back to the encrypted file when no
+/// keyring service answers.
+pub fn open_store(backend: Option<SecretBackend>, dir: &Path) -> Box<dyn SecretStore> {
+    let file = || {
+        let passphrase = std::env::var("QAI_SECRETS_PASSPHRASE").ok().filter(|p| !p.is_empty());
+        Box::new(EncryptedFileStore::new(dir).with_passphrase(passphrase))
+    };
+    match backend.unwrap_or(SecretBackend::Keyring) {
+        SecretBackend::Keyring => {
+            let keyring = KeyringStore::new(KEYRING_SERVICE);
+            if keyring.available() { Box::new(keyring) } else { file() }
+        }
+        SecretBackend::File => file(),
+        SecretBackend::Plaintext => Box::new(PlaintextStore::new(dir.join("config.toml"))),
+    }
+}
+
+/// The store `config` selects, in the default qai config dir.
+pub fn store_for(config: &Config) -> Option<Box<dyn SecretStore>> {
+    Some(open_store(config.secrets.backend, &default_secrets_dir()?))
+}
+
+// ── Keyring ───────────────────────────────────────────────────────────────────
+
+pub struct KeyringStore {
+    service: String,
+}
+
+impl KeyringStore {
+    pub fn new(service: &str) -> Self {
+        Self { service: service.to_string() }
+    }
+
+    /// Whether a keyring service answers at all.
+    pub fn available(&self) -> bool {
+        self.get("__probe__").is_ok()
+    }
+
+    /// Run a keyring call on its own thread: the Secret Service client blocks
+    /// on its own executor, which must not happen on a runtime thread.
+    fn with_entry<T: Send>(
+        &self,
+        account: &str,
+        call: impl FnOnce(&keyring::Entry) -> keyring::Result<T> + Send,
+    ) -> keyring::Result<T> {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| call(&keyring::Entry::new(&self.service, account)?))
+                .join()
+                .unwrap_or_else(|_| Err(keyring::Error::PlatformFailure("keyring call panicked".into())))
+        })
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn describe(&self) -> String {
+        "the system keyring".to_string()
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>> {
+        match self.with_entry(account, |e| e.get_password()) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(anyhow!("keyring: {e}")),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<()> {
+        self.with_entry(account, |e| e.set_password(secret)).map_err(|e| anyhow!("keyring: {e}"))
+    }
+
+    fn delete(&self, account: &str) -> Result<bool> {
+        match self.with_entry(account, |e| e.delete_credential()) {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(anyhow!("keyring: {e}")),
+        }
+    }
+}
+
+// ── Encrypted file ────────────────────────────────────────────────────────────
+
+/// `QAI` + format version.
+const MAGIC: &[u8; 4] = b"QAI\x01";
+const MODE_KEY_FILE: u8 = 0;
+const MODE_PASSPHRASE: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// All secrets in one encrypted JSON object. The header (magic, key mode,
+/// salt, nonce) is authenticated along with the ciphertext.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key_path: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl EncryptedFileStore {
+    /// `secrets.enc`, keyed by `secrets.key`, both in `dir`.
+    pub fn new(dir: &Path) -> Self {
+        Self { path: dir.join("secrets.enc"), key_path: dir.join("secrets.key"), passphrase: None }
+    }
+
+    /// Derive the key from `passphrase` instead of using the key file.
+    pub fn with_passphrase(mut self, passphrase: Option<String>) -> Self {
+        self.passphrase = passphrase;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn key(&self, mode: u8, salt: &[u8], create: bool) -> Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        if mode == MODE_PASSPHRASE {
+            let passphrase = self
+                .passphrase
+                .as_deref()
+                .with_context(|| format!("{} is locked with a passphrase; set QAI_SECRETS_PASSPHRASE", self.path.display()))?;
+            argon2::Argon2::default()
+                .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                .map_err(|e| anyhow!("could not derive the key: {e}"))?;
+            return Ok(key);
+        }
+        match std::fs::read(&self.key_path) {
+            Ok(bytes) if bytes.len() == key.len() => key.copy_from_slice(&bytes),
+            Ok(_) => bail!("{} is not a valid key file", self.key_path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+                getrandom::getrandom(&mut key).map_err(|e| anyhow!("no randomness: {e}"))?;
+                write_private(&self.key_path, &key)?;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.key_path.display())),
+        }
+        Ok(key)
+    }
+
+    fn load(&self) -> Result<Map<String, Value>> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        };
+        if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+            bail!("{} is not a qai secrets file", self.path.display());
+        }
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        let mode = header[MAGIC.len()];
+        let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+        let nonce = &header[HEADER_LEN - NONCE_LEN..];
+        let key = self.key(mode, salt, false)?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| anyhow!("cannot decrypt {}: wrong key or passphrase", self.path.display()))?;
+        match serde_json::from_slice(&plaintext)? {
+            Value::Object(secrets) => Ok(secrets),
+            _ => bail!("{} holds no secrets object", self.path.display()),
+        }
+    }
+
+    fn save(&self, secrets: &Map<String, Value>) -> Result<()> {
+        let mode = if self.passphrase.is_some() { MODE_PASSPHRASE } else { MODE_KEY_FILE };
+        let mut header = MAGIC.to_vec();
+        header.push(mode);
+        let mut random = [0u8; SALT_LEN + NONCE_LEN];
+        getrandom::getrandom(&mut random).map_err(|e| anyhow!("no randomness: {e}"))?;
+        header.extend_from_slice(&random);
+        let key = self.key(mode, &random[..SALT_LEN], true)?;
+        let plaintext = serde_json::to_vec(secrets)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(Nonce::from_slice(&random[SALT_LEN..]), Payload { msg: &plaintext, aad: &header })
+            .map_err(|_| anyhow!("encryption failed"))?;
+        header.extend_from_slice(&ciphertext);
+        write_private(&self.path, &header)
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn describe(&self) -> String {
+        format!("the encrypted file {}", self.path.display())
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>> {
+        Ok(self.load()?.get(account).and_then(Value::as_str).map(str::to_string))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<()> {
+        let mut secrets = self.load()?;
+        secrets.insert(account.to_string(), Value::String(secret.to_string()));
+        self.save(&secrets)
+    }
+
+    fn delete(&self, account: &str) -> Result<bool> {
+        let mut secrets = self.load()?;
+        let existed = secrets.remove(account).is_some();
+        if existed {
+            self.save(&secrets)?;
+        }
+        Ok(existed)
+    }
+}
+
+/// Write `bytes` to `path` readable by the owner only, replacing it atomically.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    use std::io::Write;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// ── Plaintext ─────────────────────────────────────────────────────────────────
+
+/// Tokens as `[providers.<name>] api_token` in a config file.
+pub struct PlaintextStore {
+    path: PathBuf,
+}
+
+impl PlaintextStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn key(account: &str) -> String {
+        format!("providers.{account}.api_token")
+    }
+}
+
+impl SecretStore for PlaintextStore {
+    fn describe(&self) -> String {
+        format!("plaintext in {}", self.path.display())
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>> {
+        let provider = Provider::from_name(account).with_context(|| format!("unknown provider '{account}'"))?;
+        let config = ConfigFile::open(&self.path)?.config()?;
+        Ok(config.providers.get(&provider).and_then(|p| p.api_token.clone()))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<()> {
+        let mut file = ConfigFile::open(&self.path)?;
+        file.set(&Self::key(account), secret)?;
+        file.save()
+    }
+
+    fn delete(&self, account: &str) -> Result<bool> {
+        let mut file = ConfigFile::open(&self.path)?;
+        let existed = file.remove(&Self::key(account));
+        if existed {
+            file.save()?;
+        }
+        Ok(existed)
+    }
+
+    fn is_plaintext(&self) -> bool {
+        true
+    }
+}
+
+// ── Tokens ────────────────────────────────────────────────────────────────────
+
+/// Fill each provider's token from its env var, else from `store`; both win
+/// over a token written in config.toml.
+pub fn resolve_tokens(
+    config: &mut Config,
+    store: &dyn SecretStore,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    for &provider in Provider::all() {
+        let from_env = provider.env_var().and_then(&env).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let token = match from_env {
+            Some(token) => Some(token),
+            None => store
+                .get(provider.name())
+                .with_context(|| format!("Failed to read the {} token from {}", provider.name(), store.describe()))?,
+        };
+        if let Some(token) = token {
+            config.providers.entry(provider).or_default().api_token = Some(token);
+        }
+    }
+    Ok(())
+}
+
+/// Keep `token` for `provider` in `store` (an empty token deletes it). Unless
+/// the store is plaintext itself, a copy in the `plaintext` config file is
+/// removed so the key is no longer readable on disk; so is the legacy
+/// top-level `api_token` when `provider` is the file's default provider.
+pub fn save_token(store: &dyn SecretStore, plaintext: Option<&Path>, provider: Provider, token: &str) -> Result<()> {
+    let token = token.trim();
+    if token.is_empty() {
+        store.delete(provider.name())?;
+    } else {
+        store.set(provider.name(), token)?;
+    }
+    if let Some(path) = plaintext {
+        if !store.is_plaintext() {
+            PlaintextStore::new(path.to_path_buf()).delete(provider.name())?;
+        }
+        remove_legacy_token(path, provider)?;
+    }
+    Ok(())
+}
+
+/// Drop the top-level `api_token` older versions saved, if it belongs to
+/// `provider` (the default provider of the file at `path`).
+fn remove_legacy_token(path: &Path, provider: Provider) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut file = ConfigFile::open(path)?;
+    if file.config()?.default_provider() == provider && file.remove("api_token") {
+        file.save()?;
+    }
+    Ok(())
+}
+
+/// Move every per-provider token out of the config file at `plaintext` into
+/// `store`, along with the legacy top-level `api_token`, which goes to the
+/// file's default provider. Returns the providers moved.
+pub fn migrate_plaintext(store: &dyn SecretStore, plaintext: &Path) -> Result<Vec<Provider>> {
+    if store.is_plaintext() {
+        bail!("the secrets backend is plaintext; set secrets.backend to keyring or file first");
+    }
+    let mut config = ConfigFile::open(plaintext)?.config()?;
+    config.adopt_legacy_token();
+    let mut moved = Vec::new();
+    for &provider in Provider::all() {
+        if let Some(token) = config.providers.get(&provider).and_then(|p| p.api_token.as_deref()) {
+            save_token(store, Some(plaintext), provider, token)?;
+            moved.push(provider);
+        }
+    }
+    Ok(moved)
+}
@@ -0,0 +1,533 @@
+// `qai-cli serve`: chat, agent runs, model listing and saved sessions over
+// JSON-RPC 2.0 on stdio, one message per line. IDE plugins and other
+// front-ends talk to this instead of calling the providers themselves, so
+// every front-end shares the Rust providers, tools and workspace rules.
+// Runs stream `event` notifications carrying the `--events-jsonl` records
+// (plus the answer and thought deltas) before their response arrives.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::agent::approval::{ApprovalDecision, ApprovalRequest};
+use crate::agent::event_log::{event_json, outcome_name};
+use crate::agent::tools::ToolContext;
+use crate::agent::{
+    AgentEvent, EventLog, McpServers, ReActAgent, RunOutcome, ToolMode, ToolRegistry, Workspace,
+};
+use crate::config::Config;
+use crate::sessions::{Session, SessionStore};
+use crate::tui::api::{list_github_models, list_ollama_models, stream_message, StreamRequest};
+use crate::tui::providers::{AuthStyle, Provider};
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The request was valid but failed, e.g. the provider returned an error.
+const REQUEST_FAILED: i64 = -32000;
+/// The request was cancelled (the code LSP uses).
+const REQUEST_CANCELLED: i64 = -32800;
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(REQUEST_FAILED, format!("{e:#}"))
+    }
+}
+
+type RpcResult = Result<Value, RpcError>;
+
+// ── Server ────────────────────────────────────────────────────────────────────
+
+pub struct ServeOptions {
+    /// System prompt for chat and agent runs; `chat` may override it.
+    pub system_prompt: String,
+    /// Directory the agent's file tools are confined to.
+    pub workspace: Workspace,
+    /// Where `sessions.*` and `save` look (`None` = sessions unavailable).
+    pub sessions: Option<SessionStore>,
+    /// Also log every run here.
+    pub event_log: Option<EventLog>,
+    /// MCP servers whose tools agent runs may call.
+    pub mcp: McpServers,
+    /// Defaults for whatever a request leaves out.
+    pub config: Config,
+}
+
+struct Server {
+    opts: ServeOptions,
+    out: mpsc::UnboundedSender<Value>,
+    /// Cancellation tokens of requests in flight, keyed by their JSON id.
+    running: Mutex<HashMap<String, CancellationToken>>,
+    /// Tool calls waiting for an `approve` request.
+    approvals: Mutex<HashMap<u64, oneshot::Sender<ApprovalDecision>>>,
+    next_approval: AtomicU64,
+    input_closed: AtomicBool,
+}
+
+/// Answer requests from `input` until it ends, writing responses and
+/// notifications to `output`. Requests run concurrently, so a `cancel` can
+/// reach a run in flight. Once the input ends, running requests finish and
+/// are answered, but tool calls needing approval are rejected.
+pub async fn serve<R, W>(input: R, output: W, opts: ServeOptions) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (out, messages) = mpsc::unbounded_channel();
+    let writer = tokio::spawn(write_messages(output, messages));
+    let server = Arc::new(Server {
+        opts,
+        out,
+        running: Mutex::default(),
+        approvals: Mutex::default(),
+        next_approval: AtomicU64::new(1),
+        input_closed: AtomicBool::new(false),
+    });
+
+    let mut requests = JoinSet::new();
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await.context("Failed to read a request")? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                server.reply(Value::Null, Err(RpcError::new(PARSE_ERROR, format!("parse error: {e}"))));
+                continue;
+            }
+        };
+        // Requests without an id are notifications: handled, never answered
+        let id = message.get("id").cloned();
+        let Some(method) = message["method"].as_str().map(str::to_string) else {
+            server.reply(id.unwrap_or(Value::Null), Err(RpcError::new(INVALID_REQUEST, "missing 'method'")));
+            continue;
+        };
+        let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+        let cancel = CancellationToken::new();
+        if let Some(id) = &id {
+            server.lock_running().insert(id.to_string(), cancel.clone());
+        }
+        let server = server.clone();
+        requests.spawn(async move {
+            let result = server.handle(&method, &id, &params, cancel).await;
+            if let Some(id) = id {
+                server.lock_running().remove(&id.to_string());
+                server.reply(id, result);
+            }
+        });
+        while requests.try_join_next().is_some() {}
+    }
+
+    // No one is left to answer approvals; dropping them rejects the calls
+    server.input_closed.store(true, Ordering::Relaxed);
+    server.lock_approvals().clear();
+    while requests.join_next().await.is_some() {}
+    // The last sender goes with the server; the writer then drains and ends
+    drop(server);
+    writer.await.context("the response writer panicked")?
+}
+
+pub(crate) async fn write_messages<W: AsyncWrite + Unpin>(mut output: W, mut messages: mpsc::UnboundedReceiver<Value>) -> Result<()> {
+    while let Some(message) = messages.recv().await {
+        output.write_all(format!("{message}\n").as_bytes()).await.context("Failed to write a response")?;
+        output.flush().await.context("Failed to write a response")?;
+    }
+    Ok(())
+}
+
+impl Server {
+    fn lock_running(&self) -> MutexGuard<'_, HashMap<String, CancellationToken>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_approvals(&self) -> MutexGuard<'_, HashMap<u64, oneshot::Sender<ApprovalDecision>>> {
+        self.approvals.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn reply(&self, id: Value, result: RpcResult) {
+        let message = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } }),
+        };
+        let _ = self.out.send(message);
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        let _ = self.out.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Stream `event` as an `event` notification for request `id`.
+    fn event(&self, id: &Option<Value>, event: &AgentEvent) {
+        let mut params = event_json(event);
+        params["id"] = id.clone().unwrap_or(Value::Null);
+        self.notify("event", params);
+    }
+
+    async fn handle(&self, method: &str, id: &Option<Value>, params: &Value, cancel: CancellationToken) -> RpcResult {
+        match method {
+            "initialize" => Ok(json!({
+                "name": "qai-cli",
+                "version": env!("CARGO_PKG_VERSION"),
+                "workspace": self.opts.workspace.root().display().to_string(),
+                "sessions": self.opts.sessions.is_some(),
+            })),
+            "providers" => Ok(providers_json()),
+            "models" => self.models(params).await,
+            "chat" => self.chat(id, params, cancel).await,
+            "agent.run" => self.agent_run(id, params, cancel).await,
+            "cancel" => self.cancel(params),
+            "approve" => self.approve(params),
+            "sessions.list" => self.sessions_list(),
+            "sessions.get" => Ok(self.store()?.load(required_str(params, "id")?)?.to_json()),
+            "sessions.delete" => Ok(json!({ "deleted": self.store()?.delete(required_str(params, "id")?)? })),
+            other => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method '{other}'"))),
+        }
+    }
+
+    // ── Methods ───────────────────────────────────────────────────────────────
+
+    async fn models(&self, params: &Value) -> RpcResult {
+        let llm = LlmParams::from(params, &self.opts.config)?;
+        let models = match llm.provider {
+            Provider::Ollama => list_ollama_models(&llm.url).await?,
+            Provider::GitHubModels => list_github_models(&llm.api_token).await?,
+            // No catalog to ask; any model name the provider accepts works
+            other => vec![other.default_model().to_string()],
+        };
+        Ok(json!({ "provider": llm.provider.name(), "default": llm.provider.default_model(), "models": models }))
+    }
+
+    async fn chat(&self, id: &Option<Value>, params: &Value, cancel: CancellationToken) -> RpcResult {
+        let llm = LlmParams::from(params, &self.opts.config)?;
+        let mut session = self.session_param(params, &llm, false)?;
+        let mut history = match params.get("messages") {
+            Some(messages) => messages_param(messages)?,
+            None => session.as_ref().map(|s| s.messages.clone()).unwrap_or_default(),
+        };
+        if let Some(prompt) = str_param(params, "prompt") {
+            history.push(("user".to_string(), prompt.to_string()));
+        }
+        if history.last().is_none_or(|(role, _)| role != "user") {
+            return Err(RpcError::invalid_params("pass a 'prompt', or 'messages' ending with a user message"));
+        }
+        let system_prompt = str_param(params, "system").map_or_else(|| self.opts.system_prompt.clone(), str::to_string);
+
+        let (tx, mut tokens) = mpsc::unbounded_channel();
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let events_tx = match &self.opts.event_log {
+            Some(log) => log.tee(events_tx),
+            None => events_tx,
+        };
+        let request = stream_message(StreamRequest {
+            provider: llm.provider,
+            api_token: llm.api_token,
+            custom_url: llm.url,
+            auth: llm.auth,
+            model: llm.model.clone(),
+            max_tokens: llm.max_tokens,
+            system_prompt,
+            history: history.clone(),
+            tx,
+            cancel: cancel.clone(),
+            events: Some(events_tx),
+        });
+        let mut reply = String::new();
+        let relay = async {
+            let mut streaming = true;
+            loop {
+                // Tokens first: each is sent before the events that follow it
+                tokio::select! {
+                    biased;
+                    token = tokens.recv(), if streaming => match token {
+                        Some(Some(text)) => {
+                            reply.push_str(&text);
+                            self.event(id, &AgentEvent::AnswerDelta { step: 1, text });
+                        }
+                        Some(None) => {}
+                        None => streaming = false,
+                    },
+                    event = events.recv() => match event {
+                        Some(event) => self.event(id, &event),
+                        None => break,
+                    },
+                }
+            }
+        };
+        let (sent, ()) = tokio::join!(request, relay);
+        sent?;
+        if cancel.is_cancelled() {
+            return Err(RpcError::new(REQUEST_CANCELLED, "cancelled"));
+        }
+
+        if let Some(session) = &mut session {
+            history.push(("assistant".to_string(), reply.clone()));
+            session.set_messages(&history);
+            self.store()?.save(session)?;
+        }
+        Ok(json!({
+            "response": reply,
+            "provider": llm.provider.name(),
+            "model": llm.model,
+            "session": session.map(|s| s.id),
+        }))
+    }
+
+    async fn agent_run(&self, id: &Option<Value>, params: &Value, cancel: CancellationToken) -> RpcResult {
+        let llm = LlmParams::from(params, &self.opts.config)?;
+        let task = required_str(params, "task")?.to_string();
+        let mut session = self.session_param(params, &llm, true)?;
+        let history = session.as_ref().map(|s| s.messages.clone()).unwrap_or_default();
+
+        let mut limits = self.opts.config.limits();
+        if let Some(max_steps) = params["max_steps"].as_u64() {
+            limits.max_steps = max_steps as usize;
+        }
+        if let Some(retries) = params["max_format_retries"].as_u64() {
+            limits.max_format_retries = retries as usize;
+        }
+        if let Some(secs) = params["timeout"].as_u64() {
+            limits.max_duration = Some(std::time::Duration::from_secs(secs));
+        }
+        let native_tools = params["native_tools"].as_bool().or(self.opts.config.agent.native_tools).unwrap_or(false);
+        let tool_mode = if native_tools { ToolMode::Native } else { ToolMode::Xml };
+        // Mutating tools ask the client by default, like the TUI
+        let mut policy = self.opts.config.approval_policy();
+        if let Some(approval) = str_param(params, "approval") {
+            policy.mutating = approval.parse().map_err(RpcError::invalid_params)?;
+        }
+        let (approver, mut approvals) = mpsc::unbounded_channel();
+        let mut agent = ReActAgent::new(llm.provider, llm.api_token, llm.url, llm.model, self.opts.system_prompt.clone())
+            .with_auth(llm.auth)
+            .with_max_tokens(llm.max_tokens)
+            .with_tool_mode(tool_mode)
+            .with_limits(limits)
+            .with_tools(
+                ToolRegistry::builtin_with(ToolContext::new(self.opts.workspace.clone()))
+                    .with_extra_tools(self.opts.mcp.tools()),
+            )
+            .with_approval(policy, Some(approver));
+        agent.event_log = self.opts.event_log.clone();
+
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let run = agent.run(task.clone(), history.clone(), events_tx, cancel);
+        let relay = async {
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Some(event) => self.event(id, &event),
+                        None => break,
+                    },
+                    Some(request) = approvals.recv() => self.ask_approval(id, request),
+                }
+            }
+        };
+        let (outcome, ()) = tokio::join!(run, relay);
+        let outcome = outcome?;
+
+        let mut result = json!({ "outcome": outcome_name(&outcome) });
+        match &outcome {
+            RunOutcome::Answered(answer) => {
+                result["answer"] = json!(answer);
+                if let Some(session) = &mut session {
+                    let mut messages = history;
+                    messages.push(("user".to_string(), task));
+                    messages.push(("assistant".to_string(), answer.clone()));
+                    session.set_messages(&messages);
+                    self.store()?.save(session)?;
+                }
+            }
+            RunOutcome::Stopped(reason) => result["reason"] = json!(reason.to_string()),
+            RunOutcome::Failed(message) => result["reason"] = json!(message),
+            RunOutcome::Cancelled { step } => result["step"] = json!(step),
+        }
+        result["session"] = json!(session.map(|s| s.id));
+        Ok(result)
+    }
+
+    /// Forward a tool call to the client as an `approval` notification; its
+    /// `approve` request carries the decision back.
+    fn ask_approval(&self, id: &Option<Value>, request: ApprovalRequest) {
+        let approval = self.next_approval.fetch_add(1, Ordering::Relaxed);
+        {
+            // Checked under the lock, so `serve` can't clear the map in between
+            let mut approvals = self.lock_approvals();
+            if self.input_closed.load(Ordering::Relaxed) {
+                return;
+            }
+            approvals.insert(approval, request.reply);
+        }
+        self.notify("approval", json!({
+            "id": id,
+            "approval": approval,
+            "step": request.step,
+            "tool": request.tool,
+            "input": request.input,
+            "preview": request.preview,
+        }));
+    }
+
+    fn approve(&self, params: &Value) -> RpcResult {
+        let approval = params["approval"].as_u64().ok_or_else(|| RpcError::invalid_params("missing 'approval'"))?;
+        let decision = match str_param(params, "decision").unwrap_or("approve") {
+            "approve" => ApprovalDecision::Approve,
+            "edit" => ApprovalDecision::Edit(required_str(params, "input")?.to_string()),
+            "reject" => ApprovalDecision::Reject(str_param(params, "reason").unwrap_or("").to_string()),
+            other => {
+                return Err(RpcError::invalid_params(format!(
+                    "unknown decision '{other}' (expected approve, edit or reject)"
+                )))
+            }
+        };
+        let reply = self
+            .lock_approvals()
+            .remove(&approval)
+            .ok_or_else(|| RpcError::invalid_params(format!("no pending approval {approval}")))?;
+        // A cancelled run no longer waits for its decision
+        Ok(json!({ "delivered": reply.send(decision).is_ok() }))
+    }
+
+    fn cancel(&self, params: &Value) -> RpcResult {
+        let target = params.get("id").ok_or_else(|| RpcError::invalid_params("missing 'id'"))?;
+        let token = self.lock_running().get(&target.to_string()).cloned();
+        if let Some(token) = &token {
+            token.cancel();
+        }
+        Ok(json!({ "cancelled": token.is_some() }))
+    }
+
+    fn sessions_list(&self) -> RpcResult {
+        let sessions: Vec<Value> = self
+            .store()?
+            .list()?
+            .iter()
+            .map(|s| {
+                json!({
+                    "id": s.id,
+                    "title": s.title,
+                    "provider": s.provider.name(),
+                    "model": s.model,
+                    "agent_mode": s.agent_mode,
+                    "created_at": s.created_at,
+                    "updated_at": s.updated_at,
+                    "message_count": s.messages.len(),
+                })
+            })
+            .collect();
+        Ok(json!(sessions))
+    }
+
+    // ── Params ────────────────────────────────────────────────────────────────
+
+    fn store(&self) -> Result<&SessionStore, RpcError> {
+        self.opts.sessions.as_ref().ok_or_else(|| RpcError::new(REQUEST_FAILED, "sessions are not available"))
+    }
+
+    /// The session a run continues (`session`: id), a new one (`save`: true),
+    /// or none.
+    fn session_param(&self, params: &Value, llm: &LlmParams, agent_mode: bool) -> Result<Option<Session>, RpcError> {
+        if let Some(id) = str_param(params, "session") {
+            return Ok(Some(self.store()?.load(id)?));
+        }
+        if params["save"].as_bool().unwrap_or(false) {
+            self.store()?;
+            return Ok(Some(Session::new(llm.provider, &llm.model, agent_mode)));
+        }
+        Ok(None)
+    }
+}
+
+/// Provider, model, token and URL of a request; unset values fall back to
+/// the config, then to Ollama and the provider's default model. The auth
+/// style and reply length cap always come from the config.
+struct LlmParams {
+    provider: Provider,
+    model: String,
+    api_token: String,
+    url: String,
+    auth: AuthStyle,
+    max_tokens: Option<u32>,
+}
+
+impl LlmParams {
+    fn from(params: &Value, config: &Config) -> Result<Self, RpcError> {
+        let provider = str_param(params, "provider")
+            .map(|name| {
+                Provider::from_name(name).ok_or_else(|| RpcError::invalid_params(format!("unknown provider '{name}'")))
+            })
+            .transpose()?;
+        let owned = |key| str_param(params, key).map(str::to_string);
+        let llm = config.llm(provider, owned("model"), owned("api_token"), owned("url"));
+        Ok(Self {
+            provider: llm.provider,
+            model: llm.model,
+            api_token: llm.api_token,
+            url: llm.url,
+            auth: llm.auth,
+            max_tokens: llm.max_tokens,
+        })
+    }
+}
+
+/// A non-empty string param.
+fn str_param<'a>(params: &'a Value, key: &str) -> Option<&'a str> {
+    params[key].as_str().filter(|s| !s.trim().is_empty())
+}
+
+fn required_str<'a>(params: &'a Value, key: &str) -> Result<&'a str, RpcError> {
+    str_param(params, key).ok_or_else(|| RpcError::invalid_params(format!("missing '{key}'")))
+}
+
+/// `[{ "role": …, "content": … }]` as (role, content) pairs.
+fn messages_param(messages: &Value) -> Result<Vec<(String, String)>, RpcError> {
+    let messages = messages.as_array().ok_or_else(|| RpcError::invalid_params("'messages' must be an array"))?;
+    messages
+        .iter()
+        .map(|m| match (m["role"].as_str(), m["content"].as_str()) {
+            (Some(role), Some(content)) => Ok((role.to_string(), content.to_string())),
+            _ => Err(RpcError::invalid_params("each message needs a 'role' and 'content'")),
+        })
+        .collect()
+}
+
+fn providers_json() -> Value {
+    let providers: Vec<Value> = Provider::all()
+        .iter()
+        .map(|p| {
+            json!({
+                "name": p.name(),
+                "label": p.label(),
+                "default_model": p.default_model(),
+                "url": p.api_url(),
+                "description": p.description(),
+            })
+        })
+        .collect();
+    json!(providers)
+}
@@ -0,0 +1,209 @@
+// Saved chat and agent conversations.
+// Each session is one JSON file under `~/.local/share/qai/sessions/` (the
+// platform data dir) holding the provider, model, agent-mode flag, timestamps
+// and messages, so a conversation can be listed, shown and resumed later.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::clock::{compact_time, now_secs};
+use crate::tui::providers::Provider;
+
+// ── Session ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub title: String,
+    pub provider: Provider,
+    pub model: String,
+    pub agent_mode: bool,
+    /// Unix seconds.
+    pub created_at: u64,
+    /// Unix seconds.
+    pub updated_at: u64,
+    /// (role, content), as in `App::messages`.
+    pub messages: Vec<(String, String)>,
+}
+
+/// Characters of the first user message kept as the title.
+const TITLE_LEN: usize = 60;
+
+impl Session {
+    /// A new, empty session started now.
+    pub fn new(provider: Provider, model: &str, agent_mode: bool) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        // Sortable and readable, with a little entropy against same-second clashes
+        let id = format!("{}-{:04x}", compact_time(now.as_secs()), now.subsec_nanos() & 0xffff);
+        Self {
+            id,
+            title: String::new(),
+            provider,
+            model: model.to_string(),
+            agent_mode,
+            created_at: now.as_secs(),
+            updated_at: now.as_secs(),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Replace the messages, refreshing the title and `updated_at`.
+    pub fn set_messages(&mut self, messages: &[(String, String)]) {
+        self.messages = messages.to_vec();
+        self.updated_at = now_secs().max(self.created_at);
+        if let Some((_, first)) = self.messages.iter().find(|(role, _)| role == "user") {
+            let line = first.lines().next().unwrap_or("").trim();
+            self.title = match line.char_indices().nth(TITLE_LEN) {
+                Some((cut, _)) => format!("{}…", &line[..cut]),
+                None => line.to_string(),
+            };
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let messages: Vec<Value> =
+            self.messages.iter().map(|(role, content)| json!({ "role": role, "content": content })).collect();
+        json!({
+            "id": self.id,
+            "title": self.title,
+            "provider": self.provider.name(),
+            "model": self.model,
+            "agent_mode": self.agent_mode,
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+            "messages": messages,
+        })
+    }
+
+    pub fn from_json(v: &Value) -> Result<Self> {
+        let text = |key: &str| v[key].as_str().map(str::to_string).with_context(|| format!("missing '{key}'"));
+        let provider = text("provider")?;
+        let messages = v["messages"]
+            .as_array()
+            .context("missing 'messages'")?
+            .iter()
+            .map(|m| {
+                let role = m["role"].as_str().unwrap_or("user").to_string();
+                let content = m["content"].as_str().unwrap_or("").to_string();
+                (role, content)
+            })
+            .collect();
+        Ok(Self {
+            id: text("id")?,
+            title: v["title"].as_str().unwrap_or("").to_string(),
+            provider: Provider::from_name(&provider).with_context(|| format!("unknown provider '{provider}'"))?,
+            model: text("model")?,
+            agent_mode: v["agent_mode"].as_bool().unwrap_or(false),
+            created_at: v["created_at"].as_u64().unwrap_or(0),
+            updated_at: v["updated_at"].as_u64().unwrap_or(0),
+            messages,
+        })
+    }
+}
+
+// ── Store ─────────────────────────────────────────────────────────────────────
+
+/// A directory of saved sessions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `~/.local/share/qai/sessions` on Linux; the platform data dir elsewhere.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|d| d.join("qai").join("sessions"))
+    }
+
+    /// The store in `default_dir()`.
+    pub fn open_default() -> Result<Self> {
+        Self::default_dir().map(Self::new).context("no data directory for sessions on this platform")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write `session`, replacing any earlier save.
+    pub fn save(&self, session: &Session) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path(&session.id);
+        // Write then rename, so a crash never leaves a half-written session
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&session.to_json())?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Load a session by id or unique id prefix.
+    pub fn load(&self, id: &str) -> Result<Session> {
+        let path = self.resolve(id)?;
+        read_session(&path)
+    }
+
+    /// Every readable session, most recently updated first.
+    pub fn list(&self) -> Result<Vec<Session>> {
+        let mut sessions: Vec<Session> = self.files()?.iter().filter_map(|p| read_session(p).ok()).collect();
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| b.id.cmp(&a.id)));
+        Ok(sessions)
+    }
+
+    /// Delete a session by id or unique id prefix; returns its full id.
+    pub fn delete(&self, id: &str) -> Result<String> {
+        let path = self.resolve(id)?;
+        std::fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+        Ok(file_id(&path))
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.dir.display())),
+        };
+        Ok(entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect())
+    }
+
+    fn resolve(&self, id: &str) -> Result<PathBuf> {
+        let id = id.trim();
+        if id.is_empty() || id.contains(['/', '\\']) {
+            bail!("invalid session id '{id}'");
+        }
+        let exact = self.path(id);
+        if exact.is_file() {
+            return Ok(exact);
+        }
+        let mut matches: Vec<PathBuf> = self.files()?.into_iter().filter(|p| file_id(p).starts_with(id)).collect();
+        match matches.len() {
+            0 => bail!("no session '{id}' in {}", self.dir.display()),
+            1 => Ok(matches.remove(0)),
+            n => bail!("'{id}' matches {n} sessions; use more of the id"),
+        }
+    }
+}
+
+fn read_session(path: &Path) -> Result<Session> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let value: Value = serde_json::from_str(&text).with_context(|| format!("{} is not valid JSON", path.display()))?;
+    Session::from_json(&value).with_context(|| format!("{} is not a session", path.display()))
+}
+
+fn file_id(path: &Path) -> String {
+    path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+}
@@ -1,38 +1,43 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use futures_util::StreamExt;
-use std::path::PathBuf;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
+use crate::agent::events::emit;
+use crate::config::{global_config_path, Config};
+use crate::secrets;
+use crate::agent::{AgentEvent, EventSender, RunOutcome, RunSummary};
 use crate::tui::state::App;
-use crate::tui::providers::Provider;
+use crate::llm::{self, ChatRequest, StreamChunk, TokenUsage};
+use crate::tui::providers::{AuthStyle, Provider};
 
 const OLLAMA_REQUEST_TIMEOUT_SECS: u64 = 5;
 
-fn config_path() -> Option<PathBuf> {
-    dirs::config_dir().map(|d| d.join("qai").join("config.toml"))
+/// The global config file merged with `QAI_*` variables: what picks the secret store.
+fn global_config() -> Result<(Option<std::path::PathBuf>, Config)> {
+    let path = global_config_path();
+    let config = Config::layered(path.as_slice(), None, |name| std::env::var(name).ok())?;
+    Ok((path, config))
 }
 
-pub fn save_api_token(token: &str) -> Result<()> {
-    if let Some(path) = config_path() {
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        std::fs::write(&path, format!("api_token = \"{}\"\n", token.replace('"', "\\\"")))?;
-    }
-    Ok(())
+/// Save `provider`'s token in the configured secret store (an empty token
+/// removes it) and drop any plaintext copy from the global config file.
+/// Returns where the token went, for the status line.
+pub fn save_api_token(provider: Provider, token: &str) -> Result<String> {
+    let (path, config) = global_config()?;
+    let store = secrets::store_for(&config).context("no config directory to keep the token in")?;
+    secrets::save_token(store.as_ref(), path.as_deref(), provider, token)?;
+    Ok(store.describe())
 }
 
-pub fn load_api_token() -> Option<String> {
-    let path = config_path()?;
-    let content = std::fs::read_to_string(path).ok()?;
-    for line in content.lines() {
-        if let Some(rest) = line.strip_prefix("api_token = \"") {
-            let val = rest.trim_end_matches('"');
-            return Some(val.replace("\\\"", "\""));
-        }
-    }
-    None
+/// `provider`'s token: its env var, else the secret store, else a token in the
+/// global config file (the single one older versions saved counts for the
+/// default provider only).
+pub fn load_api_token(provider: Provider) -> Option<String> {
+    let (_, mut config) = global_config().ok()?;
+    let store = secrets::store_for(&config)?;
+    secrets::resolve_tokens(&mut config, store.as_ref(), |name| std::env::var(name).ok()).ok()?;
+    config.token_for(provider)
 }
 
 // ── Streaming API call ────────────────────────────────────────────────────────
@@ -40,288 +45,190 @@ pub fn load_api_token() -> Option<String> {
 pub struct StreamRequest {
     pub provider: Provider,
     pub api_token: String,
+    /// Base URL overriding the provider's endpoint; empty for the default.
     pub custom_url: String,
+    pub auth: AuthStyle,
     pub model: String,
+    /// Reply length cap; `None` sends `llm::DEFAULT_MAX_TOKENS`.
+    pub max_tokens: Option<u32>,
     pub system_prompt: String,
     pub history: Vec<(String, String)>,
     pub tx: mpsc::UnboundedSender<Option<String>>,
     pub cancel: CancellationToken,
+    /// Also report the reply as a one-step run of agent events (for event logs).
+    pub events: Option<EventSender>,
 }
 
-pub async fn stream_message(req: StreamRequest) -> Result<()> {
-    let StreamRequest { provider, api_token, custom_url, model, system_prompt, history, tx, cancel } = req;
-    use reqwest::Client;
-    use serde_json::{json, Value};
-
-    let token = api_token.trim().to_string();
-    if token.is_empty() && provider != Provider::Ollama {
-        anyhow::bail!("API token is empty");
-    }
+/// How a streamed reply ended.
+enum Reply {
+    Complete { text: String, usage: Option<TokenUsage> },
+    Cancelled,
+}
 
-    let client = if provider == Provider::Ollama {
-        Client::builder()
-            .connect_timeout(std::time::Duration::from_secs(OLLAMA_REQUEST_TIMEOUT_SECS))
-            .build()?
-    } else {
-        Client::new()
+pub async fn stream_message(req: StreamRequest) -> Result<()> {
+    let Some(events) = req.events.clone() else {
+        return stream_reply(req).await.map(|_| ());
     };
-
-    match provider {
-        Provider::Anthropic => {
-            // Anthropic SSE streaming
-            let msgs: Vec<Value> = history
-                .iter()
-                .map(|(r, c)| json!({"role": r, "content": c}))
-                .collect();
-
-            let body = json!({
-                "model": model,
-                "max_tokens": 4096,
-                "system": system_prompt,
-                "messages": msgs,
-                "stream": true
-            });
-
-            let resp = client
-                .post(provider.api_url())
-                .header("x-api-key", token)
-                .header("anthropic-version", "2023-06-01")
-                .header("content-type", "application/json")
-                .json(&body)
-                .send()
-                .await?;
-
-            let mut stream = resp.bytes_stream();
-            loop {
-                tokio::select! {
-                    _ = cancel.cancelled() => {
-                        let _ = tx.send(None);
-                        return Ok(());
-                    }
-                    chunk = stream.next() => {
-                        match chunk {
-                            None => break,
-                            Some(Err(e)) => return Err(e.into()),
-                            Some(Ok(bytes)) => {
-                                let text = String::from_utf8_lossy(&bytes);
-                                for line in text.lines() {
-                                    if let Some(data) = line.strip_prefix("data: ") {
-                                        if data == "[DONE]" { break; }
-                                        if let Ok(v) = serde_json::from_str::<Value>(data) {
-                                            if let Some(delta) = v["delta"]["text"].as_str() {
-                                                let _ = tx.send(Some(delta.to_string()));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    let started = std::time::Instant::now();
+    emit(&events, AgentEvent::StepStart { step: 1 });
+    let result = stream_reply(req).await;
+    let outcome = match &result {
+        Ok(Reply::Complete { text, usage }) => {
+            if let Some(usage) = usage {
+                emit(&events, AgentEvent::Usage { step: 1, usage: *usage });
             }
-            let _ = tx.send(None);
-            Ok(())
+            emit(&events, AgentEvent::Answer { step: 1, text: text.clone() });
+            RunOutcome::Answered(text.clone())
         }
-        _ => {
-            // OpenAI-compatible streaming (OpenAI, xAI, Ollama, GitHubModels)
-            let url = if provider == Provider::Ollama && !custom_url.trim().is_empty() {
-                // Custom Ollama server URL — append /api/chat if not already a full path
-                let base = custom_url.trim().trim_end_matches('/');
-                if base.ends_with("/api/chat") {
-                    base.to_string()
-                } else {
-                    format!("{base}/api/chat")
-                }
-            } else {
-                provider.api_url().to_string()
-            };
+        Ok(Reply::Cancelled) => RunOutcome::Cancelled { step: 1 },
+        Err(e) => {
+            emit(&events, AgentEvent::Error { step: 1, message: e.to_string() });
+            RunOutcome::Failed(e.to_string())
+        }
+    };
+    let summary = RunSummary { steps: 1, tool_calls: Vec::new(), elapsed: started.elapsed() };
+    emit(&events, AgentEvent::Finished { outcome, summary });
+    result.map(|_| ())
+}
 
-            let mut msgs: Vec<Value> = vec![json!({"role": "system", "content": system_prompt})];
-            for (r, c) in &history {
-                msgs.push(json!({"role": r, "content": c}));
-            }
+async fn stream_reply(req: StreamRequest) -> Result<Reply> {
+    let StreamRequest {
+        provider, api_token, custom_url, auth, model, max_tokens, system_prompt, history, tx, cancel, events: _,
+    } = req;
 
-            let body = json!({
-                "model": model,
-                "messages": msgs,
-                "stream": true
-            });
+    let client = llm::client(provider, &custom_url, &api_token, auth)?;
+    let request = ChatRequest { max_tokens, ..ChatRequest::text(&model, &system_prompt, &history) };
+    // A slow server can take a while to answer at all; stay cancellable
+    let mut chunks = tokio::select! {
+        _ = cancel.cancelled() => {
+            let _ = tx.send(None);
+            return Ok(Reply::Cancelled);
+        }
+        chunks = client.stream(&request) => chunks?,
+    };
 
-            let mut req = client.post(&url).json(&body);
-            if !token.is_empty() {
-                req = req.bearer_auth(token);
+    let mut text = String::new();
+    let mut usage: Option<TokenUsage> = None;
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                let _ = tx.send(None);
+                return Ok(Reply::Cancelled);
             }
-            let resp = req.send().await?;
-
-            let mut stream = resp.bytes_stream();
-            loop {
-                tokio::select! {
-                    _ = cancel.cancelled() => {
-                        let _ = tx.send(None);
-                        return Ok(());
-                    }
-                    chunk = stream.next() => {
-                        match chunk {
-                            None => break,
-                            Some(Err(e)) => return Err(e.into()),
-                            Some(Ok(bytes)) => {
-                                let text = String::from_utf8_lossy(&bytes);
-                                for line in text.lines() {
-                                    // Ollama NDJSON: each line is a full JSON object
-                                    // OpenAI SSE: lines start with "data: "
-                                    let data = if let Some(d) = line.strip_prefix("data: ") {
-                                        if d == "[DONE]" { continue; }
-                                        d
-                                    } else {
-                                        line
-                                    };
-                                    if data.is_empty() { continue; }
-                                    if let Ok(v) = serde_json::from_str::<Value>(data) {
-                                        // OpenAI-style delta
-                                        if let Some(delta) = v["choices"][0]["delta"]["content"].as_str() {
-                                            if !delta.is_empty() {
-                                                let _ = tx.send(Some(delta.to_string()));
-                                            }
-                                        }
-                                        // Ollama NDJSON style
-                                        else if let Some(delta) = v["message"]["content"].as_str() {
-                                            if !delta.is_empty() {
-                                                let _ = tx.send(Some(delta.to_string()));
-                                            }
-                                        }
-                                        // Check if done
-                                        if v["done"].as_bool().unwrap_or(false) {
-                                            let _ = tx.send(None);
-                                            return Ok(());
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+            chunk = chunks.next() => match chunk.transpose()? {
+                Some(StreamChunk::Delta(delta)) => {
+                    text.push_str(&delta);
+                    let _ = tx.send(Some(delta));
                 }
+                Some(StreamChunk::Usage(u)) => usage.get_or_insert_with(TokenUsage::default).merge(u),
+                Some(StreamChunk::Done) | None => break,
             }
-            let _ = tx.send(None);
-            Ok(())
         }
     }
+    let _ = tx.send(None);
+    Ok(Reply::Complete { text, usage })
 }
 
-
-
-pub async fn fetch_ollama_models(app: &mut App) {
-    use reqwest::Client;
-    use serde_json::Value;
-
-    if app.selected_provider() != Provider::Ollama {
-        return;
-    }
-    let custom = app.custom_url.trim().to_string();
-    let base_owned = if custom.is_empty() {
+/// Base URL of the Ollama server: the custom URL, or the local default.
+fn ollama_base(custom_url: &str) -> String {
+    let custom = custom_url.trim();
+    if custom.is_empty() {
         "http://localhost:11434".to_string()
     } else {
         custom.trim_end_matches('/').to_string()
-    };
-    let base = base_owned.as_str();
+    }
+}
 
-    app.status = "Fetching Ollama models…".to_string();
-    let client = match Client::builder()
+/// Models installed on the Ollama server (`/api/tags`).
+pub async fn list_ollama_models(custom_url: &str) -> Result<Vec<String>> {
+    use anyhow::{anyhow, Context};
+    use reqwest::Client;
+    use serde_json::Value;
+
+    let base = ollama_base(custom_url);
+    let client = Client::builder()
         .timeout(std::time::Duration::from_secs(OLLAMA_REQUEST_TIMEOUT_SECS))
         .build()
-    {
-        Ok(client) => client,
-        Err(e) => {
-            app.status = format!("Failed to initialize HTTP client: {e}");
-            return;
-        }
-    };
-    match client
+        .context("Failed to initialize HTTP client")?;
+    let resp = client
         .get(format!("{base}/api/tags"))
         .send()
         .await
-    {
-        Ok(resp) => match resp.json::<Value>().await {
-            Ok(json) => {
-                let models: Vec<String> = json["models"]
-                    .as_array()
-                    .unwrap_or(&vec![])
-                    .iter()
-                    .filter_map(|m| m["name"].as_str().map(|s| s.to_string()))
-                    .collect();
-                if models.is_empty() {
-                    app.status = "No Ollama models found. Pull one with: ollama pull <model>".to_string();
-                } else {
-                    app.status = format!("Found {} model(s). Use ↑/↓ to select.", models.len());
-                    if !models.is_empty() {
-                        app.model_input = models[0].clone();
-                    }
-                    app.model_list_state.select(Some(0));
-                    app.ollama_models = models;
-                }
-            }
-            Err(e) => {
-                app.status = format!("Failed to parse Ollama response: {e}");
-            }
-        },
-        Err(e) => {
-            app.status = format!("Cannot reach Ollama at {base}/api/tags: {e}");
+        .map_err(|e| anyhow!("Cannot reach Ollama at {base}/api/tags: {e}"))?;
+    let json = resp.json::<Value>().await.map_err(|e| anyhow!("Failed to parse Ollama response: {e}"))?;
+    Ok(json["models"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|m| m["name"].as_str().map(|s| s.to_string()))
+        .collect())
+}
+
+pub async fn fetch_ollama_models(app: &mut App) {
+    if app.selected_provider() != Provider::Ollama {
+        return;
+    }
+    app.status = "Fetching Ollama models…".to_string();
+    match list_ollama_models(&app.custom_url).await {
+        Ok(models) if models.is_empty() => {
+            app.status = "No Ollama models found. Pull one with: ollama pull <model>".to_string();
+        }
+        Ok(models) => {
+            app.status = format!("Found {} model(s). Use ↑/↓ to select.", models.len());
+            app.model_input = models[0].clone();
+            app.model_list_state.select(Some(0));
+            app.ollama_models = models;
         }
+        Err(e) => app.status = e.to_string(),
     }
 }
 
-pub async fn fetch_github_models(app: &mut App) {
+/// Model ids in the GitHub Models catalog.
+pub async fn list_github_models(token: &str) -> Result<Vec<String>> {
+    use anyhow::{anyhow, bail};
     use reqwest::Client;
     use serde_json::Value;
 
-    let token = app.api_token.trim().to_string();
-    if token.is_empty() {
-        app.status = "Enter your GitHub OAuth token in the Token field, then press Enter.".to_string();
-        return;
-    }
-
-    app.status = "Fetching GitHub Models catalog…".to_string();
-    let client = Client::new();
-    match client
+    let resp = Client::new()
         .get("https://api.github.com/catalog/models")
-        .bearer_auth(&token)
+        .bearer_auth(token.trim())
         .header("Accept", "application/vnd.github+json")
         .header("X-GitHub-Api-Version", "2022-11-28")
         .header("User-Agent", "qai-cli/1.0")
         .send()
         .await
-    {
-        Ok(resp) => {
-            let status = resp.status();
-            if !status.is_success() {
-                let body = resp.text().await.unwrap_or_default();
-                app.status = format!("GitHub API error {status}: {body}");
-                return;
-            }
-            match resp.json::<Value>().await {
-                Ok(json) => {
-                    let models: Vec<String> = json
-                        .as_array()
-                        .unwrap_or(&vec![])
-                        .iter()
-                        .filter_map(|m| m["id"].as_str().map(|s| s.to_string()))
-                        .collect();
-                    if models.is_empty() {
-                        app.status = "No GitHub Models found. Check your GitHub OAuth token is valid.".to_string();
-                    } else {
-                        app.status = format!("Found {} GitHub Model(s). Use ↑/↓ to select.", models.len());
-                        app.model_input = models[0].clone();
-                        app.model_list_state.select(Some(0));
-                        app.ollama_models = models;
-                    }
-                }
-                Err(e) => {
-                    app.status = format!("Failed to parse GitHub Models response: {e}");
-                }
-            }
+        .map_err(|e| anyhow!("Cannot reach GitHub Models API: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        bail!("GitHub API error {status}: {body}");
+    }
+    let json = resp.json::<Value>().await.map_err(|e| anyhow!("Failed to parse GitHub Models response: {e}"))?;
+    Ok(json
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|m| m["id"].as_str().map(|s| s.to_string()))
+        .collect())
+}
+
+pub async fn fetch_github_models(app: &mut App) {
+    if app.api_token.trim().is_empty() {
+        app.status = "Enter your GitHub OAuth token in the Token field, then press Enter.".to_string();
+        return;
+    }
+    app.status = "Fetching GitHub Models catalog…".to_string();
+    match list_github_models(&app.api_token).await {
+        Ok(models) if models.is_empty() => {
+            app.status = "No GitHub Models found. Check your GitHub OAuth token is valid.".to_string();
         }
-        Err(e) => {
-            app.status = format!("Cannot reach GitHub Models API: {e}");
+        Ok(models) => {
+            app.status = format!("Found {} GitHub Model(s). Use ↑/↓ to select.", models.len());
+            app.model_input = models[0].clone();
+            app.model_list_state.select(Some(0));
+            app.ollama_models = models;
         }
+        Err(e) => app.status = e.to_string(),
     }
 }
 
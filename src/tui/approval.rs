@@ -0,0 +1,77 @@
+// Approval modal state and key handling.
+// When the agent asks to run a tool that needs approval, the request is held
+// here until the user approves, rejects, or edits the input and approves.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::agent::approval::{ApprovalDecision, ApprovalRequest};
+use crate::tui::input::{handle_text_input_key, TextInput};
+use crate::tui::state::App;
+
+/// A tool call waiting for the user's decision.
+#[derive(Debug)]
+pub struct PendingApproval {
+    pub request: ApprovalRequest,
+    /// The input being edited, when in edit-then-approve mode.
+    pub editing: Option<TextInput>,
+}
+
+impl PendingApproval {
+    pub fn new(request: ApprovalRequest) -> Self {
+        Self { request, editing: None }
+    }
+
+    /// Send the decision back to the agent. A run that was cancelled in the
+    /// meantime has stopped listening, which is fine.
+    pub fn resolve(self, decision: ApprovalDecision) {
+        let _ = self.request.reply.send(decision);
+    }
+}
+
+/// Handle a key while an approval is pending. Returns `false` (and does
+/// nothing) when there is no pending approval.
+///
+/// Choosing:  y / Enter approve · n / Esc reject · e edit the input
+/// Editing:   Enter approve the edited input · Shift+Enter newline · Esc back
+pub fn handle_approval_key(app: &mut App, key: KeyEvent) -> bool {
+    let width = app.approval_inner_width;
+    let Some(pending) = app.pending_approval.as_mut() else {
+        return false;
+    };
+
+    if let Some(editor) = pending.editing.as_mut() {
+        match key.code {
+            KeyCode::Esc => pending.editing = None,
+            KeyCode::Enter if !key.modifiers.contains(KeyModifiers::SHIFT) => {
+                let edited = editor.value.clone();
+                finish(app, ApprovalDecision::Edit(edited), "✏️ Edited input approved");
+            }
+            _ => handle_text_input_key(editor, key, width),
+        }
+        return true;
+    }
+
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+            finish(app, ApprovalDecision::Approve, "✅ Tool call approved");
+        }
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+            finish(app, ApprovalDecision::Reject(String::new()), "🚫 Tool call rejected");
+        }
+        KeyCode::Char('e') | KeyCode::Char('E') => {
+            let mut editor = TextInput::new();
+            editor.value = pending.request.input.clone();
+            editor.move_end();
+            pending.editing = Some(editor);
+        }
+        _ => {}
+    }
+    true
+}
+
+fn finish(app: &mut App, decision: ApprovalDecision, status: &str) {
+    if let Some(pending) = app.pending_approval.take() {
+        pending.resolve(decision);
+    }
+    app.status = status.to_string();
+}
@@ -5,14 +5,16 @@ use ratatui::{
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{
-        Block, Borders, List, ListItem, Paragraph, Scrollbar,
+        Block, Borders, Clear, List, ListItem, Paragraph, Scrollbar,
         ScrollbarOrientation, ScrollbarState, Wrap,
     },
     Frame, Terminal,
 };
 use crate::tui::state::{App, Screen, ChatFocus, MENU_ITEMS};
 use crate::tui::providers::Provider;
+use crate::tui::keys::KeyAction;
 use crate::tui::util::strip_model_tags;
+use crate::clock::format_time;
 
 // ── Drawing ───────────────────────────────────────────────────────────────────
 
@@ -39,9 +41,16 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         Screen::Validate => draw_validate(f, chunks[1], app),
         Screen::Tools => draw_tools(f, chunks[1], app),
         Screen::Chat => draw_chat(f, chunks[1], app),
+        Screen::Sessions => draw_sessions(f, chunks[1], app),
     }
 
     draw_footer(f, chunks[2], app);
+
+    if app.pending_approval.is_some() {
+        draw_approval(f, area, app);
+    }
+
+    app.theme.apply(f.buffer_mut());
 }
 
 fn draw_header(f: &mut Frame, area: Rect) {
@@ -73,14 +82,15 @@ fn draw_header(f: &mut Frame, area: Rect) {
 
 fn draw_footer(f: &mut Frame, area: Rect, app: &App) {
     let hint = match &app.screen {
-        Screen::Menu => " ↑↓ Navigate   Enter Select   q Quit ",
-        Screen::Show => " ↑↓/j/k Scroll   q/Esc Back ",
-        Screen::Chat => if app.agent_mode {
-            " Tab Next field   Enter Send   F2 Agent Mode ON 🤖   Esc Back "
-        } else {
-            " Tab Next field   Enter Send   F2 Agent Mode OFF   Esc Back "
-        },
-        _ => " q/Esc Back ",
+        Screen::Menu => " ↑↓ Navigate   Enter Select   q Quit ".to_string(),
+        Screen::Show => " ↑↓/j/k Scroll   q/Esc Back ".to_string(),
+        Screen::Sessions => " ↑↓/j/k Navigate   Enter Resume   d Delete   n New chat   q/Esc Back ".to_string(),
+        Screen::Chat => {
+            let key = app.keymap.label(KeyAction::AgentMode);
+            let state = if app.agent_mode { "ON 🤖" } else { "OFF" };
+            format!(" Tab Next field   Enter Send   {key} Agent Mode {state}   Esc Back ")
+        }
+        _ => " q/Esc Back ".to_string(),
     };
     let footer = Paragraph::new(hint)
         .style(Style::default().fg(Color::DarkGray).bg(Color::Rgb(15, 15, 25)))
@@ -88,6 +98,74 @@ fn draw_footer(f: &mut Frame, area: Rect, app: &App) {
     f.render_widget(footer, area);
 }
 
+// ── Approval modal ────────────────────────────────────────────────────────────
+
+fn draw_approval(f: &mut Frame, area: Rect, app: &mut App) {
+    let width = (area.width * 4 / 5).max(40).min(area.width);
+    app.approval_inner_width = (width.saturating_sub(2) as usize).max(1); // -2 borders
+    let Some(pending) = &app.pending_approval else { return };
+    let height = (area.height * 7 / 10).max(10).min(area.height);
+    let modal = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    f.render_widget(Clear, modal);
+
+    let (title, hint, lines) = match &pending.editing {
+        None => {
+            let lines: Vec<Line> = pending.request.preview.lines().map(|l| {
+                let color = if l.starts_with("+ ") {
+                    Color::Green
+                } else if l.starts_with("- ") {
+                    Color::Red
+                } else if l.starts_with("--- ") || l.starts_with("$ ") {
+                    Color::Cyan
+                } else {
+                    Color::White
+                };
+                Line::from(Span::styled(l.to_string(), Style::default().fg(color)))
+            }).collect();
+            (
+                format!(" ⚠ Approve `{}`? (step {}) ", pending.request.tool, pending.request.step),
+                " y/Enter Approve   n/Esc Reject   e Edit ",
+                lines,
+            )
+        }
+        Some(editor) => {
+            // Mark the cursor with NUL so it survives splitting into lines
+            let (before, at, after) = editor.split_at_cursor();
+            let (at, text) = if at == "\n" {
+                (" ", format!("{before}\u{0}\n{after}"))
+            } else {
+                (at, format!("{before}\u{0}{after}"))
+            };
+            let lines: Vec<Line> = text.split('\n').map(|l| match l.split_once('\u{0}') {
+                Some((b, a)) => Line::from(vec![
+                    Span::styled(b.to_string(), Style::default().fg(Color::White)),
+                    Span::styled(at.to_string(), Style::default().add_modifier(Modifier::REVERSED)),
+                    Span::styled(a.to_string(), Style::default().fg(Color::White)),
+                ]),
+                None => Line::from(Span::styled(l.to_string(), Style::default().fg(Color::White))),
+            }).collect();
+            (
+                format!(" ✏️ Edit `{}` input ", pending.request.tool),
+                " Enter Approve edited   Shift+Enter Newline   Esc Back ",
+                lines,
+            )
+        }
+    };
+
+    let block = Block::default()
+        .title(title)
+        .title_bottom(Line::from(hint).alignment(Alignment::Center))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .style(Style::default().bg(Color::Rgb(20, 20, 35)));
+    f.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: false }), modal);
+}
+
 fn draw_menu(f: &mut Frame, area: Rect, app: &App) {
     let outer = Layout::default()
         .direction(Direction::Horizontal)
@@ -105,6 +183,7 @@ fn draw_menu(f: &mut Frame, area: Rect, app: &App) {
                 3 => "  ",
                 4 => "  ",
                 5 => "  ",
+                6 => "  ",
                 _ => "  ",
             };
             ListItem::new(Line::from(vec![
@@ -330,6 +409,98 @@ fn draw_tools(f: &mut Frame, area: Rect, app: &mut App) {
     f.render_widget(detail, cols[1]);
 }
 
+fn draw_sessions(f: &mut Frame, area: Rect, app: &mut App) {
+    // Layout: left list | right detail
+    let cols = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(area);
+
+    // ── Left: saved sessions ──────────────────────────────────────────────────
+    let items: Vec<ListItem> = app
+        .sessions
+        .iter()
+        .map(|s| {
+            let title = if s.title.is_empty() { "(untitled)" } else { s.title.as_str() };
+            ListItem::new(vec![
+                Line::from(Span::raw(title.to_string())),
+                Line::from(Span::styled(
+                    format!("  {} · {}", format_time(s.updated_at), s.provider.name()),
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ])
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(format!(" Sessions ({}) ", app.sessions.len()))
+                .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Rgb(50, 50, 80))),
+        )
+        .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan))
+        .highlight_symbol("▶ ");
+    f.render_stateful_widget(list, cols[0], &mut app.sessions_state);
+
+    // ── Right: detail panel ───────────────────────────────────────────────────
+    let selected = app.sessions_state.selected().and_then(|i| app.sessions.get(i));
+    let mut lines = vec![Line::from("")];
+    match selected {
+        None => {
+            let where_ = match &app.session_store {
+                Some(store) => format!("  No saved sessions in {}", store.dir().display()),
+                None => "  Sessions are not being saved.".to_string(),
+            };
+            lines.push(Line::from(Span::styled(where_, Style::default().fg(Color::Gray))));
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                "  Conversations are saved after each reply. Press n to start one.",
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+        Some(session) => {
+            let field = |name: &str, value: String, color: Color| {
+                Line::from(vec![
+                    Span::styled(format!("  {name:<11}: "), Style::default().fg(Color::DarkGray)),
+                    Span::styled(value, Style::default().fg(color)),
+                ])
+            };
+            lines.push(field("Id", session.id.clone(), Color::White));
+            lines.push(field("Provider", session.provider.label().to_string(), Color::Cyan));
+            lines.push(field("Model", session.model.clone(), Color::White));
+            lines.push(field("Mode", if session.agent_mode { "Agent 🤖" } else { "Chat 💬" }.to_string(), Color::White));
+            lines.push(field("Created", format_time(session.created_at), Color::Gray));
+            lines.push(field("Updated", format_time(session.updated_at), Color::Gray));
+            lines.push(field("Messages", session.messages.len().to_string(), Color::Gray));
+            lines.push(Line::from(""));
+            // The tail of the conversation, as a reminder of where it left off
+            for (role, content) in session.messages.iter().rev().take(4).rev() {
+                let (label, color) = if role == "user" { ("You", Color::Green) } else { ("QA-Bot", Color::Cyan) };
+                lines.push(Line::from(Span::styled(format!("  {label}:"), Style::default().fg(color))));
+                let text = if role == "assistant" { strip_model_tags(content) } else { content.clone() };
+                for line in text.lines().take(6) {
+                    lines.push(Line::from(Span::styled(format!("    {line}"), Style::default().fg(Color::Gray))));
+                }
+            }
+        }
+    }
+    if !app.status.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(format!("  {}", app.status), Style::default().fg(Color::Yellow))));
+    }
+    let detail = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(" Session ")
+                .title_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Rgb(50, 50, 80))),
+        )
+        .wrap(Wrap { trim: false });
+    f.render_widget(detail, cols[1]);
+}
+
 fn draw_chat(f: &mut Frame, area: Rect, app: &mut App) {
     let providers = Provider::all();
     let is_ollama = app.selected_provider() == Provider::Ollama;
@@ -342,13 +513,13 @@ fn draw_chat(f: &mut Frame, area: Rect, app: &mut App) {
         .split(area);
 
     // ── Left: config panel ────────────────────────────────────────────────────
-    // Rows: provider list | model list (Ollama/GitHub) | custom url (Ollama only) | token | model display
+    // Rows: provider list | model list (Ollama/GitHub) | base url | token | model display
     let left_rows = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(6),                                      // provider list
             Constraint::Length(if is_ollama || is_github { 6 } else { 0 }), // model list
-            Constraint::Length(if is_ollama { 3 } else { 0 }),       // custom url (Ollama only)
+            Constraint::Length(3),                                   // base url
             Constraint::Length(3),                                   // token
             Constraint::Length(3),                                   // active model display
         ])
@@ -410,11 +581,16 @@ fn draw_chat(f: &mut Frame, area: Rect, app: &mut App) {
         }
     }
 
-    // Custom URL field (Ollama custom server only)
-    if is_ollama {
+    // Base URL field, overriding the provider's endpoint
+    {
         let url_focused = app.chat_focus == ChatFocus::CustomUrl;
+        let url_title = match app.selected_provider() {
+            Provider::Ollama => " Ollama Server URL (leave blank for localhost) ",
+            Provider::Custom => " Base URL (required) ",
+            _ => " Base URL (leave blank for the default) ",
+        };
         let url_block = Block::default()
-            .title(" Ollama Server URL (leave blank for localhost) ")
+            .title(url_title)
             .title_style(Style::default().fg(if url_focused { Color::Yellow } else { Color::DarkGray }))
             .borders(Borders::ALL)
             .border_style(Style::default().fg(if url_focused { Color::Yellow } else { Color::Rgb(50, 50, 80) }));
@@ -431,7 +607,7 @@ fn draw_chat(f: &mut Frame, area: Rect, app: &mut App) {
         "•".repeat(app.api_token.len().min(20))
     };
     let token_focused = app.chat_focus == ChatFocus::Token;
-    let token_title = if is_ollama { " API Token (optional) " } else { " API Token " };
+    let token_title = if app.selected_provider().requires_token() { " API Token " } else { " API Token (optional) " };
     let token_block = Block::default()
         .title(token_title)
         .title_style(Style::default().fg(if token_focused { Color::Yellow } else { Color::DarkGray }))
@@ -693,11 +869,16 @@ fn draw_chat(f: &mut Frame, area: Rect, app: &mut App) {
     }
 
     // Cursor hint
+    let keys = &app.keymap;
+    let (agent, tools) = (keys.label(KeyAction::AgentMode), keys.label(KeyAction::NativeTools));
+    let (undo, revert) = (keys.label(KeyAction::UndoStep), keys.label(KeyAction::RevertRun));
     let hint = Paragraph::new(Span::styled(
-        if app.agent_mode {
-            " Tab: cycle focus   ↑/↓: scroll/navigate   Enter: send   Shift+Enter/Ctrl+J: newline   F2: 🤖 Agent Mode ON   Esc: menu "
+        if app.agent_mode && app.native_tools {
+            format!(" Tab: cycle focus   ↑/↓: scroll/navigate   Enter: send   Shift+Enter/Ctrl+J: newline   {agent}: 🤖 Agent Mode ON   {tools}: 🛠 Native tools   {undo}/{revert}: undo step/run   Esc: menu ")
+        } else if app.agent_mode {
+            format!(" Tab: cycle focus   ↑/↓: scroll/navigate   Enter: send   Shift+Enter/Ctrl+J: newline   {agent}: 🤖 Agent Mode ON   {tools}: XML tools   {undo}/{revert}: undo step/run   Esc: menu ")
         } else {
-            " Tab: cycle focus   ↑/↓: scroll/navigate   Enter: send   Shift+Enter/Ctrl+J: newline   F2: Agent Mode   Esc: menu "
+            format!(" Tab: cycle focus   ↑/↓: scroll/navigate   Enter: send   Shift+Enter/Ctrl+J: newline   {agent}: Agent Mode   Esc: menu ")
         },
         Style::default().fg(Color::DarkGray),
     ));
@@ -6,9 +6,11 @@ use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKi
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
-use crate::agent::ReActAgent;
+use crate::agent::{events::run_as_markdown, tools::ToolContext, Checkpoints, EventLog, ReActAgent, ToolMode, ToolRegistry};
+use crate::tui::approval::handle_approval_key;
 use crate::tui::api::{fetch_ollama_models, fetch_github_models, stream_message, StreamRequest};
 use crate::tui::input::handle_text_input_key;
+use crate::tui::keys::KeyAction;
 use crate::tui::providers::Provider;
 use crate::tui::state_manager::StateManager;
 use crate::{App, ChatFocus, Screen};
@@ -71,7 +73,8 @@ pub async fn handle_menu_key(
                 2 => state_manager.navigate_to_validate(),
                 3 => state_manager.navigate_to_tools(),
                 4 => state_manager.navigate_to_chat(),
-                5 => return Ok(()), // Quit
+                5 => state_manager.navigate_to_sessions(),
+                6 => return Ok(()), // Quit
                 _ => {}
             }
         }
@@ -124,8 +127,7 @@ pub async fn handle_tools_key(
             app.tools_provider_list_state.select(Some(i));
         }
         KeyCode::Enter => {
-            app.provider_index = app.tools_provider_index;
-            app.provider_list_state.select(Some(app.tools_provider_index));
+            app.select_provider(app.tools_provider_index);
             state_manager.navigate_to_chat();
         }
         _ => {}
@@ -139,6 +141,18 @@ pub async fn handle_chat_key(
     stream_tx: mpsc::UnboundedSender<Option<String>>,
     state_manager: &mut StateManager,
 ) -> Result<()> {
+    // The approval modal takes every key until it is answered
+    if handle_approval_key(app, *key) {
+        return Ok(());
+    }
+    if let Some(action) = app.keymap.action_for(key) {
+        match action {
+            KeyAction::AgentMode => state_manager.toggle_agent_mode(),
+            KeyAction::NativeTools => state_manager.toggle_native_tools(),
+            action => app.run_key_action(action),
+        }
+        return Ok(());
+    }
     match key.code {
         KeyCode::Esc => {
             if app.streaming {
@@ -159,9 +173,6 @@ pub async fn handle_chat_key(
                 state_manager.navigate_to_menu();
             }
         }
-        KeyCode::F(2) => {
-            state_manager.toggle_agent_mode();
-        }
         KeyCode::Tab => {
             state_manager.cycle_chat_focus(true);
         }
@@ -286,18 +297,37 @@ pub async fn handle_chat_key(
                     let tx = stream_tx.clone();
                     let cancel = app.cancel_token.clone();
                     let agent_mode = app.agent_mode;
+                    let tool_mode = if app.native_tools { ToolMode::Native } else { ToolMode::Xml };
+                    let approval_policy = app.approval_policy.clone();
+                    let limits = app.config.limits();
+                    let auth = app.config.auth_for(provider);
+                    let max_tokens = app.config.max_tokens;
+                    let approval_tx = app.approval_tx.clone();
+                    let workspace = app.workspace.clone();
+                    // Fresh checkpoints per run; undo_step/revert_run roll back the latest one
+                    let checkpoints = Checkpoints::new();
+                    app.checkpoints = checkpoints.clone();
+                    let event_log = app.event_log.clone();
+                    let mcp_tools = app.mcp.tools();
                     tokio::spawn(async move {
                         if agent_mode {
-                            let agent = ReActAgent::new(
+                            let mut agent = ReActAgent::new(
                                 provider, token, custom_url, model, system_prompt,
-                            );
-                            if let Err(e) = agent.run(task, prior, tx.clone()).await {
-                                let _ = tx.send(Some(format!("\n[Agent error: {e}]")));
-                                let _ = tx.send(None);
-                            }
+                            )
+                            .with_auth(auth)
+                            .with_max_tokens(max_tokens)
+                            .with_tool_mode(tool_mode)
+                            .with_limits(limits)
+                            .with_approval(approval_policy, approval_tx)
+                            .with_tools(ToolRegistry::builtin_with(
+                                ToolContext::new(workspace).with_checkpoints(checkpoints.clone()),
+                            ).with_extra_tools(mcp_tools))
+                            .with_checkpoints(checkpoints);
+                            agent.event_log = event_log;
+                            run_as_markdown(&agent, task, prior, tx, cancel).await;
                         } else if let Err(e) = stream_message(StreamRequest {
-                            provider, api_token: token, custom_url, model, system_prompt, history,
-                            tx: tx.clone(), cancel,
+                            provider, api_token: token, custom_url, auth, model, max_tokens, system_prompt, history,
+                            tx: tx.clone(), cancel, events: event_log.as_ref().map(EventLog::sender),
                         }).await {
                             let _ = tx.send(Some(format!("\n[Error: {e}]")));
                             let _ = tx.send(None);
@@ -326,15 +356,11 @@ pub async fn handle_chat_key(
                         app.message_input.select_all();
                         app.status = "Selected all message text".to_string();
                     }
-                    ChatFocus::Token => {
-                        if copy_to_clipboard(app.api_token.clone()) {
-                            app.status = "📋 Copied all token text".to_string();
-                        }
+                    ChatFocus::Token if copy_to_clipboard(app.api_token.clone()) => {
+                        app.status = "📋 Copied all token text".to_string();
                     }
-                    ChatFocus::CustomUrl => {
-                        if copy_to_clipboard(app.custom_url.clone()) {
-                            app.status = "📋 Copied all URL text".to_string();
-                        }
+                    ChatFocus::CustomUrl if copy_to_clipboard(app.custom_url.clone()) => {
+                        app.status = "📋 Copied all URL text".to_string();
                     }
                     _ => {}
                 }
@@ -418,30 +444,21 @@ pub async fn handle_chat_key(
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{is_copy_shortcut, is_select_all_shortcut};
-    use crossterm::event::KeyModifiers;
-
-    #[test]
-    fn copy_shortcut_on_macos_accepts_cmd_and_ctrl() {
-        assert!(is_copy_shortcut('c', KeyModifiers::SUPER, true));
-        assert!(is_copy_shortcut('c', KeyModifiers::META, true));
-        assert!(is_copy_shortcut('c', KeyModifiers::CONTROL, true));
-    }
-
-    #[test]
-    fn copy_shortcut_on_non_macos_accepts_only_ctrl() {
-        assert!(is_copy_shortcut('c', KeyModifiers::CONTROL, false));
-        assert!(!is_copy_shortcut('c', KeyModifiers::SUPER, false));
-    }
-
-    #[test]
-    fn select_all_shortcut_on_macos_accepts_cmd_only() {
-        assert!(is_select_all_shortcut('a', KeyModifiers::SUPER, true));
-        assert!(is_select_all_shortcut('a', KeyModifiers::META, true));
-        assert!(!is_select_all_shortcut('a', KeyModifiers::CONTROL, true));
+pub async fn handle_sessions_key(
+    app: &mut App,
+    key: &KeyEvent,
+    state_manager: &mut StateManager,
+) -> Result<()> {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => state_manager.navigate_to_menu(),
+        KeyCode::Up | KeyCode::Char('k') => app.select_session(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.select_session(1),
+        KeyCode::Enter => app.resume_selected_session(),
+        KeyCode::Char('d') | KeyCode::Delete => app.delete_selected_session(),
+        KeyCode::Char('n') => app.new_session(),
+        _ => {}
     }
+    Ok(())
 }
 
 pub async fn handle_validate_key(
@@ -466,117 +483,113 @@ pub async fn handle_mouse_event(
         MouseEventKind::ScrollDown => {
             state_manager.scroll_down();
         }
-        MouseEventKind::Down(_) => {
-            if app.screen == Screen::Chat {
-                // Input scrollbar hit-test
-                let ir = app.input_rect;
-                let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
-                if mouse.column == input_scrollbar_col && ir.height > 2
-                    && mouse.row >= ir.y && mouse.row < ir.y + ir.height
-                    && app.input_max_scroll_stored > 0 {
-                    let track_top = ir.y + 1;
-                    let track_bottom = ir.y + ir.height.saturating_sub(2);
-                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                    if track_len > 0 {
-                        let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
-                        app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
-                    }
-                }
-                if mouse.column >= ir.x + 1
-                    && mouse.column < ir.x + ir.width.saturating_sub(1)
-                    && mouse.row >= ir.y + 1
-                    && mouse.row < ir.y + ir.height.saturating_sub(1) {
-                    app.chat_focus = ChatFocus::Message;
-                    let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
-                        .saturating_add(app.input_scroll as usize);
-                    let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
-                    let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
-                    app.message_input.set_cursor(pos, false);
-                    app.message_input.sel_anchor = Some(pos);
+        MouseEventKind::Down(_) if app.screen == Screen::Chat => {
+            // Input scrollbar hit-test
+            let ir = app.input_rect;
+            let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
+            if mouse.column == input_scrollbar_col && ir.height > 2
+                && mouse.row >= ir.y && mouse.row < ir.y + ir.height
+                && app.input_max_scroll_stored > 0 {
+                let track_top = ir.y + 1;
+                let track_bottom = ir.y + ir.height.saturating_sub(2);
+                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                if track_len > 0 {
+                    let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
+                    app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
                 }
+            }
+            if mouse.column > ir.x
+                && mouse.column < ir.x + ir.width.saturating_sub(1)
+                && mouse.row > ir.y
+                && mouse.row < ir.y + ir.height.saturating_sub(1) {
+                app.chat_focus = ChatFocus::Message;
+                let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
+                    .saturating_add(app.input_scroll as usize);
+                let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
+                let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
+                app.message_input.set_cursor(pos, false);
+                app.message_input.sel_anchor = Some(pos);
+            }
 
-                let r = app.conv_rect;
-                let scrollbar_col = r.x + r.width.saturating_sub(1);
-                if mouse.column == scrollbar_col && r.height > 2 {
-                    let track_top = r.y + 1;
-                    let track_bottom = r.y + r.height.saturating_sub(2);
-                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                    if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
-                        let ratio = (mouse.row - track_top) as f32 / track_len as f32;
-                        let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
-                        app.chat_scroll = new_scroll;
-                        app.chat_scroll_manual = true;
-                    }
-                } else if mouse.column >= r.x && mouse.column < r.x + r.width
-                    && mouse.row >= r.y && mouse.row < r.y + r.height {
-                    // Start a new text selection — store as content line index
-                    let inner_top = r.y + 1;
-                    let eff = if app.chat_scroll_manual {
-                        app.chat_scroll.min(app.conv_max_scroll)
-                    } else {
-                        app.conv_max_scroll
-                    };
-                    let idx = (mouse.row.saturating_sub(inner_top) as usize)
-                        .saturating_add(eff as usize);
-                    app.sel_start = Some(idx);
-                    app.sel_end = Some(idx);
+            let r = app.conv_rect;
+            let scrollbar_col = r.x + r.width.saturating_sub(1);
+            if mouse.column == scrollbar_col && r.height > 2 {
+                let track_top = r.y + 1;
+                let track_bottom = r.y + r.height.saturating_sub(2);
+                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
+                    let ratio = (mouse.row - track_top) as f32 / track_len as f32;
+                    let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
+                    app.chat_scroll = new_scroll;
+                    app.chat_scroll_manual = true;
                 }
+            } else if mouse.column >= r.x && mouse.column < r.x + r.width
+                && mouse.row >= r.y && mouse.row < r.y + r.height {
+                // Start a new text selection — store as content line index
+                let inner_top = r.y + 1;
+                let eff = if app.chat_scroll_manual {
+                    app.chat_scroll.min(app.conv_max_scroll)
+                } else {
+                    app.conv_max_scroll
+                };
+                let idx = (mouse.row.saturating_sub(inner_top) as usize)
+                    .saturating_add(eff as usize);
+                app.sel_start = Some(idx);
+                app.sel_end = Some(idx);
             }
         }
-        MouseEventKind::Drag(_) => {
-            if app.screen == Screen::Chat {
-                // Input scrollbar drag hit-test
-                let ir = app.input_rect;
-                let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
-                if mouse.column == input_scrollbar_col && ir.height > 2
-                    && mouse.row >= ir.y && mouse.row < ir.y + ir.height
-                    && app.input_max_scroll_stored > 0 {
-                    let track_top = ir.y + 1;
-                    let track_bottom = ir.y + ir.height.saturating_sub(2);
-                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                    if track_len > 0 {
-                        let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
-                        app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
-                    }
-                }
-                if app.message_input.sel_anchor.is_some()
-                    && mouse.column >= ir.x + 1
-                    && mouse.column < ir.x + ir.width.saturating_sub(1)
-                    && mouse.row >= ir.y + 1
-                    && mouse.row < ir.y + ir.height.saturating_sub(1) {
-                    let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
-                        .saturating_add(app.input_scroll as usize);
-                    let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
-                    let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
-                    app.message_input.set_cursor(pos, true);
+        MouseEventKind::Drag(_) if app.screen == Screen::Chat => {
+            // Input scrollbar drag hit-test
+            let ir = app.input_rect;
+            let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
+            if mouse.column == input_scrollbar_col && ir.height > 2
+                && mouse.row >= ir.y && mouse.row < ir.y + ir.height
+                && app.input_max_scroll_stored > 0 {
+                let track_top = ir.y + 1;
+                let track_bottom = ir.y + ir.height.saturating_sub(2);
+                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                if track_len > 0 {
+                    let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
+                    app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
                 }
+            }
+            if app.message_input.sel_anchor.is_some()
+                && mouse.column > ir.x
+                && mouse.column < ir.x + ir.width.saturating_sub(1)
+                && mouse.row > ir.y
+                && mouse.row < ir.y + ir.height.saturating_sub(1) {
+                let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
+                    .saturating_add(app.input_scroll as usize);
+                let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
+                let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
+                app.message_input.set_cursor(pos, true);
+            }
 
-                let r = app.conv_rect;
-                let scrollbar_col = r.x + r.width.saturating_sub(1);
-                if mouse.column == scrollbar_col && r.height > 2 {
-                    let track_top = r.y + 1;
-                    let track_bottom = r.y + r.height.saturating_sub(2);
-                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                    if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
-                        let ratio = (mouse.row - track_top) as f32 / track_len as f32;
-                        let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
-                        app.chat_scroll = new_scroll;
-                        app.chat_scroll_manual = true;
-                    }
-                } else if app.sel_start.is_some()
-                    && mouse.column >= r.x && mouse.column < r.x + r.width
-                    && mouse.row >= r.y && mouse.row < r.y + r.height {
-                    // Extend selection — store as content line index
-                    let inner_top = r.y + 1;
-                    let eff = if app.chat_scroll_manual {
-                        app.chat_scroll.min(app.conv_max_scroll)
-                    } else {
-                        app.conv_max_scroll
-                    };
-                    let idx = (mouse.row.saturating_sub(inner_top) as usize)
-                        .saturating_add(eff as usize);
-                    app.sel_end = Some(idx);
+            let r = app.conv_rect;
+            let scrollbar_col = r.x + r.width.saturating_sub(1);
+            if mouse.column == scrollbar_col && r.height > 2 {
+                let track_top = r.y + 1;
+                let track_bottom = r.y + r.height.saturating_sub(2);
+                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
+                    let ratio = (mouse.row - track_top) as f32 / track_len as f32;
+                    let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
+                    app.chat_scroll = new_scroll;
+                    app.chat_scroll_manual = true;
                 }
+            } else if app.sel_start.is_some()
+                && mouse.column >= r.x && mouse.column < r.x + r.width
+                && mouse.row >= r.y && mouse.row < r.y + r.height {
+                // Extend selection — store as content line index
+                let inner_top = r.y + 1;
+                let eff = if app.chat_scroll_manual {
+                    app.chat_scroll.min(app.conv_max_scroll)
+                } else {
+                    app.conv_max_scroll
+                };
+                let idx = (mouse.row.saturating_sub(inner_top) as usize)
+                    .saturating_add(eff as usize);
+                app.sel_end = Some(idx);
             }
         }
         MouseEventKind::Up(_) => {
@@ -586,4 +599,30 @@ pub async fn handle_mouse_event(
         _ => {}
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_copy_shortcut, is_select_all_shortcut};
+    use crossterm::event::KeyModifiers;
+
+    #[test]
+    fn copy_shortcut_on_macos_accepts_cmd_and_ctrl() {
+        assert!(is_copy_shortcut('c', KeyModifiers::SUPER, true));
+        assert!(is_copy_shortcut('c', KeyModifiers::META, true));
+        assert!(is_copy_shortcut('c', KeyModifiers::CONTROL, true));
+    }
+
+    #[test]
+    fn copy_shortcut_on_non_macos_accepts_only_ctrl() {
+        assert!(is_copy_shortcut('c', KeyModifiers::CONTROL, false));
+        assert!(!is_copy_shortcut('c', KeyModifiers::SUPER, false));
+    }
+
+    #[test]
+    fn select_all_shortcut_on_macos_accepts_cmd_only() {
+        assert!(is_select_all_shortcut('a', KeyModifiers::SUPER, true));
+        assert!(is_select_all_shortcut('a', KeyModifiers::META, true));
+        assert!(!is_select_all_shortcut('a', KeyModifiers::CONTROL, true));
+    }
+}
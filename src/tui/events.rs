@@ -15,8 +15,14 @@ use ratatui::{
 };
 use crate::tui::state::{App, Screen, ChatFocus, MENU_ITEMS};
 use crate::tui::providers::Provider;
-use crate::tui::api::{save_api_token, fetch_ollama_models, fetch_github_models, stream_message, StreamRequest};
-use crate::agent::ReActAgent;
+use crate::tui::api::{fetch_ollama_models, fetch_github_models, stream_message, StreamRequest};
+use crate::agent::{
+    approval::ApprovalRequest, events::run_as_markdown, tools::ToolContext, Checkpoints, EventLog, McpServers, ReActAgent,
+    ToolMode, ToolRegistry, Workspace,
+};
+use crate::config::Config;
+use crate::sessions::{Session, SessionStore};
+use crate::tui::approval::{handle_approval_key, PendingApproval};
 use crate::tui::draw::draw;
 use crate::tui::input::{TextInput, handle_text_input_key};
 use crate::tui::util::strip_model_tags;
@@ -61,7 +67,14 @@ fn copy_to_clipboard(text: String) -> bool {
 
 // ── Entry point ───────────────────────────────────────────────────────────────
 
-pub async fn run(prompt_path: PathBuf) -> Result<()> {
+pub async fn run(
+    prompt_path: PathBuf,
+    workspace: Workspace,
+    resume: Option<Session>,
+    event_log: Option<EventLog>,
+    mcp: McpServers,
+    config: Config,
+) -> Result<()> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     // Try to enable kitty keyboard protocol so terminals that support it
@@ -78,6 +91,17 @@ pub async fn run(prompt_path: PathBuf) -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     let mut app = App::new(prompt_path);
+    app.apply_config(config);
+    app.workspace = workspace;
+    app.session_store = SessionStore::open_default().ok();
+    app.event_log = event_log;
+    if !mcp.errors().is_empty() {
+        app.status = mcp.errors().join("; ");
+    }
+    app.mcp = mcp;
+    if let Some(session) = resume {
+        app.resume_session(session);
+    }
     let result = event_loop(&mut terminal, &mut app).await;
 
     disable_raw_mode()?;
@@ -86,6 +110,18 @@ pub async fn run(prompt_path: PathBuf) -> Result<()> {
     }
     execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
     terminal.show_cursor()?;
+    // Quitting from the token field still keeps what was typed
+    if app.api_token_edited {
+        app.save_provider_token();
+        if app.status.starts_with('⚠') {
+            eprintln!("{}", app.status);
+        }
+    }
+    if let Some(log) = &app.event_log {
+        // Let a run still in flight record how it ended before the summary
+        app.cancel_token.cancel();
+        log.close().await;
+    }
     result
 }
 
@@ -99,8 +135,13 @@ async fn event_loop(
     let mut tick = tokio::time::interval(std::time::Duration::from_millis(50));
     // Channel for streaming token chunks: sender given to spawn, receiver polled here
     let (stream_tx, mut stream_rx) = mpsc::unbounded_channel::<Option<String>>();
+    // Channel for agent tool calls that need the user's approval
+    let (approval_tx, mut approval_rx) = mpsc::unbounded_channel::<ApprovalRequest>();
+    app.approval_tx = Some(approval_tx);
 
     loop {
+        // Keys and clicks can both move focus off the token field
+        app.save_token_on_blur();
         terminal.draw(|f| draw(f, app))?;
 
         tokio::select! {
@@ -109,7 +150,7 @@ async fn event_loop(
                 let _ = app.streaming; // keep redraw active while thinking
                 if let Some(saved_at) = app.token_saved_at {
                     if saved_at.elapsed() >= std::time::Duration::from_secs(3) {
-                        if app.status == "✓ API token saved" {
+                        if app.status.starts_with("✓ API token saved") {
                             app.status = String::new();
                         }
                         app.token_saved_at = None;
@@ -137,10 +178,18 @@ async fn event_loop(
                         // Stream finished
                         app.streaming = false;
                         app.status = String::new();
+                        app.pending_approval = None;
+                        app.save_session();
                     }
                 }
             }
 
+            // An agent tool call waiting for approval
+            Some(request) = approval_rx.recv() => {
+                app.status = format!("⏸ Approve `{}`?", request.tool);
+                app.pending_approval = Some(PendingApproval::new(request));
+            }
+
             // Keyboard / terminal events
             Some(Ok(event)) = event_stream.next() => {
                 // Trackpad / mouse scroll — works on any screen
@@ -176,115 +225,111 @@ async fn event_loop(
                         }
                         // Scrollbar click or drag — hit-test against the right edge of conv_rect or input_rect
                         // Also track mouse selection inside the conversation area
-                        MouseEventKind::Down(_) => {
-                            if app.screen == Screen::Chat {
-                                // Input scrollbar hit-test
-                                let ir = app.input_rect;
-                                let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
-                                if mouse.column == input_scrollbar_col && ir.height > 2
-                                    && mouse.row >= ir.y && mouse.row < ir.y + ir.height
-                                    && app.input_max_scroll_stored > 0 {
-                                    let track_top = ir.y + 1;
-                                    let track_bottom = ir.y + ir.height.saturating_sub(2);
-                                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                                    if track_len > 0 {
-                                        let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
-                                        app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
-                                    }
-                                }
-                                if mouse.column >= ir.x + 1
-                                    && mouse.column < ir.x + ir.width.saturating_sub(1)
-                                    && mouse.row >= ir.y + 1
-                                    && mouse.row < ir.y + ir.height.saturating_sub(1) {
-                                    app.chat_focus = ChatFocus::Message;
-                                    let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
-                                        .saturating_add(app.input_scroll as usize);
-                                    let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
-                                    let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
-                                    app.message_input.set_cursor(pos, false);
-                                    app.message_input.sel_anchor = Some(pos);
+                        MouseEventKind::Down(_) if app.screen == Screen::Chat => {
+                            // Input scrollbar hit-test
+                            let ir = app.input_rect;
+                            let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
+                            if mouse.column == input_scrollbar_col && ir.height > 2
+                                && mouse.row >= ir.y && mouse.row < ir.y + ir.height
+                                && app.input_max_scroll_stored > 0 {
+                                let track_top = ir.y + 1;
+                                let track_bottom = ir.y + ir.height.saturating_sub(2);
+                                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                                if track_len > 0 {
+                                    let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
+                                    app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
                                 }
-                                let r = app.conv_rect;
-                                let scrollbar_col = r.x + r.width.saturating_sub(1);
-                                if mouse.column == scrollbar_col && r.height > 2 {
-                                    let track_top = r.y + 1;
-                                    let track_bottom = r.y + r.height.saturating_sub(2);
-                                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                                    if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
-                                        let ratio = (mouse.row - track_top) as f32 / track_len as f32;
-                                        let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
-                                        app.chat_scroll = new_scroll;
-                                        app.chat_scroll_manual = true;
-                                    }
-                                } else if mouse.column >= r.x && mouse.column < r.x + r.width
-                                    && mouse.row >= r.y && mouse.row < r.y + r.height {
-                                    // Start a new text selection — store as content line index
-                                    let inner_top = r.y + 1;
-                                    let eff = if app.chat_scroll_manual {
-                                        app.chat_scroll.min(app.conv_max_scroll)
-                                    } else {
-                                        app.conv_max_scroll
-                                    };
-                                    let idx = (mouse.row.saturating_sub(inner_top) as usize)
-                                        .saturating_add(eff as usize);
-                                    app.sel_start = Some(idx);
-                                    app.sel_end = Some(idx);
+                            }
+                            if mouse.column > ir.x
+                                && mouse.column < ir.x + ir.width.saturating_sub(1)
+                                && mouse.row > ir.y
+                                && mouse.row < ir.y + ir.height.saturating_sub(1) {
+                                app.chat_focus = ChatFocus::Message;
+                                let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
+                                    .saturating_add(app.input_scroll as usize);
+                                let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
+                                let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
+                                app.message_input.set_cursor(pos, false);
+                                app.message_input.sel_anchor = Some(pos);
+                            }
+                            let r = app.conv_rect;
+                            let scrollbar_col = r.x + r.width.saturating_sub(1);
+                            if mouse.column == scrollbar_col && r.height > 2 {
+                                let track_top = r.y + 1;
+                                let track_bottom = r.y + r.height.saturating_sub(2);
+                                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                                if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
+                                    let ratio = (mouse.row - track_top) as f32 / track_len as f32;
+                                    let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
+                                    app.chat_scroll = new_scroll;
+                                    app.chat_scroll_manual = true;
                                 }
+                            } else if mouse.column >= r.x && mouse.column < r.x + r.width
+                                && mouse.row >= r.y && mouse.row < r.y + r.height {
+                                // Start a new text selection — store as content line index
+                                let inner_top = r.y + 1;
+                                let eff = if app.chat_scroll_manual {
+                                    app.chat_scroll.min(app.conv_max_scroll)
+                                } else {
+                                    app.conv_max_scroll
+                                };
+                                let idx = (mouse.row.saturating_sub(inner_top) as usize)
+                                    .saturating_add(eff as usize);
+                                app.sel_start = Some(idx);
+                                app.sel_end = Some(idx);
                             }
                         }
-                        MouseEventKind::Drag(_) => {
-                            if app.screen == Screen::Chat {
-                                // Input scrollbar drag hit-test
-                                let ir = app.input_rect;
-                                let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
-                                if mouse.column == input_scrollbar_col && ir.height > 2
-                                    && mouse.row >= ir.y && mouse.row < ir.y + ir.height
-                                    && app.input_max_scroll_stored > 0 {
-                                    let track_top = ir.y + 1;
-                                    let track_bottom = ir.y + ir.height.saturating_sub(2);
-                                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                                    if track_len > 0 {
-                                        let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
-                                        app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
-                                    }
+                        MouseEventKind::Drag(_) if app.screen == Screen::Chat => {
+                            // Input scrollbar drag hit-test
+                            let ir = app.input_rect;
+                            let input_scrollbar_col = ir.x + ir.width.saturating_sub(1);
+                            if mouse.column == input_scrollbar_col && ir.height > 2
+                                && mouse.row >= ir.y && mouse.row < ir.y + ir.height
+                                && app.input_max_scroll_stored > 0 {
+                                let track_top = ir.y + 1;
+                                let track_bottom = ir.y + ir.height.saturating_sub(2);
+                                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                                if track_len > 0 {
+                                    let ratio = (mouse.row.saturating_sub(track_top)) as f32 / track_len as f32;
+                                    app.input_scroll = (ratio * app.input_max_scroll_stored as f32).round() as u16;
                                 }
-                                if app.message_input.sel_anchor.is_some()
-                                    && mouse.column >= ir.x + 1
-                                    && mouse.column < ir.x + ir.width.saturating_sub(1)
-                                    && mouse.row >= ir.y + 1
-                                    && mouse.row < ir.y + ir.height.saturating_sub(1) {
-                                    let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
-                                        .saturating_add(app.input_scroll as usize);
-                                    let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
-                                    let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
-                                    app.message_input.set_cursor(pos, true);
-                                }
-                                let r = app.conv_rect;
-                                let scrollbar_col = r.x + r.width.saturating_sub(1);
-                                if mouse.column == scrollbar_col && r.height > 2 {
-                                    let track_top = r.y + 1;
-                                    let track_bottom = r.y + r.height.saturating_sub(2);
-                                    let track_len = track_bottom.saturating_sub(track_top) as usize;
-                                    if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
-                                        let ratio = (mouse.row - track_top) as f32 / track_len as f32;
-                                        let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
-                                        app.chat_scroll = new_scroll;
-                                        app.chat_scroll_manual = true;
-                                    }
-                                } else if app.sel_start.is_some()
-                                    && mouse.column >= r.x && mouse.column < r.x + r.width
-                                    && mouse.row >= r.y && mouse.row < r.y + r.height {
-                                    // Extend selection — store as content line index
-                                    let inner_top = r.y + 1;
-                                    let eff = if app.chat_scroll_manual {
-                                        app.chat_scroll.min(app.conv_max_scroll)
-                                    } else {
-                                        app.conv_max_scroll
-                                    };
-                                    let idx = (mouse.row.saturating_sub(inner_top) as usize)
-                                        .saturating_add(eff as usize);
-                                    app.sel_end = Some(idx);
+                            }
+                            if app.message_input.sel_anchor.is_some()
+                                && mouse.column > ir.x
+                                && mouse.column < ir.x + ir.width.saturating_sub(1)
+                                && mouse.row > ir.y
+                                && mouse.row < ir.y + ir.height.saturating_sub(1) {
+                                let visual_row = (mouse.row.saturating_sub(ir.y + 1) as usize)
+                                    .saturating_add(app.input_scroll as usize);
+                                let visual_col = mouse.column.saturating_sub(ir.x + 1) as usize;
+                                let pos = app.message_input.byte_pos_at_visual(app.input_inner_width, visual_row, visual_col);
+                                app.message_input.set_cursor(pos, true);
+                            }
+                            let r = app.conv_rect;
+                            let scrollbar_col = r.x + r.width.saturating_sub(1);
+                            if mouse.column == scrollbar_col && r.height > 2 {
+                                let track_top = r.y + 1;
+                                let track_bottom = r.y + r.height.saturating_sub(2);
+                                let track_len = track_bottom.saturating_sub(track_top) as usize;
+                                if track_len > 0 && mouse.row >= track_top && mouse.row <= track_bottom {
+                                    let ratio = (mouse.row - track_top) as f32 / track_len as f32;
+                                    let new_scroll = (ratio * app.conv_max_scroll as f32).round() as u16;
+                                    app.chat_scroll = new_scroll;
+                                    app.chat_scroll_manual = true;
                                 }
+                            } else if app.sel_start.is_some()
+                                && mouse.column >= r.x && mouse.column < r.x + r.width
+                                && mouse.row >= r.y && mouse.row < r.y + r.height {
+                                // Extend selection — store as content line index
+                                let inner_top = r.y + 1;
+                                let eff = if app.chat_scroll_manual {
+                                    app.chat_scroll.min(app.conv_max_scroll)
+                                } else {
+                                    app.conv_max_scroll
+                                };
+                                let idx = (mouse.row.saturating_sub(inner_top) as usize)
+                                    .saturating_add(eff as usize);
+                                app.sel_end = Some(idx);
                             }
                         }
                         MouseEventKind::Up(_) => {
@@ -295,6 +340,10 @@ async fn event_loop(
                     }
                 }
                 if let Event::Key(key) = event {
+                    // The approval modal takes every key until it is answered
+                    if handle_approval_key(app, key) {
+                        continue;
+                    }
                     match &app.screen {
                         Screen::Menu => match key.code {
                             KeyCode::Char('q') | KeyCode::Char('Q') => return Ok(()),
@@ -314,7 +363,8 @@ async fn event_loop(
                                     2 => app.screen = Screen::Validate,
                                     3 => app.screen = Screen::Tools,
                                     4 => app.screen = Screen::Chat,
-                                    5 => return Ok(()),
+                                    5 => app.open_sessions(),
+                                    6 => return Ok(()),
                                     _ => {}
                                 }
                             }
@@ -338,8 +388,7 @@ async fn event_loop(
                                 app.tools_provider_list_state.select(Some(i));
                             }
                             KeyCode::Enter => {
-                                app.provider_index = app.tools_provider_index;
-                                app.provider_list_state.select(Some(app.tools_provider_index));
+                                app.select_provider(app.tools_provider_index);
                                 app.screen = Screen::Chat;
                             }
                             _ => {}
@@ -352,6 +401,15 @@ async fn event_loop(
                             }
                             _ => {}
                         },
+                        Screen::Sessions => match key.code {
+                            KeyCode::Esc | KeyCode::Char('q') => app.screen = Screen::Menu,
+                            KeyCode::Up | KeyCode::Char('k') => app.select_session(-1),
+                            KeyCode::Down | KeyCode::Char('j') => app.select_session(1),
+                            KeyCode::Enter => app.resume_selected_session(),
+                            KeyCode::Char('d') | KeyCode::Delete => app.delete_selected_session(),
+                            KeyCode::Char('n') => app.new_session(),
+                            _ => {}
+                        },
                         Screen::Chat => {
                             handle_chat_key(app, key, stream_tx.clone()).await?;
                         }
@@ -367,6 +425,10 @@ async fn handle_chat_key(
     key: crossterm::event::KeyEvent,
     stream_tx: mpsc::UnboundedSender<Option<String>>,
 ) -> Result<()> {
+    if let Some(action) = app.keymap.action_for(&key) {
+        app.run_key_action(action);
+        return Ok(());
+    }
     match key.code {
         KeyCode::Esc => {
             if app.streaming {
@@ -387,14 +449,6 @@ async fn handle_chat_key(
                 app.screen = Screen::Menu;
             }
         }
-        KeyCode::F(2) => {
-            app.agent_mode = !app.agent_mode;
-            app.status = if app.agent_mode {
-                "🤖 Agent Mode ON (ReAct loop) — F2 to toggle".to_string()
-            } else {
-                "💬 Chat Mode — F2 to enable Agent Mode".to_string()
-            };
-        }
         KeyCode::Tab => {
             let is_ollama = app.selected_provider() == Provider::Ollama;
             let is_github = app.selected_provider() == Provider::GitHubModels;
@@ -405,10 +459,8 @@ async fn handle_chat_key(
                             fetch_ollama_models(app).await;
                         }
                         ChatFocus::ModelList
-                    } else if is_github {
-                        ChatFocus::Token
                     } else {
-                        ChatFocus::Token
+                        ChatFocus::CustomUrl
                     }
                 }
                 ChatFocus::ModelList => {
@@ -438,14 +490,14 @@ async fn handle_chat_key(
             app.chat_focus = match app.chat_focus {
                 ChatFocus::ProviderList => ChatFocus::Conversation,
                 ChatFocus::ModelList => ChatFocus::ProviderList,
-                ChatFocus::Token => {
+                ChatFocus::Token => ChatFocus::CustomUrl,
+                ChatFocus::CustomUrl => {
                     if is_ollama {
-                        ChatFocus::CustomUrl
+                        ChatFocus::ModelList
                     } else {
                         ChatFocus::ProviderList
                     }
                 }
-                ChatFocus::CustomUrl => ChatFocus::ModelList,
                 ChatFocus::Conversation => ChatFocus::Message,
                 ChatFocus::Message => ChatFocus::Token,
             };
@@ -478,15 +530,7 @@ async fn handle_chat_key(
                 app.chat_scroll = app.chat_scroll.saturating_sub(3);
                 app.chat_scroll_manual = true;
             }
-            ChatFocus::ProviderList => {
-                let i = app.provider_index.saturating_sub(1);
-                app.provider_index = i;
-                app.provider_list_state.select(Some(i));
-                // reset model list when provider changes
-                app.ollama_models.clear();
-                app.model_input.clear();
-                app.model_list_state.select(Some(0));
-            }
+            ChatFocus::ProviderList => app.select_provider(app.provider_index.saturating_sub(1)),
             ChatFocus::ModelList => {
                 let max = app.ollama_models.len().saturating_sub(1);
                 let i = app.model_list_state.selected().unwrap_or(0).saturating_sub(1);
@@ -504,15 +548,7 @@ async fn handle_chat_key(
                 app.chat_scroll = app.chat_scroll.saturating_add(3);
                 app.chat_scroll_manual = true;
             }
-            ChatFocus::ProviderList => {
-                let i = (app.provider_index + 1).min(Provider::all().len() - 1);
-                app.provider_index = i;
-                app.provider_list_state.select(Some(i));
-                // reset model list when provider changes
-                app.ollama_models.clear();
-                app.model_input.clear();
-                app.model_list_state.select(Some(0));
-            }
+            ChatFocus::ProviderList => app.select_provider(app.provider_index + 1),
             ChatFocus::ModelList => {
                 let max = app.ollama_models.len().saturating_sub(1);
                 let i = (app.model_list_state.selected().unwrap_or(0) + 1).min(max);
@@ -599,18 +635,37 @@ async fn handle_chat_key(
                     let tx = stream_tx.clone();
                     let cancel = app.cancel_token.clone();
                     let agent_mode = app.agent_mode;
+                    let tool_mode = if app.native_tools { ToolMode::Native } else { ToolMode::Xml };
+                    let approval_policy = app.approval_policy.clone();
+                    let limits = app.config.limits();
+                    let auth = app.config.auth_for(provider);
+                    let max_tokens = app.config.max_tokens;
+                    let approval_tx = app.approval_tx.clone();
+                    let workspace = app.workspace.clone();
+                    // Fresh checkpoints per run; undo_step/revert_run roll back the latest one
+                    let checkpoints = Checkpoints::new();
+                    app.checkpoints = checkpoints.clone();
+                    let event_log = app.event_log.clone();
+                    let mcp_tools = app.mcp.tools();
                     tokio::spawn(async move {
                         if agent_mode {
-                            let agent = ReActAgent::new(
+                            let mut agent = ReActAgent::new(
                                 provider, token, custom_url, model, system_prompt,
-                            );
-                            if let Err(e) = agent.run(task, prior, tx.clone()).await {
-                                let _ = tx.send(Some(format!("\n[Agent error: {e}]")));
-                                let _ = tx.send(None);
-                            }
+                            )
+                            .with_auth(auth)
+                            .with_max_tokens(max_tokens)
+                            .with_tool_mode(tool_mode)
+                            .with_limits(limits)
+                            .with_approval(approval_policy, approval_tx)
+                            .with_tools(ToolRegistry::builtin_with(
+                                ToolContext::new(workspace).with_checkpoints(checkpoints.clone()),
+                            ).with_extra_tools(mcp_tools))
+                            .with_checkpoints(checkpoints);
+                            agent.event_log = event_log;
+                            run_as_markdown(&agent, task, prior, tx, cancel).await;
                         } else if let Err(e) = stream_message(StreamRequest {
-                            provider, api_token: token, custom_url, model, system_prompt, history,
-                            tx: tx.clone(), cancel,
+                            provider, api_token: token, custom_url, auth, model, max_tokens, system_prompt, history,
+                            tx: tx.clone(), cancel, events: event_log.as_ref().map(EventLog::sender),
                         }).await {
                             let _ = tx.send(Some(format!("\n[Error: {e}]")));
                             let _ = tx.send(None);
@@ -621,7 +676,10 @@ async fn handle_chat_key(
             _ => {}
         },
         KeyCode::Backspace => match app.chat_focus {
-            ChatFocus::Token => { app.api_token.pop(); }
+            ChatFocus::Token => {
+                app.api_token.pop();
+                app.api_token_edited = true;
+            }
             ChatFocus::CustomUrl => { app.custom_url.pop(); }
             ChatFocus::Message => { handle_text_input_key(&mut app.message_input, key, app.input_inner_width); }
             _ => {}
@@ -639,15 +697,11 @@ async fn handle_chat_key(
                         app.message_input.select_all();
                         app.status = "Selected all message text".to_string();
                     }
-                    ChatFocus::Token => {
-                        if copy_to_clipboard(app.api_token.clone()) {
-                            app.status = "📋 Copied all token text".to_string();
-                        }
+                    ChatFocus::Token if copy_to_clipboard(app.api_token.clone()) => {
+                        app.status = "📋 Copied all token text".to_string();
                     }
-                    ChatFocus::CustomUrl => {
-                        if copy_to_clipboard(app.custom_url.clone()) {
-                            app.status = "📋 Copied all URL text".to_string();
-                        }
+                    ChatFocus::CustomUrl if copy_to_clipboard(app.custom_url.clone()) => {
+                        app.status = "📋 Copied all URL text".to_string();
                     }
                     _ => {}
                 }
@@ -715,11 +769,7 @@ async fn handle_chat_key(
             match app.chat_focus {
                 ChatFocus::Token => {
                     app.api_token.push(c);
-                    // Save token on every keystroke
-                    let _ = save_api_token(&app.api_token);
-                    app.api_token_saved = true;
-                    app.token_saved_at = Some(Instant::now());
-                    app.status = "✓ API token saved".to_string();
+                    app.api_token_edited = true;
                 }
                 ChatFocus::CustomUrl => app.custom_url.push(c),
                 ChatFocus::Message => { handle_text_input_key(&mut app.message_input, key, app.input_inner_width); }
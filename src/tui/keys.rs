@@ -0,0 +1,152 @@
+// Rebindable Chat-screen shortcuts.
+// `[tui.keybindings]` in config.toml maps an action name to a key such as
+// `f6` or `ctrl+t`; unbound actions keep their default function key.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+
+/// An action a Chat-screen shortcut triggers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyAction {
+    AgentMode,
+    NativeTools,
+    UndoStep,
+    RevertRun,
+}
+
+impl KeyAction {
+    pub fn all() -> &'static [KeyAction] {
+        &[KeyAction::AgentMode, KeyAction::NativeTools, KeyAction::UndoStep, KeyAction::RevertRun]
+    }
+
+    /// The name used in `[tui.keybindings]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::AgentMode => "agent_mode",
+            KeyAction::NativeTools => "native_tools",
+            KeyAction::UndoStep => "undo_step",
+            KeyAction::RevertRun => "revert_run",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyAction> {
+        KeyAction::all().iter().copied().find(|a| a.name() == name)
+    }
+
+    fn default_binding(self) -> KeyBinding {
+        let f = match self {
+            KeyAction::AgentMode => 2,
+            KeyAction::NativeTools => 3,
+            KeyAction::UndoStep => 4,
+            KeyAction::RevertRun => 5,
+        };
+        KeyBinding { code: KeyCode::F(f), modifiers: KeyModifiers::NONE }
+    }
+}
+
+/// A key with its modifiers, written like `f2`, `ctrl+t` or `alt+shift+u`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        let code = match key.code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            other => other,
+        };
+        code == self.code && key.modifiers == self.modifiers
+    }
+}
+
+impl std::str::FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let mut parts: Vec<&str> = lower.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|k| !k.is_empty()).ok_or_else(|| format!("empty key in '{s}'"))?;
+        let mut modifiers = KeyModifiers::NONE;
+        for part in parts {
+            modifiers |= match part {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                other => return Err(format!("unknown modifier '{other}' in '{s}' (expected ctrl, alt or shift)")),
+            };
+        }
+        let code = match key {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            f if f.starts_with('f') && f.len() > 1 => match f[1..].parse::<u8>() {
+                Ok(n @ 1..=12) => KeyCode::F(n),
+                _ => return Err(format!("unknown key '{key}' in '{s}'")),
+            },
+            c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().expect("one char")),
+            _ => return Err(format!("unknown key '{key}' in '{s}'")),
+        };
+        // A bare letter would be typed into the message box instead
+        if matches!(code, KeyCode::Char(_)) && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return Err(format!("'{s}' needs ctrl or alt, or it would shadow typing"));
+        }
+        Ok(KeyBinding { code, modifiers })
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [(KeyModifiers::CONTROL, "Ctrl+"), (KeyModifiers::ALT, "Alt+"), (KeyModifiers::SHIFT, "Shift+")] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::PageUp => f.write_str("PageUp"),
+            KeyCode::PageDown => f.write_str("PageDown"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// The Chat-screen shortcuts in effect: defaults with the configured overrides.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keymap {
+    overrides: BTreeMap<KeyAction, KeyBinding>,
+}
+
+impl Keymap {
+    pub fn new(overrides: BTreeMap<KeyAction, KeyBinding>) -> Self {
+        Self { overrides }
+    }
+
+    pub fn binding(&self, action: KeyAction) -> KeyBinding {
+        self.overrides.get(&action).copied().unwrap_or_else(|| action.default_binding())
+    }
+
+    /// The action `key` is bound to, if any.
+    pub fn action_for(&self, key: &KeyEvent) -> Option<KeyAction> {
+        KeyAction::all().iter().copied().find(|&a| self.binding(a).matches(key))
+    }
+
+    /// How to show `action`'s key in hints, e.g. `F2` or `Ctrl+T`.
+    pub fn label(&self, action: KeyAction) -> String {
+        self.binding(action).to_string()
+    }
+}
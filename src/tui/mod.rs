@@ -1,11 +1,14 @@
 pub mod api;
+pub mod approval;
 pub mod draw;
 pub mod event_handlers;
 pub mod events;
 pub mod input;
+pub mod keys;
 pub mod providers;
 pub mod state;
 pub mod state_manager;
+pub mod theme;
 pub mod util;
 
 pub use api::{load_api_token, save_api_token};
@@ -13,7 +16,7 @@ pub use draw::render_to_buffer;
 pub use events::run;
 pub use event_handlers::*;
 pub use input::{handle_text_input_key, TextInput};
-pub use providers::Provider;
+pub use providers::{AuthStyle, Provider};
 pub use state::{App, ChatFocus, Screen};
 pub use state_manager::StateManager;
 pub use util::strip_model_tags;
@@ -1,12 +1,15 @@
 // ── Model providers ──────────────────────────────────────────────────────────
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Provider {
     OpenAI,
     Anthropic,
     XAI,
     Ollama,
     GitHubModels,
+    Zen,
+    /// Any OpenAI-compatible endpoint; its URL comes from config or the Chat screen.
+    Custom,
 }
 
 impl Provider {
@@ -17,6 +20,8 @@ impl Provider {
             Provider::XAI          => "xAI (Grok)",
             Provider::Ollama       => "Ollama (local)",
             Provider::GitHubModels => "GitHub Models",
+            Provider::Zen          => "Zen (OpenCode)",
+            Provider::Custom       => "Custom (OpenAI-compatible)",
         }
     }
     pub fn all() -> &'static [Provider] {
@@ -26,8 +31,35 @@ impl Provider {
             Provider::XAI,
             Provider::Ollama,
             Provider::GitHubModels,
+            Provider::Zen,
+            Provider::Custom,
         ]
     }
+    /// Look up a provider by a short CLI name (`openai`, `anthropic`, `xai`, `ollama`, `github`, `zen`, `custom`).
+    pub fn from_name(name: &str) -> Option<Provider> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "openai"                            => Some(Provider::OpenAI),
+            "anthropic" | "claude"              => Some(Provider::Anthropic),
+            "xai" | "grok"                      => Some(Provider::XAI),
+            "ollama"                            => Some(Provider::Ollama),
+            "github" | "github-models" | "githubmodels" => Some(Provider::GitHubModels),
+            "zen" | "opencode"                  => Some(Provider::Zen),
+            "custom"                            => Some(Provider::Custom),
+            _ => None,
+        }
+    }
+    /// The short name `from_name` accepts, for config and saved sessions.
+    pub fn name(&self) -> &str {
+        match self {
+            Provider::OpenAI       => "openai",
+            Provider::Anthropic    => "anthropic",
+            Provider::XAI          => "xai",
+            Provider::Ollama       => "ollama",
+            Provider::GitHubModels => "github",
+            Provider::Zen          => "zen",
+            Provider::Custom       => "custom",
+        }
+    }
     pub fn default_model(&self) -> &str {
         match self {
             Provider::OpenAI       => "gpt-4o",
@@ -35,8 +67,23 @@ impl Provider {
             Provider::XAI          => "grok-3",
             Provider::Ollama       => "gemma3",
             Provider::GitHubModels => "openai/gpt-4o",
+            Provider::Zen          => "anthropic/claude-sonnet-4-5",
+            Provider::Custom       => "gpt-4o",
+        }
+    }
+    /// Environment variable holding the provider's key; it wins over a stored token.
+    pub fn env_var(&self) -> Option<&str> {
+        match self {
+            Provider::OpenAI       => Some("OPENAI_API_KEY"),
+            Provider::Anthropic    => Some("ANTHROPIC_API_KEY"),
+            Provider::XAI          => Some("XAI_API_KEY"),
+            Provider::Ollama       => None,
+            Provider::GitHubModels => Some("GITHUB_TOKEN"),
+            Provider::Zen          => Some("OPENCODE_API_KEY"),
+            Provider::Custom       => None,
         }
     }
+    /// Default chat endpoint; empty for Custom, which has none.
     pub fn api_url(&self) -> &str {
         match self {
             Provider::OpenAI       => "https://api.openai.com/v1/chat/completions",
@@ -44,8 +91,34 @@ impl Provider {
             Provider::XAI          => "https://api.x.ai/v1/chat/completions",
             Provider::Ollama       => "http://localhost:11434/api/chat",
             Provider::GitHubModels => "https://models.github.com/v1/chat/completions",
+            Provider::Zen          => "https://api.opencode.ai/v1/chat/completions",
+            Provider::Custom       => "",
+        }
+    }
+    /// Path a base URL override is completed with to reach the chat endpoint.
+    pub fn chat_path(&self) -> &str {
+        match self {
+            Provider::Anthropic => "/messages",
+            Provider::Ollama    => "/api/chat",
+            _                   => "/chat/completions",
+        }
+    }
+    /// How the API token is sent unless config says otherwise.
+    pub fn auth_style(&self) -> AuthStyle {
+        match self {
+            Provider::Anthropic => AuthStyle::XApiKey,
+            _                   => AuthStyle::Bearer,
         }
     }
+    /// Local servers and self-hosted endpoints often run without a key.
+    pub fn requires_token(&self) -> bool {
+        !matches!(self, Provider::Ollama | Provider::Custom)
+    }
+    /// Accepts OpenAI's `stream_options` field; other OpenAI-compatible
+    /// servers may reject a field they don't know with a 400.
+    pub fn accepts_stream_options(&self) -> bool {
+        matches!(self, Provider::OpenAI | Provider::XAI)
+    }
     pub fn description(&self) -> &str {
         match self {
             Provider::OpenAI       => "Cloud · Requires API key · https://platform.openai.com/",
@@ -53,7 +126,48 @@ impl Provider {
             Provider::XAI          => "Cloud · Requires API key · https://x.ai/",
             Provider::Ollama       => "Local · No API key needed · https://ollama.com/",
             Provider::GitHubModels => "Cloud · GitHub OAuth token (models:read) · https://github.com/marketplace/models",
+            Provider::Zen          => "Cloud · Requires API key · https://opencode.ai/zen",
+            Provider::Custom       => "Any OpenAI-compatible endpoint · Azure OpenAI, LiteLLM, vLLM, LM Studio",
+        }
+    }
+}
+
+// ── Auth header styles ───────────────────────────────────────────────────────
+
+/// How a request carries the API token.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <token>` (OpenAI and most compatible servers).
+    Bearer,
+    /// `api-key: <token>` (Azure OpenAI).
+    ApiKey,
+    /// `x-api-key: <token>` (Anthropic).
+    XApiKey,
+    /// Send no token at all.
+    None,
+}
+
+impl std::str::FromStr for AuthStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bearer" => Ok(AuthStyle::Bearer),
+            "api-key" => Ok(AuthStyle::ApiKey),
+            "x-api-key" => Ok(AuthStyle::XApiKey),
+            "none" => Ok(AuthStyle::None),
+            other => Err(format!("unknown auth style '{other}' (expected bearer, api-key, x-api-key or none)")),
         }
     }
 }
 
+impl AuthStyle {
+    pub fn name(&self) -> &str {
+        match self {
+            AuthStyle::Bearer => "bearer",
+            AuthStyle::ApiKey => "api-key",
+            AuthStyle::XApiKey => "x-api-key",
+            AuthStyle::None => "none",
+        }
+    }
+}
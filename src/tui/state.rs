@@ -6,8 +6,15 @@ use tokio_util::sync::CancellationToken;
 
 pub mod app_logic;
 
-use crate::tui::api::load_api_token;
+use crate::agent::approval::{ApprovalPolicy, ApprovalSender};
+use crate::agent::{Checkpoints, EventLog, McpServers, Workspace};
+use crate::sessions::{Session, SessionStore};
+use crate::config::{global_config_path, Config, ConfigFile};
+use crate::tui::providers::Provider;
+use crate::tui::approval::PendingApproval;
 use crate::tui::input::TextInput;
+use crate::tui::keys::Keymap;
+use crate::tui::theme::Theme;
 
 // ── Screens ───────────────────────────────────────────────────────────────────
 
@@ -19,6 +26,7 @@ pub enum Screen {
     Validate,
     Tools,
     Chat,
+    Sessions,
 }
 
 // ── Chat input focus ──────────────────────────────────────────────────────────
@@ -63,6 +71,8 @@ pub struct App {
     pub chat_scroll: u16,         // manual scroll offset for the conversation panel
     pub chat_scroll_manual: bool, // true when user has scrolled up manually
     pub api_token_saved: bool,
+    /// Typed since the last save; saved once focus leaves the token field.
+    pub api_token_edited: bool,
     pub token_saved_at: Option<Instant>,
     pub last_esc_at: Option<Instant>,
     pub cancel_token: CancellationToken,
@@ -83,9 +93,39 @@ pub struct App {
     /// Inner width of the message input box — updated every draw, used for cursor navigation.
     pub input_inner_width: usize,
     pub agent_mode: bool,  // true = route messages through ReActAgent
+    pub native_tools: bool, // true = agent uses native function calling instead of XML tags
+    /// Which agent tool calls need approval (default: ask before mutating tools).
+    pub approval_policy: ApprovalPolicy,
+    /// Where agent runs send approval requests — set by the event loop.
+    pub approval_tx: Option<ApprovalSender>,
+    /// The tool call currently shown in the approval modal.
+    pub pending_approval: Option<PendingApproval>,
+    /// Inner width of the approval modal — updated every draw, used for cursor navigation while editing.
+    pub approval_inner_width: usize,
+    /// Directory the agent's file tools are confined to (default: launch directory).
+    pub workspace: Workspace,
+    /// File edits of the last agent run, for "undo step" (F4) and "revert run" (F5) by default.
+    pub checkpoints: Checkpoints,
+    /// The conversation in Chat, once it has been saved.
+    pub session: Option<Session>,
+    /// Where conversations are saved (`None` = not saved) — set by `run`.
+    pub session_store: Option<SessionStore>,
+    /// Saved sessions shown on the Sessions screen, newest first.
+    pub sessions: Vec<Session>,
+    pub sessions_state: ListState,
+    /// Where chat and agent runs are also logged as JSON lines (`--events-jsonl`).
+    pub event_log: Option<EventLog>,
+    /// Running MCP servers; their tools are offered to agent runs.
+    pub mcp: McpServers,
+    /// Settings from config.toml — set by `run` via `apply_config`.
+    pub config: Config,
+    /// Colours the screens are drawn in (`[tui] theme`).
+    pub theme: Theme,
+    /// Chat-screen shortcuts (`[tui.keybindings]`).
+    pub keymap: Keymap,
 }
 
-pub const MENU_ITEMS: &[&str] = &["Info", "Show Prompt", "Validate", "Tools", "Chat", "Quit"];
+pub const MENU_ITEMS: &[&str] = &["Info", "Show Prompt", "Validate", "Tools", "Chat", "Sessions", "Quit"];
 
 impl App {
     pub fn new(prompt_path: PathBuf) -> Self {
@@ -99,8 +139,11 @@ impl App {
         tools_provider_list_state.select(Some(0));
         let mut model_list_state = ListState::default();
         model_list_state.select(Some(0));
-        // Load saved API token from config file if present
-        let saved_token = load_api_token().unwrap_or_default();
+        // Saved settings and tokens; `run` replaces these with the layered config
+        let config = global_config_path()
+            .and_then(|path| ConfigFile::open(&path).ok()?.config().ok())
+            .unwrap_or_default();
+        let saved_token = config.token_for(Provider::all()[0]).unwrap_or_default();
         let message_input = TextInput::new();
         App {
             screen: Screen::Menu,
@@ -125,6 +168,7 @@ impl App {
             chat_scroll: 0,
             chat_scroll_manual: false,
             api_token_saved: false,
+            api_token_edited: false,
             token_saved_at: None,
             last_esc_at: None,
             cancel_token: CancellationToken::new(),
@@ -137,6 +181,22 @@ impl App {
             input_scroll: 0,
             input_inner_width: 60,
             agent_mode: false,
+            native_tools: false,
+            approval_policy: ApprovalPolicy::default(),
+            approval_tx: None,
+            pending_approval: None,
+            approval_inner_width: 60,
+            workspace: Workspace::launch_dir(),
+            checkpoints: Checkpoints::new(),
+            session: None,
+            session_store: None,
+            sessions: Vec::new(),
+            sessions_state: ListState::default(),
+            event_log: None,
+            mcp: McpServers::default(),
+            theme: config.theme(),
+            keymap: config.keymap(),
+            config,
         }
     }
 }
@@ -1,4 +1,8 @@
-use crate::tui::state::App;
+use crate::config::Config;
+use crate::sessions::Session;
+use crate::tui::state::{App, ChatFocus, Screen};
+use crate::tui::api::save_api_token;
+use crate::tui::keys::KeyAction;
 use crate::tui::providers::Provider;
 
 impl App {
@@ -12,19 +16,237 @@ impl App {
         Provider::all()[self.provider_index]
     }
 
-    /// Returns the model name to use: typed override, or provider default.
+    /// Returns the model name to use: typed override, configured model, or provider default.
     pub fn active_model(&self) -> String {
         let m = self.model_input.trim();
         if m.is_empty() {
-            self.selected_provider().default_model().to_string()
+            let provider = self.selected_provider();
+            self.config.model_for(provider).unwrap_or_else(|| provider.default_model().to_string())
         } else {
             m.to_string()
         }
     }
 
+    /// Select the provider at `index` in Chat, with its own saved token and base URL.
+    pub fn select_provider(&mut self, index: usize) {
+        // Keep the URL typed for the previous provider for when it comes back
+        let url = self.custom_url.trim().to_string();
+        let previous = self.selected_provider();
+        self.config.providers.entry(previous).or_default().url = (!url.is_empty()).then_some(url);
+        let index = index.min(Provider::all().len() - 1);
+        self.provider_index = index;
+        self.provider_list_state.select(Some(index));
+        // The model list belongs to the previous provider
+        self.ollama_models.clear();
+        self.model_input.clear();
+        self.model_list_state.select(Some(0));
+        self.api_token = self.config.token_for(self.selected_provider()).unwrap_or_default();
+        self.custom_url = self.config.url_for(self.selected_provider()).unwrap_or_default();
+    }
+
+    /// Save the token being typed as the selected provider's, leaving the
+    /// other providers' tokens alone.
+    pub fn save_provider_token(&mut self) {
+        let provider = self.selected_provider();
+        self.api_token_edited = false;
+        self.config.providers.entry(provider).or_default().api_token = Some(self.api_token.clone());
+        self.status = match save_api_token(provider, &self.api_token) {
+            Ok(store) => {
+                self.api_token_saved = true;
+                self.token_saved_at = Some(std::time::Instant::now());
+                format!("✓ API token saved for {} in {store}", provider.label())
+            }
+            Err(e) => format!("⚠ Could not save the API token: {e:#}"),
+        };
+    }
+
+    /// Save an edited token once focus has left the token field, rather than
+    /// writing to the secret store on every keystroke.
+    pub fn save_token_on_blur(&mut self) {
+        let editing = self.screen == Screen::Chat && self.chat_focus == ChatFocus::Token;
+        if self.api_token_edited && !editing {
+            self.save_provider_token();
+        }
+    }
+
+    /// Start from `config`: its provider selected with that provider's URL and
+    /// token, its agent mode and approval settings, and its theme and keys.
+    pub fn apply_config(&mut self, config: Config) {
+        let provider = config.default_provider();
+        let index = Provider::all().iter().position(|p| *p == provider).unwrap_or(0);
+        self.provider_index = index;
+        self.provider_list_state.select(Some(index));
+        if let Some(url) = config.url_for(provider) {
+            self.custom_url = url;
+        }
+        if let Some(token) = config.token_for(provider) {
+            self.api_token = token;
+        }
+        if let Some(native_tools) = config.agent.native_tools {
+            self.native_tools = native_tools;
+        }
+        self.approval_policy = config.approval_policy();
+        self.theme = config.theme();
+        self.keymap = config.keymap();
+        self.config = config;
+    }
+
+    /// Run the Chat-screen action a shortcut is bound to.
+    pub fn run_key_action(&mut self, action: KeyAction) {
+        match action {
+            KeyAction::AgentMode => self.toggle_agent_mode(),
+            KeyAction::NativeTools => self.toggle_native_tools(),
+            KeyAction::UndoStep => self.undo_agent_step(),
+            KeyAction::RevertRun => self.revert_agent_run(),
+        }
+    }
+
+    pub fn toggle_agent_mode(&mut self) {
+        self.agent_mode = !self.agent_mode;
+        let key = self.keymap.label(KeyAction::AgentMode);
+        self.status = if self.agent_mode {
+            format!("🤖 Agent Mode ON (ReAct loop) — {key} to toggle")
+        } else {
+            format!("💬 Chat Mode — {key} to enable Agent Mode")
+        };
+    }
+
+    /// Switch agent runs between native tool calling and XML tags.
+    pub fn toggle_native_tools(&mut self) {
+        self.native_tools = !self.native_tools;
+        let key = self.keymap.label(KeyAction::NativeTools);
+        self.status = if self.native_tools {
+            format!("🛠 Native tool calling ON — {key} to use XML tags")
+        } else {
+            format!("🏷 XML tool tags — {key} to enable native tool calling")
+        };
+    }
+
     /// Returns true if the current provider is Ollama.
     pub fn is_ollama_provider(&self) -> bool {
         self.selected_provider() == Provider::Ollama
     }
 
-}
\ No newline at end of file
+    /// Revert the file edits of the last agent step that made any.
+    pub fn undo_agent_step(&mut self) {
+        if self.streaming {
+            self.status = "Wait for the agent to finish before undoing".to_string();
+            return;
+        }
+        self.status = match self.checkpoints.undo_step() {
+            Some((step, reports)) => format!("↩ Undid step {step}: {}", reports.join("; ")),
+            None => "Nothing to undo".to_string(),
+        };
+    }
+
+    /// Revert every file edit of the last agent run.
+    pub fn revert_agent_run(&mut self) {
+        if self.streaming {
+            self.status = "Wait for the agent to finish before reverting".to_string();
+            return;
+        }
+        let reports = self.checkpoints.revert_all();
+        self.status = if reports.is_empty() {
+            "Nothing to revert".to_string()
+        } else {
+            format!("⏪ Reverted the run: {}", reports.join("; "))
+        };
+    }
+
+    /// Save the conversation in Chat to the session store, starting a
+    /// session on its first save.
+    pub fn save_session(&mut self) {
+        let Some(store) = &self.session_store else { return };
+        if self.messages.is_empty() {
+            return;
+        }
+        let provider = self.selected_provider();
+        let model = self.active_model();
+        let session = self.session.get_or_insert_with(|| Session::new(provider, &model, self.agent_mode));
+        session.provider = provider;
+        session.model = model;
+        session.agent_mode = self.agent_mode;
+        session.set_messages(&self.messages);
+        if let Err(e) = store.save(session) {
+            self.status = format!("⚠ Could not save session: {e:#}");
+        }
+    }
+
+    /// Load the saved sessions and show the Sessions screen.
+    pub fn open_sessions(&mut self) {
+        self.sessions = match &self.session_store {
+            Some(store) => store.list().unwrap_or_else(|e| {
+                self.status = format!("⚠ Could not list sessions: {e:#}");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        self.sessions_state.select(if self.sessions.is_empty() { None } else { Some(0) });
+        self.screen = Screen::Sessions;
+    }
+
+    /// Continue `session` in Chat with its provider, model and mode.
+    pub fn resume_session(&mut self, session: Session) {
+        let provider_index = Provider::all().iter().position(|p| *p == session.provider).unwrap_or(0);
+        self.select_provider(provider_index);
+        self.model_input = session.model.clone();
+        self.agent_mode = session.agent_mode;
+        // The agent gets these as prior history on the next message
+        self.messages = session.messages.clone();
+        self.status = format!("↻ Resumed \"{}\"", session.title);
+        self.session = Some(session);
+        self.chat_scroll_manual = false;
+        self.chat_focus = ChatFocus::Message;
+        self.screen = Screen::Chat;
+    }
+
+    /// Resume the session selected on the Sessions screen.
+    pub fn resume_selected_session(&mut self) {
+        let selected = self.sessions_state.selected().and_then(|i| self.sessions.get(i)).cloned();
+        if let Some(session) = selected {
+            self.resume_session(session);
+        }
+    }
+
+    /// Delete the session selected on the Sessions screen.
+    pub fn delete_selected_session(&mut self) {
+        let Some(i) = self.sessions_state.selected().filter(|&i| i < self.sessions.len()) else { return };
+        let Some(store) = &self.session_store else { return };
+        match store.delete(&self.sessions[i].id) {
+            Ok(id) => {
+                let removed = self.sessions.remove(i);
+                if self.session.as_ref().is_some_and(|s| s.id == id) {
+                    self.session = None;
+                }
+                self.status = format!("🗑 Deleted \"{}\"", removed.title);
+            }
+            Err(e) => self.status = format!("⚠ Could not delete session: {e:#}"),
+        }
+        let last = self.sessions.len().checked_sub(1);
+        self.sessions_state.select(last.map(|last| i.min(last)));
+    }
+
+    /// Leave the current conversation and open an empty Chat.
+    pub fn new_session(&mut self) {
+        if self.streaming {
+            self.status = "Wait for the reply to finish before starting a new chat".to_string();
+            return;
+        }
+        self.messages.clear();
+        self.session = None;
+        self.chat_scroll = 0;
+        self.chat_scroll_manual = false;
+        self.status = String::new();
+        self.chat_focus = ChatFocus::Message;
+        self.screen = Screen::Chat;
+    }
+
+    /// Move the Sessions screen selection by `delta`.
+    pub fn select_session(&mut self, delta: isize) {
+        if self.sessions.is_empty() {
+            return;
+        }
+        let i = self.sessions_state.selected().unwrap_or(0).saturating_add_signed(delta);
+        self.sessions_state.select(Some(i.min(self.sessions.len() - 1)));
+    }
+}
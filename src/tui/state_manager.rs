@@ -24,6 +24,7 @@ impl StateManager {
     // Screen navigation methods
     pub fn navigate_to_menu(&mut self) {
         self.app.screen = Screen::Menu;
+        self.app.save_token_on_blur();
     }
 
     pub fn navigate_to_chat(&mut self) {
@@ -47,6 +48,10 @@ impl StateManager {
         self.app.screen = Screen::Tools;
     }
 
+    pub fn navigate_to_sessions(&mut self) {
+        self.app.open_sessions();
+    }
+
     // Chat focus management
     pub fn cycle_chat_focus(&mut self, forward: bool) {
         let is_ollama = self.app.selected_provider() == Provider::Ollama;
@@ -54,7 +59,7 @@ impl StateManager {
         if forward {
             self.app.chat_focus = match self.app.chat_focus {
                 ChatFocus::ProviderList => {
-                    if is_ollama { ChatFocus::ModelList } else { ChatFocus::Token }
+                    if is_ollama { ChatFocus::ModelList } else { ChatFocus::CustomUrl }
                 }
                 ChatFocus::ModelList => {
                     if is_ollama { ChatFocus::CustomUrl } else { ChatFocus::Token }
@@ -68,35 +73,24 @@ impl StateManager {
             self.app.chat_focus = match self.app.chat_focus {
                 ChatFocus::ProviderList => ChatFocus::Conversation,
                 ChatFocus::ModelList => ChatFocus::ProviderList,
-                ChatFocus::Token => {
-                    if is_ollama { ChatFocus::CustomUrl } else { ChatFocus::ProviderList }
+                ChatFocus::Token => ChatFocus::CustomUrl,
+                ChatFocus::CustomUrl => {
+                    if is_ollama { ChatFocus::ModelList } else { ChatFocus::ProviderList }
                 }
-                ChatFocus::CustomUrl => ChatFocus::ModelList,
                 ChatFocus::Conversation => ChatFocus::Message,
                 ChatFocus::Message => ChatFocus::Token,
             };
         }
+        self.app.save_token_on_blur();
     }
 
     // Provider management
     pub fn select_previous_provider(&mut self) {
-        let i = self.app.provider_index.saturating_sub(1);
-        self.app.provider_index = i;
-        self.app.provider_list_state.select(Some(i));
-        // Reset model list when provider changes
-        self.app.ollama_models.clear();
-        self.app.model_input.clear();
-        self.app.model_list_state.select(Some(0));
+        self.app.select_provider(self.app.provider_index.saturating_sub(1));
     }
 
     pub fn select_next_provider(&mut self) {
-        let i = (self.app.provider_index + 1).min(Provider::all().len() - 1);
-        self.app.provider_index = i;
-        self.app.provider_list_state.select(Some(i));
-        // Reset model list when provider changes
-        self.app.ollama_models.clear();
-        self.app.model_input.clear();
-        self.app.model_list_state.select(Some(0));
+        self.app.select_provider(self.app.provider_index + 1);
     }
 
     // Scroll management
@@ -159,15 +153,12 @@ impl StateManager {
     // Token management
     pub fn add_token_char(&mut self, c: char) {
         self.app.api_token.push(c);
-        // Save token on every keystroke
-        let _ = crate::tui::api::save_api_token(&self.app.api_token);
-        self.app.api_token_saved = true;
-        self.app.token_saved_at = Some(std::time::Instant::now());
-        self.app.status = "✓ API token saved".to_string();
+        self.app.api_token_edited = true;
     }
 
     pub fn remove_token_char(&mut self) {
         self.app.api_token.pop();
+        self.app.api_token_edited = true;
     }
 
     // URL management
@@ -181,11 +172,11 @@ impl StateManager {
 
     // Agent mode toggle
     pub fn toggle_agent_mode(&mut self) {
-        self.app.agent_mode = !self.app.agent_mode;
-        self.app.status = if self.app.agent_mode {
-            "🤖 Agent Mode ON (ReAct loop) — F2 to toggle".to_string()
-        } else {
-            "💬 Chat Mode — F2 to enable Agent Mode".to_string()
-        };
+        self.app.toggle_agent_mode();
+    }
+
+    // Native tool-calling toggle (agent mode only)
+    pub fn toggle_native_tools(&mut self) {
+        self.app.toggle_native_tools();
     }
 }
\ No newline at end of file
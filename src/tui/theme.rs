@@ -0,0 +1,68 @@
+// Colour themes for the TUI.
+// The screens are drawn in the dark palette; other themes remap its colours
+// over the finished frame, so drawing code names one set of colours.
+
+use ratatui::buffer::Buffer;
+use ratatui::style::Color;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Theme {
+    /// Light text on a dark background.
+    #[default]
+    Dark,
+    /// Dark text on a light background.
+    Light,
+}
+
+impl Theme {
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        }
+    }
+
+    /// This theme's colour for `color` from the dark palette.
+    pub fn color(self, color: Color) -> Color {
+        match self {
+            Theme::Dark => color,
+            Theme::Light => match color {
+                Color::White => Color::Black,
+                Color::Black => Color::White,
+                Color::Gray => Color::DarkGray,
+                Color::DarkGray => Color::Gray,
+                Color::Cyan => Color::Blue,
+                Color::Yellow => Color::Rgb(150, 100, 0),
+                Color::Green => Color::Rgb(0, 120, 0),
+                Color::Rgb(15, 15, 25) => Color::Rgb(245, 245, 248),
+                Color::Rgb(20, 20, 35) => Color::Rgb(235, 235, 242),
+                Color::Rgb(50, 50, 80) => Color::Rgb(170, 170, 200),
+                Color::Rgb(60, 80, 120) => Color::Rgb(190, 210, 240),
+                other => other,
+            },
+        }
+    }
+
+    /// Recolour a frame drawn in the dark palette.
+    pub fn apply(self, buf: &mut Buffer) {
+        if self == Theme::Dark {
+            return;
+        }
+        for cell in buf.content.iter_mut() {
+            cell.fg = self.color(cell.fg);
+            cell.bg = self.color(cell.bg);
+        }
+    }
+}
+
+impl std::str::FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            other => Err(format!("unknown theme '{other}' (expected dark or light)")),
+        }
+    }
+}
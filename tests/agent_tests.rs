@@ -1,6 +1,11 @@
-use qai_cli::agent::{parse_step, parse_steps, try_recover_plain_tool, StepKind, ReActAgent, tools};
+use qai_cli::agent::{
+    parse_step, parse_steps, try_recover_plain_tool, tools, AgentEvent, MarkdownRenderer, ReActAgent, StepKind,
+    ToolMode,
+};
 use qai_cli::tui::providers::Provider;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 // ── parse_step tests ──────────────────────────────────────────────────────────
 
@@ -149,74 +154,6 @@ fn react_agent_new_stores_fields() {
 }
 
 
-// ── ReActAgent loop logic (mock via channel) ──────────────────────────────────
-
-/// Helper: run the agent with a mock LLM that always returns `response`.
-async fn run_agent_with_mock_response(response: &str) -> Vec<String> {
-    // We can't easily mock the HTTP call, so we test the channel/token flow
-    // by directly calling parse_step and simulating what the loop would do.
-    let (tx, mut rx) = mpsc::unbounded_channel::<Option<String>>();
-
-    let step = parse_step(response);
-    match step {
-        Some(StepKind::Answer(ans)) => {
-            let _ = tx.send(Some(format!("✅ **Answer:**\n{ans}")));
-            let _ = tx.send(None);
-        }
-        Some(StepKind::Thought) => {
-            let _ = tx.send(Some("💭 **Thought:** thinking...\n\n".to_string()));
-            let _ = tx.send(None);
-        }
-        Some(StepKind::ToolCall { name, input }) => {
-            let _ = tx.send(Some(format!("🔧 **Tool:** `{name}({input})`\n")));
-            let _ = tx.send(None);
-        }
-        _ => {
-            let _ = tx.send(Some(response.to_string()));
-            let _ = tx.send(None);
-        }
-    }
-
-    let mut tokens = Vec::new();
-    while let Some(msg) = rx.recv().await {
-        match msg {
-            Some(t) => tokens.push(t),
-            None => break,
-        }
-    }
-    tokens
-}
-
-#[tokio::test]
-async fn agent_loop_answer_tag_produces_answer_token() {
-    let tokens = run_agent_with_mock_response("<answer>The result is 42</answer>").await;
-    assert_eq!(tokens.len(), 1);
-    assert!(tokens[0].contains("The result is 42"));
-    assert!(tokens[0].contains("✅"));
-}
-
-#[tokio::test]
-async fn agent_loop_think_tag_produces_thought_token() {
-    let tokens = run_agent_with_mock_response("<think>I should check the docs</think>").await;
-    assert_eq!(tokens.len(), 1);
-    assert!(tokens[0].contains("💭"));
-}
-
-#[tokio::test]
-async fn agent_loop_tool_tag_produces_tool_token() {
-    let tokens = run_agent_with_mock_response(r#"<tool name="shell">echo hi</tool>"#).await;
-    assert_eq!(tokens.len(), 1);
-    assert!(tokens[0].contains("🔧"));
-    assert!(tokens[0].contains("shell"));
-}
-
-#[tokio::test]
-async fn agent_loop_plain_text_passes_through() {
-    let tokens = run_agent_with_mock_response("Just a plain response").await;
-    assert_eq!(tokens.len(), 1);
-    assert_eq!(tokens[0], "Just a plain response");
-}
-
 // ── StepKind equality ─────────────────────────────────────────────────────────
 
 #[test]
@@ -268,12 +205,19 @@ fn app_agent_mode_can_be_toggled() {
 
 // ── tools::dispatch tests ─────────────────────────────────────────────────────
 
+use qai_cli::agent::{ToolRegistry, Workspace};
+
+/// Dispatch a built-in tool confined to the temp directory `dir`.
+async fn dispatch_in(dir: &tempfile::TempDir, tool: &str, input: &str) -> anyhow::Result<String> {
+    ToolRegistry::builtin_in(Workspace::new(dir.path())).dispatch(tool, input).await
+}
+
 #[tokio::test]
 async fn tool_write_file_creates_file() {
     let dir = tempfile::tempdir().unwrap();
     let path = dir.path().join("out.txt");
     let input = format!("{}\nhello world", path.display());
-    let result = tools::dispatch("write_file", &input).await.unwrap();
+    let result = dispatch_in(&dir, "write_file", &input).await.unwrap();
     assert!(result.contains("wrote"), "expected write confirmation, got: {result}");
     assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
 }
@@ -289,7 +233,7 @@ async fn tool_write_file_creates_parent_dirs() {
     let dir = tempfile::tempdir().unwrap();
     let path = dir.path().join("sub/dir/file.txt");
     let input = format!("{}\ncontent", path.display());
-    let result = tools::dispatch("write_file", &input).await.unwrap();
+    let result = dispatch_in(&dir, "write_file", &input).await.unwrap();
     assert!(result.contains("wrote"), "got: {result}");
     assert!(path.exists());
 }
@@ -300,7 +244,7 @@ async fn tool_edit_file_replaces_content() {
     let path = dir.path().join("edit_me.txt");
     std::fs::write(&path, "foo bar baz").unwrap();
     let input = format!("{}\n<<<\nfoo bar\n===\nreplaced\n>>>", path.display());
-    let result = tools::dispatch("edit_file", &input).await.unwrap();
+    let result = dispatch_in(&dir, "edit_file", &input).await.unwrap();
     assert!(result.contains("applied edit"), "got: {result}");
     assert_eq!(std::fs::read_to_string(&path).unwrap(), "replaced baz");
 }
@@ -311,7 +255,7 @@ async fn tool_edit_file_search_not_found() {
     let path = dir.path().join("edit_me2.txt");
     std::fs::write(&path, "hello world").unwrap();
     let input = format!("{}\n<<<\nnotfound\n===\nreplacement\n>>>", path.display());
-    let result = tools::dispatch("edit_file", &input).await.unwrap();
+    let result = dispatch_in(&dir, "edit_file", &input).await.unwrap();
     assert!(result.contains("not found"), "got: {result}");
 }
 
@@ -321,7 +265,7 @@ async fn tool_edit_file_missing_separator_returns_error() {
     let path = dir.path().join("edit_me3.txt");
     std::fs::write(&path, "content").unwrap();
     let input = format!("{}\n<<<\nno separator here", path.display());
-    let result = tools::dispatch("edit_file", &input).await.unwrap();
+    let result = dispatch_in(&dir, "edit_file", &input).await.unwrap();
     assert!(result.contains("[edit_file error"), "got: {result}");
 }
 
@@ -379,7 +323,7 @@ async fn tool_read_file_existing() {
     let dir = tempfile::tempdir().unwrap();
     let path = dir.path().join("read_me.txt");
     std::fs::write(&path, "read content").unwrap();
-    let result = tools::dispatch("read_file", &path.display().to_string()).await.unwrap();
+    let result = dispatch_in(&dir, "read_file", &path.display().to_string()).await.unwrap();
     assert_eq!(result, "read content");
 }
 
@@ -515,7 +459,7 @@ fn recover_plain_tool_colon_prefix() {
 
 #[test]
 fn recover_plain_tool_unknown_name_returns_none() {
-    let text = "search_paths_by_glob\npattern=\"**/README.md\"";
+    let text = "discover_tools\ntask=\"run the tests\"";
     assert_eq!(try_recover_plain_tool(text), None);
 }
 
@@ -542,7 +486,7 @@ async fn tool_grep_search_finds_match() {
     let path = dir.path().join("hello.txt");
     std::fs::write(&path, "hello world\nfoo bar\nhello again\n").unwrap();
     let input = format!("hello\n{}", dir.path().display());
-    let result = tools::dispatch("grep_search", &input).await.unwrap();
+    let result = dispatch_in(&dir, "grep_search", &input).await.unwrap();
     assert!(result.contains("hello"), "got: {result}");
 }
 
@@ -552,7 +496,7 @@ async fn tool_grep_search_no_match_returns_message() {
     let path = dir.path().join("empty.txt");
     std::fs::write(&path, "nothing here\n").unwrap();
     let input = format!("ZZZNOMATCH\n{}", dir.path().display());
-    let result = tools::dispatch("grep_search", &input).await.unwrap();
+    let result = dispatch_in(&dir, "grep_search", &input).await.unwrap();
     assert_eq!(result, "[grep_search: no matches found]");
 }
 
@@ -568,7 +512,7 @@ async fn tool_grep_search_with_glob_filter() {
     std::fs::write(dir.path().join("a.rs"), "fn main() {}\n").unwrap();
     std::fs::write(dir.path().join("b.txt"), "fn main() {}\n").unwrap();
     let input = format!("fn main\n{}\n*.rs", dir.path().display());
-    let result = tools::dispatch("grep_search", &input).await.unwrap();
+    let result = dispatch_in(&dir, "grep_search", &input).await.unwrap();
     assert!(result.contains("a.rs"), "got: {result}");
     assert!(!result.contains("b.txt"), "got: {result}");
 }
@@ -721,3 +665,2773 @@ fn agent_run_prior_history_only_deduplicates_user_role() {
     assert_eq!(history.len(), 3);
     assert_eq!(history[0].0, "assistant"); // assistant entry preserved
 }
+
+// ── PRReviewAgent: diff parsing and findings ─────────────────────────────────
+
+use qai_cli::agent::pr_review::{
+    findings_to_json, format_findings, parse_diff, parse_findings, render_file_for_review,
+    ReviewFinding, Severity,
+};
+
+const SAMPLE_DIFF: &str = "diff --git a/tests/login.spec.ts b/tests/login.spec.ts
+index 1111111..2222222 100644
+--- a/tests/login.spec.ts
++++ b/tests/login.spec.ts
+@@ -10,3 +10,4 @@ test('login', async ({ page }) => {
+   await page.goto('/login');
+-  await page.click('#submit');
++  await page.waitForTimeout(3000);
++  await page.getByRole('button').click();
+   expect(page).toBeTruthy();
+@@ -40,2 +41,2 @@
+-  old();
++  new();
+ }
+diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -1 +1 @@
+-old title
++new title
+";
+
+#[test]
+fn parse_diff_splits_files_and_hunks() {
+    let files = parse_diff(SAMPLE_DIFF);
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].path, "tests/login.spec.ts");
+    assert_eq!(files[0].hunks.len(), 2);
+    assert_eq!(files[0].hunks[0].new_start, 10);
+    assert_eq!(files[0].hunks[1].new_start, 41);
+    assert_eq!(files[1].path, "README.md");
+    assert_eq!(files[1].hunks[0].new_start, 1);
+}
+
+#[test]
+fn parse_diff_empty_input_returns_no_files() {
+    assert!(parse_diff("").is_empty());
+}
+
+#[test]
+fn render_file_for_review_numbers_new_lines_only() {
+    let files = parse_diff(SAMPLE_DIFF);
+    let rendered = render_file_for_review(&files[0]);
+    assert!(rendered.contains("   11 | +  await page.waitForTimeout(3000);"), "got: {rendered}");
+    assert!(rendered.contains("      | -  await page.click('#submit');"), "got: {rendered}");
+    assert!(rendered.contains("   13 |    expect(page).toBeTruthy();"), "got: {rendered}");
+}
+
+#[test]
+fn parse_findings_reads_tagged_json_array() {
+    let resp = r#"<findings>[
+        {"line": 11, "severity": "warning", "message": "Hard-coded sleep makes the test flaky."},
+        {"line": null, "severity": "critical", "message": "No assertion on the login result."}
+    ]</findings>"#;
+    let findings = parse_findings("tests/login.spec.ts", resp);
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].line, Some(11));
+    assert_eq!(findings[0].severity, Severity::Warning);
+    assert_eq!(findings[1].line, None);
+    assert_eq!(findings[1].severity, Severity::Error);
+    assert_eq!(findings[1].file, "tests/login.spec.ts");
+}
+
+#[test]
+fn parse_findings_accepts_loose_objects_and_drops_empty_messages() {
+    let resp = r#"Here you go: {"line": "7", "severity": "nit", "message": "Rename test."} and {"line": 8, "message": ""}"#;
+    let findings = parse_findings("a.py", resp);
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].line, Some(7));
+    assert_eq!(findings[0].severity, Severity::Info);
+}
+
+#[test]
+fn parse_findings_empty_array_returns_nothing() {
+    assert!(parse_findings("a.py", "<findings>[]</findings>").is_empty());
+}
+
+#[test]
+fn format_and_json_output_include_all_fields() {
+    let findings = vec![ReviewFinding {
+        file: "a.spec.ts".to_string(),
+        line: Some(3),
+        severity: Severity::Error,
+        message: "Test is skipped.".to_string(),
+    }];
+    assert_eq!(format_findings(&findings), "a.spec.ts:3: [error] Test is skipped.");
+    let v: serde_json::Value = serde_json::from_str(&findings_to_json(&findings)).unwrap();
+    assert_eq!(v[0]["file"], "a.spec.ts");
+    assert_eq!(v[0]["line"], 3);
+    assert_eq!(v[0]["severity"], "error");
+    assert_eq!(format_findings(&[]), "No review findings.");
+}
+
+// ── Native function calling ──────────────────────────────────────────────────
+
+use qai_cli::agent::native::{self, NativeToolCall, NativeTurn, Wire};
+use qai_cli::llm::{self, AnthropicMessages, Endpoint, LlmClient, OllamaChat, OpenAiChat};
+use serde_json::json;
+
+#[test]
+fn wire_for_provider_maps_protocols() {
+    assert_eq!(Wire::for_provider(Provider::Anthropic), Wire::Anthropic);
+    assert_eq!(Wire::for_provider(Provider::Ollama), Wire::Ollama);
+    assert_eq!(Wire::for_provider(Provider::OpenAI), Wire::OpenAi);
+    assert_eq!(Wire::for_provider(Provider::XAI), Wire::OpenAi);
+    assert_eq!(Wire::for_provider(Provider::GitHubModels), Wire::OpenAi);
+}
+
+#[test]
+fn tool_schemas_cover_prompt_tools() {
+    let schemas = tools::builtin_registry().schemas();
+    let names: Vec<&str> = schemas.iter().map(|s| s.name.as_str()).collect();
+    for expected in ["read_file", "write_file", "edit_file", "shell", "grep_search", "git_status", "answer"] {
+        assert!(names.contains(&expected), "missing schema for {expected}");
+    }
+    for s in &schemas {
+        assert_eq!(s.parameters["type"], "object");
+    }
+}
+
+#[test]
+fn input_from_args_builds_dispatch_input() {
+    assert_eq!(tools::builtin_registry().input_from_args("read_file", &json!({"path": "a.txt"})), "a.txt");
+    assert_eq!(
+        tools::builtin_registry().input_from_args("write_file", &json!({"path": "a.txt", "content": "hi"})),
+        "a.txt\nhi"
+    );
+    assert_eq!(
+        tools::builtin_registry().input_from_args("edit_file", &json!({"path": "a.rs", "search": "x", "replace": "y"})),
+        "a.rs\n<<<\nx\n===\ny\n>>>"
+    );
+    assert_eq!(
+        tools::builtin_registry().input_from_args("grep_search", &json!({"pattern": "fn", "glob": "*.rs"})),
+        "fn\n\n*.rs"
+    );
+    assert_eq!(tools::builtin_registry().input_from_args("git_log", &json!({"count": 5})), "5");
+    assert_eq!(tools::builtin_registry().input_from_args("custom", &json!({"input": "raw"})), "raw");
+}
+
+fn tools_request(messages: Vec<serde_json::Value>) -> ChatRequest {
+    ChatRequest {
+        model: "m".into(),
+        system: "sys".into(),
+        messages,
+        tools: tools::builtin_registry().schemas(),
+        max_tokens: None,
+    }
+}
+
+#[test]
+fn openai_request_includes_function_tools_and_system() {
+    let client = OpenAiChat { endpoint: Endpoint::default(), stream_usage: false };
+    let body = client.body(&tools_request(vec![native::text_message("user", "hi")]), false);
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(body["messages"][1]["content"], "hi");
+    assert_eq!(body["tools"][0]["type"], "function");
+    assert_eq!(body["tools"][0]["function"]["name"], "read_file");
+}
+
+#[test]
+fn anthropic_request_uses_input_schema_and_top_level_system() {
+    let body = AnthropicMessages { endpoint: Endpoint::default() }.body(&tools_request(vec![]), false);
+    assert_eq!(body["system"], "sys");
+    assert_eq!(body["tools"][0]["name"], "read_file");
+    assert!(body["tools"][0]["input_schema"].is_object());
+}
+
+#[test]
+fn parse_openai_tool_calls_with_string_arguments() {
+    let v = json!({"choices": [{"message": {
+        "content": null,
+        "tool_calls": [{"id": "call_1", "type": "function",
+            "function": {"name": "read_file", "arguments": "{\"path\":\"README.md\"}"}}]
+    }}]});
+    let turn = OpenAiChat { endpoint: Endpoint::default(), stream_usage: false }.parse(&v).unwrap();
+    assert_eq!(turn.text, "");
+    assert_eq!(turn.calls.len(), 1);
+    assert_eq!(turn.calls[0].id, "call_1");
+    assert_eq!(turn.calls[0].arguments["path"], "README.md");
+}
+
+#[test]
+fn parse_anthropic_tool_use_blocks() {
+    let v = json!({"content": [
+        {"type": "text", "text": "Let me look."},
+        {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": {"command": "ls"}}
+    ]});
+    let turn = AnthropicMessages { endpoint: Endpoint::default() }.parse(&v).unwrap();
+    assert_eq!(turn.text, "Let me look.");
+    assert_eq!(turn.calls[0].name, "shell");
+    assert_eq!(turn.calls[0].arguments["command"], "ls");
+}
+
+#[test]
+fn parse_ollama_tool_calls_synthesise_ids() {
+    let v = json!({"message": {"role": "assistant", "content": "",
+        "tool_calls": [{"function": {"name": "git_status", "arguments": {}}}]}});
+    let turn = OllamaChat { endpoint: Endpoint::default() }.parse(&v).unwrap();
+    assert_eq!(turn.calls[0].name, "git_status");
+    assert_eq!(turn.calls[0].id, "call_0");
+}
+
+#[test]
+fn parse_response_rejects_unknown_shape() {
+    assert!(OpenAiChat { endpoint: Endpoint::default(), stream_usage: false }.parse(&json!({"error": "x"})).is_none());
+    assert!(AnthropicMessages { endpoint: Endpoint::default() }.parse(&json!({"error": "x"})).is_none());
+    assert!(OllamaChat { endpoint: Endpoint::default() }.parse(&json!({"error": "x"})).is_none());
+}
+
+#[test]
+fn tool_results_follow_each_wire_format() {
+    let call = NativeToolCall { id: "c1".into(), name: "shell".into(), arguments: json!({}) };
+    let results = vec![(call.clone(), "ok".to_string())];
+
+    let openai = native::tool_result_messages(Wire::OpenAi, &results);
+    assert_eq!(openai[0]["role"], "tool");
+    assert_eq!(openai[0]["tool_call_id"], "c1");
+
+    let anthropic = native::tool_result_messages(Wire::Anthropic, &results);
+    assert_eq!(anthropic[0]["role"], "user");
+    assert_eq!(anthropic[0]["content"][0]["type"], "tool_result");
+    assert_eq!(anthropic[0]["content"][0]["tool_use_id"], "c1");
+
+    let ollama = native::tool_result_messages(Wire::Ollama, &results);
+    assert_eq!(ollama[0]["tool_name"], "shell");
+
+    let turn = NativeTurn { calls: vec![call], ..NativeTurn::default() };
+    let msg = native::assistant_message(Wire::OpenAi, &turn);
+    assert_eq!(msg["tool_calls"][0]["function"]["arguments"], "{}");
+}
+
+/// Serve canned HTTP responses, one per connection, and return the base URL.
+/// A status of 0 accepts the request but never answers.
+async fn spawn_mock_llm(responses: Vec<(u16, String)>) -> String {
+    spawn_recording_mock_llm(responses).await.0
+}
+
+/// Like `spawn_mock_llm`, also keeping each request's body.
+async fn spawn_recording_mock_llm(responses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    tokio::spawn(async move {
+        for (status, body) in responses {
+            let Ok((mut sock, _)) = listener.accept().await else { return };
+            // Read headers, then the declared body length
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 4096];
+            loop {
+                let n = sock.read(&mut chunk).await.unwrap_or(0);
+                if n == 0 {
+                    break;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buf).to_string();
+                if let Some(idx) = text.find("\r\n\r\n") {
+                    let len = text[..idx]
+                        .lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                        .unwrap_or(0);
+                    if buf.len() >= idx + 4 + len {
+                        let body = serde_json::from_slice(&buf[idx + 4..]).unwrap_or_default();
+                        seen.lock().unwrap().push(body);
+                        break;
+                    }
+                }
+            }
+            if status == 0 {
+                tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+                return;
+            }
+            let resp = format!(
+                "HTTP/1.1 {status} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = sock.write_all(resp.as_bytes()).await;
+            let _ = sock.shutdown().await;
+        }
+    });
+    (format!("http://{addr}"), requests)
+}
+
+/// Drain a finished run's events.
+async fn collect_events(mut rx: mpsc::UnboundedReceiver<AgentEvent>) -> Vec<AgentEvent> {
+    let mut events = Vec::new();
+    while let Some(event) = rx.recv().await {
+        events.push(event);
+    }
+    events
+}
+
+/// Drain a finished run's events and render them as the TUI would.
+async fn collect(rx: mpsc::UnboundedReceiver<AgentEvent>) -> String {
+    let mut renderer = MarkdownRenderer::new();
+    collect_events(rx).await.iter().filter_map(|e| renderer.render(e)).collect()
+}
+
+#[tokio::test]
+async fn native_mode_dispatches_tool_calls_then_answers() {
+    let url = spawn_mock_llm(vec![
+        (200, json!({"message": {"role": "assistant", "content": "",
+            "tool_calls": [{"function": {"name": "shell", "arguments": {"command": "echo native-ok"}}}]}}).to_string()),
+        (200, json!({"message": {"role": "assistant", "content": "All done."}}).to_string()),
+    ])
+    .await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_tool_mode(ToolMode::Native);
+    let (tx, rx) = mpsc::unbounded_channel();
+    agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    let out = collect(rx).await;
+    assert!(out.contains("🔧 **Tool `shell`:**"), "{out}");
+    assert!(out.contains("native-ok"), "{out}");
+    assert!(out.contains("✅ **Answer:**\nAll done."), "{out}");
+}
+
+#[tokio::test]
+async fn native_mode_falls_back_to_xml_when_tools_rejected() {
+    let url = spawn_mock_llm(vec![
+        (400, json!({"error": "model does not support tools"}).to_string()),
+        (200, json!({"message": {"role": "assistant", "content": "<answer>xml path</answer>"}}).to_string()),
+    ])
+    .await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_tool_mode(ToolMode::Native);
+    let (tx, rx) = mpsc::unbounded_channel();
+    agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    let out = collect(rx).await;
+    assert!(out.contains("falling back to XML"), "{out}");
+    assert!(out.contains("xml path"), "{out}");
+}
+
+#[tokio::test]
+async fn native_fallback_keeps_announcing_step_one_once() {
+    let url = spawn_mock_llm(vec![
+        (400, json!({"error": "model does not support tools"}).to_string()),
+        (200, json!({"message": {"role": "assistant", "content": "<answer>xml path</answer>"}}).to_string()),
+    ])
+    .await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_tool_mode(ToolMode::Native);
+    let (tx, rx) = mpsc::unbounded_channel();
+    agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    let starts: Vec<usize> = collect_events(rx)
+        .await
+        .iter()
+        .filter_map(|e| match e {
+            AgentEvent::StepStart { step } => Some(*step),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(starts, vec![1]);
+}
+
+#[tokio::test]
+async fn native_mode_reports_other_bad_requests_instead_of_falling_back() {
+    let url = spawn_mock_llm(vec![(400, json!({"error": "model 'nope' not found"}).to_string())]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "nope".into(), "sys".into())
+        .with_tool_mode(ToolMode::Native);
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    let RunOutcome::Failed(message) = outcome else { panic!("expected a failed run, got {outcome:?}") };
+    assert!(message.contains("model 'nope' not found"), "{message}");
+    assert!(!collect(rx).await.contains("falling back to XML"));
+}
+
+// ── Run limits ───────────────────────────────────────────────────────────────
+
+use qai_cli::agent::{AgentLimits, RunOutcome, StopReason};
+use std::time::Duration;
+
+fn ollama_reply(content: &str) -> (u16, String) {
+    (200, json!({"message": {"role": "assistant", "content": content}}).to_string())
+}
+
+#[test]
+fn agent_limits_default_to_fifteen_steps() {
+    let limits = AgentLimits::default();
+    assert_eq!(limits.max_steps, 15);
+    assert!(limits.max_format_retries > 0);
+    assert!(limits.max_duration.is_some());
+}
+
+#[tokio::test]
+async fn run_stops_at_max_steps_with_summary() {
+    let tool = "<think>look</think><tool name=\"shell\">echo step</tool>";
+    let url = spawn_mock_llm(vec![ollama_reply(tool), ollama_reply(tool), ollama_reply(tool)]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_limits(AgentLimits { max_steps: 2, ..AgentLimits::default() });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Stopped(StopReason::MaxSteps(2)));
+    let out = collect(rx).await;
+    assert!(out.contains("⏹ **Stopped:** step limit reached (2 steps)"), "{out}");
+    assert!(out.contains("2 tool call(s)"), "{out}");
+    assert!(out.contains("- `shell` echo step"), "{out}");
+    assert!(!out.contains("Step 3"), "{out}");
+}
+
+#[tokio::test]
+async fn run_summary_truncates_multibyte_tool_input() {
+    // "echo " plus 74 bytes puts the 2-byte 'é' across the 80-byte summary limit
+    let command = format!("echo {}é日本", "a".repeat(74));
+    let tool = format!("<tool name=\"shell\">{command}</tool>");
+    let url = spawn_mock_llm(vec![ollama_reply(&tool), ollama_reply(&tool)]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_limits(AgentLimits { max_steps: 1, ..AgentLimits::default() });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Stopped(StopReason::MaxSteps(1)));
+    let out = collect(rx).await;
+    assert!(out.contains(&format!("- `shell` echo {}…(truncated)", "a".repeat(74))), "{out}");
+}
+
+#[tokio::test]
+async fn run_stops_after_consecutive_format_nudges() {
+    let think_only = "<think>still thinking</think>";
+    let url = spawn_mock_llm(vec![ollama_reply(think_only); 4]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_limits(AgentLimits { max_format_retries: 2, ..AgentLimits::default() });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Stopped(StopReason::FormatRetries(3)));
+    assert!(collect(rx).await.contains("ignored the response format"));
+}
+
+#[tokio::test]
+async fn run_stops_when_time_budget_expires_mid_request() {
+    let url = spawn_mock_llm(vec![(0, String::new())]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_limits(AgentLimits { max_duration: Some(Duration::from_millis(200)), ..AgentLimits::default() });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert!(matches!(outcome, RunOutcome::Stopped(StopReason::TimeBudget(_))));
+    assert!(collect(rx).await.contains("time budget exhausted"));
+}
+
+#[tokio::test]
+async fn run_returns_answer_outcome() {
+    let url = spawn_mock_llm(vec![ollama_reply("<answer>done</answer>")]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into());
+    let (tx, _rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Answered("done".to_string()));
+}
+
+// ── Cancellation ─────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn cancel_aborts_in_flight_llm_request() {
+    let url = spawn_mock_llm(vec![(0, String::new())]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        trigger.cancel();
+    });
+    let outcome = tokio::time::timeout(Duration::from_secs(5), agent.run("task".into(), vec![], tx, cancel))
+        .await
+        .expect("run should stop promptly after cancel")
+        .unwrap();
+    assert_eq!(outcome, RunOutcome::Cancelled { step: 1 });
+    assert!(collect(rx).await.contains("⛔ **Run stopped by user at step 1.**"));
+}
+
+#[tokio::test]
+async fn cancel_kills_running_shell_tool() {
+    let dir = tempfile::tempdir().unwrap();
+    let marker = dir.path().join("finished");
+    let cmd = format!("sleep 3 && touch {}", marker.display());
+    let url = spawn_mock_llm(vec![ollama_reply(&format!("<tool name=\"shell\">{cmd}</tool>"))]).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        trigger.cancel();
+    });
+    let started = std::time::Instant::now();
+    let outcome = agent.run("task".into(), vec![], tx, cancel).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Cancelled { step: 1 });
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(collect(rx).await.contains("stopped by user at step 1"));
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert!(!marker.exists(), "shell child kept running after cancel");
+}
+
+#[tokio::test]
+async fn already_cancelled_run_does_not_call_llm() {
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), "http://127.0.0.1:9".into(), "m".into(), "sys".into());
+    let (tx, _rx) = mpsc::unbounded_channel();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let outcome = agent.run("task".into(), vec![], tx, cancel).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Cancelled { step: 0 });
+}
+
+// ── Streaming tag parser ─────────────────────────────────────────────────────
+
+use qai_cli::agent::stream::{StreamingTagParser, TagEvent};
+
+fn feed_all(parser: &mut StreamingTagParser, deltas: &[&str]) -> Vec<TagEvent> {
+    deltas.iter().flat_map(|d| parser.push(d)).collect()
+}
+
+#[test]
+fn stream_parser_emits_thought_text_as_it_arrives() {
+    let mut p = StreamingTagParser::new();
+    assert_eq!(p.push("<thi"), vec![]);
+    assert_eq!(p.push("nk> I will"), vec![TagEvent::Open("think"), TagEvent::Text("think", "I will".into())]);
+    assert_eq!(p.push(" read</th"), vec![TagEvent::Text("think", " read".into())]);
+    assert_eq!(p.push("ink>"), vec![TagEvent::Close("think")]);
+    assert!(p.streamed("think"));
+    assert!(!p.streamed("answer"));
+}
+
+#[test]
+fn stream_parser_signals_tool_close_and_drops_trailing_text() {
+    let mut p = StreamingTagParser::new();
+    let events = feed_all(&mut p, &[
+        "<think>x</think><tool name=\"shell\">ls</to",
+        "ol>\n<observation>hallucinated</observation>",
+    ]);
+    assert_eq!(events.last(), Some(&TagEvent::ToolClosed));
+    assert!(p.tool_closed());
+    assert_eq!(p.text(), "<think>x</think><tool name=\"shell\">ls</tool>");
+    assert!(p.push("more").is_empty());
+}
+
+#[test]
+fn stream_parser_ignores_tool_input_and_other_tags() {
+    let mut p = StreamingTagParser::new();
+    let events = feed_all(&mut p, &["<tool name=\"read_file\">a<b.txt"]);
+    assert!(events.is_empty());
+}
+
+#[test]
+fn stream_parser_treats_tool_bodies_as_opaque() {
+    let mut p = StreamingTagParser::new();
+    let events = feed_all(&mut p, &[
+        "<think>write it</think><tool name=\"write_file\">notes.md\n<answer>not ",
+        "yet</answer> <think>nor this</think></to",
+        "ol><answer>after</answer>",
+    ]);
+    assert_eq!(events, vec![
+        TagEvent::Open("think"),
+        TagEvent::Text("think", "write it".into()),
+        TagEvent::Close("think"),
+        TagEvent::ToolClosed,
+    ]);
+    assert!(!p.streamed("answer"));
+    assert!(p.text().ends_with("<think>nor this</think></tool>"), "{}", p.text());
+}
+
+#[test]
+fn stream_parser_finish_flushes_unclosed_answer() {
+    let mut p = StreamingTagParser::new();
+    let mut events = feed_all(&mut p, &["<answer>partial <"]);
+    events.extend(p.finish());
+    assert_eq!(events, vec![
+        TagEvent::Open("answer"),
+        TagEvent::Text("answer", "partial ".into()),
+        TagEvent::Text("answer", "<".into()),
+        TagEvent::Close("answer"),
+    ]);
+}
+
+fn ndjson(deltas: &[&str]) -> String {
+    let mut body: String = deltas
+        .iter()
+        .map(|d| format!("{}\n", json!({"message": {"role": "assistant", "content": d}, "done": false})))
+        .collect();
+    body.push_str(&format!("{}\n", json!({"message": {"role": "assistant", "content": ""}, "done": true})));
+    body
+}
+
+#[tokio::test]
+async fn streamed_step_dispatches_tool_once_tag_closes() {
+    let url = spawn_mock_llm(vec![
+        (200, ndjson(&["<think>Listing", " files</think>", "<tool name=\"shell\">echo streamed", "-ok</tool>",
+            "<observation>fake</observation><answer>too early</answer>"])),
+        (200, ndjson(&["<think>done</think><answer>", "finished", "</answer>"])),
+    ])
+    .await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Answered("finished".to_string()));
+    let out = collect(rx).await;
+    assert!(out.contains("💭 **Thought:** Listing files\n\n"), "{out}");
+    assert!(out.contains("streamed-ok"), "{out}");
+    assert!(!out.contains("too early"), "{out}");
+    assert_eq!(out.matches("✅ **Answer:**").count(), 1, "{out}");
+    assert!(out.contains("✅ **Answer:**\nfinished\n"), "{out}");
+}
+
+// ── Agent events ─────────────────────────────────────────────────────────────
+
+use qai_cli::agent::RunSummary;
+use qai_cli::llm::TokenUsage;
+
+async fn run_events(responses: Vec<(u16, String)>) -> (RunOutcome, Vec<AgentEvent>) {
+    let url = spawn_mock_llm(responses).await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    (outcome, collect_events(rx).await)
+}
+
+#[tokio::test]
+async fn agent_loop_answer_tag_produces_answer_event() {
+    let (outcome, events) = run_events(vec![ollama_reply("<answer>The result is 42</answer>")]).await;
+    assert_eq!(outcome, RunOutcome::Answered("The result is 42".to_string()));
+    assert_eq!(events[0], AgentEvent::StepStart { step: 1 });
+    assert!(events.contains(&AgentEvent::Answer { step: 1, text: "The result is 42".into() }));
+    assert!(matches!(events.last(), Some(AgentEvent::Finished { outcome: RunOutcome::Answered(_), .. })));
+}
+
+#[tokio::test]
+async fn agent_loop_think_tag_produces_thought_events() {
+    let (_, events) = run_events(vec![
+        ollama_reply("<think>I should check the docs</think>"),
+        ollama_reply("<answer>ok</answer>"),
+    ])
+    .await;
+    assert!(events.contains(&AgentEvent::ThoughtDelta { step: 1, text: "I should check the docs".into() }));
+    assert!(events.contains(&AgentEvent::Thought { step: 1, text: "I should check the docs".into() }));
+}
+
+#[tokio::test]
+async fn agent_loop_tool_tag_produces_tool_call_and_observation() {
+    let (_, events) = run_events(vec![
+        ollama_reply(r#"<tool name="shell">echo hi</tool>"#),
+        ollama_reply("<answer>ok</answer>"),
+    ])
+    .await;
+    assert!(events.contains(&AgentEvent::ToolCall { step: 1, name: "shell".into(), input: "echo hi".into() }));
+    assert!(events.iter().any(|e| matches!(e,
+        AgentEvent::Observation { step: 1, name, output }
+            if name == "shell" && output.starts_with("hi\n[exit code: 0]\n<terminal_status>"))));
+}
+
+#[tokio::test]
+async fn agent_loop_plain_text_becomes_answer() {
+    let (outcome, events) = run_events(vec![ollama_reply("Just a plain response")]).await;
+    assert_eq!(outcome, RunOutcome::Answered("Just a plain response".to_string()));
+    assert!(events.contains(&AgentEvent::Answer { step: 1, text: "Just a plain response".into() }));
+}
+
+#[tokio::test]
+async fn agent_loop_llm_failure_reports_error_and_fails() {
+    let (outcome, events) = run_events(vec![(500, "{\"error\":\"boom\"}".to_string())]).await;
+    assert!(matches!(outcome, RunOutcome::Failed(ref m) if m.contains("500")));
+    assert!(events.iter().any(|e| matches!(e, AgentEvent::Error { step: 1, .. })));
+    assert!(matches!(events.last(), Some(AgentEvent::Finished { outcome: RunOutcome::Failed(_), .. })));
+}
+
+#[tokio::test]
+async fn agent_loop_reports_token_usage() {
+    let body = format!(
+        "{}\n{}\n",
+        json!({"message": {"content": "<answer>hi</answer>"}, "done": false}),
+        json!({"message": {"content": ""}, "done": true, "prompt_eval_count": 12, "eval_count": 5}),
+    );
+    let (_, events) = run_events(vec![(200, body)]).await;
+    assert!(events.contains(&AgentEvent::Usage {
+        step: 1,
+        usage: TokenUsage { input_tokens: 12, output_tokens: 5 },
+    }));
+}
+
+#[test]
+fn markdown_renderer_joins_streamed_thought_deltas() {
+    let mut r = MarkdownRenderer::new();
+    let out: String = [
+        AgentEvent::ThoughtDelta { step: 1, text: "a".into() },
+        AgentEvent::ThoughtDelta { step: 1, text: "b".into() },
+        AgentEvent::Thought { step: 1, text: "ab".into() },
+        AgentEvent::Thought { step: 1, text: "whole".into() },
+    ]
+    .iter()
+    .filter_map(|e| r.render(e))
+    .collect();
+    assert_eq!(out, "💭 **Thought:** ab\n\n💭 **Thought:** whole\n\n");
+}
+
+#[test]
+fn markdown_renderer_truncates_multibyte_text_on_a_char_boundary() {
+    let mut r = MarkdownRenderer::new();
+    // The 3-byte '日' starts one byte before each limit
+    let input = format!("{}日本", "a".repeat(119));
+    let out = r.render(&AgentEvent::ToolCall { step: 1, name: "shell".into(), input }).unwrap();
+    assert_eq!(out, format!("🔧 **Tool `shell`:** `{}…(truncated)`\n", "a".repeat(119)));
+    let output = format!("{}🙂", "a".repeat(798));
+    let out = r.render(&AgentEvent::Observation { step: 1, name: "shell".into(), output }).unwrap();
+    assert!(out.contains(&format!("{}…(truncated)", "a".repeat(798))), "{out}");
+}
+
+#[test]
+fn markdown_renderer_formats_stop_summary_and_hides_usage() {
+    let mut r = MarkdownRenderer::new();
+    assert_eq!(r.render(&AgentEvent::Usage { step: 1, usage: TokenUsage::default() }), None);
+    let out = r
+        .render(&AgentEvent::Finished {
+            outcome: RunOutcome::Stopped(StopReason::MaxSteps(2)),
+            summary: RunSummary { steps: 2, tool_calls: vec!["`shell` ls".into()], elapsed: Duration::ZERO },
+        })
+        .unwrap();
+    assert!(out.contains("⏹ **Stopped:** step limit reached (2 steps)"));
+    assert!(out.contains("- `shell` ls"));
+}
+
+// ── Tool registry ────────────────────────────────────────────────────────────
+
+use futures_util::future::BoxFuture;
+use qai_cli::agent::{registry::Tool, try_recover_plain_tool_in};
+
+struct Echo;
+
+impl Tool for Echo {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn description(&self) -> &str {
+        "repeat the input"
+    }
+
+    fn input_format(&self) -> &str {
+        "any text"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {"text": {"type": "string"}}, "required": ["text"]})
+    }
+
+    fn input_from_args(&self, args: &serde_json::Value) -> String {
+        args["text"].as_str().unwrap_or_default().to_string()
+    }
+
+    fn execute<'a>(&'a self, input: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move { Ok(format!("echo: {input}")) })
+    }
+}
+
+#[tokio::test]
+async fn registry_dispatches_registered_tool() {
+    let mut registry = ToolRegistry::new();
+    registry.register(Echo);
+    assert_eq!(registry.names(), vec!["echo"]);
+    assert_eq!(registry.dispatch("echo", "hi").await.unwrap(), "echo: hi");
+    assert_eq!(registry.dispatch("nope", "").await.unwrap(), "[unknown tool: nope]");
+    assert_eq!(registry.input_from_args("echo", &json!({"text": "x"})), "x");
+}
+
+#[test]
+fn registry_register_replaces_tool_with_same_name() {
+    let mut registry = ToolRegistry::builtin();
+    let count = registry.names().len();
+    registry.register(Echo).register(Echo);
+    assert_eq!(registry.names().len(), count + 1);
+}
+
+#[test]
+fn prompt_section_lists_tools_except_answer() {
+    let section = tools::builtin_registry().prompt_section();
+    // Names are padded to the longest one, search_paths_by_glob
+    assert!(section.contains("  read_file            — read a file. Input: file path\n"), "{section}");
+    assert!(section.contains("  git_log              — show git log."), "{section}");
+    assert!(!section.contains("answer "), "{section}");
+
+    let mut registry = ToolRegistry::new();
+    registry.register(Echo);
+    assert_eq!(registry.prompt_section(), "  echo         — repeat the input. Input: any text\n");
+}
+
+#[test]
+fn schemas_come_from_registered_tools() {
+    let mut registry = ToolRegistry::new();
+    registry.register(Echo);
+    let schemas = registry.schemas();
+    assert_eq!(schemas.len(), 1);
+    assert_eq!(schemas[0].name, "echo");
+    assert_eq!(schemas[0].parameters["required"][0], "text");
+}
+
+#[test]
+fn recovery_recognises_custom_tool_names() {
+    assert_eq!(try_recover_plain_tool("echo\nhello"), None);
+    assert_eq!(
+        try_recover_plain_tool_in("echo\nhello", &["echo"]),
+        Some(StepKind::ToolCall { name: "echo".to_string(), input: "hello".to_string() })
+    );
+}
+
+#[tokio::test]
+async fn agent_runs_tools_from_its_own_registry() {
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<think>t</think><tool name=\"echo\">ping</tool>"),
+        ollama_reply("<answer>done</answer>"),
+    ])
+    .await;
+    let mut registry = ToolRegistry::new();
+    registry.register(Echo);
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into()).with_tools(registry);
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Answered("done".to_string()));
+    let events = collect_events(rx).await;
+    assert!(events.iter().any(|e| matches!(e,
+        AgentEvent::Observation { name, output, .. } if name == "echo" && output == "echo: ping")));
+}
+
+struct Sleep;
+
+impl Tool for Sleep {
+    fn name(&self) -> &str {
+        "sleep"
+    }
+
+    fn description(&self) -> &str {
+        "wait a long time"
+    }
+
+    fn input_format(&self) -> &str {
+        "nothing"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {}})
+    }
+
+    fn execute<'a>(&'a self, _input: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            Ok("woke up".to_string())
+        })
+    }
+}
+
+#[tokio::test]
+async fn run_stops_when_time_budget_expires_mid_tool() {
+    let url = spawn_mock_llm(vec![ollama_reply("<think>t</think><tool name=\"sleep\"></tool>")]).await;
+    let mut registry = ToolRegistry::new();
+    registry.register(Sleep);
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_tools(registry)
+        .with_limits(AgentLimits { max_duration: Some(Duration::from_millis(300)), ..AgentLimits::default() });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let started = std::time::Instant::now();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(matches!(outcome, RunOutcome::Stopped(StopReason::TimeBudget(_))));
+    assert!(collect(rx).await.contains("time budget exhausted"));
+}
+
+// ── Tool approval ────────────────────────────────────────────────────────────
+
+use qai_cli::agent::approval::{line_diff, Approval, ApprovalDecision, ApprovalPolicy};
+
+#[test]
+fn default_policy_asks_before_mutating_tools() {
+    let registry = tools::builtin_registry();
+    let policy = ApprovalPolicy::default();
+    for name in ["write_file", "edit_file", "shell", "git_add", "git_commit"] {
+        assert_eq!(policy.for_tool(registry.get(name).unwrap().as_ref()), Approval::Ask, "{name}");
+    }
+    for name in ["read_file", "grep_search", "git_status", "git_diff", "answer"] {
+        assert_eq!(policy.for_tool(registry.get(name).unwrap().as_ref()), Approval::Allow, "{name}");
+    }
+}
+
+#[test]
+fn policy_rules_override_defaults() {
+    let registry = tools::builtin_registry();
+    let mut policy = ApprovalPolicy::default();
+    policy.set("shell", Approval::Deny).set("git_status", Approval::Ask);
+    assert_eq!(policy.for_tool(registry.get("shell").unwrap().as_ref()), Approval::Deny);
+    assert_eq!(policy.for_tool(registry.get("git_status").unwrap().as_ref()), Approval::Ask);
+    assert_eq!(
+        ApprovalPolicy::allow_all().for_tool(registry.get("shell").unwrap().as_ref()),
+        Approval::Allow
+    );
+}
+
+#[test]
+fn approval_parses_from_str() {
+    assert_eq!("ask".parse::<Approval>(), Ok(Approval::Ask));
+    assert_eq!(" Deny ".parse::<Approval>(), Ok(Approval::Deny));
+    assert!("maybe".parse::<Approval>().is_err());
+}
+
+#[test]
+fn line_diff_marks_changed_lines_and_collapses_context() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+    let new = "a\nb\nc\nd\ne\nf\nG\nh\n";
+    let diff = line_diff(old, new);
+    assert!(diff.contains("- g\n+ G\n"), "{diff}");
+    assert!(diff.starts_with("  …\n"), "{diff}");
+    assert!(!diff.contains("  a\n"), "{diff}");
+    assert_eq!(line_diff("x", "x"), "(no changes)");
+}
+
+#[test]
+fn builtin_previews_show_command_or_diff() {
+    let registry = tools::builtin_registry();
+    assert_eq!(registry.get("shell").unwrap().preview("ls -la"), "$ ls -la");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("f.txt");
+    std::fs::write(&path, "one\ntwo\n").unwrap();
+    let registry = ToolRegistry::builtin_in(Workspace::new(dir.path()));
+    let preview = registry.get("write_file").unwrap().preview("f.txt\none\nthree\n");
+    assert!(preview.contains("- two\n+ three\n"), "{preview}");
+    let preview = registry.get("edit_file").unwrap().preview("f.txt\n<<<\none\n===\nONE\n>>>");
+    assert!(preview.contains("- one\n+ ONE\n"), "{preview}");
+    // Previewing must not touch the file
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+}
+
+async fn run_with_approval(
+    policy: ApprovalPolicy,
+    decision: Option<ApprovalDecision>,
+    command: &str,
+) -> Vec<AgentEvent> {
+    let tool = format!("<think>run it</think><tool name=\"shell\">{command}</tool>");
+    let url = spawn_mock_llm(vec![ollama_reply(&tool), ollama_reply("<answer>done</answer>")]).await;
+    let approver = decision.map(|decision| {
+        let (tx, mut rx) = mpsc::unbounded_channel::<qai_cli::agent::approval::ApprovalRequest>();
+        tokio::spawn(async move {
+            while let Some(req) = rx.recv().await {
+                assert_eq!(req.tool, "shell");
+                assert_eq!(req.preview, format!("$ {}", req.input));
+                let _ = req.reply.send(decision.clone());
+            }
+        });
+        tx
+    });
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_approval(policy, approver);
+    let (tx, rx) = mpsc::unbounded_channel();
+    agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    collect_events(rx).await
+}
+
+fn shell_observation(events: &[AgentEvent]) -> String {
+    events
+        .iter()
+        .find_map(|e| match e {
+            AgentEvent::Observation { name, output, .. } if name == "shell" => Some(output.clone()),
+            _ => None,
+        })
+        .expect("no shell observation")
+}
+
+#[tokio::test]
+async fn approved_tool_call_runs() {
+    let events = run_with_approval(ApprovalPolicy::default(), Some(ApprovalDecision::Approve), "echo ok").await;
+    assert!(shell_observation(&events).starts_with("ok\n[exit code: 0]\n<terminal_status>"));
+}
+
+#[tokio::test]
+async fn rejected_tool_call_is_reported_to_the_model() {
+    let decision = ApprovalDecision::Reject("too risky".into());
+    let events = run_with_approval(ApprovalPolicy::default(), Some(decision), "echo nope").await;
+    assert_eq!(shell_observation(&events), "[rejected by user: `shell` was not run — too risky]");
+}
+
+#[tokio::test]
+async fn edited_tool_call_runs_with_new_input() {
+    let decision = ApprovalDecision::Edit("echo edited".into());
+    let events = run_with_approval(ApprovalPolicy::default(), Some(decision), "echo original").await;
+    assert!(shell_observation(&events).starts_with("edited\n[exit code: 0]\n<terminal_status>"));
+    assert!(events.iter().any(|e| matches!(e, AgentEvent::Notice { message, .. } if message.contains("edited"))));
+}
+
+#[tokio::test]
+async fn denied_tool_never_runs() {
+    let mut policy = ApprovalPolicy::allow_all();
+    policy.set("shell", Approval::Deny);
+    let events = run_with_approval(policy, None, "echo ran").await;
+    assert!(shell_observation(&events).starts_with("[denied:"));
+}
+
+#[tokio::test]
+async fn ask_without_approver_is_refused() {
+    let events = run_with_approval(ApprovalPolicy::default(), None, "echo ran").await;
+    assert!(shell_observation(&events).contains("needs the user's approval"));
+}
+
+#[tokio::test]
+async fn unanswered_approval_counts_against_the_time_budget() {
+    let url = spawn_mock_llm(vec![ollama_reply("<think>t</think><tool name=\"shell\">echo hi</tool>")]).await;
+    // Holds every request without answering it
+    let (approver, mut requests) = mpsc::unbounded_channel::<qai_cli::agent::approval::ApprovalRequest>();
+    let held = tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Some(req) = requests.recv().await {
+            held.push(req);
+        }
+        held.len()
+    });
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_approval(ApprovalPolicy::default(), Some(approver))
+        .with_limits(AgentLimits { max_duration: Some(Duration::from_millis(300)), ..AgentLimits::default() });
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert!(matches!(outcome, RunOutcome::Stopped(StopReason::TimeBudget(_))));
+    drop(agent);
+    assert_eq!(held.await.unwrap(), 1);
+    assert!(!collect(rx).await.contains("[exit code"));
+}
+
+// ── Workspace confinement ────────────────────────────────────────────────────
+
+fn workspace_fixture() -> (tempfile::TempDir, tempfile::TempDir, ToolRegistry) {
+    let root = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("inside.txt"), "inside").unwrap();
+    std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+    let registry = ToolRegistry::builtin_in(Workspace::new(root.path()));
+    (root, outside, registry)
+}
+
+#[tokio::test]
+async fn relative_paths_resolve_against_workspace_root() {
+    let (_root, _outside, registry) = workspace_fixture();
+    assert_eq!(registry.dispatch("read_file", "inside.txt").await.unwrap(), "inside");
+    assert_eq!(registry.dispatch("read_file", "./inside.txt").await.unwrap(), "inside");
+}
+
+#[tokio::test]
+async fn parent_and_absolute_paths_outside_root_are_rejected() {
+    let (_root, outside, registry) = workspace_fixture();
+    let secret = outside.path().join("secret.txt").display().to_string();
+    for input in [secret.clone(), "../../../../etc/passwd".to_string()] {
+        let out = registry.dispatch("read_file", &input).await.unwrap();
+        assert!(out.contains("outside the workspace root"), "{input}: {out}");
+    }
+    let out = registry.dispatch("write_file", &format!("{secret}\npwned")).await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+    assert_eq!(std::fs::read_to_string(&secret).unwrap(), "secret");
+    let out = registry.dispatch("edit_file", &format!("{secret}\n<<<\nsecret\n===\nx\n>>>")).await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+    let out = registry.dispatch("grep_search", &format!("secret\n{}", outside.path().display())).await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks_escaping_root_are_rejected() {
+    let (root, outside, registry) = workspace_fixture();
+    std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+    std::os::unix::fs::symlink(outside.path().join("secret.txt"), root.path().join("secret-link")).unwrap();
+    std::os::unix::fs::symlink(outside.path().join("new.txt"), root.path().join("dangling")).unwrap();
+
+    let out = registry.dispatch("read_file", "link/secret.txt").await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+    let out = registry.dispatch("read_file", "secret-link").await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+    let out = registry.dispatch("write_file", "link/new.txt\nx").await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+    let out = registry.dispatch("write_file", "dangling\nx").await.unwrap();
+    assert!(out.contains("[write_file error"), "{out}");
+    assert!(!outside.path().join("new.txt").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn grep_search_pattern_cannot_enable_symlink_following() {
+    let (root, outside, registry) = workspace_fixture();
+    std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+    let out = registry.dispatch("grep_search", "-R\n.").await.unwrap();
+    assert!(!out.contains("secret"), "{out}");
+    let out = registry.dispatch("grep_search", "-R\n.\n*.txt").await.unwrap();
+    assert!(!out.contains("secret"), "{out}");
+}
+
+#[tokio::test]
+async fn new_files_in_new_dirs_inside_root_are_allowed() {
+    let (root, _outside, registry) = workspace_fixture();
+    let out = registry.dispatch("write_file", "a/b/c.txt\nhi").await.unwrap();
+    assert!(out.contains("wrote"), "{out}");
+    assert_eq!(std::fs::read_to_string(root.path().join("a/b/c.txt")).unwrap(), "hi");
+    let out = registry.dispatch("write_file", "missing/../../escape.txt\nhi").await.unwrap();
+    assert!(out.contains("[write_file error"), "{out}");
+}
+
+#[tokio::test]
+async fn read_only_roots_allow_reads_but_not_writes() {
+    let (root, outside, _) = workspace_fixture();
+    let registry = ToolRegistry::builtin_in(Workspace::new(root.path()).with_read_only_root(outside.path()));
+    let secret = outside.path().join("secret.txt").display().to_string();
+    assert_eq!(registry.dispatch("read_file", &secret).await.unwrap(), "secret");
+    let out = registry.dispatch("grep_search", &format!("secret\n{}", outside.path().display())).await.unwrap();
+    assert!(out.contains("secret.txt"), "{out}");
+    let out = registry.dispatch("write_file", &format!("{secret}\nx")).await.unwrap();
+    assert!(out.contains("read-only root"), "{out}");
+}
+
+#[tokio::test]
+async fn grep_search_reports_paths_relative_to_root() {
+    let (_root, _outside, registry) = workspace_fixture();
+    let out = registry.dispatch("grep_search", "inside").await.unwrap();
+    assert_eq!(out, "./inside.txt:1:inside");
+}
+
+fn git_fixture() -> (tempfile::TempDir, ToolRegistry) {
+    let (root, _outside, registry) = workspace_fixture();
+    let git = |args: &[&str]| {
+        let out = std::process::Command::new("git").args(args).current_dir(root.path()).output().unwrap();
+        assert!(out.status.success(), "git {args:?}: {}", String::from_utf8_lossy(&out.stderr));
+    };
+    git(&["init", "-q"]);
+    git(&["-c", "user.name=t", "-c", "user.email=t@example.com", "commit", "-q", "--allow-empty", "-m", "init"]);
+    (root, registry)
+}
+
+#[tokio::test]
+async fn git_tools_run_in_the_workspace_root() {
+    let (_root, registry) = git_fixture();
+    let out = registry.dispatch("git_status", "").await.unwrap();
+    assert!(out.contains("?? inside.txt"), "{out}");
+    let out = registry.dispatch("git_add", "inside.txt").await.unwrap();
+    assert_eq!(out, "(no output)");
+    assert!(registry.dispatch("git_status", "inside.txt").await.unwrap().contains("A  inside.txt"));
+}
+
+#[tokio::test]
+async fn git_tools_refuse_options_and_outside_paths() {
+    let (root, registry) = git_fixture();
+    let target = root.path().join("written-by-git.txt");
+    let out = registry.dispatch("git_diff", &format!("--output={}", target.display())).await.unwrap();
+    assert!(out.contains("[git_diff error: options are not allowed"), "{out}");
+    assert!(!target.exists());
+    let out = registry.dispatch("git_add", "inside.txt --chmod=+x").await.unwrap();
+    assert!(out.contains("[git_add error: options are not allowed"), "{out}");
+    let out = registry.dispatch("git_status", "-uall").await.unwrap();
+    assert!(out.contains("[git_status error: options are not allowed"), "{out}");
+    let out = registry.dispatch("git_add", "../../etc/passwd").await.unwrap();
+    assert!(out.contains("outside the workspace root"), "{out}");
+}
+
+#[tokio::test]
+async fn git_tools_treat_pathspec_magic_literally() {
+    // The workspace is a subdirectory, so ":/" would name the repository root
+    let (repo, _) = git_fixture();
+    let sub = repo.path().join("sub");
+    std::fs::create_dir(&sub).unwrap();
+    std::fs::write(sub.join("inner.txt"), "inner").unwrap();
+    let registry = ToolRegistry::builtin_in(Workspace::new(&sub));
+    for input in [":/inside.txt", ":(top)inside.txt"] {
+        let out = registry.dispatch("git_add", input).await.unwrap();
+        assert!(out.contains("did not match"), "{input}: {out}");
+    }
+    let out = registry.dispatch("git_status", ":/").await.unwrap();
+    assert!(!out.contains("inside.txt"), "{out}");
+    let status = std::process::Command::new("git").args(["status", "--short"]).current_dir(repo.path()).output().unwrap();
+    assert!(String::from_utf8_lossy(&status.stdout).contains("?? inside.txt"));
+    let out = registry.dispatch("git_add", "inner.txt").await.unwrap();
+    assert_eq!(out, "(no output)");
+}
+
+#[tokio::test]
+async fn git_diff_takes_a_path_or_a_ref() {
+    let (root, registry) = git_fixture();
+    registry.dispatch("git_add", "inside.txt").await.unwrap();
+    std::fs::write(root.path().join("inside.txt"), "changed").unwrap();
+    let out = registry.dispatch("git_diff", "inside.txt").await.unwrap();
+    assert!(out.contains("+changed"), "{out}");
+    let out = registry.dispatch("git_diff", "HEAD").await.unwrap();
+    assert!(out.contains("inside.txt"), "{out}");
+}
+
+// ── Shell tool ───────────────────────────────────────────────────────────────
+
+use qai_cli::agent::shell::{self, cap_output, ShellOptions};
+
+fn shell_opts(timeout_ms: u64, max_output: usize) -> ShellOptions {
+    ShellOptions { timeout: Duration::from_millis(timeout_ms), max_output }
+}
+
+#[tokio::test]
+async fn shell_reports_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = shell::run("echo hi; exit 3", dir.path(), &ShellOptions::default()).await;
+    assert_eq!(out, "hi\n[exit code: 3]");
+    let out = shell::run("true", dir.path(), &ShellOptions::default()).await;
+    assert_eq!(out, "(no output)\n[exit code: 0]");
+}
+
+#[tokio::test]
+async fn shell_runs_in_workspace_root() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("here.txt"), "").unwrap();
+    let registry = ToolRegistry::builtin_in(Workspace::new(dir.path()));
+    let out = registry.dispatch("shell", "ls").await.unwrap();
+    assert!(out.starts_with("here.txt\n"), "{out}");
+}
+
+#[tokio::test]
+async fn shell_timeout_kills_whole_process_group() {
+    let dir = tempfile::tempdir().unwrap();
+    let marker = dir.path().join("survived");
+    // A background grandchild that would outlive a plain kill of `sh`
+    let cmd = format!("echo started; (sleep 1 && touch {}) & sleep 30", marker.display());
+    let started = std::time::Instant::now();
+    let out = shell::run(&cmd, dir.path(), &shell_opts(300, 1024)).await;
+    assert!(started.elapsed() < Duration::from_secs(5), "timeout not enforced");
+    assert!(out.starts_with("started\n"), "partial output lost: {out}");
+    assert!(out.ends_with("[timed out after 300ms; process group killed]"), "{out}");
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!marker.exists(), "grandchild kept running after timeout");
+}
+
+#[tokio::test]
+async fn shell_output_is_capped_to_head_and_tail() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = shell::run("seq 1 10000", dir.path(), &shell_opts(10_000, 200)).await;
+    assert!(out.starts_with("1\n2\n3\n"), "{out}");
+    assert!(out.contains("bytes omitted"), "{out}");
+    assert!(out.ends_with("9999\n10000\n[exit code: 0]"), "{out}");
+    assert!(out.len() < 400, "{}", out.len());
+}
+
+#[test]
+fn cap_output_respects_char_boundaries() {
+    assert_eq!(cap_output("short", 10), "short");
+    let text = "é".repeat(20);
+    let capped = cap_output(&text, 9);
+    assert!(capped.starts_with("éé\n[... "), "{capped}");
+    assert!(capped.ends_with("\néé"), "{capped}");
+}
+
+#[tokio::test]
+async fn dropping_shell_call_kills_process_group() {
+    let dir = tempfile::tempdir().unwrap();
+    let marker = dir.path().join("survived");
+    let cmd = format!("(sleep 1 && touch {}) & sleep 30", marker.display());
+    let cancelled = tokio::time::timeout(
+        Duration::from_millis(200),
+        shell::run(&cmd, dir.path(), &ShellOptions::default()),
+    )
+    .await;
+    assert!(cancelled.is_err());
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!marker.exists(), "grandchild kept running after the call was dropped");
+}
+
+#[tokio::test]
+async fn shell_kills_processes_a_command_leaves_running() {
+    let dir = tempfile::tempdir().unwrap();
+    let marker = dir.path().join("survived");
+    let cmd = format!("(sleep 1 && touch {}) & echo done", marker.display());
+    let out = shell::run(&cmd, dir.path(), &ShellOptions::default()).await;
+    assert!(out.starts_with("done\n[exit code: 0]\n[killed processes"), "{out}");
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!marker.exists(), "leftover process kept running");
+}
+
+// ── Terminal session ─────────────────────────────────────────────────────────
+
+use qai_cli::agent::terminal::TerminalSession;
+
+#[tokio::test]
+async fn terminal_keeps_cwd_and_exported_env_between_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    let term = TerminalSession::new(dir.path(), ShellOptions::default());
+    term.run("cd sub && export GREETING=hello").await;
+    let out = term.run("pwd; echo $GREETING").await;
+    let sub = dir.path().canonicalize().unwrap().join("sub");
+    assert!(out.starts_with(&format!("{}\nhello\n[exit code: 0]", sub.display())), "{out}");
+    assert!(out.contains(&format!("<terminal_status>\ncwd: {}\n", sub.display())), "{out}");
+    assert!(out.ends_with("processes:\n  (none)\n</terminal_status>"), "{out}");
+}
+
+#[tokio::test]
+async fn terminal_keeps_state_after_exit() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    let term = TerminalSession::new(dir.path(), ShellOptions::default());
+    let out = term.run("cd sub; exit 4").await;
+    assert!(out.contains("[exit code: 4]"), "{out}");
+    assert!(term.cwd().await.ends_with("sub"));
+}
+
+#[tokio::test]
+async fn terminal_background_job_is_listed_and_killed_with_the_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let marker = dir.path().join("survived");
+    let term = TerminalSession::new(dir.path(), ShellOptions::default());
+    let out = term.run(&format!("echo serving; sleep 1 && touch {} &", marker.display())).await;
+    assert!(out.starts_with("[started background job [1] pid "), "{out}");
+    assert!(out.contains("  [1] pid "), "{out}");
+    assert!(out.contains("running 0s: echo serving; sleep 1"), "{out}");
+
+    // Foreground commands still return while the job runs
+    let out = term.run("echo next").await;
+    assert!(out.starts_with("next\n[exit code: 0]\n"), "{out}");
+    assert!(out.contains("running"), "{out}");
+
+    drop(term);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!marker.exists(), "background job outlived the session");
+}
+
+#[tokio::test]
+async fn terminal_reports_an_exited_job_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let term = TerminalSession::new(dir.path(), ShellOptions::default());
+    let out = term.run("echo logged; sleep 0.2; exit 2 &").await;
+    let log = out.split("output goes to ").nth(1).unwrap().split(']').next().unwrap().to_string();
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let status = term.status().await;
+    assert!(status.contains("  [1] exited with code 2: echo logged; sleep 0.2; exit 2"), "{status}");
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "logged\n");
+    assert!(term.status().await.contains("  (none)"));
+}
+
+#[tokio::test]
+async fn terminal_sessions_keep_state_in_fresh_private_dirs() {
+    let dir = tempfile::tempdir().unwrap();
+    let state_dir = |out: String| {
+        let log = out.split("output goes to ").nth(1).unwrap().split(']').next().unwrap().to_string();
+        std::path::PathBuf::from(log).parent().unwrap().to_path_buf()
+    };
+    let first = TerminalSession::new(dir.path(), ShellOptions::default());
+    let second = TerminalSession::new(dir.path(), ShellOptions::default());
+    let a = state_dir(first.run("true &").await);
+    let b = state_dir(second.run("true &").await);
+    assert_ne!(a, b);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&a).unwrap().permissions().mode() & 0o777, 0o700);
+    }
+    drop(first);
+    assert!(!a.exists(), "the state dir is removed with the session");
+}
+
+#[tokio::test]
+async fn terminal_and_and_is_not_a_background_job() {
+    let dir = tempfile::tempdir().unwrap();
+    let term = TerminalSession::new(dir.path(), ShellOptions::default());
+    let out = term.run("true && echo yes").await;
+    assert!(out.starts_with("yes\n[exit code: 0]"), "{out}");
+}
+
+#[tokio::test]
+async fn terminal_falls_back_to_root_when_cwd_is_deleted() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("gone")).unwrap();
+    let term = TerminalSession::new(dir.path(), ShellOptions::default());
+    term.run("cd gone").await;
+    std::fs::remove_dir(dir.path().join("gone")).unwrap();
+    let out = term.run("pwd").await;
+    assert!(out.contains("no longer exists; back in"), "{out}");
+    assert_eq!(term.cwd().await, dir.path().canonicalize().unwrap());
+}
+
+#[tokio::test]
+async fn shell_tool_shares_one_session_per_registry() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    let registry = ToolRegistry::builtin_in(Workspace::new(dir.path()));
+    registry.dispatch("shell", "cd sub").await.unwrap();
+    let out = registry.dispatch("shell", "ls ..").await.unwrap();
+    assert!(out.starts_with("sub\n"), "{out}");
+}
+
+// ── Viewer, path search and editing tools ────────────────────────────────────
+
+fn registry_in(dir: &tempfile::TempDir) -> ToolRegistry {
+    ToolRegistry::builtin_in(Workspace::new(dir.path()))
+}
+
+fn numbered_file(dir: &tempfile::TempDir, name: &str, lines: usize) {
+    let text: String = (1..=lines).map(|n| format!("line {n}\n")).collect();
+    std::fs::write(dir.path().join(name), text).unwrap();
+}
+
+#[test]
+fn prompt_advertised_tools_are_registered() {
+    let names = tools::builtin_registry().names();
+    for tool in [
+        "open",
+        "open_entire_file",
+        "scroll_up",
+        "scroll_down",
+        "search_paths_by_glob",
+        "multi_edit",
+        "create",
+        "undo_edit",
+        "submit",
+    ] {
+        assert!(names.contains(&tool), "{tool} missing from {names:?}");
+    }
+}
+
+#[tokio::test]
+async fn open_shows_a_numbered_window() {
+    let dir = tempfile::tempdir().unwrap();
+    numbered_file(&dir, "big.txt", 250);
+    let out = registry_in(&dir).dispatch("open", "big.txt").await.unwrap();
+    assert!(out.starts_with("[File: big.txt (250 lines total)]\n  1: line 1\n"), "{out}");
+    assert!(out.contains("100: line 100\n(150 more lines below)"), "{out}");
+    assert!(!out.contains("line 101"), "{out}");
+}
+
+#[tokio::test]
+async fn open_centers_the_window_on_a_line() {
+    let dir = tempfile::tempdir().unwrap();
+    numbered_file(&dir, "big.txt", 250);
+    let out = registry_in(&dir).dispatch("open", "big.txt\n120").await.unwrap();
+    assert!(out.contains("(69 more lines above)\n 70: line 70\n"), "{out}");
+    assert!(out.ends_with("169: line 169\n(81 more lines below)"), "{out}");
+
+    let out = registry_in(&dir).dispatch("open", "big.txt\n999").await.unwrap();
+    assert_eq!(out, "[open error: line 999 is out of range; big.txt has 250 lines]");
+}
+
+#[tokio::test]
+async fn scroll_moves_the_window_and_stops_at_the_edges() {
+    let dir = tempfile::tempdir().unwrap();
+    numbered_file(&dir, "big.txt", 250);
+    let registry = registry_in(&dir);
+    assert_eq!(
+        registry.dispatch("scroll_down", "").await.unwrap(),
+        "[scroll_down: no file is open; use `open` first]"
+    );
+    registry.dispatch("open", "big.txt").await.unwrap();
+
+    let out = registry.dispatch("scroll_down", "").await.unwrap();
+    assert!(out.contains("(100 more lines above)\n101: line 101\n"), "{out}");
+    // The last window is full rather than running past the end
+    let out = registry.dispatch("scroll_down", "").await.unwrap();
+    assert!(out.contains("(150 more lines above)\n151: line 151\n"), "{out}");
+    assert!(out.ends_with("250: line 250"), "{out}");
+    assert_eq!(registry.dispatch("scroll_down", "").await.unwrap(), "[scroll_down: already at the end of big.txt]");
+
+    let out = registry.dispatch("scroll_up", "").await.unwrap();
+    assert!(out.contains("(50 more lines above)\n 51: line 51\n"), "{out}");
+    registry.dispatch("scroll_up", "").await.unwrap();
+    assert_eq!(registry.dispatch("scroll_up", "").await.unwrap(), "[scroll_up: already at the top of big.txt]");
+}
+
+#[tokio::test]
+async fn open_entire_file_shows_every_line() {
+    let dir = tempfile::tempdir().unwrap();
+    numbered_file(&dir, "big.txt", 150);
+    let out = registry_in(&dir).dispatch("open_entire_file", "big.txt").await.unwrap();
+    assert!(out.starts_with("[File: big.txt (150 lines total)]\n  1: line 1\n"), "{out}");
+    assert!(out.ends_with("150: line 150"), "{out}");
+}
+
+#[tokio::test]
+async fn open_is_confined_to_the_workspace() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = registry_in(&dir).dispatch("open", "../outside.txt").await.unwrap();
+    assert!(out.starts_with("[open error: '../outside.txt' is outside the workspace root"), "{out}");
+}
+
+#[tokio::test]
+async fn search_paths_by_glob_finds_files_at_any_depth() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("src/agent")).unwrap();
+    std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+    for file in ["src/main.rs", "src/agent/mod.rs", "README.md", ".git/config.rs"] {
+        std::fs::write(dir.path().join(file), "").unwrap();
+    }
+    let registry = registry_in(&dir);
+    let out = registry.dispatch("search_paths_by_glob", "*.rs").await.unwrap();
+    assert_eq!(out, "src/agent/mod.rs\nsrc/main.rs");
+    let out = registry.dispatch("search_paths_by_glob", "src/*").await.unwrap();
+    assert_eq!(out, "src/agent/\nsrc/main.rs");
+    let out = registry.dispatch("search_paths_by_glob", "*.rs\nsrc/agent").await.unwrap();
+    assert_eq!(out, "src/agent/mod.rs");
+    let out = registry.dispatch("search_paths_by_glob", "*.toml").await.unwrap();
+    assert_eq!(out, "[search_paths_by_glob: no matches found]");
+    let out = registry.dispatch("search_paths_by_glob", "../*").await.unwrap();
+    assert!(out.starts_with("[search_paths_by_glob error: the pattern must be relative"), "{out}");
+}
+
+#[tokio::test]
+async fn create_refuses_to_overwrite() {
+    let dir = tempfile::tempdir().unwrap();
+    let registry = registry_in(&dir);
+    let out = registry.dispatch("create", "new/file.txt\nhello").await.unwrap();
+    assert_eq!(out, "[create: created new/file.txt (5 bytes)]");
+    assert_eq!(std::fs::read_to_string(dir.path().join("new/file.txt")).unwrap(), "hello");
+    let out = registry.dispatch("create", "new/file.txt\nagain").await.unwrap();
+    assert!(out.starts_with("[create error: new/file.txt already exists"), "{out}");
+    assert_eq!(std::fs::read_to_string(dir.path().join("new/file.txt")).unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn multi_edit_applies_every_hunk() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("f.txt"), "alpha\nbeta\ngamma\n").unwrap();
+    let input = "f.txt\n<<<\nalpha\n===\nALPHA\n>>>\n<<<\ngamma\n===\nGAMMA\n>>>";
+    let out = registry_in(&dir).dispatch("multi_edit", input).await.unwrap();
+    assert_eq!(out, "[multi_edit: applied 2 edits to f.txt]");
+    assert_eq!(std::fs::read_to_string(dir.path().join("f.txt")).unwrap(), "ALPHA\nbeta\nGAMMA\n");
+}
+
+#[tokio::test]
+async fn multi_edit_is_all_or_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("f.txt"), "alpha\nbeta\n").unwrap();
+    let input = "f.txt\n<<<\nalpha\n===\nALPHA\n>>>\n<<<\nmissing\n===\nx\n>>>";
+    let out = registry_in(&dir).dispatch("multi_edit", input).await.unwrap();
+    assert_eq!(out, "[multi_edit error: search string of edit 2 not found in f.txt; no edits applied]");
+    assert_eq!(std::fs::read_to_string(dir.path().join("f.txt")).unwrap(), "alpha\nbeta\n");
+}
+
+#[tokio::test]
+async fn multi_edit_reports_malformed_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("f.txt"), "alpha\n").unwrap();
+    let registry = registry_in(&dir);
+    let out = registry.dispatch("multi_edit", "f.txt\n<<<\nalpha\nALPHA\n>>>").await.unwrap();
+    assert_eq!(out, "[multi_edit error: edit 1 is missing the '===' separator]");
+    let out = registry.dispatch("multi_edit", "f.txt").await.unwrap();
+    assert_eq!(out, "[multi_edit error: no edits given]");
+}
+
+#[test]
+fn multi_edit_native_arguments_become_blocks() {
+    let registry = tools::builtin_registry();
+    let args = json!({"path": "f.txt", "edits": [{"search": "a", "replace": "b"}, {"search": "c", "replace": "d"}]});
+    let expected = "f.txt\n<<<\na\n===\nb\n>>>\n<<<\nc\n===\nd\n>>>";
+    assert_eq!(registry.input_from_args("multi_edit", &args), expected);
+    // Some models send the array as a JSON string
+    let args = json!({"path": "f.txt", "edits": r#"[{"search": "a", "replace": "b"}, {"search": "c", "replace": "d"}]"#});
+    assert_eq!(registry.input_from_args("multi_edit", &args), expected);
+}
+
+#[tokio::test]
+async fn undo_edit_reverts_edits_newest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("f.txt"), "one\n").unwrap();
+    let registry = registry_in(&dir);
+    registry.dispatch("edit_file", "f.txt\n<<<\none\n===\ntwo\n>>>").await.unwrap();
+    registry.dispatch("create", "g.txt\nnew").await.unwrap();
+
+    assert_eq!(registry.dispatch("undo_edit", "").await.unwrap(), "[undo_edit: removed g.txt, which the edit created]");
+    assert!(!dir.path().join("g.txt").exists());
+    assert_eq!(registry.dispatch("undo_edit", "").await.unwrap(), "[undo_edit: restored f.txt]");
+    assert_eq!(std::fs::read_to_string(dir.path().join("f.txt")).unwrap(), "one\n");
+    assert_eq!(registry.dispatch("undo_edit", "").await.unwrap(), "[undo_edit: no edits to undo]");
+}
+
+#[tokio::test]
+async fn undo_edit_restores_a_file_that_was_not_utf8() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = [0xff, 0xfe, b'x', 0x80];
+    std::fs::write(dir.path().join("f.bin"), original).unwrap();
+    let registry = registry_in(&dir);
+    registry.dispatch("write_file", "f.bin\ntext").await.unwrap();
+
+    assert_eq!(registry.dispatch("undo_edit", "").await.unwrap(), "[undo_edit: restored f.bin]");
+    assert_eq!(std::fs::read(dir.path().join("f.bin")).unwrap(), original);
+}
+
+#[tokio::test]
+async fn undo_edit_preview_shows_the_revert() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("f.txt"), "one\n").unwrap();
+    let registry = registry_in(&dir);
+    registry.dispatch("write_file", "f.txt\ntwo\n").await.unwrap();
+    let preview = registry.get("undo_edit").unwrap().preview("");
+    assert_eq!(preview, "--- f.txt\n- two\n+ one\n");
+}
+
+#[tokio::test]
+async fn submit_ends_the_run_like_answer() {
+    let (outcome, events) = run_events(vec![ollama_reply(r#"<tool name="submit">Fixed the parser.</tool>"#)]).await;
+    assert_eq!(outcome, RunOutcome::Answered("Fixed the parser.".to_string()));
+    assert!(events.contains(&AgentEvent::Answer { step: 1, text: "Fixed the parser.".into() }));
+    assert!(!events.iter().any(|e| matches!(e, AgentEvent::ToolCall { .. })));
+}
+
+// ── Checkpoints ──────────────────────────────────────────────────────────────
+
+use qai_cli::agent::tools::ToolContext;
+use qai_cli::agent::Checkpoints;
+
+fn checkpointed_registry(dir: &tempfile::TempDir, checkpoints: &Checkpoints) -> ToolRegistry {
+    ToolRegistry::builtin_with(ToolContext::new(Workspace::new(dir.path())).with_checkpoints(checkpoints.clone()))
+}
+
+fn read(dir: &tempfile::TempDir, name: &str) -> String {
+    std::fs::read_to_string(dir.path().join(name)).unwrap()
+}
+
+#[tokio::test]
+async fn file_tools_record_pre_edit_content() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "old").unwrap();
+    let checkpoints = Checkpoints::new();
+    let registry = checkpointed_registry(&dir, &checkpoints);
+    registry.dispatch("write_file", "a.txt\nnew").await.unwrap();
+    registry.dispatch("create", "b.txt\nfresh").await.unwrap();
+    registry.dispatch("read_file", "a.txt").await.unwrap();
+
+    let list = checkpoints.list();
+    assert_eq!(list.len(), 2);
+    assert_eq!((list[0].display.as_str(), list[0].before.as_deref(), list[0].after.as_str()), ("a.txt", Some(&b"old"[..]), "new"));
+    assert_eq!((list[1].display.as_str(), list[1].before.as_deref()), ("b.txt", None));
+}
+
+#[tokio::test]
+async fn failed_edits_are_not_checkpointed() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "old").unwrap();
+    let checkpoints = Checkpoints::new();
+    let registry = checkpointed_registry(&dir, &checkpoints);
+    registry.dispatch("edit_file", "a.txt\n<<<\nmissing\n===\nx\n>>>").await.unwrap();
+    registry.dispatch("create", "a.txt\nclash").await.unwrap();
+    assert!(checkpoints.is_empty());
+}
+
+#[tokio::test]
+async fn undo_step_reverts_only_the_latest_step() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "v0").unwrap();
+    let checkpoints = Checkpoints::new();
+    let registry = checkpointed_registry(&dir, &checkpoints);
+    checkpoints.set_step(1);
+    registry.dispatch("write_file", "a.txt\nv1").await.unwrap();
+    checkpoints.set_step(2);
+    registry.dispatch("write_file", "a.txt\nv2").await.unwrap();
+    registry.dispatch("create", "new.txt\nx").await.unwrap();
+
+    let (step, reports) = checkpoints.undo_step().unwrap();
+    assert_eq!(step, 2);
+    assert_eq!(reports, vec!["removed new.txt, which the edit created", "restored a.txt"]);
+    assert_eq!(read(&dir, "a.txt"), "v1");
+    assert!(!dir.path().join("new.txt").exists());
+
+    let (step, _) = checkpoints.undo_step().unwrap();
+    assert_eq!(step, 1);
+    assert_eq!(read(&dir, "a.txt"), "v0");
+    assert!(checkpoints.undo_step().is_none());
+}
+
+#[tokio::test]
+async fn revert_all_restores_the_state_before_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "v0").unwrap();
+    let checkpoints = Checkpoints::new();
+    let registry = checkpointed_registry(&dir, &checkpoints);
+    for (step, input) in [(1, "a.txt\nv1"), (2, "a.txt\nv2"), (3, "untracked/b.txt\nb")] {
+        checkpoints.set_step(step);
+        registry.dispatch("write_file", input).await.unwrap();
+    }
+    assert_eq!(checkpoints.revert_all().len(), 3);
+    assert_eq!(read(&dir, "a.txt"), "v0");
+    assert!(!dir.path().join("untracked/b.txt").exists());
+    assert!(checkpoints.is_empty());
+}
+
+#[tokio::test]
+async fn restoring_a_file_changed_since_the_edit_says_so() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "v0").unwrap();
+    let checkpoints = Checkpoints::new();
+    checkpointed_registry(&dir, &checkpoints).dispatch("write_file", "a.txt\nv1").await.unwrap();
+    std::fs::write(dir.path().join("a.txt"), "hand edit").unwrap();
+    assert_eq!(
+        checkpoints.undo_last().unwrap(),
+        "restored a.txt (it had changed since the edit; those changes were overwritten)"
+    );
+    assert_eq!(read(&dir, "a.txt"), "v0");
+}
+
+#[tokio::test]
+async fn undo_edit_tool_uses_the_shared_checkpoints() {
+    let dir = tempfile::tempdir().unwrap();
+    let checkpoints = Checkpoints::new();
+    let registry = checkpointed_registry(&dir, &checkpoints);
+    registry.dispatch("create", "a.txt\nx").await.unwrap();
+    assert_eq!(checkpoints.len(), 1);
+    registry.dispatch("undo_edit", "").await.unwrap();
+    assert!(checkpoints.is_empty());
+}
+
+#[tokio::test]
+async fn agent_tags_checkpoints_with_their_step() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<tool name=\"write_file\">a.txt\none</tool>"),
+        ollama_reply("<tool name=\"write_file\">b.txt\ntwo</tool>"),
+        ollama_reply("<answer>done</answer>"),
+    ])
+    .await;
+    let checkpoints = Checkpoints::new();
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), String::new())
+        .with_tools(checkpointed_registry(&dir, &checkpoints))
+        .with_checkpoints(checkpoints.clone());
+    let (tx, _rx) = mpsc::unbounded_channel();
+    agent.run("write".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    let steps: Vec<(usize, String)> = checkpoints.list().into_iter().map(|c| (c.step, c.display)).collect();
+    assert_eq!(steps, vec![(1, "a.txt".to_string()), (2, "b.txt".to_string())]);
+}
+
+// ── Headless runs (`qai-cli run`) ─────────────────────────────────────────────
+
+fn run_options(dir: &tempfile::TempDir, url: String) -> qai_cli::RunOptions {
+    qai_cli::RunOptions {
+        task: "create hello.txt".into(),
+        provider: Provider::Ollama,
+        model: Some("m".into()),
+        api_token: Some(String::new()),
+        custom_url: url,
+        auth: None,
+        max_tokens: None,
+        system_prompt: "sys".into(),
+        workspace: Workspace::new(dir.path()),
+        native_tools: false,
+        max_steps: None,
+        max_format_retries: None,
+        timeout_secs: None,
+        event_log: None,
+        mcp: Default::default(),
+        approval: ApprovalPolicy::allow_all(),
+    }
+}
+
+#[tokio::test]
+async fn run_task_returns_the_answer_and_reports_progress() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<think>I will create it</think><tool name=\"create\">hello.txt\nhi\n</tool>"),
+        ollama_reply("<answer>Created hello.txt</answer>"),
+    ])
+    .await;
+    let mut progress = Vec::new();
+    let answer = qai_cli::run_task(run_options(&dir, url), CancellationToken::new(), &mut progress).await.unwrap();
+    assert_eq!(answer, "Created hello.txt");
+    assert_eq!(read(&dir, "hello.txt"), "hi");
+    let progress = String::from_utf8(progress).unwrap();
+    assert!(progress.contains("[step 1]\n  thought: I will create it\n  tool: create hello.txt"), "{progress}");
+    assert!(progress.contains("[step 2]"), "{progress}");
+    assert!(progress.contains("answered (2 step(s), 1 tool call(s) in "), "{progress}");
+    assert!(!progress.contains("Created hello.txt"), "the answer goes to stdout only: {progress}");
+}
+
+#[tokio::test]
+async fn run_task_errors_when_the_run_stops_without_an_answer() {
+    let dir = tempfile::tempdir().unwrap();
+    let tool = "<tool name=\"shell\">echo again</tool>";
+    let url = spawn_mock_llm(vec![ollama_reply(tool), ollama_reply(tool)]).await;
+    let mut opts = run_options(&dir, url);
+    opts.max_steps = Some(1);
+    let mut progress = Vec::new();
+    let err = qai_cli::run_task(opts, CancellationToken::new(), &mut progress).await.unwrap_err();
+    assert!(err.to_string().contains("step limit reached (1 steps)"), "{err}");
+    assert!(String::from_utf8(progress).unwrap().contains("stopped: step limit reached"));
+}
+
+#[tokio::test]
+async fn run_task_applies_max_format_retries() {
+    let dir = tempfile::tempdir().unwrap();
+    let think = "<think>still thinking</think>";
+    let url = spawn_mock_llm(vec![ollama_reply(think), ollama_reply(think), ollama_reply(think)]).await;
+    let mut opts = run_options(&dir, url);
+    opts.max_format_retries = Some(1);
+    let err = qai_cli::run_task(opts, CancellationToken::new(), &mut Vec::new()).await.unwrap_err();
+    assert!(err.to_string().contains("ignored the response format 2 times"), "{err}");
+}
+
+#[tokio::test]
+async fn run_task_applies_the_approval_policy() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<tool name=\"create\">hello.txt\nhi\n</tool>"),
+        ollama_reply("<answer>Could not create it</answer>"),
+    ])
+    .await;
+    let mut opts = run_options(&dir, url);
+    opts.approval = ApprovalPolicy::default();
+    let mut progress = Vec::new();
+    qai_cli::run_task(opts, CancellationToken::new(), &mut progress).await.unwrap();
+    assert!(!dir.path().join("hello.txt").exists());
+    let progress = String::from_utf8(progress).unwrap();
+    assert!(progress.contains("needs the user's approval and no one is there to give it"), "{progress}");
+}
+
+#[tokio::test]
+async fn run_task_errors_when_cancelled() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![ollama_reply("<answer>never</answer>")]).await;
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let err = qai_cli::run_task(run_options(&dir, url), cancel, &mut Vec::new()).await.unwrap_err();
+    assert!(err.to_string().contains("cancelled"), "{err}");
+}
+
+#[tokio::test]
+async fn run_task_errors_when_the_llm_is_unreachable() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = qai_cli::run_task(run_options(&dir, "http://127.0.0.1:9".into()), CancellationToken::new(), &mut Vec::new())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("failed"), "{err}");
+}
+
+#[test]
+fn render_progress_skips_deltas_answers_and_usage() {
+    use qai_cli::agent::render_progress;
+    assert_eq!(render_progress(&AgentEvent::StepStart { step: 3 }).as_deref(), Some("[step 3]"));
+    assert_eq!(
+        render_progress(&AgentEvent::Observation { step: 1, name: "shell".into(), output: "a\nb\nc".into() }).as_deref(),
+        Some("  observation (3 lines): a")
+    );
+    assert!(render_progress(&AgentEvent::ThoughtDelta { step: 1, text: "x".into() }).is_none());
+    assert!(render_progress(&AgentEvent::AnswerDelta { step: 1, text: "x".into() }).is_none());
+    assert!(render_progress(&AgentEvent::Answer { step: 1, text: "x".into() }).is_none());
+}
+
+#[test]
+fn render_progress_truncates_multibyte_text_on_a_char_boundary() {
+    use qai_cli::agent::render_progress;
+    // Each multi-byte char starts one byte before the line's limit
+    let text = format!("{}é", "a".repeat(299));
+    let out = render_progress(&AgentEvent::Thought { step: 1, text }).unwrap();
+    assert_eq!(out, format!("  thought: {}…(truncated)", "a".repeat(299)));
+    let input = format!("{}日本\nsecond line", "a".repeat(119));
+    let out = render_progress(&AgentEvent::ToolCall { step: 1, name: "shell".into(), input }).unwrap();
+    assert_eq!(out, format!("  tool: shell {}…(truncated)", "a".repeat(119)));
+    let output = format!("{}🙂\nmore", "a".repeat(119));
+    let out = render_progress(&AgentEvent::Observation { step: 1, name: "shell".into(), output }).unwrap();
+    assert_eq!(out, format!("  observation (2 lines): {}…(truncated)", "a".repeat(119)));
+}
+
+// ── One-shot questions (`qai-cli ask`) ────────────────────────────────────────
+
+fn ask_options(url: String, json: bool) -> qai_cli::AskOptions {
+    qai_cli::AskOptions {
+        prompt: "write tests for this".into(),
+        provider: Provider::Ollama,
+        model: Some("m".into()),
+        api_token: Some(String::new()),
+        custom_url: url,
+        auth: None,
+        max_tokens: None,
+        system_prompt: "sys".into(),
+        json,
+        event_log: None,
+    }
+}
+
+#[tokio::test]
+async fn ask_streams_the_reply_to_the_writer() {
+    let url = spawn_mock_llm(vec![ollama_reply("Here are the tests.")]).await;
+    let mut out = Vec::new();
+    let reply = qai_cli::ask(ask_options(url, false), CancellationToken::new(), &mut out).await.unwrap();
+    assert_eq!(reply, "Here are the tests.");
+    assert_eq!(String::from_utf8(out).unwrap(), "Here are the tests.\n");
+}
+
+#[tokio::test]
+async fn ask_json_prints_one_object() {
+    let url = spawn_mock_llm(vec![ollama_reply("42")]).await;
+    let mut out = Vec::new();
+    qai_cli::ask(ask_options(url, true), CancellationToken::new(), &mut out).await.unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), 1, "{out}");
+    let v: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(v, json!({ "provider": "ollama", "model": "m", "response": "42" }));
+}
+
+#[tokio::test]
+async fn ask_fails_on_an_http_error() {
+    let url = spawn_mock_llm(vec![(500, "model not loaded".to_string())]).await;
+    let err = qai_cli::ask(ask_options(url, false), CancellationToken::new(), &mut Vec::new()).await.unwrap_err();
+    assert!(err.to_string().contains("HTTP 500"), "{err}");
+    assert!(err.to_string().contains("model not loaded"), "{err}");
+}
+
+// ── Base URLs and auth styles ─────────────────────────────────────────────────
+
+/// Answer one request with `body` and hand back its request line and headers, lowercased.
+async fn spawn_recording_llm(body: String) -> (String, tokio::sync::oneshot::Receiver<String>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (seen_tx, seen) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let Ok((mut sock, _)) = listener.accept().await else { return };
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let head = loop {
+            let n = sock.read(&mut chunk).await.unwrap_or(0);
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).to_string();
+            if let Some(idx) = text.find("\r\n\r\n") {
+                break text[..idx].to_string();
+            }
+            if n == 0 {
+                break text;
+            }
+        };
+        let resp = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _ = sock.write_all(resp.as_bytes()).await;
+        let _ = sock.shutdown().await;
+        let _ = seen_tx.send(head.to_ascii_lowercase());
+    });
+    (format!("http://{addr}"), seen)
+}
+
+#[tokio::test]
+async fn agent_uses_the_base_url_override_and_auth_style() {
+    let reply = json!({"choices": [{"message": {"content": "<answer>from azure</answer>"}}]}).to_string();
+    let (base, seen) = spawn_recording_llm(reply).await;
+    // Azure OpenAI: a deployment URL with its api-version, and an `api-key` header
+    let url = format!("{base}/openai/deployments/gpt4o?api-version=2024-10-21");
+    let agent = ReActAgent::new(Provider::OpenAI, "az-key".into(), url, "gpt-4o".into(), "sys".into())
+        .with_auth(qai_cli::AuthStyle::ApiKey);
+    let (tx, rx) = mpsc::unbounded_channel();
+    agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert!(collect(rx).await.contains("from azure"));
+    let head = seen.await.unwrap();
+    assert!(head.starts_with("post /openai/deployments/gpt4o/chat/completions?api-version=2024-10-21 "), "{head}");
+    assert!(head.contains("\r\napi-key: az-key"), "{head}");
+    assert!(!head.contains("authorization"), "{head}");
+}
+
+#[tokio::test]
+async fn ask_sends_custom_provider_requests_to_its_base_url() {
+    let reply = json!({"choices": [{"message": {"content": "from lm studio"}}]}).to_string();
+    let (base, seen) = spawn_recording_llm(reply).await;
+    let opts = qai_cli::AskOptions {
+        provider: Provider::Custom,
+        api_token: Some("lm-key".into()),
+        custom_url: format!("{base}/v1"),
+        ..ask_options(String::new(), false)
+    };
+    let reply = qai_cli::ask(opts, CancellationToken::new(), &mut Vec::new()).await.unwrap();
+    assert_eq!(reply, "from lm studio");
+    let head = seen.await.unwrap();
+    assert!(head.starts_with("post /v1/chat/completions "), "{head}");
+    assert!(head.contains("authorization: bearer lm-key"), "{head}");
+}
+
+#[tokio::test]
+async fn custom_provider_without_a_url_explains_itself() {
+    let opts = qai_cli::AskOptions { provider: Provider::Custom, ..ask_options(String::new(), false) };
+    let err = qai_cli::ask(opts, CancellationToken::new(), &mut Vec::new()).await.unwrap_err();
+    assert!(err.to_string().contains("providers.custom.url"), "{err}");
+}
+
+// ── LLM clients ──────────────────────────────────────────────────────────────
+
+use futures_util::StreamExt;
+use qai_cli::llm::{ChatRequest, HttpError, StreamChunk};
+
+fn body_for(wire: Wire, max_tokens: Option<u32>) -> serde_json::Value {
+    let history = [("user".to_string(), "hi".to_string())];
+    let req = ChatRequest { max_tokens, ..ChatRequest::text("m", "sys", &history) };
+    llm::for_wire(wire, Endpoint::default()).body(&req, true)
+}
+
+#[test]
+fn every_wire_gets_the_default_max_tokens() {
+    assert_eq!(body_for(Wire::Anthropic, None)["max_tokens"], llm::DEFAULT_MAX_TOKENS);
+    assert_eq!(body_for(Wire::OpenAi, None)["max_tokens"], llm::DEFAULT_MAX_TOKENS);
+    assert_eq!(body_for(Wire::Ollama, None)["options"]["num_predict"], llm::DEFAULT_MAX_TOKENS);
+}
+
+#[test]
+fn every_wire_applies_a_requested_max_tokens() {
+    assert_eq!(body_for(Wire::Anthropic, Some(256))["max_tokens"], 256);
+    assert_eq!(body_for(Wire::OpenAi, Some(256))["max_tokens"], 256);
+    assert_eq!(body_for(Wire::Ollama, Some(256))["options"]["num_predict"], 256);
+}
+
+#[tokio::test]
+async fn agent_and_ask_send_the_configured_max_tokens() {
+    let replies = vec![ollama_reply("<answer>a</answer>"), ollama_reply("<answer>b</answer>"), ollama_reply("c")];
+    let (url, requests) = spawn_recording_mock_llm(replies).await;
+    for mode in [ToolMode::Xml, ToolMode::Native] {
+        let agent = ReActAgent::new(Provider::Ollama, String::new(), url.clone(), "m".into(), "sys".into())
+            .with_tool_mode(mode)
+            .with_max_tokens(Some(256));
+        let (tx, _rx) = mpsc::unbounded_channel();
+        agent.run("task".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    }
+    let opts = qai_cli::AskOptions { max_tokens: Some(256), ..ask_options(url, false) };
+    qai_cli::ask(opts, CancellationToken::new(), &mut Vec::new()).await.unwrap();
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    for body in requests.iter() {
+        assert_eq!(body["options"]["num_predict"], 256, "{body}");
+    }
+}
+
+#[test]
+fn plain_chat_requests_offer_no_tools() {
+    for wire in [Wire::OpenAi, Wire::Anthropic, Wire::Ollama] {
+        let body = body_for(wire, None);
+        assert!(body.get("tools").is_none(), "{wire:?}: {body}");
+        assert_eq!(body["stream"], true);
+    }
+    assert_eq!(body_for(Wire::Anthropic, None)["system"], "sys");
+    assert_eq!(body_for(Wire::Ollama, None)["messages"][0]["role"], "system");
+}
+
+#[test]
+fn only_providers_that_accept_it_get_stream_options() {
+    let req = ChatRequest::text("m", "sys", &[]);
+    let body = |provider: Provider, stream: bool| {
+        let client = llm::client(provider, "http://localhost:1", "tok", qai_cli::AuthStyle::Bearer).unwrap();
+        client.body(&req, stream)
+    };
+    for provider in [Provider::OpenAI, Provider::XAI] {
+        assert_eq!(body(provider, true)["stream_options"]["include_usage"], true, "{provider:?}");
+        assert!(body(provider, false).get("stream_options").is_none(), "{provider:?}");
+    }
+    // Self-hosted and proxy servers may reject a field they don't know
+    for provider in [Provider::Custom, Provider::GitHubModels, Provider::Zen, Provider::Anthropic, Provider::Ollama] {
+        assert!(body(provider, true).get("stream_options").is_none(), "{provider:?}");
+    }
+    assert!(body_for(Wire::OpenAi, None).get("stream_options").is_none());
+}
+
+#[tokio::test]
+async fn openai_stream_reports_usage_from_the_final_chunk() {
+    let body = [
+        json!({"choices": [{"delta": {"content": "Hi"}}]}).to_string(),
+        json!({"choices": [], "usage": {"prompt_tokens": 12, "completion_tokens": 4}}).to_string(),
+        "[DONE]".to_string(),
+    ]
+    .map(|line| format!("data: {line}\n\n"))
+    .concat();
+    let url = spawn_mock_llm(vec![(200, body)]).await;
+    let client = llm::client(Provider::OpenAI, &url, "tok", qai_cli::AuthStyle::Bearer).unwrap();
+    let request = ChatRequest::text("m", "sys", &[]);
+    let chunks: Vec<StreamChunk> = client.stream(&request).await.unwrap().map(|c| c.unwrap()).collect().await;
+    assert_eq!(
+        chunks,
+        vec![
+            StreamChunk::Delta("Hi".into()),
+            StreamChunk::Usage(TokenUsage { input_tokens: 12, output_tokens: 4 }),
+            StreamChunk::Done,
+        ]
+    );
+}
+
+#[test]
+fn client_needs_a_token_only_where_the_provider_does() {
+    let auth = qai_cli::AuthStyle::Bearer;
+    let err = llm::client(Provider::OpenAI, "", " ", auth).err().unwrap();
+    assert!(err.to_string().contains("API token is empty"), "{err}");
+    assert!(llm::client(Provider::OpenAI, "", "", qai_cli::AuthStyle::None).is_ok());
+    let ollama = llm::client(Provider::Ollama, "", "", auth).unwrap();
+    assert_eq!(ollama.wire(), Wire::Ollama);
+    assert_eq!(ollama.endpoint().url, "http://localhost:11434/api/chat");
+}
+
+#[tokio::test]
+async fn complete_returns_text_and_usage() {
+    let reply = json!({"message": {"role": "assistant", "content": "hello"}, "prompt_eval_count": 7, "eval_count": 2});
+    let url = spawn_mock_llm(vec![(200, reply.to_string())]).await;
+    let client = llm::client(Provider::Ollama, &url, "", qai_cli::AuthStyle::Bearer).unwrap();
+    let turn = client.complete(&ChatRequest::text("m", "sys", &[])).await.unwrap();
+    assert_eq!(turn.text, "hello");
+    assert_eq!(turn.usage, Some(TokenUsage { input_tokens: 7, output_tokens: 2 }));
+}
+
+#[tokio::test]
+async fn complete_rejects_a_reply_in_another_wire_format() {
+    let reply = json!({"choices": [{"message": {"content": "openai shaped"}}]});
+    let url = spawn_mock_llm(vec![(200, reply.to_string())]).await;
+    let client = llm::client(Provider::Ollama, &url, "", qai_cli::AuthStyle::Bearer).unwrap();
+    let err = client.complete(&ChatRequest::text("m", "sys", &[])).await.unwrap_err();
+    assert!(err.to_string().starts_with("unexpected response"), "{err}");
+}
+
+#[tokio::test]
+async fn stream_yields_deltas_and_usage_until_done() {
+    let body = [
+        json!({"message": {"content": "Hel"}, "done": false}).to_string(),
+        json!({"message": {"content": "lo"}, "done": false}).to_string(),
+        json!({"message": {"content": ""}, "done": true, "eval_count": 3}).to_string(),
+    ]
+    .join("\n");
+    let url = spawn_mock_llm(vec![(200, body)]).await;
+    let client = llm::client(Provider::Ollama, &url, "", qai_cli::AuthStyle::Bearer).unwrap();
+    let request = ChatRequest::text("m", "sys", &[]);
+    let chunks: Vec<StreamChunk> = client
+        .stream(&request)
+        .await
+        .unwrap()
+        .map(|c| c.unwrap())
+        .collect()
+        .await;
+    assert_eq!(
+        chunks,
+        vec![
+            StreamChunk::Delta("Hel".into()),
+            StreamChunk::Delta("lo".into()),
+            StreamChunk::Usage(TokenUsage { input_tokens: 0, output_tokens: 3 }),
+            StreamChunk::Done,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn http_errors_keep_their_status() {
+    let url = spawn_mock_llm(vec![(422, json!({"error": "tools not supported"}).to_string())]).await;
+    let client = llm::client(Provider::Ollama, &url, "", qai_cli::AuthStyle::Bearer).unwrap();
+    let err = client.stream(&ChatRequest::text("m", "sys", &[])).await.err().unwrap();
+    let http = err.downcast_ref::<HttpError>().expect("typed HTTP error");
+    assert_eq!(http.status.as_u16(), 422);
+    assert!(err.to_string().starts_with("HTTP 422"), "{err}");
+    assert!(err.to_string().contains("tools not supported"), "{err}");
+}
+
+// ── JSONL event log (`--events-jsonl`) ────────────────────────────────────────
+
+use qai_cli::agent::EventLog;
+
+fn log_records(path: &std::path::Path) -> Vec<serde_json::Value> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("bad line {line}: {e}")))
+        .collect()
+}
+
+fn record_types(records: &[serde_json::Value]) -> Vec<&str> {
+    records.iter().map(|r| r["type"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn event_log_records_an_agent_run_and_closes_with_a_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.jsonl");
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<think>make it</think><tool name=\"create\">a.txt\nA</tool>"),
+        ollama_reply("<answer>made a.txt</answer>"),
+    ])
+    .await;
+    let mut opts = run_options(&dir, url);
+    opts.event_log = Some(EventLog::open(path.to_str().unwrap()).unwrap());
+    qai_cli::run_task(opts, CancellationToken::new(), &mut Vec::new()).await.unwrap();
+
+    let records = log_records(&path);
+    assert_eq!(
+        record_types(&records),
+        ["step", "thought", "tool_call", "observation", "step", "answer", "run_end", "summary"]
+    );
+    for r in &records {
+        assert!(r["ts"].as_str().unwrap().ends_with('Z'), "{r}");
+    }
+    assert_eq!(records[2]["name"], "create");
+    assert_eq!(records[2]["step"], 1);
+    assert_eq!(records[2]["run"], 1);
+    assert_eq!(records[5]["text"], "made a.txt");
+    assert_eq!(records[5]["step"], 2);
+    assert_eq!(records[6]["outcome"], "answered");
+    let summary = &records[7];
+    assert_eq!(summary["runs"], 1);
+    assert_eq!(summary["steps"], 2);
+    assert_eq!(summary["tool_calls"], 1);
+    assert_eq!(summary["errors"], 0);
+    assert_eq!(summary["outcome"], "answered");
+}
+
+#[tokio::test]
+async fn event_log_records_a_chat_reply_with_usage() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chat.jsonl");
+    let body = json!({"message": {"role": "assistant", "content": "hi"}, "done": true, "prompt_eval_count": 7, "eval_count": 3});
+    let url = spawn_mock_llm(vec![(200, body.to_string())]).await;
+    let mut opts = ask_options(url, false);
+    opts.event_log = Some(EventLog::open(path.to_str().unwrap()).unwrap());
+    qai_cli::ask(opts, CancellationToken::new(), &mut Vec::new()).await.unwrap();
+
+    let records = log_records(&path);
+    assert_eq!(record_types(&records), ["step", "usage", "answer", "run_end", "summary"]);
+    assert_eq!(records[1]["input_tokens"], 7);
+    assert_eq!(records[1]["output_tokens"], 3);
+    assert_eq!(records[2]["text"], "hi");
+    assert_eq!(records[4]["usage"], json!({ "input_tokens": 7, "output_tokens": 3 }));
+}
+
+#[tokio::test]
+async fn event_log_records_chat_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("err.jsonl");
+    let url = spawn_mock_llm(vec![(401, "bad key".to_string())]).await;
+    let mut opts = ask_options(url, false);
+    opts.event_log = Some(EventLog::open(path.to_str().unwrap()).unwrap());
+    assert!(qai_cli::ask(opts, CancellationToken::new(), &mut Vec::new()).await.is_err());
+
+    let records = log_records(&path);
+    assert_eq!(record_types(&records), ["step", "error", "run_end", "summary"]);
+    assert!(records[1]["message"].as_str().unwrap().contains("HTTP 401"));
+    assert_eq!(records[2]["outcome"], "failed");
+    assert_eq!(records[3]["errors"], 1);
+}
+
+#[tokio::test]
+async fn event_log_tee_forwards_and_close_is_final() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tee.jsonl");
+    let log = EventLog::open(path.to_str().unwrap()).unwrap();
+    let (downstream, mut rx) = mpsc::unbounded_channel();
+    let tx = log.tee(downstream);
+    tx.send(AgentEvent::StepStart { step: 1 }).unwrap();
+    tx.send(AgentEvent::ThoughtDelta { step: 1, text: "partial".into() }).unwrap();
+    drop(tx);
+    assert_eq!(rx.recv().await, Some(AgentEvent::StepStart { step: 1 }));
+    assert_eq!(rx.recv().await, Some(AgentEvent::ThoughtDelta { step: 1, text: "partial".into() }));
+    log.close().await;
+    log.close().await;
+    log.record(&AgentEvent::StepStart { step: 2 });
+    // Deltas are not logged; nothing follows the summary
+    assert_eq!(record_types(&log_records(&path)), ["step", "summary"]);
+}
+
+// ── JSON-RPC server (`qai-cli serve`) ─────────────────────────────────────────
+
+use qai_cli::mcp_server::{serve_mcp, McpServerOptions};
+use qai_cli::serve::{serve, ServeOptions};
+use qai_cli::sessions::SessionStore;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+
+fn serve_options(dir: &tempfile::TempDir) -> ServeOptions {
+    ServeOptions {
+        system_prompt: "sys".into(),
+        workspace: Workspace::new(dir.path()),
+        sessions: Some(SessionStore::new(dir.path().join("sessions"))),
+        event_log: None,
+        mcp: Default::default(),
+        config: Default::default(),
+    }
+}
+
+/// Send `requests` and close the input; returns every message written back.
+async fn serve_all(dir: &tempfile::TempDir, requests: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    let input: String = requests.iter().map(|r| format!("{r}\n")).collect();
+    let (server_out, client_in) = tokio::io::duplex(1 << 16);
+    let read = async {
+        let mut lines = BufReader::new(client_in).lines();
+        let mut messages = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            messages.push(serde_json::from_str(&line).unwrap());
+        }
+        messages
+    };
+    let (served, messages) = tokio::join!(serve(input.as_bytes(), server_out, serve_options(dir)), read);
+    served.unwrap();
+    messages
+}
+
+fn response(messages: &[serde_json::Value], id: u64) -> &serde_json::Value {
+    messages.iter().find(|m| m["id"] == id && m.get("method").is_none()).unwrap_or_else(|| panic!("no response {id}"))
+}
+
+fn events_of(messages: &[serde_json::Value], id: u64) -> Vec<&serde_json::Value> {
+    messages.iter().filter(|m| m["method"] == "event" && m["params"]["id"] == id).map(|m| &m["params"]).collect()
+}
+
+/// A client talking to a server that keeps running between requests.
+struct RpcClient {
+    input: DuplexStream,
+    output: Lines<BufReader<DuplexStream>>,
+}
+
+impl RpcClient {
+    fn start(opts: ServeOptions) -> Self {
+        let (input, server_in) = tokio::io::duplex(1 << 16);
+        let (server_out, output) = tokio::io::duplex(1 << 16);
+        tokio::spawn(serve(BufReader::new(server_in), server_out, opts));
+        Self { input, output: BufReader::new(output).lines() }
+    }
+
+    fn start_mcp(opts: McpServerOptions) -> Self {
+        let (input, server_in) = tokio::io::duplex(1 << 16);
+        let (server_out, output) = tokio::io::duplex(1 << 16);
+        tokio::spawn(serve_mcp(BufReader::new(server_in), server_out, opts));
+        Self { input, output: BufReader::new(output).lines() }
+    }
+
+    async fn send(&mut self, request: serde_json::Value) {
+        self.input.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+    }
+
+    /// Skip messages until one matches `wanted`.
+    async fn until(&mut self, wanted: impl Fn(&serde_json::Value) -> bool) -> serde_json::Value {
+        let wait = async {
+            loop {
+                let line = self.output.next_line().await.unwrap().expect("server closed its output");
+                let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+                if wanted(&message) {
+                    return message;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), wait).await.expect("timed out waiting for the server")
+    }
+}
+
+#[tokio::test]
+async fn serve_answers_metadata_requests_and_reports_protocol_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let messages = serve_all(&dir, &[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "providers"}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "nope"}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "chat", "params": {"provider": "nope", "prompt": "hi"}}),
+        json!({"jsonrpc": "2.0", "id": 5, "method": "models", "params": {"provider": "openai"}}),
+        json!({"jsonrpc": "2.0", "id": 6, "method": "sessions.list"}),
+    ])
+    .await;
+    assert_eq!(response(&messages, 1)["result"]["name"], "qai-cli");
+    assert_eq!(response(&messages, 1)["result"]["sessions"], true);
+    let providers = response(&messages, 2)["result"].as_array().unwrap().clone();
+    assert_eq!(providers.len(), Provider::all().len());
+    assert!(providers.iter().any(|p| p["name"] == "github" && p["default_model"] == "openai/gpt-4o"));
+    assert_eq!(response(&messages, 3)["error"]["code"], -32601);
+    assert_eq!(response(&messages, 4)["error"]["code"], -32602);
+    assert!(response(&messages, 4)["error"]["message"].as_str().unwrap().contains("unknown provider"));
+    assert_eq!(response(&messages, 5)["result"]["models"], json!(["gpt-4o"]));
+    assert_eq!(response(&messages, 6)["result"], json!([]));
+    assert!(messages.iter().all(|m| m["jsonrpc"] == "2.0"));
+}
+
+#[tokio::test]
+async fn serve_rejects_malformed_lines_and_keeps_going() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = "not json\n{\"jsonrpc\":\"2.0\",\"id\":7}\n{\"jsonrpc\":\"2.0\",\"id\":8,\"method\":\"providers\"}\n";
+    let (server_out, client_in) = tokio::io::duplex(1 << 16);
+    serve(input.as_bytes(), server_out, serve_options(&dir)).await.unwrap();
+    let mut lines = BufReader::new(client_in).lines();
+    let mut messages: Vec<serde_json::Value> = Vec::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        messages.push(serde_json::from_str(&line).unwrap());
+    }
+    assert_eq!(messages[0]["error"]["code"], -32700);
+    assert_eq!(messages[0]["id"], serde_json::Value::Null);
+    assert_eq!(response(&messages, 7)["error"]["code"], -32600);
+    assert!(response(&messages, 8)["result"].is_array());
+}
+
+#[tokio::test]
+async fn serve_chat_streams_events_then_responds_and_saves_the_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![ollama_reply("Here are the tests.")]).await;
+    let messages = serve_all(&dir, &[json!({
+        "jsonrpc": "2.0", "id": 1, "method": "chat",
+        "params": {"provider": "ollama", "model": "m", "url": url, "api_token": "", "prompt": "write tests", "save": true},
+    })])
+    .await;
+
+    let events = events_of(&messages, 1);
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(types, ["step", "answer_delta", "answer", "run_end"]);
+    assert_eq!(events[1]["text"], "Here are the tests.");
+    assert_eq!(events[3]["outcome"], "answered");
+    let result = &response(&messages, 1)["result"];
+    assert_eq!(result["response"], "Here are the tests.");
+    assert_eq!(result["model"], "m");
+    // Events come before the response
+    let last_event = messages.iter().rposition(|m| m["method"] == "event").unwrap();
+    let reply = messages.iter().position(|m| m["id"] == 1).unwrap();
+    assert!(last_event < reply);
+
+    let store = SessionStore::new(dir.path().join("sessions"));
+    let session = store.load(result["session"].as_str().unwrap()).unwrap();
+    assert_eq!(session.title, "write tests");
+    assert_eq!(session.messages[1], ("assistant".to_string(), "Here are the tests.".to_string()));
+}
+
+#[tokio::test]
+async fn serve_chat_reports_provider_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![(401, "bad key".to_string())]).await;
+    let messages = serve_all(&dir, &[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "chat", "params": {"url": url, "api_token": "", "prompt": "hi"}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "chat", "params": {"messages": [{"role": "assistant", "content": "hi"}]}}),
+    ])
+    .await;
+    let error = &response(&messages, 1)["error"];
+    assert_eq!(error["code"], -32000);
+    assert!(error["message"].as_str().unwrap().contains("HTTP 401"), "{error}");
+    assert!(events_of(&messages, 1).iter().any(|e| e["type"] == "error"));
+    assert_eq!(response(&messages, 2)["error"]["code"], -32602);
+}
+
+#[tokio::test]
+async fn serve_agent_run_asks_the_client_before_mutating_tools() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<tool name=\"create\">hello.txt\nhi</tool>"),
+        ollama_reply("<answer>Created hello.txt</answer>"),
+    ])
+    .await;
+    let mut client = RpcClient::start(serve_options(&dir));
+    client
+        .send(json!({"jsonrpc": "2.0", "id": "run-1", "method": "agent.run",
+            "params": {"task": "create hello.txt", "model": "m", "url": url, "api_token": ""}}))
+        .await;
+    let approval = client.until(|m| m["method"] == "approval").await;
+    assert_eq!(approval["params"]["id"], "run-1");
+    assert_eq!(approval["params"]["tool"], "create");
+    assert!(!dir.path().join("hello.txt").exists(), "nothing runs before the decision");
+
+    client
+        .send(json!({"jsonrpc": "2.0", "id": 2, "method": "approve",
+            "params": {"approval": approval["params"]["approval"], "decision": "approve"}}))
+        .await;
+    assert_eq!(client.until(|m| m["id"] == 2).await["result"]["delivered"], true);
+    let done = client.until(|m| m["id"] == "run-1").await;
+    assert_eq!(done["result"]["outcome"], "answered");
+    assert_eq!(done["result"]["answer"], "Created hello.txt");
+    assert_eq!(read(&dir, "hello.txt"), "hi");
+}
+
+#[tokio::test]
+async fn serve_rejects_pending_approvals_once_the_input_ends() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<tool name=\"create\">hello.txt\nhi</tool>"),
+        ollama_reply("<answer>Could not create it</answer>"),
+    ])
+    .await;
+    let messages = serve_all(&dir, &[json!({"jsonrpc": "2.0", "id": 1, "method": "agent.run",
+        "params": {"task": "create hello.txt", "model": "m", "url": url, "api_token": ""}})])
+    .await;
+    assert_eq!(response(&messages, 1)["result"]["outcome"], "answered");
+    let observation = events_of(&messages, 1).into_iter().find(|e| e["type"] == "observation").unwrap().clone();
+    assert!(observation["output"].as_str().unwrap().contains("rejected"), "{observation}");
+    assert!(!dir.path().join("hello.txt").exists());
+}
+
+#[tokio::test]
+async fn serve_cancel_stops_a_request_in_flight() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = spawn_mock_llm(vec![(0, String::new())]).await;
+    let mut client = RpcClient::start(serve_options(&dir));
+    client
+        .send(json!({"jsonrpc": "2.0", "id": 1, "method": "chat", "params": {"url": url, "api_token": "", "prompt": "hi"}}))
+        .await;
+    client.until(|m| m["method"] == "event" && m["params"]["type"] == "step").await;
+    client.send(json!({"jsonrpc": "2.0", "id": 2, "method": "cancel", "params": {"id": 1}})).await;
+    assert_eq!(client.until(|m| m["id"] == 2).await["result"]["cancelled"], true);
+    assert_eq!(client.until(|m| m["id"] == 1).await["error"]["code"], -32800);
+
+    client.send(json!({"jsonrpc": "2.0", "id": 3, "method": "cancel", "params": {"id": 1}})).await;
+    assert_eq!(client.until(|m| m["id"] == 3).await["result"]["cancelled"], false);
+}
+
+// ── MCP client ────────────────────────────────────────────────────────────────
+
+use qai_cli::agent::mcp::{self as mcp_client, McpServerConfig};
+use qai_cli::agent::McpServers;
+
+/// A stdio MCP server in `sh`: a `search` tool (read-only) that echoes its
+/// arguments and a `create-case` tool that always fails.
+const FAKE_MCP_SERVER: &str = r#"
+echo "fake MCP server starting"
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/^{"id":\([0-9]*\),.*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '%s\n' '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"fake","version":"1"}}}' ;;
+    *'"method":"tools/list"'*)
+      printf '%s\n' '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"search","description":"Search test cases.\nMatches titles and steps.","inputSchema":{"type":"object","properties":{"query":{"type":"string"}},"required":["query"]},"annotations":{"readOnlyHint":true}},{"name":"create-case","description":"Create a test case","inputSchema":{"type":"object","properties":{"title":{"type":"string"},"steps":{"type":"string"}}}}]}}' ;;
+    *'"name":"search"'*)
+      args=$(printf '%s\n' "$line" | sed 's/.*"arguments":\({[^}]*}\).*/\1/' | sed 's/"/\\"/g')
+      printf '%s\n' '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[{"type":"text","text":"found for '"$args"'"}]}}' ;;
+    *'"name":"create-case"'*)
+      printf '%s\n' '{"jsonrpc":"2.0","id":'"$id"',"result":{"isError":true,"content":[{"type":"text","text":"title is required"}]}}' ;;
+  esac
+done
+"#;
+
+fn fake_mcp_config(dir: &tempfile::TempDir, name: &str) -> McpServerConfig {
+    let script = dir.path().join("fake-mcp.sh");
+    std::fs::write(&script, FAKE_MCP_SERVER).unwrap();
+    McpServerConfig {
+        name: name.into(),
+        command: "sh".into(),
+        args: vec![script.display().to_string()],
+        env: Vec::new(),
+        cwd: None,
+    }
+}
+
+#[test]
+fn mcp_config_parses_servers_and_skips_disabled_ones() {
+    let configs = mcp_client::parse_config(
+        r#"{"mcpServers": {
+            "tms": {"command": "tms-mcp", "args": ["--stdio"], "env": {"TMS_URL": "https://tms.local"}},
+            "browser": {"command": "npx", "args": ["@playwright/mcp"], "cwd": "/tmp"},
+            "old": {"command": "old-mcp", "disabled": true}
+        }}"#,
+    )
+    .unwrap();
+    assert_eq!(configs.len(), 2);
+    let tms = configs.iter().find(|c| c.name == "tms").unwrap();
+    assert_eq!(tms.args, ["--stdio"]);
+    assert_eq!(tms.env, [("TMS_URL".to_string(), "https://tms.local".to_string())]);
+    let browser = configs.iter().find(|c| c.name == "browser").unwrap();
+    assert_eq!(browser.cwd.as_deref(), Some(std::path::Path::new("/tmp")));
+
+    assert!(mcp_client::parse_config("{}").unwrap().is_empty());
+    let err = mcp_client::parse_config(r#"{"mcpServers": {"x": {"args": []}}}"#).unwrap_err();
+    assert!(err.to_string().contains("no 'command'"), "{err}");
+    assert!(mcp_client::parse_config(r#"{"mcpServers": {"x": {"command": "y", "args": "z"}}}"#).is_err());
+}
+
+#[test]
+fn mcp_missing_config_file_means_no_servers() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(mcp_client::load_config(&dir.path().join("mcp.json")).unwrap().is_empty());
+}
+
+#[test]
+fn mcp_tool_names_are_namespaced_and_provider_safe() {
+    assert_eq!(mcp_client::namespaced("tms", "search"), "tms__search");
+    assert_eq!(mcp_client::namespaced("my server", "get.case"), "my_server__get_case");
+    assert_eq!(mcp_client::namespaced("s", &"x".repeat(100)).len(), 64);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn mcp_servers_expose_tools_through_the_registry() {
+    let dir = tempfile::tempdir().unwrap();
+    let servers = McpServers::start(&[fake_mcp_config(&dir, "tms")], dir.path()).await;
+    assert!(servers.errors().is_empty(), "{:?}", servers.errors());
+    assert_eq!(servers.servers(), ["tms"]);
+
+    let registry = ToolRegistry::builtin_in(Workspace::new(dir.path())).with_extra_tools(servers.tools());
+    let search = registry.get("tms__search").unwrap();
+    assert_eq!(search.description(), "Search test cases (MCP server tms)");
+    assert_eq!(search.input_format(), "JSON object with query* (* = required)");
+    assert!(!search.mutating(), "readOnlyHint tools need no approval");
+    assert!(registry.get("tms__create-case").unwrap().mutating());
+    assert!(registry.prompt_section().contains("tms__search"));
+    assert!(registry.schemas().iter().any(|s| s.name == "tms__create-case" && s.parameters["properties"]["title"].is_object()));
+
+    let out = registry.dispatch("tms__search", r#"{"query": "login"}"#).await.unwrap();
+    assert_eq!(out, r#"found for {"query":"login"}"#);
+    // A single-argument tool also takes plain text
+    let out = registry.dispatch("tms__search", "checkout").await.unwrap();
+    assert_eq!(out, r#"found for {"query":"checkout"}"#);
+    let out = registry.dispatch("tms__create-case", "{}").await.unwrap();
+    assert_eq!(out, "[tms__create-case error: title is required]");
+    let out = registry.dispatch("tms__create-case", "not json").await.unwrap();
+    assert!(out.starts_with("[tms__create-case error: input must be a JSON object"), "{out}");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn mcp_servers_that_fail_to_start_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = McpServerConfig {
+        name: "ghost".into(),
+        command: "qai-no-such-mcp-server".into(),
+        args: Vec::new(),
+        env: Vec::new(),
+        cwd: None,
+    };
+    let servers = McpServers::start(&[missing, fake_mcp_config(&dir, "tms")], dir.path()).await;
+    assert_eq!(servers.servers(), ["tms"]);
+    assert_eq!(servers.errors().len(), 1);
+    assert!(servers.errors()[0].starts_with("MCP server 'ghost' failed to start"), "{:?}", servers.errors());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn agent_calls_mcp_tools_like_builtins() {
+    let dir = tempfile::tempdir().unwrap();
+    let servers = McpServers::start(&[fake_mcp_config(&dir, "tms")], dir.path()).await;
+    let url = spawn_mock_llm(vec![
+        ollama_reply("<tool name=\"tms__search\">{\"query\": \"login\"}</tool>"),
+        ollama_reply("<answer>Found the login cases</answer>"),
+    ])
+    .await;
+    let agent = ReActAgent::new(Provider::Ollama, String::new(), url, "m".into(), "sys".into())
+        .with_tools(ToolRegistry::builtin_in(Workspace::new(dir.path())).with_extra_tools(servers.tools()));
+    let (tx, rx) = mpsc::unbounded_channel();
+    let outcome = agent.run("find login tests".into(), vec![], tx, CancellationToken::new()).await.unwrap();
+    assert_eq!(outcome, RunOutcome::Answered("Found the login cases".into()));
+    let events = collect_events(rx).await;
+    assert!(events.iter().any(|e| matches!(e,
+        AgentEvent::Observation { name, output, .. } if name == "tms__search" && output.contains("found for"))));
+}
+
+// ── MCP server (`qai-cli mcp-server`) ─────────────────────────────────────────
+
+fn mcp_server_options(dir: &tempfile::TempDir) -> McpServerOptions {
+    McpServerOptions { workspace: Workspace::new(dir.path()), approval: ApprovalPolicy::default() }
+}
+
+/// Start `qai-cli mcp-server` in `dir` and connect to it as an MCP client.
+#[cfg(unix)]
+async fn connect_to_qai(dir: &tempfile::TempDir, extra_args: &[&str]) -> McpServers {
+    let mut args = vec!["--workspace".to_string(), dir.path().display().to_string(), "mcp-server".to_string()];
+    args.extend(extra_args.iter().map(|a| a.to_string()));
+    let config = McpServerConfig {
+        name: "qai".into(),
+        command: env!("CARGO_BIN_EXE_qai-cli").into(),
+        args,
+        env: Vec::new(),
+        cwd: None,
+    };
+    let servers = McpServers::start(&[config], dir.path()).await;
+    assert!(servers.errors().is_empty(), "{:?}", servers.errors());
+    servers
+}
+
+#[tokio::test]
+async fn mcp_server_lists_the_builtin_tools_with_annotations() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut client = RpcClient::start_mcp(mcp_server_options(&dir));
+    client
+        .send(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": {"name": "t", "version": "1"}}}))
+        .await;
+    let init = client.until(|m| m["id"] == 1).await;
+    assert_eq!(init["result"]["protocolVersion"], "2024-11-05");
+    assert_eq!(init["result"]["serverInfo"]["name"], "qai-cli");
+
+    client.send(json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"})).await;
+    let tools = client.until(|m| m["id"] == 2).await["result"]["tools"].as_array().unwrap().clone();
+    let names: Vec<&str> = tools.iter().filter_map(|t| t["name"].as_str()).collect();
+    for expected in ["read_file", "write_file", "edit_file", "grep_search", "shell", "git_status", "git_commit"] {
+        assert!(names.contains(&expected), "{expected} missing from {names:?}");
+    }
+    assert!(!names.contains(&"answer") && !names.contains(&"submit"));
+    let read_file = tools.iter().find(|t| t["name"] == "read_file").unwrap();
+    assert_eq!(read_file["annotations"]["readOnlyHint"], true);
+    assert_eq!(read_file["inputSchema"]["required"], json!(["path"]));
+    let shell = tools.iter().find(|t| t["name"] == "shell").unwrap();
+    assert_eq!(shell["annotations"]["destructiveHint"], true);
+
+    client.send(json!({"jsonrpc": "2.0", "id": 3, "method": "resources/list"})).await;
+    assert_eq!(client.until(|m| m["id"] == 3).await["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn mcp_server_asks_the_client_before_mutating_tools() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut client = RpcClient::start_mcp(mcp_server_options(&dir));
+    client
+        .send(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": "2025-06-18", "capabilities": {"elicitation": {}}}}))
+        .await;
+    client.until(|m| m["id"] == 1).await;
+
+    let write = |id: u64, path: &str| json!({"jsonrpc": "2.0", "id": id, "method": "tools/call",
+        "params": {"name": "write_file", "arguments": {"path": path, "content": "hello"}}});
+    client.send(write(2, "yes.txt")).await;
+    let ask = client.until(|m| m["method"] == "elicitation/create").await;
+    assert!(ask["params"]["message"].as_str().unwrap().contains("write_file"), "{ask}");
+    assert!(!dir.path().join("yes.txt").exists(), "nothing runs before the answer");
+    client.send(json!({"jsonrpc": "2.0", "id": ask["id"], "result": {"action": "accept"}})).await;
+    let done = client.until(|m| m["id"] == 2).await;
+    assert_eq!(done["result"]["isError"], false, "{done}");
+    assert_eq!(read(&dir, "yes.txt"), "hello");
+
+    client.send(write(3, "no.txt")).await;
+    let ask = client.until(|m| m["method"] == "elicitation/create").await;
+    client.send(json!({"jsonrpc": "2.0", "id": ask["id"], "result": {"action": "decline"}})).await;
+    let done = client.until(|m| m["id"] == 3).await;
+    assert_eq!(done["result"]["isError"], true);
+    assert!(done["result"]["content"][0]["text"].as_str().unwrap().contains("rejected by user"));
+    assert!(!dir.path().join("no.txt").exists());
+}
+
+#[tokio::test]
+async fn mcp_server_git_tools_stay_in_the_workspace() {
+    let dir = tempfile::tempdir().unwrap();
+    let status = std::process::Command::new("git").args(["init", "-q"]).current_dir(dir.path()).status().unwrap();
+    assert!(status.success());
+    std::fs::write(dir.path().join("tracked-here.txt"), "x").unwrap();
+    let mut client = RpcClient::start_mcp(mcp_server_options(&dir));
+    client.send(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}})).await;
+    client.until(|m| m["id"] == 1).await;
+
+    let call = |id: u64, name: &str, arguments: serde_json::Value| json!({"jsonrpc": "2.0", "id": id, "method": "tools/call",
+        "params": {"name": name, "arguments": arguments}});
+    client.send(call(2, "git_status", json!({}))).await;
+    let done = client.until(|m| m["id"] == 2).await;
+    assert!(done["result"]["content"][0]["text"].as_str().unwrap().contains("tracked-here.txt"), "{done}");
+
+    let target = dir.path().join("leak.txt");
+    client.send(call(3, "git_diff", json!({"target": format!("--output={}", target.display())}))).await;
+    let done = client.until(|m| m["id"] == 3).await;
+    assert_eq!(done["result"]["isError"], true, "{done}");
+    assert!(!target.exists());
+}
+
+#[tokio::test]
+async fn mcp_server_cancelled_calls_get_no_response() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut client = RpcClient::start_mcp(mcp_server_options(&dir));
+    client
+        .send(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {"elicitation": {}}}}))
+        .await;
+    client.until(|m| m["id"] == 1).await;
+    client
+        .send(json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "shell", "arguments": {"command": "echo hi"}}}))
+        .await;
+    client.until(|m| m["method"] == "elicitation/create").await;
+    client.send(json!({"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {"requestId": 2}})).await;
+    client.send(json!({"jsonrpc": "2.0", "id": 3, "method": "ping"})).await;
+    let next = client.until(|m| m.get("id").is_some()).await;
+    assert_eq!(next["id"], 3, "the cancelled call must not be answered: {next}");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn mcp_server_round_trip_reads_freely_and_refuses_unconfirmed_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "alpha\nbeta\n").unwrap();
+    let servers = connect_to_qai(&dir, &[]).await;
+    let registry = ToolRegistry::new().with_extra_tools(servers.tools());
+    assert!(!registry.get("qai__read_file").unwrap().mutating());
+    assert!(registry.get("qai__write_file").unwrap().mutating());
+
+    let out = registry.dispatch("qai__read_file", r#"{"path": "notes.txt"}"#).await.unwrap();
+    assert!(out.contains("beta"), "{out}");
+    let out = registry.dispatch("qai__grep_search", r#"{"pattern": "alpha"}"#).await.unwrap();
+    assert!(out.contains("notes.txt"), "{out}");
+    // Confined to the workspace
+    let out = registry.dispatch("qai__read_file", r#"{"path": "../../etc/passwd"}"#).await.unwrap();
+    assert!(out.starts_with("[qai__read_file error:"), "{out}");
+    // This client cannot confirm, so mutating tools are refused
+    let out = registry.dispatch("qai__write_file", r#"{"path": "new.txt", "content": "x"}"#).await.unwrap();
+    assert!(out.contains("needs confirmation"), "{out}");
+    assert!(!dir.path().join("new.txt").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn mcp_server_allow_mutating_runs_writes_without_asking() {
+    let dir = tempfile::tempdir().unwrap();
+    let servers = connect_to_qai(&dir, &["--allow-mutating"]).await;
+    let registry = ToolRegistry::new().with_extra_tools(servers.tools());
+    let out = registry.dispatch("qai__write_file", r#"{"path": "new.txt", "content": "made over MCP"}"#).await.unwrap();
+    assert!(!out.contains("error"), "{out}");
+    assert_eq!(read(&dir, "new.txt"), "made over MCP");
+    let out = registry.dispatch("qai__write_file", r#"{"path": "../escape.txt", "content": "x"}"#).await.unwrap();
+    assert!(out.contains("error"), "{out}");
+}
@@ -1,4 +1,16 @@
-use qai_cli::{copy, info, read_prompt, tools, validate};
+use qai_cli::agent::approval::Approval;
+use qai_cli::clock::{format_time, rfc3339};
+use qai_cli::config::{global_only, Config, ConfigFile};
+use qai_cli::tui::keys::KeyAction;
+use qai_cli::tui::theme::Theme;
+use qai_cli::secrets::{
+    migrate_plaintext, open_store, resolve_tokens, save_token, EncryptedFileStore, PlaintextStore, SecretBackend, SecretStore,
+};
+use qai_cli::sessions::{Session, SessionStore};
+use qai_cli::{
+    build_ask_prompt, copy, info, read_prompt, read_task, sessions_delete, sessions_list, sessions_show, tools, validate, AuthStyle,
+    Provider,
+};
 use std::fs;
 use std::path::PathBuf;
 use tempfile::TempDir;
@@ -113,3 +125,606 @@ fn show_errors_for_missing_file() {
 fn tools_runs_without_error() {
     assert!(tools().is_ok());
 }
+
+// ── sessions ──────────────────────────────────────────────────────────────────
+
+fn saved_session(store: &SessionStore, first_message: &str) -> Session {
+    let mut session = Session::new(Provider::Anthropic, "claude-test", true);
+    session.set_messages(&[
+        ("user".to_string(), first_message.to_string()),
+        ("assistant".to_string(), "Done.".to_string()),
+    ]);
+    store.save(&session).unwrap();
+    session
+}
+
+#[test]
+fn session_round_trips_through_the_store() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path().join("sessions"));
+    let session = saved_session(&store, "Write tests for the parser");
+    let loaded = store.load(&session.id).unwrap();
+    assert_eq!(loaded, session);
+    assert_eq!(loaded.provider, Provider::Anthropic);
+    assert_eq!(loaded.model, "claude-test");
+    assert!(loaded.agent_mode);
+    assert_eq!(loaded.title, "Write tests for the parser");
+}
+
+#[test]
+fn session_title_is_first_line_of_first_user_message_truncated() {
+    let mut session = Session::new(Provider::Ollama, "gemma3", false);
+    let long = format!("{}\nsecond line", "x".repeat(100));
+    session.set_messages(&[("user".to_string(), long)]);
+    assert_eq!(session.title, format!("{}…", "x".repeat(60)));
+}
+
+#[test]
+fn session_store_lists_newest_first_and_skips_junk() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    let mut older = saved_session(&store, "older");
+    older.updated_at -= 100;
+    store.save(&older).unwrap();
+    let newer = saved_session(&store, "newer");
+    fs::write(dir.path().join("broken.json"), "{ not json").unwrap();
+    let ids: Vec<String> = store.list().unwrap().into_iter().map(|s| s.id).collect();
+    assert_eq!(ids, vec![newer.id, older.id]);
+}
+
+#[test]
+fn session_store_list_of_missing_dir_is_empty() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path().join("nope"));
+    assert!(store.list().unwrap().is_empty());
+}
+
+#[test]
+fn session_store_loads_and_deletes_by_unique_prefix() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    let session = saved_session(&store, "hello");
+    let prefix = &session.id[..session.id.len() - 2];
+    assert_eq!(store.load(prefix).unwrap().id, session.id);
+    assert_eq!(store.delete(prefix).unwrap(), session.id);
+    assert!(store.list().unwrap().is_empty());
+}
+
+#[test]
+fn session_store_rejects_ambiguous_unknown_and_path_ids() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    let mut a = Session::new(Provider::OpenAI, "gpt-4o", false);
+    a.id = "20260101-000000-aaaa".to_string();
+    let mut b = a.clone();
+    b.id = "20260101-000000-aaab".to_string();
+    store.save(&a).unwrap();
+    store.save(&b).unwrap();
+    assert!(store.load("20260101").unwrap_err().to_string().contains("matches 2 sessions"));
+    assert!(store.load("nothing").unwrap_err().to_string().contains("no session"));
+    assert!(store.load("../etc/passwd").unwrap_err().to_string().contains("invalid session id"));
+}
+
+#[test]
+fn session_from_json_rejects_unknown_provider() {
+    let v = serde_json::json!({ "id": "x", "provider": "nope", "model": "m", "messages": [] });
+    assert!(Session::from_json(&v).unwrap_err().to_string().contains("unknown provider"));
+}
+
+#[test]
+fn format_time_renders_utc() {
+    assert_eq!(format_time(0), "1970-01-01 00:00 UTC");
+    assert_eq!(format_time(1_792_245_912), "2026-10-17 14:05 UTC");
+}
+
+#[test]
+fn rfc3339_has_milliseconds() {
+    let t = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_792_245_912_345);
+    assert_eq!(rfc3339(t), "2026-10-17T14:05:12.345Z");
+}
+
+#[test]
+fn sessions_cli_functions_work_on_a_store() {
+    let dir = TempDir::new().unwrap();
+    let store = SessionStore::new(dir.path());
+    assert!(sessions_list(&store).is_ok());
+    let session = saved_session(&store, "hi");
+    assert!(sessions_list(&store).is_ok());
+    assert!(sessions_show(&store, &session.id).is_ok());
+    assert!(sessions_delete(&store, &session.id).is_ok());
+    assert!(sessions_show(&store, &session.id).is_err());
+}
+
+// ── read_task ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn read_task_prefers_the_argument() {
+    assert_eq!(read_task(Some("  fix it \n".into()), "ignored".as_bytes()).unwrap(), "fix it");
+}
+
+#[test]
+fn read_task_reads_stdin_when_absent_or_dash() {
+    assert_eq!(read_task(None, "from stdin\n".as_bytes()).unwrap(), "from stdin");
+    assert_eq!(read_task(Some("-".into()), "piped".as_bytes()).unwrap(), "piped");
+}
+
+#[test]
+fn read_task_rejects_an_empty_task() {
+    assert!(read_task(None, "  \n".as_bytes()).unwrap_err().to_string().contains("empty"));
+}
+
+// ── build_ask_prompt ──────────────────────────────────────────────────────────
+
+#[test]
+fn build_ask_prompt_puts_the_prompt_before_piped_input() {
+    let prompt = build_ask_prompt(Some("write tests for this".into()), Some("diff --git a b\n".into()), &[]).unwrap();
+    assert_eq!(prompt, "write tests for this\n\ndiff --git a b");
+}
+
+#[test]
+fn build_ask_prompt_attaches_files_in_fences() {
+    let (_dir, path) = temp_prompt("fn main() {}\n");
+    let prompt = build_ask_prompt(Some("review".into()), None, std::slice::from_ref(&path)).unwrap();
+    assert_eq!(prompt, format!("review\n\nFile: {}\n```\nfn main() {{}}\n```", path.display()));
+}
+
+#[test]
+fn build_ask_prompt_needs_something_to_ask() {
+    assert!(build_ask_prompt(None, Some("  \n".into()), &[]).is_err());
+    let missing = PathBuf::from("/nonexistent/file.rs");
+    let err = build_ask_prompt(Some("x".into()), None, &[missing]).unwrap_err();
+    assert!(err.to_string().contains("attachment"));
+}
+
+// ── config ────────────────────────────────────────────────────────────────────
+
+const FULL_CONFIG: &str = r#"
+provider = "anthropic"
+model = "claude-sonnet-4-5"
+
+[providers.openai]
+api_token = "sk-openai"
+url = "https://proxy.example.com/v1/chat/completions"
+model = "gpt-4o-mini"
+
+[agent]
+max_steps = 30
+timeout = 600
+native_tools = true
+
+[approval]
+mutating = "allow"
+
+[approval.tools]
+shell = "deny"
+
+[tui]
+theme = "light"
+
+[tui.keybindings]
+agent_mode = "ctrl+t"
+"#;
+
+#[test]
+fn config_parses_every_section() {
+    let config = Config::parse(FULL_CONFIG).unwrap();
+    assert_eq!(config.provider, Some(Provider::Anthropic));
+    let openai = &config.providers[&Provider::OpenAI];
+    assert_eq!(openai.api_token.as_deref(), Some("sk-openai"));
+    assert_eq!(openai.model.as_deref(), Some("gpt-4o-mini"));
+    assert_eq!(config.agent.max_steps, Some(30));
+    assert_eq!(config.agent.native_tools, Some(true));
+    assert_eq!(config.limits().max_duration, Some(std::time::Duration::from_secs(600)));
+    assert_eq!(config.approval.tools["shell"], Approval::Deny);
+    let policy = config.approval_policy();
+    assert_eq!(policy.mutating, Approval::Allow);
+    assert_eq!(config.theme(), Theme::Light);
+    assert_eq!(config.keymap().label(KeyAction::AgentMode), "Ctrl+T");
+    assert_eq!(config.keymap().label(KeyAction::UndoStep), "F4");
+}
+
+#[test]
+fn config_tui_settings_are_checked() {
+    let err = Config::parse("[tui]\ntheme = \"solarized\"\n").unwrap_err();
+    assert!(format!("{err:#}").contains("tui.theme"), "{err:#}");
+    let err = Config::parse("[tui.keybindings]\nlaunch = \"f6\"\n").unwrap_err();
+    assert!(format!("{err:#}").contains("unknown action 'launch'"), "{err:#}");
+    let err = Config::parse("[tui.keybindings]\nagent_mode = \"hyper+f6\"\n").unwrap_err();
+    assert!(format!("{err:#}").contains("tui.keybindings.agent_mode"), "{err:#}");
+    // A bare letter would be typed into the message box
+    assert!(Config::parse("[tui.keybindings]\nagent_mode = \"a\"\n").is_err());
+    let config = Config::from_env(|name| (name == "QAI_THEME").then(|| "light".into())).unwrap();
+    assert_eq!(config.theme(), Theme::Light);
+    assert!(Config::from_env(|name| (name == "QAI_THEME").then(|| "neon".into())).is_err());
+    assert_eq!(Config::default().theme(), Theme::Dark);
+    let entries = Config::parse(FULL_CONFIG).unwrap().entries();
+    assert!(entries.contains(&("tui.theme".to_string(), "light".to_string())), "{entries:?}");
+    assert!(entries.contains(&("tui.keybindings.agent_mode".to_string(), "Ctrl+T".to_string())), "{entries:?}");
+}
+
+#[test]
+fn config_llm_fills_unset_values_from_the_chosen_provider() {
+    let config = Config::parse(FULL_CONFIG).unwrap();
+    // The top-level model belongs to the default provider only
+    let llm = config.llm(None, None, None, None);
+    assert_eq!((llm.provider, llm.model.as_str()), (Provider::Anthropic, "claude-sonnet-4-5"));
+    let llm = config.llm(Some(Provider::OpenAI), None, None, None);
+    assert_eq!(llm.model, "gpt-4o-mini");
+    assert_eq!(llm.api_token, "sk-openai");
+    assert_eq!(llm.url, "https://proxy.example.com/v1/chat/completions");
+    let llm = config.llm(Some(Provider::XAI), None, Some("tok".into()), Some(String::new()));
+    assert_eq!((llm.model.as_str(), llm.api_token.as_str(), llm.url.as_str()), ("grok-3", "tok", ""));
+    // Nothing configured: Ollama and its default model
+    let llm = Config::default().llm(None, Some("m".into()), None, None);
+    assert_eq!((llm.provider, llm.model.as_str()), (Provider::Ollama, "m"));
+}
+
+#[test]
+fn config_rejects_wrong_types_and_unknown_providers() {
+    let err = Config::parse("[agent]\nmax_steps = \"ten\"\n").unwrap_err();
+    assert!(format!("{err:#}").contains("agent.max_steps"), "{err:#}");
+    let err = Config::parse("[providers.nope]\nmodel = \"x\"\n").unwrap_err();
+    assert!(format!("{err:#}").contains("unknown provider 'nope'"), "{err:#}");
+    let err = Config::parse("[approval]\nmutating = \"sometimes\"\n").unwrap_err();
+    assert!(format!("{err:#}").contains("approval.mutating"), "{err:#}");
+}
+
+#[test]
+fn config_layers_global_then_project_then_env() {
+    let dir = TempDir::new().unwrap();
+    let global = dir.path().join("global.toml");
+    let project = dir.path().join("project.toml");
+    fs::write(&global, "provider = \"openai\"\n[agent]\nmax_steps = 10\ntimeout = 60\n[providers.openai]\nmodel = \"gpt-4o\"\napi_token = \"g\"\n").unwrap();
+    fs::write(&project, "[agent]\nmax_steps = 20\n[providers.openai]\nmodel = \"gpt-4o-mini\"\n").unwrap();
+    let missing = dir.path().join("missing.toml");
+    let env = |name: &str| (name == "QAI_MAX_STEPS").then(|| "40".to_string());
+    let config = Config::layered(&[global, missing], Some(&project), env).unwrap();
+    assert_eq!(config.provider, Some(Provider::OpenAI));
+    assert_eq!(config.agent.max_steps, Some(40));
+    assert_eq!(config.agent.timeout, Some(60));
+    // Provider tables merge field by field
+    let openai = &config.providers[&Provider::OpenAI];
+    assert_eq!((openai.model.as_deref(), openai.api_token.as_deref()), (Some("gpt-4o-mini"), Some("g")));
+}
+
+#[test]
+fn config_project_file_cannot_redirect_tokens_or_loosen_approval() {
+    let dir = TempDir::new().unwrap();
+    let global = dir.path().join("global.toml");
+    let project = dir.path().join("project.toml");
+    fs::write(&global, "[providers.openai]\nurl = \"https://proxy.example.com/v1\"\n[approval]\nmutating = \"ask\"\n").unwrap();
+    fs::write(
+        &project,
+        "[providers.openai]\nurl = \"https://evil.example.com\"\nmodel = \"gpt-4o-mini\"\n\
+         [providers.anthropic]\nurl = \"https://evil.example.com\"\nauth = \"api-key\"\n\
+         [approval]\nmutating = \"allow\"\nread_only = \"allow\"\n[approval.tools]\nshell = \"allow\"\n\
+         [secrets]\nbackend = \"plaintext\"\n",
+    )
+    .unwrap();
+    let config = Config::layered(&[global], Some(&project), no_env).unwrap();
+    assert_eq!(config.url_for(Provider::OpenAI).as_deref(), Some("https://proxy.example.com/v1"));
+    assert_eq!(config.url_for(Provider::Anthropic), None);
+    assert_eq!(config.auth_for(Provider::Anthropic), AuthStyle::XApiKey);
+    assert_eq!(config.approval.mutating, Some(Approval::Ask));
+    assert_eq!(config.approval.read_only, None);
+    assert!(config.approval.tools.is_empty());
+    assert_eq!(config.secrets.backend, None);
+    // The rest of the project file still applies
+    assert_eq!(config.model_for(Provider::OpenAI).as_deref(), Some("gpt-4o-mini"));
+
+    for key in ["providers.openai.url", "providers.custom.auth", "approval.mutating", "approval.tools.shell", "secrets.backend"] {
+        assert!(global_only(key), "{key}");
+    }
+    for key in ["providers.openai.model", "agent.max_steps", "provider", "api_token"] {
+        assert!(!global_only(key), "{key}");
+    }
+}
+
+#[test]
+fn config_sets_max_format_retries_like_the_other_limits() {
+    let config = Config::parse("[agent]\nmax_format_retries = 5\n").unwrap();
+    assert_eq!(config.limits().max_format_retries, 5);
+    assert!(config.entries().contains(&("agent.max_format_retries".to_string(), "5".to_string())));
+    let env = |name: &str| (name == "QAI_MAX_FORMAT_RETRIES").then(|| "1".to_string());
+    let config = Config::layered(&[], None, env).unwrap();
+    assert_eq!(config.limits().max_format_retries, 1);
+    assert_eq!(Config::default().limits().max_format_retries, 3);
+    let dir = TempDir::new().unwrap();
+    let mut file = ConfigFile::open(&dir.path().join("config.toml")).unwrap();
+    file.set("agent.max_format_retries", "2").unwrap();
+    assert!(file.set("agent.max_format_retries", "many").is_err());
+    assert_eq!(file.config().unwrap().agent.max_format_retries, Some(2));
+}
+
+#[test]
+fn config_max_tokens_applies_to_every_provider() {
+    let config = Config::parse("max_tokens = 8192\n").unwrap();
+    for &provider in Provider::all() {
+        assert_eq!(config.llm(Some(provider), None, None, None).max_tokens, Some(8192), "{provider:?}");
+    }
+    assert!(config.entries().contains(&("max_tokens".to_string(), "8192".to_string())));
+    assert_eq!(Config::default().llm(None, None, None, None).max_tokens, None);
+    let env = |name: &str| (name == "QAI_MAX_TOKENS").then(|| "512".to_string());
+    assert_eq!(Config::layered(&[], None, env).unwrap().max_tokens, Some(512));
+    assert!(Config::from_env(|name| (name == "QAI_MAX_TOKENS").then(|| "lots".into())).is_err());
+    assert!(Config::parse("max_tokens = 0\n").is_err());
+    assert!(Config::parse("max_tokens = 5000000000\n").is_err());
+    let dir = TempDir::new().unwrap();
+    let mut file = ConfigFile::open(&dir.path().join("config.toml")).unwrap();
+    assert!(file.set("max_tokens", "0").is_err());
+    file.set("max_tokens", "1024").unwrap();
+    assert_eq!(file.config().unwrap().max_tokens, Some(1024));
+}
+
+#[test]
+fn config_env_values_are_checked() {
+    let err = Config::from_env(|name| (name == "QAI_PROVIDER").then(|| "nope".into())).unwrap_err();
+    assert!(err.to_string().contains("QAI_PROVIDER"), "{err}");
+    let err = Config::from_env(|name| (name == "QAI_TIMEOUT").then(|| "soon".into())).unwrap_err();
+    assert!(err.to_string().contains("QAI_TIMEOUT"), "{err}");
+    let config = Config::from_env(|name| (name == "QAI_APPROVAL").then(|| "deny".into())).unwrap();
+    assert_eq!(config.approval.mutating, Some(Approval::Deny));
+}
+
+#[test]
+fn config_file_set_keeps_comments_and_layout() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("qai").join("config.toml");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let original = "# my settings\nprovider = \"openai\"  # work account\n\n[agent]\n# be generous\nmax_steps = 30\n";
+    fs::write(&path, original).unwrap();
+
+    let mut file = ConfigFile::open(&path).unwrap();
+    file.set("provider", "anthropic").unwrap();
+    file.set("agent.max_steps", "50").unwrap();
+    file.set("providers.anthropic.model", "claude-sonnet-4-5").unwrap();
+    file.save().unwrap();
+
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(written.starts_with("# my settings\nprovider = \"anthropic\"  # work account\n"), "{written}");
+    assert!(written.contains("# be generous\nmax_steps = 50\n"), "{written}");
+    assert!(written.contains("[providers.anthropic]\nmodel = \"claude-sonnet-4-5\"\n"), "{written}");
+    assert!(!written.contains("[providers]\n"), "the parent table stays implicit: {written}");
+    let config = ConfigFile::open(&path).unwrap().config().unwrap();
+    assert_eq!(config.model_for(Provider::Anthropic).as_deref(), Some("claude-sonnet-4-5"));
+}
+
+#[test]
+fn config_file_set_validates_before_writing() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    let mut file = ConfigFile::open(&path).unwrap();
+    assert!(file.set("agent.max_steps", "lots").is_err());
+    assert!(file.set("agent.native_tools", "maybe").is_err());
+    assert!(file.set("approval.mutating", "sometimes").is_err());
+    assert!(file.set("provider", "nope").is_err());
+    let err = file.set("agent.max_stpes", "3").unwrap_err();
+    assert!(err.to_string().contains("unknown config key"), "{err}");
+    assert!(file.set("tui.theme", "solarized").is_err());
+    assert!(file.set("tui.keybindings.launch", "f6").is_err());
+    assert!(file.set("tui.keybindings.agent_mode", "x").is_err());
+    assert_eq!(file.to_string(), "", "failed sets leave the file alone");
+
+    file.set("tui.theme", "light").unwrap();
+    file.set("tui.keybindings.revert_run", "alt+r").unwrap();
+    let config = file.config().unwrap();
+    assert_eq!(config.theme(), Theme::Light);
+    assert_eq!(config.keymap().label(KeyAction::RevertRun), "Alt+R");
+    assert!(file.remove("tui.theme"));
+
+    file.set("approval.tools.shell", "deny").unwrap();
+    assert!(file.remove("approval.tools.shell"));
+    assert!(!file.remove("approval.tools.shell"));
+    assert!(!file.remove("tui.theme"));
+}
+
+#[test]
+fn config_entries_mask_tokens() {
+    let config = Config::parse(FULL_CONFIG).unwrap();
+    let entries = config.entries();
+    assert!(entries.contains(&("providers.openai.api_token".to_string(), "…enai".to_string())), "{entries:?}");
+    assert!(entries.contains(&("agent.max_steps".to_string(), "30".to_string())));
+    let config = Config::parse("api_token = \"short\"\n").unwrap();
+    assert_eq!(config.entries(), vec![("api_token".to_string(), "…".to_string())]);
+}
+
+#[test]
+fn config_file_keeps_one_token_per_provider() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "api_token = \"legacy\"\n").unwrap();
+    for (provider, token) in [("openai", "sk-openai"), ("anthropic", "sk-ant"), ("openai", "sk-openai-2")] {
+        let mut file = ConfigFile::open(&path).unwrap();
+        file.set(&format!("providers.{provider}.api_token"), token).unwrap();
+        file.save().unwrap();
+    }
+    let config = ConfigFile::open(&path).unwrap().config().unwrap();
+    assert_eq!(config.token_for(Provider::OpenAI).as_deref(), Some("sk-openai-2"));
+    assert_eq!(config.token_for(Provider::Anthropic).as_deref(), Some("sk-ant"));
+    // The legacy token is not handed to providers it wasn't saved for
+    assert_eq!(config.token_for(Provider::XAI), None);
+}
+
+#[test]
+fn config_legacy_token_goes_to_the_default_provider_only() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "provider = \"openai\"\napi_token = \"legacy\"\n").unwrap();
+    let config = Config::layered(std::slice::from_ref(&path), None, no_env).unwrap();
+    assert_eq!(config.token_for(Provider::OpenAI).as_deref(), Some("legacy"));
+    for provider in [Provider::Anthropic, Provider::XAI, Provider::Zen] {
+        assert_eq!(config.token_for(provider), None, "{provider:?}");
+    }
+    // The default provider's own token wins
+    fs::write(&path, "provider = \"openai\"\napi_token = \"legacy\"\n[providers.openai]\napi_token = \"sk-openai\"\n").unwrap();
+    let config = Config::layered(&[path], None, no_env).unwrap();
+    assert_eq!(config.token_for(Provider::OpenAI).as_deref(), Some("sk-openai"));
+    assert_eq!(config.token_for(Provider::XAI), None);
+}
+
+// ── secrets ───────────────────────────────────────────────────────────────────
+
+fn no_env(_: &str) -> Option<String> {
+    None
+}
+
+#[test]
+fn encrypted_file_store_round_trips_without_plaintext_on_disk() {
+    let dir = TempDir::new().unwrap();
+    let store = EncryptedFileStore::new(dir.path());
+    assert_eq!(store.get("openai").unwrap(), None);
+    store.set("openai", "sk-secret-openai").unwrap();
+    store.set("anthropic", "sk-secret-ant").unwrap();
+    let bytes = fs::read(store.path()).unwrap();
+    assert!(!String::from_utf8_lossy(&bytes).contains("sk-secret"));
+
+    let reopened = EncryptedFileStore::new(dir.path());
+    assert_eq!(reopened.get("openai").unwrap().as_deref(), Some("sk-secret-openai"));
+    assert!(reopened.delete("openai").unwrap());
+    assert!(!reopened.delete("openai").unwrap());
+    assert_eq!(reopened.get("openai").unwrap(), None);
+    assert_eq!(reopened.get("anthropic").unwrap().as_deref(), Some("sk-secret-ant"));
+}
+
+#[test]
+fn encrypted_file_store_with_passphrase_rejects_a_wrong_or_missing_one() {
+    let dir = TempDir::new().unwrap();
+    let store = EncryptedFileStore::new(dir.path()).with_passphrase(Some("correct horse".into()));
+    store.set("xai", "xai-secret").unwrap();
+    // No key file is needed when the key comes from the passphrase
+    assert!(!dir.path().join("secrets.key").exists());
+    assert_eq!(store.get("xai").unwrap().as_deref(), Some("xai-secret"));
+
+    let wrong = EncryptedFileStore::new(dir.path()).with_passphrase(Some("battery staple".into()));
+    assert!(format!("{:#}", wrong.get("xai").unwrap_err()).contains("wrong key or passphrase"));
+    let missing = EncryptedFileStore::new(dir.path());
+    assert!(format!("{:#}", missing.get("xai").unwrap_err()).contains("QAI_SECRETS_PASSPHRASE"));
+}
+
+#[cfg(unix)]
+#[test]
+fn encrypted_file_store_files_are_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = TempDir::new().unwrap();
+    let store = EncryptedFileStore::new(dir.path());
+    store.set("openai", "sk-secret").unwrap();
+    for name in ["secrets.enc", "secrets.key"] {
+        let mode = fs::metadata(dir.path().join(name)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "{name}");
+    }
+}
+
+#[test]
+fn open_store_picks_the_configured_backend() {
+    let dir = TempDir::new().unwrap();
+    let file = open_store(Some(SecretBackend::File), dir.path());
+    assert!(file.describe().contains("secrets.enc"));
+    assert!(!file.is_plaintext());
+    let plaintext = open_store(Some(SecretBackend::Plaintext), dir.path());
+    plaintext.set("openai", "sk-plain").unwrap();
+    assert!(fs::read_to_string(dir.path().join("config.toml")).unwrap().contains("sk-plain"));
+    assert!(plaintext.is_plaintext());
+}
+
+#[test]
+fn secrets_backend_is_read_from_config_and_env() {
+    let config = Config::parse("[secrets]\nbackend = \"file\"\n").unwrap();
+    assert_eq!(config.secrets.backend, Some(SecretBackend::File));
+    assert!(Config::parse("[secrets]\nbackend = \"vault\"\n").is_err());
+    let env = |name: &str| (name == "QAI_SECRETS_BACKEND").then(|| "plaintext".to_string());
+    let config = Config::layered(&[], None, env).unwrap();
+    assert_eq!(config.secrets.backend, Some(SecretBackend::Plaintext));
+    assert!(config.entries().contains(&("secrets.backend".to_string(), "plaintext".to_string())));
+}
+
+#[test]
+fn resolve_tokens_prefers_env_then_store_then_config() {
+    let dir = TempDir::new().unwrap();
+    let store = EncryptedFileStore::new(dir.path());
+    store.set("openai", "sk-stored-openai").unwrap();
+    store.set("anthropic", "sk-stored-ant").unwrap();
+    let mut config = Config::parse(
+        "[providers.openai]\napi_token = \"sk-plain-openai\"\n[providers.xai]\napi_token = \"xai-plain\"\n",
+    )
+    .unwrap();
+    let env = |name: &str| (name == "ANTHROPIC_API_KEY").then(|| "sk-env-ant".to_string());
+    resolve_tokens(&mut config, &store, env).unwrap();
+    assert_eq!(config.token_for(Provider::Anthropic).as_deref(), Some("sk-env-ant"));
+    assert_eq!(config.token_for(Provider::OpenAI).as_deref(), Some("sk-stored-openai"));
+    assert_eq!(config.token_for(Provider::XAI).as_deref(), Some("xai-plain"));
+    assert_eq!(config.token_for(Provider::Ollama), None);
+}
+
+#[test]
+fn save_token_removes_the_plaintext_copy() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "# my settings\n[providers.openai]\napi_token = \"sk-old\"\nmodel = \"gpt-4o-mini\"\n").unwrap();
+    let store = EncryptedFileStore::new(&dir.path().join("secrets"));
+    save_token(&store, Some(&path), Provider::OpenAI, "sk-new\n").unwrap();
+    assert_eq!(store.get("openai").unwrap().as_deref(), Some("sk-new"));
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(!text.contains("sk-old"));
+    assert!(text.contains("# my settings") && text.contains("gpt-4o-mini"));
+
+    // An empty token forgets the stored one
+    save_token(&store, Some(&path), Provider::OpenAI, "").unwrap();
+    assert_eq!(store.get("openai").unwrap(), None);
+}
+
+#[test]
+fn migrate_plaintext_moves_every_provider_token() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "[providers.openai]\napi_token = \"sk-o\"\n[providers.github]\napi_token = \"ghp-g\"\n").unwrap();
+    let store = EncryptedFileStore::new(&dir.path().join("secrets"));
+    let moved = migrate_plaintext(&store, &path).unwrap();
+    assert_eq!(moved, vec![Provider::OpenAI, Provider::GitHubModels]);
+    assert_eq!(store.get("github").unwrap().as_deref(), Some("ghp-g"));
+    let mut config = ConfigFile::open(&path).unwrap().config().unwrap();
+    assert_eq!(config.token_for(Provider::OpenAI), None);
+    resolve_tokens(&mut config, &store, no_env).unwrap();
+    assert_eq!(config.token_for(Provider::OpenAI).as_deref(), Some("sk-o"));
+
+    let plaintext = PlaintextStore::new(path.clone());
+    assert!(migrate_plaintext(&plaintext, &path).is_err());
+}
+
+#[test]
+fn migrate_plaintext_moves_the_legacy_token() {
+    // The one line older versions wrote
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "api_token = \"sk-legacy\"\n").unwrap();
+    let store = EncryptedFileStore::new(&dir.path().join("secrets"));
+    assert_eq!(migrate_plaintext(&store, &path).unwrap(), vec![Provider::Ollama]);
+    assert_eq!(store.get("ollama").unwrap().as_deref(), Some("sk-legacy"));
+    assert!(!fs::read_to_string(&path).unwrap().contains("sk-legacy"));
+
+    // Saving a token for the default provider replaces the legacy one on disk
+    fs::write(&path, "provider = \"openai\"\napi_token = \"sk-legacy\"\n").unwrap();
+    save_token(&store, Some(&path), Provider::Anthropic, "sk-ant").unwrap();
+    assert!(fs::read_to_string(&path).unwrap().contains("sk-legacy"));
+    save_token(&store, Some(&path), Provider::OpenAI, "sk-new").unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(!text.contains("sk-legacy") && text.contains("provider = \"openai\""));
+    assert_eq!(store.get("openai").unwrap().as_deref(), Some("sk-new"));
+}
+
+#[test]
+fn config_auth_style_applies_per_provider() {
+    let config = Config::parse("[providers.openai]\nurl = \"https://r.openai.azure.com/openai/deployments/d\"\nauth = \"api-key\"\n").unwrap();
+    let llm = config.llm(Some(Provider::OpenAI), None, None, None);
+    assert_eq!(llm.auth, AuthStyle::ApiKey);
+    assert_eq!(llm.url, "https://r.openai.azure.com/openai/deployments/d");
+    assert_eq!(config.auth_for(Provider::Anthropic), AuthStyle::XApiKey);
+    assert_eq!(config.auth_for(Provider::Zen), AuthStyle::Bearer);
+    assert!(config.entries().contains(&("providers.openai.auth".to_string(), "api-key".to_string())));
+    assert!(Config::parse("[providers.custom]\nauth = \"basic\"\n").is_err());
+
+    let dir = TempDir::new().unwrap();
+    let mut file = ConfigFile::open(&dir.path().join("config.toml")).unwrap();
+    file.set("providers.custom.auth", "none").unwrap();
+    assert!(file.set("providers.custom.auth", "digest").is_err());
+    assert_eq!(file.config().unwrap().auth_for(Provider::Custom), AuthStyle::None);
+}
@@ -1,6 +1,9 @@
 use qai_cli::tui::{render_to_buffer, App, ChatFocus, Provider, Screen};
 #[allow(unused_imports)]
 use qai_cli::{save_api_token, load_api_token, strip_model_tags, TextInput};
+use qai_cli::agent::approval::Approval;
+use qai_cli::config::Config;
+use qai_cli::sessions::{Session, SessionStore};
 use std::fs;
 use std::path::PathBuf;
 use tempfile::TempDir;
@@ -118,8 +121,21 @@ fn provider_ollama_api_url() {
 // ── Provider::all ─────────────────────────────────────────────────────────────
 
 #[test]
-fn provider_all_returns_five_variants() {
-    assert_eq!(Provider::all().len(), 5);
+fn provider_all_returns_seven_variants() {
+    assert_eq!(Provider::all().len(), 7);
+}
+
+#[test]
+fn provider_zen_and_custom_are_first_class() {
+    assert_eq!(Provider::from_name("zen"), Some(Provider::Zen));
+    assert_eq!(Provider::from_name("custom"), Some(Provider::Custom));
+    assert_eq!(Provider::Zen.default_model(), "anthropic/claude-sonnet-4-5");
+    assert_eq!(Provider::Zen.api_url(), "https://api.opencode.ai/v1/chat/completions");
+    assert!(Provider::Custom.api_url().is_empty());
+    assert!(!Provider::Custom.requires_token());
+    for p in Provider::all() {
+        assert_eq!(Provider::from_name(p.name()), Some(*p));
+    }
 }
 
 #[test]
@@ -358,13 +374,14 @@ fn chat_focus_conversation_is_not_message() {
 
 #[test]
 fn screen_all_variants_are_distinct() {
-    let screens = vec![
+    let screens = [
         Screen::Menu,
         Screen::Info,
         Screen::Show,
         Screen::Validate,
         Screen::Tools,
         Screen::Chat,
+        Screen::Sessions,
     ];
     for (i, a) in screens.iter().enumerate() {
         for (j, b) in screens.iter().enumerate() {
@@ -565,6 +582,101 @@ fn app_active_model_whitespace_only_falls_back_to_default() {
     assert_eq!(app.active_model(), Provider::OpenAI.default_model());
 }
 
+#[test]
+fn app_apply_config_selects_the_configured_provider_and_settings() {
+    let (_dir, mut app) = make_app_with_content("content");
+    let config = Config::parse(
+        "provider = \"anthropic\"\nmodel = \"claude-sonnet-4-5\"\n\
+         [providers.anthropic]\napi_token = \"sk-ant\"\nurl = \"https://gateway.example.com/v1/messages\"\n\
+         [agent]\nnative_tools = true\nmax_steps = 7\n[approval]\nmutating = \"deny\"\n",
+    )
+    .unwrap();
+    app.apply_config(config);
+    assert_eq!(app.selected_provider(), Provider::Anthropic);
+    assert_eq!(app.provider_list_state.selected(), Some(1));
+    assert_eq!(app.active_model(), "claude-sonnet-4-5");
+    assert_eq!(app.api_token, "sk-ant");
+    assert_eq!(app.custom_url, "https://gateway.example.com/v1/messages");
+    assert!(app.native_tools);
+    assert_eq!(app.approval_policy.mutating, Approval::Deny);
+    assert_eq!(app.config.limits().max_steps, 7);
+    // The configured model is only the default provider's
+    app.provider_index = 0;
+    assert_eq!(app.active_model(), Provider::OpenAI.default_model());
+}
+
+#[test]
+fn app_select_provider_loads_that_providers_token() {
+    let (_dir, mut app) = make_app_with_content("content");
+    app.config = Config::parse(
+        "[providers.openai]\napi_token = \"sk-openai\"\n[providers.anthropic]\napi_token = \"sk-ant\"\n\
+         [providers.xai]\napi_token = \"xai-key\"\n",
+    )
+    .unwrap();
+    app.model_input = "gpt-4o-mini".into();
+    app.select_provider(0);
+    assert_eq!(app.api_token, "sk-openai");
+    assert!(app.model_input.is_empty(), "the typed model belonged to the previous provider");
+    app.select_provider(1);
+    assert_eq!((app.selected_provider(), app.api_token.as_str()), (Provider::Anthropic, "sk-ant"));
+    app.select_provider(2);
+    assert_eq!(app.api_token, "xai-key");
+    app.select_provider(3);
+    assert_eq!(app.api_token, "", "Ollama has no token of its own");
+    app.select_provider(99);
+    assert_eq!(app.selected_provider(), *Provider::all().last().unwrap());
+    app.select_provider(0);
+    assert_eq!(app.api_token, "sk-openai");
+}
+
+#[test]
+fn state_manager_provider_keys_switch_tokens() {
+    let (_dir, mut app) = make_app_with_content("content");
+    app.config = Config::parse("api_token = \"legacy\"\n[providers.anthropic]\napi_token = \"sk-ant\"\n").unwrap();
+    app.select_provider(0);
+    let mut manager = qai_cli::tui::StateManager::new(app);
+    // The token saved by older versions is not lent to providers without their own
+    assert_eq!(manager.app().api_token, "");
+    assert_eq!(manager.app().config.token_for(Provider::XAI), None);
+    manager.select_next_provider();
+    assert_eq!(manager.app().api_token, "sk-ant");
+    manager.select_previous_provider();
+    assert_eq!(manager.app().api_token, "");
+}
+
+#[test]
+fn app_select_provider_keeps_each_providers_base_url() {
+    let (_dir, mut app) = make_app_with_content("content");
+    app.config = Config::parse("[providers.custom]\nurl = \"http://localhost:1234/v1\"\n").unwrap();
+    app.select_provider(0);
+    assert!(app.custom_url.is_empty());
+    app.custom_url = "https://litellm.internal".to_string();
+    let custom = Provider::all().iter().position(|p| *p == Provider::Custom).unwrap();
+    app.select_provider(custom);
+    assert_eq!(app.custom_url, "http://localhost:1234/v1");
+    app.select_provider(0);
+    assert_eq!(app.custom_url, "https://litellm.internal");
+}
+
+#[test]
+fn state_manager_typing_a_token_does_not_save_each_keystroke() {
+    let (_dir, mut app) = make_app_with_content("content");
+    app.screen = Screen::Chat;
+    app.chat_focus = ChatFocus::Token;
+    let mut manager = qai_cli::tui::StateManager::new(app);
+    for c in "sk-typed".chars() {
+        manager.add_token_char(c);
+    }
+    manager.remove_token_char();
+    // Still in the field: nothing is written yet
+    manager.app_mut().save_token_on_blur();
+    let app = manager.app();
+    assert_eq!(app.api_token, "sk-type");
+    assert!(app.api_token_edited);
+    assert!(!app.api_token_saved);
+    assert!(app.status.is_empty());
+}
+
 // ── Provider descriptions ─────────────────────────────────────────────────────
 
 #[test]
@@ -700,6 +812,7 @@ fn render_menu_screen_contains_all_menu_items() {
     assert!(text.contains("Validate"));
     assert!(text.contains("Tools"));
     assert!(text.contains("Chat"));
+    assert!(text.contains("Sessions"));
     assert!(text.contains("Quit"));
 }
 
@@ -736,7 +849,7 @@ fn render_show_screen_missing_file_shows_fallback() {
     app.screen = Screen::Show;
     let buf = render_to_buffer(&mut app, 120, 30);
     let text = buffer_text(&buf);
-    assert!(text.contains("not found") || text.contains("Failed") || text.contains("Error") || text.len() > 0);
+    assert!(text.contains("not found") || text.contains("Failed") || text.contains("Error") || !text.is_empty());
 }
 
 #[test]
@@ -755,7 +868,7 @@ fn render_validate_screen_missing_sections_shows_error() {
     app.screen = Screen::Validate;
     let buf = render_to_buffer(&mut app, 120, 30);
     let text = buffer_text(&buf);
-    assert!(text.contains("Missing") || text.contains("failed") || text.contains("Error") || text.len() > 0);
+    assert!(text.contains("Missing") || text.contains("failed") || text.contains("Error") || !text.is_empty());
 }
 
 #[test]
@@ -864,7 +977,9 @@ fn render_does_not_panic_with_tiny_terminal() {
 
 #[test]
 fn render_all_screens_do_not_panic() {
-    let screens = [Screen::Menu, Screen::Info, Screen::Show, Screen::Validate, Screen::Tools, Screen::Chat];
+    let screens = [
+        Screen::Menu, Screen::Info, Screen::Show, Screen::Validate, Screen::Tools, Screen::Chat, Screen::Sessions,
+    ];
     for screen in screens {
         let (_dir, mut app) = make_app_with_content("## ENVIRONMENT\n### PRIMARY OBJECTIVE\n### MODE SELECTION PRIMER\n");
         app.screen = screen;
@@ -1373,3 +1488,392 @@ fn thinking_indicator_hidden_when_not_streaming() {
     let content: String = buf.content().iter().map(|c| c.symbol()).collect();
     assert!(!content.contains("Thinking"), "indicator should not appear when not streaming");
 }
+
+#[test]
+fn provider_from_name_accepts_cli_names() {
+    assert_eq!(Provider::from_name("openai"), Some(Provider::OpenAI));
+    assert_eq!(Provider::from_name("Anthropic"), Some(Provider::Anthropic));
+    assert_eq!(Provider::from_name("xai"), Some(Provider::XAI));
+    assert_eq!(Provider::from_name("ollama"), Some(Provider::Ollama));
+    assert_eq!(Provider::from_name("github"), Some(Provider::GitHubModels));
+    assert_eq!(Provider::from_name("nope"), None);
+}
+
+// ── StreamDecoder ─────────────────────────────────────────────────────────────
+
+use qai_cli::llm::{chat_url, StreamChunk, StreamDecoder};
+
+#[test]
+fn stream_decoder_joins_lines_split_across_chunks() {
+    let mut d = StreamDecoder::new();
+    assert!(d.feed(br#"{"message":{"content":"Hel"#).is_empty());
+    assert_eq!(
+        d.feed(b"lo\"},\"done\":false}\n"),
+        vec![StreamChunk::Delta("Hello".to_string())]
+    );
+    assert_eq!(d.feed(b"{\"message\":{\"content\":\"\"},\"done\":true}\n"), vec![StreamChunk::Done]);
+}
+
+#[test]
+fn stream_decoder_handles_openai_sse_and_done_marker() {
+    let mut d = StreamDecoder::new();
+    let out = d.feed(b"data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n");
+    assert_eq!(out, vec![StreamChunk::Delta("hi".to_string()), StreamChunk::Done]);
+}
+
+#[test]
+fn stream_decoder_handles_anthropic_sse() {
+    let mut d = StreamDecoder::new();
+    let out = d.feed(
+        b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"yo\"}}\n\
+          event: message_stop\ndata: {\"type\":\"message_stop\"}\n",
+    );
+    assert_eq!(out, vec![StreamChunk::Delta("yo".to_string()), StreamChunk::Done]);
+}
+
+#[test]
+fn stream_decoder_keeps_multibyte_chars_split_across_chunks() {
+    let line = "{\"message\":{\"content\":\"✅\"}}\n".as_bytes();
+    let split = line.iter().position(|&b| b >= 0x80).unwrap() + 1;
+    let mut d = StreamDecoder::new();
+    assert!(d.feed(&line[..split]).is_empty());
+    assert_eq!(d.feed(&line[split..]), vec![StreamChunk::Delta("✅".to_string())]);
+}
+
+#[test]
+fn stream_decoder_finish_flushes_unterminated_line() {
+    let mut d = StreamDecoder::new();
+    assert!(d.feed(br#"{"message":{"content":"tail"}}"#).is_empty());
+    assert_eq!(d.finish(), vec![StreamChunk::Delta("tail".to_string())]);
+}
+
+#[test]
+fn chat_url_appends_ollama_chat_path_once() {
+    assert_eq!(chat_url(Provider::Ollama, "http://h:1/"), "http://h:1/api/chat");
+    assert_eq!(chat_url(Provider::Ollama, "http://h:1/api/chat"), "http://h:1/api/chat");
+    assert_eq!(chat_url(Provider::OpenAI, ""), Provider::OpenAI.api_url());
+}
+
+#[test]
+fn chat_url_overrides_every_providers_base_url() {
+    // LM Studio / vLLM style base URLs
+    assert_eq!(chat_url(Provider::Custom, "http://localhost:1234/v1"), "http://localhost:1234/v1/chat/completions");
+    assert_eq!(chat_url(Provider::OpenAI, "http://vllm:8000/v1/"), "http://vllm:8000/v1/chat/completions");
+    // A full chat URL is used as is
+    assert_eq!(chat_url(Provider::Zen, "https://proxy/v1/chat/completions"), "https://proxy/v1/chat/completions");
+    assert_eq!(chat_url(Provider::Anthropic, "https://gateway/v1"), "https://gateway/v1/messages");
+    // Azure OpenAI keeps its api-version query
+    assert_eq!(
+        chat_url(Provider::OpenAI, "https://r.openai.azure.com/openai/deployments/gpt4o?api-version=2024-10-21"),
+        "https://r.openai.azure.com/openai/deployments/gpt4o/chat/completions?api-version=2024-10-21"
+    );
+    assert!(qai_cli::llm::endpoint(Provider::Custom, " ").is_err());
+}
+
+// ── Theme and keybindings ────────────────────────────────────────────────────
+
+#[test]
+fn config_keybindings_rebind_chat_actions() {
+    use qai_cli::tui::keys::KeyAction;
+    let mut app = make_app_no_file();
+    app.apply_config(Config::parse("[tui.keybindings]\nagent_mode = \"ctrl+t\"\n").unwrap());
+    let ctrl_t = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::CONTROL);
+    assert_eq!(app.keymap.action_for(&ctrl_t), Some(KeyAction::AgentMode));
+    assert_eq!(app.keymap.action_for(&key(KeyCode::F(2))), None);
+    assert_eq!(app.keymap.action_for(&key(KeyCode::F(4))), Some(KeyAction::UndoStep));
+    app.run_key_action(KeyAction::AgentMode);
+    assert!(app.agent_mode);
+    assert!(app.status.contains("Ctrl+T to toggle"), "{}", app.status);
+
+    app.screen = Screen::Chat;
+    let text = buffer_text(&render_to_buffer(&mut app, 200, 40));
+    assert!(text.contains("Ctrl+T: 🤖"), "{text}");
+    assert!(text.contains("Ctrl+T Agent Mode ON"), "{text}");
+    assert!(!text.contains("F2"), "{text}");
+}
+
+#[test]
+fn config_theme_recolours_the_screen() {
+    use ratatui::style::Color;
+    let mut app = make_app_no_file();
+    let dark = render_to_buffer(&mut app, 80, 24);
+    assert_eq!(dark[(0, 10)].bg, Color::Rgb(15, 15, 25));
+    app.apply_config(Config::parse("[tui]\ntheme = \"light\"\n").unwrap());
+    let light = render_to_buffer(&mut app, 80, 24);
+    assert_eq!(light[(0, 10)].bg, Color::Rgb(245, 245, 248));
+    // The banner title is white on dark, black on light
+    let title = (0..80).find(|&x| dark[(x, 0)].symbol() == "Q").unwrap();
+    assert_eq!(dark[(title, 0)].fg, Color::White);
+    assert_eq!(light[(title, 0)].fg, Color::Black);
+}
+
+// ── Approval modal ────────────────────────────────────────────────────────────
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use qai_cli::agent::approval::{ApprovalDecision, ApprovalRequest};
+use qai_cli::tui::approval::{handle_approval_key, PendingApproval};
+use tokio::sync::oneshot;
+
+fn pending(app: &mut App, tool: &str, input: &str) -> oneshot::Receiver<ApprovalDecision> {
+    let (reply, rx) = oneshot::channel();
+    app.pending_approval = Some(PendingApproval::new(ApprovalRequest {
+        step: 2,
+        tool: tool.to_string(),
+        input: input.to_string(),
+        preview: format!("$ {input}"),
+        reply,
+    }));
+    rx
+}
+
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+#[test]
+fn approval_key_is_ignored_without_pending_request() {
+    let mut app = make_app_no_file();
+    assert!(!handle_approval_key(&mut app, key(KeyCode::Char('y'))));
+}
+
+#[test]
+fn approval_y_approves_and_closes_modal() {
+    let mut app = make_app_no_file();
+    let mut rx = pending(&mut app, "shell", "ls");
+    assert!(handle_approval_key(&mut app, key(KeyCode::Char('y'))));
+    assert!(app.pending_approval.is_none());
+    assert_eq!(rx.try_recv().unwrap(), ApprovalDecision::Approve);
+}
+
+#[test]
+fn approval_esc_rejects() {
+    let mut app = make_app_no_file();
+    let mut rx = pending(&mut app, "shell", "rm -rf /");
+    assert!(handle_approval_key(&mut app, key(KeyCode::Esc)));
+    assert_eq!(rx.try_recv().unwrap(), ApprovalDecision::Reject(String::new()));
+}
+
+#[test]
+fn approval_edit_then_enter_sends_edited_input() {
+    let mut app = make_app_no_file();
+    let mut rx = pending(&mut app, "shell", "ls");
+    handle_approval_key(&mut app, key(KeyCode::Char('e')));
+    assert!(app.pending_approval.as_ref().unwrap().editing.is_some());
+    // Keys now edit the input instead of choosing
+    for c in [' ', '-', 'l'] {
+        handle_approval_key(&mut app, key(KeyCode::Char(c)));
+    }
+    assert!(app.pending_approval.is_some());
+    handle_approval_key(&mut app, key(KeyCode::Enter));
+    assert_eq!(rx.try_recv().unwrap(), ApprovalDecision::Edit("ls -l".to_string()));
+}
+
+#[test]
+fn approval_editor_moves_by_the_rendered_modal_width() {
+    let mut app = make_app_no_file();
+    let _rx = pending(&mut app, "shell", &"a".repeat(200));
+    handle_approval_key(&mut app, key(KeyCode::Char('e')));
+    // A 100-column terminal gives an 80-column modal, 78 inside its borders
+    render_to_buffer(&mut app, 100, 30);
+    assert_eq!(app.approval_inner_width, 78);
+    handle_approval_key(&mut app, key(KeyCode::Up));
+    let editor = app.pending_approval.as_ref().unwrap().editing.as_ref().unwrap();
+    assert_eq!(editor.cursor, 200 - 78);
+}
+
+#[test]
+fn approval_esc_while_editing_returns_to_choice() {
+    let mut app = make_app_no_file();
+    let mut rx = pending(&mut app, "shell", "ls");
+    handle_approval_key(&mut app, key(KeyCode::Char('e')));
+    handle_approval_key(&mut app, key(KeyCode::Esc));
+    let p = app.pending_approval.as_ref().unwrap();
+    assert!(p.editing.is_none());
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn render_shows_approval_modal_with_preview() {
+    let (_dir, mut app) = make_app_with_content("prompt");
+    app.screen = Screen::Chat;
+    let _rx = pending(&mut app, "shell", "cargo test");
+    let text = buffer_text(&render_to_buffer(&mut app, 120, 30));
+    assert!(text.contains("Approve `shell`?"), "{text}");
+    assert!(text.contains("$ cargo test"), "{text}");
+    assert!(text.contains("e Edit"), "{text}");
+}
+
+// ── Undo / revert agent edits ─────────────────────────────────────────────────
+
+use qai_cli::agent::Checkpoints;
+
+fn app_with_edits(dir: &TempDir) -> App {
+    let mut app = make_app_no_file();
+    let checkpoints = Checkpoints::new();
+    for (step, content) in [(1, "one"), (2, "two")] {
+        let path = dir.path().join("f.txt");
+        let before = fs::read(&path).ok();
+        fs::write(&path, content).unwrap();
+        checkpoints.set_step(step);
+        checkpoints.record(path, "f.txt", before, content);
+    }
+    app.checkpoints = checkpoints;
+    app
+}
+
+#[test]
+fn undo_agent_step_reverts_the_last_step() {
+    let dir = TempDir::new().unwrap();
+    let mut app = app_with_edits(&dir);
+    app.undo_agent_step();
+    assert_eq!(app.status, "↩ Undid step 2: restored f.txt");
+    assert_eq!(fs::read_to_string(dir.path().join("f.txt")).unwrap(), "one");
+    app.undo_agent_step();
+    assert!(!dir.path().join("f.txt").exists());
+    app.undo_agent_step();
+    assert_eq!(app.status, "Nothing to undo");
+}
+
+#[test]
+fn revert_agent_run_reverts_everything() {
+    let dir = TempDir::new().unwrap();
+    let mut app = app_with_edits(&dir);
+    app.revert_agent_run();
+    assert!(app.status.starts_with("⏪ Reverted the run: restored f.txt; removed f.txt"), "{}", app.status);
+    assert!(!dir.path().join("f.txt").exists());
+    app.revert_agent_run();
+    assert_eq!(app.status, "Nothing to revert");
+}
+
+#[test]
+fn undo_waits_for_a_running_agent() {
+    let dir = TempDir::new().unwrap();
+    let mut app = app_with_edits(&dir);
+    app.streaming = true;
+    app.undo_agent_step();
+    app.revert_agent_run();
+    assert_eq!(app.checkpoints.len(), 2);
+    assert_eq!(fs::read_to_string(dir.path().join("f.txt")).unwrap(), "two");
+}
+
+// ── Sessions ──────────────────────────────────────────────────────────────────
+
+fn app_with_store() -> (TempDir, App) {
+    let dir = TempDir::new().unwrap();
+    let mut app = make_app_no_file();
+    app.session_store = Some(SessionStore::new(dir.path()));
+    (dir, app)
+}
+
+fn chat(app: &mut App, user: &str, reply: &str) {
+    app.messages.push(("user".to_string(), user.to_string()));
+    app.messages.push(("assistant".to_string(), reply.to_string()));
+    app.save_session();
+}
+
+#[test]
+fn save_session_writes_the_conversation_once_per_session() {
+    let (_dir, mut app) = app_with_store();
+    app.provider_index = 3; // Ollama
+    app.agent_mode = true;
+    chat(&mut app, "first question", "first answer");
+    chat(&mut app, "second question", "second answer");
+    let sessions = app.session_store.as_ref().unwrap().list().unwrap();
+    assert_eq!(sessions.len(), 1);
+    let saved = &sessions[0];
+    assert_eq!(saved.title, "first question");
+    assert_eq!(saved.provider, Provider::Ollama);
+    assert_eq!(saved.model, "gemma3");
+    assert!(saved.agent_mode);
+    assert_eq!(saved.messages.len(), 4);
+}
+
+#[test]
+fn save_session_without_store_or_messages_does_nothing() {
+    let mut app = make_app_no_file();
+    chat(&mut app, "hi", "hello");
+    assert!(app.session.is_none());
+    let (_dir, mut app) = app_with_store();
+    app.save_session();
+    assert!(app.session.is_none());
+}
+
+#[test]
+fn resume_session_restores_provider_model_mode_and_messages() {
+    let (_dir, mut app) = app_with_store();
+    let mut session = Session::new(Provider::Anthropic, "claude-custom", true);
+    session.set_messages(&[("user".to_string(), "fix the bug".to_string()), ("assistant".to_string(), "fixed".to_string())]);
+    app.resume_session(session.clone());
+    assert_eq!(app.screen, Screen::Chat);
+    assert_eq!(app.chat_focus, ChatFocus::Message);
+    assert_eq!(app.selected_provider(), Provider::Anthropic);
+    assert_eq!(app.provider_list_state.selected(), Some(1));
+    assert_eq!(app.active_model(), "claude-custom");
+    assert!(app.agent_mode);
+    assert_eq!(app.messages, session.messages);
+    // Further replies are saved into the resumed session
+    chat(&mut app, "and the other one", "done");
+    let sessions = app.session_store.as_ref().unwrap().list().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, session.id);
+    assert_eq!(sessions[0].messages.len(), 4);
+}
+
+#[test]
+fn sessions_screen_lists_resumes_and_deletes() {
+    let (_dir, mut app) = app_with_store();
+    chat(&mut app, "one", "1");
+    app.new_session();
+    assert!(app.messages.is_empty());
+    chat(&mut app, "two", "2");
+    app.open_sessions();
+    assert_eq!(app.screen, Screen::Sessions);
+    assert_eq!(app.sessions.len(), 2);
+    assert_eq!(app.sessions_state.selected(), Some(0));
+
+    app.select_session(5);
+    assert_eq!(app.sessions_state.selected(), Some(1));
+    app.select_session(-5);
+    assert_eq!(app.sessions_state.selected(), Some(0));
+
+    let first = app.sessions[0].clone();
+    app.delete_selected_session();
+    assert_eq!(app.sessions.len(), 1);
+    assert!(app.status.contains("Deleted"));
+    assert!(app.session_store.as_ref().unwrap().load(&first.id).is_err());
+
+    let remaining = app.sessions[0].clone();
+    app.resume_selected_session();
+    assert_eq!(app.screen, Screen::Chat);
+    assert_eq!(app.session.as_ref().map(|s| s.id.clone()), Some(remaining.id));
+}
+
+#[test]
+fn deleting_the_open_session_detaches_chat_from_it() {
+    let (_dir, mut app) = app_with_store();
+    chat(&mut app, "only", "reply");
+    app.open_sessions();
+    app.delete_selected_session();
+    assert!(app.session.is_none());
+    assert_eq!(app.sessions_state.selected(), None);
+}
+
+#[test]
+fn render_sessions_screen_shows_saved_sessions() {
+    let (_dir, mut app) = app_with_store();
+    chat(&mut app, "Audit the login flow", "ok");
+    app.open_sessions();
+    let text = buffer_text(&render_to_buffer(&mut app, 140, 40));
+    assert!(text.contains("Sessions (1)"));
+    assert!(text.contains("Audit the login flow"));
+    assert!(text.contains("openai"));
+    assert!(text.contains("Enter Resume"));
+}
+
+#[test]
+fn render_sessions_screen_when_empty() {
+    let (_dir, mut app) = app_with_store();
+    app.open_sessions();
+    let text = buffer_text(&render_to_buffer(&mut app, 140, 40));
+    assert!(text.contains("No saved sessions"));
+}